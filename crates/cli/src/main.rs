@@ -1,22 +1,28 @@
 use std::{
     fs::{self, File},
-    io::Write as _,
-    path::Path,
+    io::{self, BufReader, BufWriter, Write as _},
+    path::{Path, PathBuf},
 };
 
 use anyhow::Context as _;
+use chrono::Utc;
 use clap::{Args, Parser, Subcommand};
 use pkcs8::der::Decode;
 use plazer_service::{
     config::{
-        LogLevel, ServiceConfigBuilder, DEFAULT_ADDRESS, DEFAULT_CONFIG_PATH, DEFAULT_DATABASE,
-        DEFAULT_HOST, DEFAULT_LOG_DIR, DEFAULT_LOG_LEVEL_FILE, DEFAULT_LOG_LEVEL_STDOUT,
-        DEFAULT_NAMESPACE, DEFAULT_PORT, DEFAULT_PRIVATE_KEY_PATH,
+        DbAuthLevel, DbConfig, LogLevel, LogRotation, ServiceConfigBuilder, DEFAULT_ADDRESS,
+        DEFAULT_CONFIG_PATH, DEFAULT_DATABASE, DEFAULT_DB_AUTH_LEVEL, DEFAULT_HOST,
+        DEFAULT_LOG_DIR, DEFAULT_LOG_LEVEL_FILE, DEFAULT_LOG_LEVEL_STDOUT, DEFAULT_LOG_MAX_SIZE_MB,
+        DEFAULT_LOG_ROTATION, DEFAULT_NAMESPACE, DEFAULT_PORT, DEFAULT_PRIVATE_KEY_PATH,
+        ENV_VAR_CONFIG, ENV_VAR_DB_PASSWORD,
     },
-    init_logging, schema, serve,
+    export, import, init_logging, list_locks, migrate, migration_status, openapi, release_lock,
+    rollback_migrations, schema, serve, set_admin, Allowlist,
 };
 use ring::{rand, signature};
 
+static DEFAULT_UI_SRC: &str = "./packages/ui/src";
+
 #[derive(Parser)]
 #[command(author, version, about)]
 #[command(propagate_version = true)]
@@ -35,13 +41,32 @@ enum Commands {
     Run(RunCommand),
     #[command(about = "Generate schema")]
     Schema(SchemaCommand),
+    #[command(about = "Generate the OpenAPI document of the REST API")]
+    Openapi(OpenapiCommand),
     #[command(about = "Generate JWT signing key")]
     GenerateKey(GenerateKeyCommand),
+    #[command(about = "Manage site administrators")]
+    Admin(AdminCommand),
+    #[command(about = "Inspect and release locks")]
+    Lock(LockCommand),
+    #[command(about = "Inspect and apply database migrations")]
+    Migrate(MigrateCommand),
+    #[command(about = "Export everything in the database to an archive")]
+    Export(ExportCommand),
+    #[command(about = "Import an archive into a fresh database")]
+    Import(ImportCommand),
+    #[command(about = "Inspect the configuration")]
+    Config(ConfigCommand),
+    #[command(about = "Generate the allowlist of persisted queries")]
+    Allowlist(AllowlistCommand),
 }
 
 #[derive(Args)]
 #[command(about = "Starts the server")]
 struct RunCommand {
+    #[clap(flatten)]
+    config: ConfigArgs,
+
     #[arg(
         short,
         long,
@@ -55,6 +80,15 @@ struct RunCommand {
     )]
     host: Option<String>,
 
+    #[arg(
+        long,
+        help = "A PEM file with the certificate chain to serve HTTPS with (needs --tls-key)"
+    )]
+    tls_cert: Option<String>,
+
+    #[arg(long, help = "A PEM file with the private key for --tls-cert")]
+    tls_key: Option<String>,
+
     #[arg(
         short,
         long,
@@ -76,6 +110,9 @@ struct RunCommand {
     )]
     database: Option<String>,
 
+    #[clap(flatten)]
+    db_auth: DbAuthArgs,
+
     #[arg(
         long,
         help = "The private key for authenticating (overrides --private-key-path)"
@@ -109,10 +146,29 @@ struct RunCommand {
     )]
     log_level_file: Option<LogLevel>,
 
+    #[arg(
+        long,
+        help = format!("When to start a new log file\n\n[default: {DEFAULT_LOG_ROTATION}]"),
+        value_enum
+    )]
+    log_rotation: Option<LogRotation>,
+
+    #[arg(
+        long,
+        help = format!("The size in megabytes that log files are rotated at, with `--log-rotation size`\n\n[default: {DEFAULT_LOG_MAX_SIZE_MB}]")
+    )]
+    log_max_size_mb: Option<u64>,
+
+    #[arg(
+        long,
+        help = "How many log files to keep, including the current one\n\n[default: all of them]"
+    )]
+    log_max_files: Option<usize>,
+
     #[arg(
         short,
         long,
-        help = "Write the resolved configuration to the config file and exit",
+        help = "Write the resolved configuration to the (first) config file and exit",
         default_value_t = false
     )]
     write_config: bool,
@@ -125,6 +181,28 @@ struct SchemaCommand {
     output: Option<String>,
 }
 
+#[derive(Args)]
+#[command(about = "Generate the OpenAPI document of the REST API")]
+struct OpenapiCommand {
+    #[arg(short, long, help = "The output file")]
+    output: Option<String>,
+}
+
+#[derive(Args)]
+#[command(about = "Generate the allowlist of persisted queries from the UI's `gql` templates")]
+struct AllowlistCommand {
+    #[arg(
+        short,
+        long,
+        help = "The directory of TypeScript sources to read",
+        default_value = DEFAULT_UI_SRC
+    )]
+    src: String,
+
+    #[arg(short, long, help = "The output file, or stdout if not given")]
+    output: Option<String>,
+}
+
 #[derive(Args)]
 #[command(about = "Generate JWT signing key")]
 struct GenerateKeyCommand {
@@ -137,6 +215,269 @@ struct GenerateKeyCommand {
     output: String,
 }
 
+#[derive(Args)]
+#[command(about = "Manage site administrators")]
+struct AdminCommand {
+    #[command(subcommand)]
+    command: AdminCommands,
+
+    #[clap(flatten)]
+    db: DbArgs,
+}
+
+#[derive(Subcommand)]
+enum AdminCommands {
+    #[command(about = "Make an account a site administrator")]
+    Grant {
+        #[arg(help = "The user ID of the account")]
+        user_id: String,
+    },
+    #[command(about = "Remove site administrator from an account")]
+    Revoke {
+        #[arg(help = "The user ID of the account")]
+        user_id: String,
+    },
+}
+
+#[derive(Args)]
+#[command(about = "Inspect and release locks")]
+struct LockCommand {
+    #[command(subcommand)]
+    command: LockCommands,
+
+    #[clap(flatten)]
+    db: DbArgs,
+}
+
+#[derive(Subcommand)]
+enum LockCommands {
+    #[command(about = "List the locks that are currently held")]
+    List,
+    #[command(
+        about = "Release a lock, even if it is held by a running instance",
+        long_about = "Release a lock, even if it is held by a running instance\n\n\
+                      Only do this if the instance holding the lock is known to have stopped, \
+                      as whatever the lock protects might be done twice otherwise."
+    )]
+    Release {
+        #[arg(help = "The ID of the lock")]
+        id: String,
+    },
+}
+
+#[derive(Args)]
+#[command(about = "Inspect and apply database migrations")]
+struct MigrateCommand {
+    #[command(subcommand)]
+    command: MigrateCommands,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Only look at the migrations for this subsystem"
+    )]
+    subsystem: Option<String>,
+
+    #[clap(flatten)]
+    db: DbArgs,
+}
+
+#[derive(Subcommand)]
+enum MigrateCommands {
+    #[command(about = "Show the migrations that have been applied and those that are pending")]
+    Status,
+    #[command(about = "Apply the pending migrations")]
+    Apply {
+        #[arg(
+            long,
+            help = "Print the queries that would be run instead of running them",
+            default_value_t = false
+        )]
+        dry_run: bool,
+    },
+    #[command(
+        about = "Roll a subsystem back to an earlier step",
+        long_about = "Roll a subsystem back to an earlier step\n\n\
+                      The steps applied after the given step are undone, most recent first. \
+                      If no step is given, every step is undone."
+    )]
+    Rollback {
+        #[arg(help = "The step to roll back to")]
+        step: Option<String>,
+
+        #[arg(
+            long,
+            help = "Print the queries that would be run instead of running them",
+            default_value_t = false
+        )]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args)]
+#[command(about = "Export everything in the database to an archive")]
+struct ExportCommand {
+    #[arg(short, long, help = "The output file, or stdout if not given")]
+    output: Option<String>,
+
+    #[arg(
+        long,
+        help = "Include password hashes, so that accounts can still be logged into once imported",
+        default_value_t = false
+    )]
+    password_hashes: bool,
+
+    #[clap(flatten)]
+    db: DbArgs,
+}
+
+#[derive(Args)]
+#[command(about = "Import an archive into a fresh database")]
+struct ImportCommand {
+    #[arg(help = "The archive to import, or - for stdin")]
+    input: String,
+
+    #[clap(flatten)]
+    db: DbArgs,
+}
+
+#[derive(Args)]
+#[command(about = "Inspect the configuration")]
+struct ConfigCommand {
+    #[command(subcommand)]
+    command: ConfigSubcommand,
+
+    #[clap(flatten)]
+    config: ConfigArgs,
+}
+
+#[derive(Subcommand)]
+enum ConfigSubcommand {
+    #[command(about = "Print the resolved configuration and where each value came from")]
+    Show,
+}
+
+#[derive(Args)]
+struct ConfigArgs {
+    #[arg(
+        long = "config",
+        global = true,
+        help = format!("A config file, or a directory of .toml config files. Can be given several times, with later files overriding earlier ones (overrides {ENV_VAR_CONFIG})\n\n[default: {DEFAULT_CONFIG_PATH}]")
+    )]
+    paths: Vec<String>,
+}
+
+impl ConfigArgs {
+    fn apply(self, config: ServiceConfigBuilder) -> ServiceConfigBuilder {
+        if self.paths.is_empty() {
+            config
+        } else {
+            config.config_paths(self.paths)
+        }
+    }
+}
+
+#[derive(Args)]
+struct DbArgs {
+    #[clap(flatten)]
+    config: ConfigArgs,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = format!("The address of the remote database or the path to a local file\n\n[default: {DEFAULT_ADDRESS}]")
+    )]
+    address: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = format!("The namespace to use in the database\n\n[default: {DEFAULT_NAMESPACE}]")
+    )]
+    namespace: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = format!("The database to use in the namespace\n\n[default: {DEFAULT_DATABASE}]")
+    )]
+    database: Option<String>,
+
+    #[clap(flatten)]
+    db_auth: DbAuthArgs,
+}
+
+impl DbArgs {
+    fn config(self) -> anyhow::Result<DbConfig> {
+        let config = self
+            .db_auth
+            .apply(self.config.apply(ServiceConfigBuilder::new()))
+            .set_address(self.address)
+            .set_namespace(self.namespace)
+            .set_database(self.database)
+            .build()?;
+        config.db_config()
+    }
+}
+
+#[derive(Args)]
+struct DbAuthArgs {
+    #[arg(long, global = true, help = "The user to sign into the database as")]
+    db_username: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = format!("The password of the database user (prefer setting {ENV_VAR_DB_PASSWORD}, as arguments can be seen by other processes)")
+    )]
+    db_password: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = format!("The level that the database user signs in at\n\n[default: {DEFAULT_DB_AUTH_LEVEL}]"),
+        value_enum
+    )]
+    db_auth_level: Option<DbAuthLevel>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "A PEM file with extra certificate authorities to trust when connecting to the database"
+    )]
+    db_tls_ca: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "A PEM file with a client certificate to present to the database"
+    )]
+    db_tls_cert: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "A PEM file with the private key for --db-tls-cert"
+    )]
+    db_tls_key: Option<String>,
+}
+
+impl DbAuthArgs {
+    fn apply(self, config: ServiceConfigBuilder) -> ServiceConfigBuilder {
+        config
+            .set_db_username(self.db_username)
+            .set_db_password(self.db_password)
+            .set_db_auth_level(self.db_auth_level)
+            .set_db_tls_ca(self.db_tls_ca)
+            .set_db_tls_cert(self.db_tls_cert)
+            .set_db_tls_key(self.db_tls_key)
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
@@ -144,9 +485,17 @@ async fn main() -> anyhow::Result<()> {
     match cli.command.unwrap_or(Commands::Run(cli.run)) {
         Commands::Run(cmd) => run(cmd).await?,
         Commands::Schema(cmd) => output_schema(cmd)?,
+        Commands::Openapi(cmd) => output_openapi(cmd)?,
         Commands::GenerateKey(cmd) => {
             generate_key(cmd.output)?;
         }
+        Commands::Admin(cmd) => admin(cmd).await?,
+        Commands::Lock(cmd) => lock(cmd).await?,
+        Commands::Migrate(cmd) => migrate_db(cmd).await?,
+        Commands::Export(cmd) => export_db(cmd).await?,
+        Commands::Import(cmd) => import_db(cmd).await?,
+        Commands::Config(cmd) => show_config(cmd)?,
+        Commands::Allowlist(cmd) => output_allowlist(cmd)?,
     };
 
     Ok(())
@@ -154,22 +503,32 @@ async fn main() -> anyhow::Result<()> {
 
 async fn run(
     RunCommand {
+        config,
         port,
         host,
+        tls_cert,
+        tls_key,
         address,
         namespace,
         database,
+        db_auth,
         private_key,
         private_key_path,
         log_dir,
         log_level_stdout,
         log_level_file,
+        log_rotation,
+        log_max_size_mb,
+        log_max_files,
         write_config,
     }: RunCommand,
 ) -> anyhow::Result<()> {
-    let config = ServiceConfigBuilder::new()
+    let config = db_auth
+        .apply(config.apply(ServiceConfigBuilder::new()))
         .set_port(port)
         .set_host(host)
+        .set_tls_cert(tls_cert)
+        .set_tls_key(tls_key)
         .set_address(address)
         .set_namespace(namespace)
         .set_database(database)
@@ -179,17 +538,208 @@ async fn run(
         .set_log_dir(log_dir)
         .set_log_level_stdout(log_level_stdout)
         .set_log_level_file(log_level_file)
+        .set_log_rotation(log_rotation)
+        .set_log_max_size_mb(log_max_size_mb)
+        .set_log_max_files(log_max_files)
         .build()?;
 
     if write_config {
-        fs::write(DEFAULT_CONFIG_PATH, toml::to_string(&config)?)?;
+        let path: &Path = config
+            .config_paths()
+            .first()
+            .map_or_else(|| DEFAULT_CONFIG_PATH.as_ref(), AsRef::as_ref);
+        if path.is_dir() {
+            anyhow::bail!(
+                "Can't write config to {}, as it's a directory",
+                path.display()
+            );
+        }
+        fs::write(path, toml::to_string(&config)?)?;
         return Ok(());
     }
 
     let (serve_config, log_config) = config.try_into()?;
 
-    let _guard = init_logging(log_config);
-    serve(serve_config).await?;
+    let (_guard, log_levels) = init_logging(log_config);
+    serve(serve_config, Some(log_levels)).await?;
+
+    Ok(())
+}
+
+async fn admin(AdminCommand { command, db }: AdminCommand) -> anyhow::Result<()> {
+    let (user_id, grant) = match command {
+        AdminCommands::Grant { user_id } => (user_id, true),
+        AdminCommands::Revoke { user_id } => (user_id, false),
+    };
+
+    if !set_admin(db.config()?, user_id.clone(), grant).await? {
+        anyhow::bail!("No account with user ID {user_id:?}");
+    }
+
+    if grant {
+        println!("{user_id} is now a site administrator");
+    } else {
+        println!("{user_id} is no longer a site administrator");
+    }
+
+    Ok(())
+}
+
+async fn lock(LockCommand { command, db }: LockCommand) -> anyhow::Result<()> {
+    match command {
+        LockCommands::List => {
+            let now = Utc::now();
+            for lock in list_locks(db.config()?).await? {
+                let owner = lock.owner.as_deref().unwrap_or("unknown");
+                let acquired_at = lock
+                    .acquired_at
+                    .map_or_else(|| "unknown".into(), |t| t.to_rfc3339());
+                let expires_at = lock
+                    .expires_at
+                    .map_or_else(|| "never".into(), |t| t.to_rfc3339());
+                let stale = if lock.is_stale(now) { " (stale)" } else { "" };
+                println!(
+                    "{}\towner {owner}\tacquired {acquired_at}\texpires {expires_at}{stale}",
+                    lock.name()
+                );
+            }
+        }
+        LockCommands::Release { id } => match release_lock(db.config()?, id.clone()).await? {
+            Some(lock) => println!(
+                "Released lock {id} held by {}",
+                lock.owner.as_deref().unwrap_or("unknown")
+            ),
+            None => anyhow::bail!("No lock with ID {id:?}"),
+        },
+    }
+
+    Ok(())
+}
+
+async fn migrate_db(
+    MigrateCommand {
+        command,
+        subsystem,
+        db,
+    }: MigrateCommand,
+) -> anyhow::Result<()> {
+    match command {
+        MigrateCommands::Status => {
+            for status in migration_status(db.config()?, subsystem).await? {
+                println!(
+                    "{}: {}",
+                    status.subsystem,
+                    status.current.as_deref().unwrap_or("not started")
+                );
+                for record in status.history {
+                    let timestamp = record
+                        .timestamp
+                        .map_or_else(|| "unknown".into(), |t| t.to_rfc3339());
+                    let action = if record.rollback {
+                        "rolled back"
+                    } else {
+                        "applied"
+                    };
+                    println!("  {action} {} at {timestamp}", record.step);
+                }
+                for pending in status.pending {
+                    println!("  pending {}", pending.step);
+                }
+            }
+        }
+        MigrateCommands::Apply { dry_run: true } => {
+            for status in migration_status(db.config()?, subsystem).await? {
+                for pending in status.pending {
+                    println!(
+                        "-- {}: {}\n{:#}\n",
+                        pending.subsystem, pending.step, pending.query
+                    );
+                }
+            }
+        }
+        MigrateCommands::Apply { dry_run: false } => {
+            let applied = migrate(db.config()?, subsystem).await?;
+            if applied.is_empty() {
+                println!("No migrations to apply");
+            }
+            for step in applied {
+                println!("Applied {}: {}", step.subsystem, step.step);
+            }
+        }
+        MigrateCommands::Rollback { step, dry_run } => {
+            let Some(subsystem) = subsystem else {
+                anyhow::bail!("A subsystem must be given with --subsystem to roll back");
+            };
+            let undone = rollback_migrations(db.config()?, subsystem, step, dry_run).await?;
+            if undone.is_empty() {
+                println!("No migrations to roll back");
+            }
+            for step in undone {
+                if dry_run {
+                    println!("-- {}: {}\n{:#}\n", step.subsystem, step.step, step.query);
+                } else {
+                    println!("Rolled back {}: {}", step.subsystem, step.step);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn export_db(
+    ExportCommand {
+        output,
+        password_hashes,
+        db,
+    }: ExportCommand,
+) -> anyhow::Result<()> {
+    match output {
+        Some(output) => {
+            let file = File::create(&output).context("Unable to create archive")?;
+            let count = export(db.config()?, BufWriter::new(file), password_hashes).await?;
+            println!("Exported {count} records to {output}");
+        }
+        None => {
+            export(db.config()?, io::stdout().lock(), password_hashes).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn import_db(ImportCommand { input, db }: ImportCommand) -> anyhow::Result<()> {
+    let count = if input == "-" {
+        import(db.config()?, io::stdin().lock()).await?
+    } else {
+        let file = File::open(&input).context("Unable to open archive")?;
+        import(db.config()?, BufReader::new(file)).await?
+    };
+    println!("Imported {count} records");
+
+    Ok(())
+}
+
+fn show_config(ConfigCommand { command, config }: ConfigCommand) -> anyhow::Result<()> {
+    match command {
+        ConfigSubcommand::Show => {
+            let config = config.apply(ServiceConfigBuilder::new()).build()?;
+            let values = config.values()?;
+            let width = values
+                .iter()
+                .map(|value| value.key.len())
+                .max()
+                .unwrap_or(0);
+            for value in values {
+                println!(
+                    "{:width$} = {:32} # {}",
+                    value.key,
+                    value.value.as_deref().unwrap_or("(not set)"),
+                    value.source,
+                );
+            }
+        }
+    }
 
     Ok(())
 }
@@ -208,6 +758,74 @@ fn output_schema(SchemaCommand { output }: SchemaCommand) -> anyhow::Result<()>
     Ok(())
 }
 
+fn output_openapi(OpenapiCommand { output }: OpenapiCommand) -> anyhow::Result<()> {
+    let document = format!("{:#}", openapi());
+
+    match output {
+        Some(output) => {
+            let mut file = File::create(output)?;
+            file.write_all(document.as_bytes())?;
+        }
+        None => println!("{}", document),
+    }
+
+    Ok(())
+}
+
+fn output_allowlist(AllowlistCommand { src, output }: AllowlistCommand) -> anyhow::Result<()> {
+    fn find_sources(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if path.file_name().is_some_and(|name| name != "node_modules") {
+                    find_sources(&path, paths)?;
+                }
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext == "ts" || ext == "tsx")
+            {
+                paths.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut paths = vec![];
+    find_sources(src.as_ref(), &mut paths)
+        .with_context(|| format!("Unable to read sources in {src}"))?;
+    paths.sort();
+    let sources = paths
+        .iter()
+        .map(|path| {
+            let source = fs::read_to_string(path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            Ok((path.display().to_string(), source))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let allowlist = Allowlist::from_sources(
+        sources
+            .iter()
+            .map(|(path, source)| (path.as_str(), source.as_str())),
+    )?;
+    match output {
+        Some(output) => {
+            let file = File::create(&output).context("Unable to create allowlist")?;
+            allowlist.write(BufWriter::new(file))?;
+            println!(
+                "Wrote {} operations to {output}",
+                allowlist.operations().len()
+            );
+        }
+        None => {
+            allowlist.write(io::stdout().lock())?;
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
 fn generate_key(path: impl AsRef<Path>) -> anyhow::Result<String> {
     fn inner(path: &Path) -> anyhow::Result<String> {
         let rng = rand::SystemRandom::new();
@@ -7,7 +7,7 @@ use axum::{
 };
 use base64::prelude::*;
 use chrono::{DateTime, Duration, LocalResult, TimeZone, Utc};
-use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use jsonwebtoken::{Algorithm, Validation};
 use ring::{
     digest, pbkdf2,
     rand::{SecureRandom as _, SystemRandom},
@@ -15,9 +15,12 @@ use ring::{
 use secrecy::{ExposeSecret as _, SecretString};
 use serde::{Deserialize, Serialize};
 
-use super::{CurrentAccount, PartialAccount};
+use super::{CurrentAccount, DecodingKeys, PartialAccount};
 use crate::prelude::*;
 
+/// How many days refresh tokens last for.
+pub(super) const REFRESH_TOKEN_DAYS: i64 = 30;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct JwtClaims {
     // aud: String, // Optional. Audience
@@ -58,7 +61,7 @@ impl RefreshClaims {
     pub fn new(id: ID) -> Self {
         Self {
             id,
-            jwt: JwtClaims::new(Duration::days(30), JwtKind::Refresh),
+            jwt: JwtClaims::new(Duration::days(REFRESH_TOKEN_DAYS), JwtKind::Refresh),
         }
     }
 
@@ -92,9 +95,10 @@ impl TryFrom<AccessClaims<'_>> for CurrentAccount {
     type Error = Error;
 
     fn try_from(claims: AccessClaims) -> Result<Self> {
-        Ok(Self::new(
+        Ok(Self::with_issued_at(
             claims.acc.into_owned(),
             into_utc(claims.jwt.exp)?,
+            into_utc(claims.jwt.iat)?,
         ))
     }
 }
@@ -114,9 +118,9 @@ pub fn create_access_token(
 
 pub fn authenticate(
     input: impl Into<AuthenticateInput>,
-    dec_key: &DecodingKey,
+    dec_keys: &DecodingKeys,
 ) -> Result<CurrentAccount> {
-    fn inner(input: &AuthenticateInput, dec_key: &DecodingKey) -> Result<CurrentAccount> {
+    fn inner(input: &AuthenticateInput, dec_keys: &DecodingKeys) -> Result<CurrentAccount> {
         let token = match input {
             AuthenticateInput::Header(header) => header.as_ref().map(|h| h.0.token()),
             AuthenticateInput::Init(init) => {
@@ -144,7 +148,7 @@ pub fn authenticate(
         };
 
         let validation = default_validation();
-        let token_data = jsonwebtoken::decode::<AccessClaims>(token, dec_key, &validation)?;
+        let token_data = dec_keys.decode::<AccessClaims>(token, &validation)?;
 
         match token_data.claims.jwt.kind {
             JwtKind::Access => Ok(token_data.claims.try_into()?),
@@ -152,7 +156,7 @@ pub fn authenticate(
         }
     }
 
-    inner(&input.into(), dec_key)
+    inner(&input.into(), dec_keys)
 }
 
 pub fn create_refresh_token(id: ID, enc_key: &jsonwebtoken::EncodingKey) -> Result<String> {
@@ -165,9 +169,9 @@ pub fn create_refresh_token(id: ID, enc_key: &jsonwebtoken::EncodingKey) -> Resu
     Ok(token)
 }
 
-pub fn verify_refresh_token(token: &str, dec_key: &DecodingKey) -> Result<RefreshClaims> {
+pub fn verify_refresh_token(token: &str, dec_keys: &DecodingKeys) -> Result<RefreshClaims> {
     let validation = default_validation();
-    let token_data = jsonwebtoken::decode::<RefreshClaims>(token, dec_key, &validation)?;
+    let token_data = dec_keys.decode::<RefreshClaims>(token, &validation)?;
 
     match token_data.claims.jwt.kind {
         JwtKind::Refresh => Ok(token_data.claims),
@@ -281,6 +285,7 @@ mod tests {
     #[test]
     fn test_access_token_valid() {
         let (enc_key, dec_key) = generate_keys();
+        let dec_key = DecodingKeys::from(dec_key);
 
         let acc = PartialAccount::new("id".into(), "user_id".into());
         let token = create_access_token(&acc, &enc_key).unwrap();
@@ -305,7 +310,9 @@ mod tests {
     #[test]
     fn test_access_token_invalid() {
         let (enc_key_a, dec_key_a) = generate_keys();
+        let dec_key_a = DecodingKeys::from(dec_key_a);
         let (enc_key_b, dec_key_b) = generate_keys();
+        let dec_key_b = DecodingKeys::from(dec_key_b);
 
         // Invalid token
         let auth = authenticate(json!({ "token": "not a token" }), &dec_key_a);
@@ -349,6 +356,7 @@ mod tests {
     #[test]
     fn test_refresh_token_valid() {
         let (enc_key, dec_key) = generate_keys();
+        let dec_key = DecodingKeys::from(dec_key);
 
         let token = create_refresh_token("id".into(), &enc_key).unwrap();
 
@@ -363,7 +371,9 @@ mod tests {
     #[test]
     fn test_refresh_token_invalid() {
         let (enc_key_a, dec_key_a) = generate_keys();
+        let dec_key_a = DecodingKeys::from(dec_key_a);
         let (enc_key_b, dec_key_b) = generate_keys();
+        let dec_key_b = DecodingKeys::from(dec_key_b);
 
         // Invalid token
         let auth = verify_refresh_token("not a token", &dec_key_a);
@@ -445,6 +455,15 @@ pub mod testing {
         pub acc: Account,
     }
 
+    impl AccData {
+        pub fn current(&self) -> CurrentAccount {
+            CurrentAccount::new(
+                PartialAccount::new(self.acc.id.to_gql_id(), self.user_id.clone()),
+                Utc::now() + Duration::minutes(30),
+            )
+        }
+    }
+
     impl TestData {
         pub async fn new() -> Self {
             let (jwt_enc_key, jwt_dec_key) = generate_keys();
@@ -461,15 +480,17 @@ pub mod testing {
             let mut data = Self::new().await;
             let account = data.account();
             let acc = account.create_test_user().await;
-            data.current = CurrentAccount::new(
-                PartialAccount::new(acc.acc.id.to_gql_id(), acc.user_id.clone()),
-                Utc::now() + Duration::minutes(30),
-            );
+            data.current = acc.current();
             (data, acc)
         }
 
         pub fn account(&self) -> AccountPersist<'_> {
-            AccountPersist::new(&self.persist, &self.current, &self.csrng, &self.jwt_dec_key)
+            AccountPersist::new(
+                &self.persist,
+                &self.current,
+                &self.csrng,
+                self.jwt_dec_key.clone().into(),
+            )
         }
     }
 }
@@ -0,0 +1,108 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{errors::ErrorKind, DecodingKey, EncodingKey, TokenData, Validation};
+use serde::de::DeserializeOwned;
+
+use super::REFRESH_TOKEN_DAYS;
+
+/// The keys that tokens are signed and verified with. Clones share their keys,
+/// so that the keys can be replaced while the server is running.
+#[derive(Clone)]
+pub struct SigningKeys {
+    keys: Arc<RwLock<Keys>>,
+}
+
+struct Keys {
+    enc_key: Arc<EncodingKey>,
+    dec_key: Arc<DecodingKey>,
+    /// Keys that were replaced, with when they were replaced. Tokens signed
+    /// with them are still accepted until they would have expired.
+    previous: Vec<(Arc<DecodingKey>, DateTime<Utc>)>,
+}
+
+impl Keys {
+    /// The previous keys that tokens might still have been signed with, which
+    /// are those replaced within the lifetime of the longest lived tokens.
+    fn live_previous(&self) -> impl Iterator<Item = &(Arc<DecodingKey>, DateTime<Utc>)> {
+        let cutoff = Utc::now() - Duration::days(REFRESH_TOKEN_DAYS);
+        self.previous
+            .iter()
+            .filter(move |(_, replaced_at)| *replaced_at > cutoff)
+    }
+}
+
+impl SigningKeys {
+    pub fn new(enc_key: EncodingKey, dec_key: DecodingKey) -> Self {
+        Self {
+            keys: Arc::new(RwLock::new(Keys {
+                enc_key: Arc::new(enc_key),
+                dec_key: Arc::new(dec_key),
+                previous: vec![],
+            })),
+        }
+    }
+
+    /// The key that new tokens are signed with.
+    pub fn encoding(&self) -> Arc<EncodingKey> {
+        self.keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .enc_key
+            .clone()
+    }
+
+    /// The keys that tokens are verified with, which are the current key and
+    /// any previous keys that tokens might still have been signed with.
+    pub fn decoding(&self) -> DecodingKeys {
+        let keys = self.keys.read().unwrap_or_else(PoisonError::into_inner);
+        let previous = keys.live_previous().map(|(dec_key, _)| dec_key.clone());
+        DecodingKeys([keys.dec_key.clone()].into_iter().chain(previous).collect())
+    }
+
+    /// Replaces the keys for this and every clone of it. New tokens are signed
+    /// with the new key, but tokens signed with the previous key are still
+    /// accepted until they would have expired.
+    pub fn replace(&self, enc_key: EncodingKey, dec_key: DecodingKey) {
+        let mut keys = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+        let mut previous: Vec<_> = keys.live_previous().cloned().collect();
+        previous.insert(0, (keys.dec_key.clone(), Utc::now()));
+        *keys = Keys {
+            enc_key: Arc::new(enc_key),
+            dec_key: Arc::new(dec_key),
+            previous,
+        };
+    }
+}
+
+/// The keys that tokens are verified with, current key first.
+#[derive(Clone)]
+pub struct DecodingKeys(Arc<[Arc<DecodingKey>]>);
+
+impl DecodingKeys {
+    /// Decodes a token with whichever key it was signed with. If none of them
+    /// signed it, the error is the one from the current key.
+    pub(super) fn decode<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        validation: &Validation,
+    ) -> jsonwebtoken::errors::Result<TokenData<T>> {
+        let mut first_err = None;
+        for dec_key in self.0.iter() {
+            match jsonwebtoken::decode(token, dec_key, validation) {
+                Ok(token_data) => return Ok(token_data),
+                Err(err) if matches!(err.kind(), ErrorKind::InvalidSignature) => {
+                    first_err.get_or_insert(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(first_err.expect("there is always a current key"))
+    }
+}
+
+impl From<DecodingKey> for DecodingKeys {
+    fn from(dec_key: DecodingKey) -> Self {
+        Self(Arc::new([Arc::new(dec_key)]))
+    }
+}
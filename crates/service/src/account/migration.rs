@@ -7,6 +7,8 @@ use crate::{migration::Migration, prelude::*};
 pub enum AccountMigration {
     #[default]
     Init,
+    Restriction,
+    NormalisedUserId,
 }
 
 impl Migration for AccountMigration {
@@ -14,7 +16,9 @@ impl Migration for AccountMigration {
 
     fn next(self) -> Option<Self> {
         match self {
-            Self::Init => None,
+            Self::Init => Some(Self::Restriction),
+            Self::Restriction => Some(Self::NormalisedUserId),
+            Self::NormalisedUserId => None,
         }
     }
 
@@ -22,8 +26,23 @@ impl Migration for AccountMigration {
         use AccountMigration as S;
         match self {
             S::Init => Self::build_init(statements),
+            // Restrictions are stored in a new optional field, so there is
+            // nothing to change. The step is kept so databases that already
+            // recorded it still load.
+            S::Restriction => {}
+            S::NormalisedUserId => Self::build_normalised_user_id(statements),
         }
     }
+
+    fn build_down(&self, statements: &mut Vec<srql::Statement>) -> bool {
+        use AccountMigration as S;
+        match self {
+            S::Init => Self::build_init_down(statements),
+            S::Restriction => {}
+            S::NormalisedUserId => Self::build_normalised_user_id_down(statements),
+        }
+        true
+    }
 }
 
 impl AccountMigration {
@@ -34,4 +53,48 @@ impl AccountMigration {
             [srql::field("user_id")],
         ));
     }
+
+    fn build_init_down(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::remove_index("account_user_id_index", ACC_TABLE_NAME));
+    }
+
+    fn build_normalised_user_id(statements: &mut Vec<srql::Statement>) {
+        // User IDs are unique regardless of case. If existing accounts only
+        // differ by case, this will fail and they must be renamed manually.
+        statements.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::table(ACC_TABLE_NAME),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("normalised_user_id"),
+                srql::Operator::Equal,
+                srql::lowercase(srql::field("user_id")),
+            )])
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        statements.push(srql::remove_index("account_user_id_index", ACC_TABLE_NAME));
+        statements.push(srql::define_uniq_index(
+            "account_normalised_user_id_index",
+            ACC_TABLE_NAME,
+            [srql::field("normalised_user_id")],
+        ));
+    }
+
+    fn build_normalised_user_id_down(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::remove_index(
+            "account_normalised_user_id_index",
+            ACC_TABLE_NAME,
+        ));
+        statements.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::table(ACC_TABLE_NAME),
+            data: srql::Data::UnsetExpression(vec![srql::field("normalised_user_id")]).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        statements.push(srql::define_uniq_index(
+            "account_user_id_index",
+            ACC_TABLE_NAME,
+            [srql::field("user_id")],
+        ));
+    }
 }
@@ -1,13 +1,17 @@
 mod auth;
+mod keys;
 mod migration;
 mod models;
 mod persist;
 mod schema;
+mod status;
 
 pub use auth::*;
+pub use keys::*;
 pub use migration::*;
 pub use models::*;
 pub use persist::*;
 pub use schema::*;
+pub use status::*;
 
-static ACC_TABLE_NAME: &str = "account";
+pub static ACC_TABLE_NAME: &str = "account";
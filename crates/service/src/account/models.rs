@@ -1,15 +1,17 @@
-use async_graphql::{ComplexObject, Context, InputObject, SimpleObject, ID};
+use async_graphql::{ComplexObject, Context, Enum, InputObject, SimpleObject, ID};
 use chrono::{DateTime, Utc};
 use secrecy::SecretString;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 use tracing::instrument;
 
-use super::{create_access_token, create_refresh_token, StoredPword};
-use crate::{id_obj_impls, prelude::*, EncodingKey};
+use super::{create_access_token, create_refresh_token, SigningKeys, StoredPword};
+use crate::{id_obj_impls, ident, prelude::*, query::OpaqueCursor};
 
 static TABLE_NAME: &str = "account";
 
+pub type AccountCursor = OpaqueCursor<String>;
+
 pub trait ToAccountThing {
     fn to_account_thing(&self) -> Thing;
 }
@@ -38,15 +40,31 @@ pub struct Account {
     /// This is used to invalidate all tokens that were issued before the
     /// revocation.
     pub revoked_at: Option<DateTime<Utc>>,
+    /// Whether the account is a site administrator.
+    #[serde(default)]
+    pub admin: bool,
+    /// A restriction placed on the account by an administrator.
+    ///
+    /// Restricted accounts cannot log in, refresh their tokens or use any
+    /// tokens that were issued before the restriction.
+    pub restriction: Option<AccountRestriction>,
     /// A timestamp indicating the last time the account was updated.
     pub updated_at: DateTime<Utc>,
 
+    /// Accounts imported from an archive without their password hashes have
+    /// no password, and so can't be logged into.
     #[graphql(skip)]
+    #[serde(default = "no_pword")]
     pub(super) pword_salt: SecretString,
     #[graphql(skip)]
+    #[serde(default = "no_pword")]
     pub(super) pword_hash: SecretString,
 }
 
+fn no_pword() -> SecretString {
+    SecretString::new(String::new())
+}
+
 #[ComplexObject]
 impl Account {
     /// The account's unique ID.
@@ -71,6 +89,113 @@ impl Account {
             .push_field(srql::field("pword_hash"), &mut create);
         srql::obj_create_query(TABLE_NAME, create)
     }
+
+    /// Builds the update that revokes every token issued for the account
+    /// before `now`.
+    pub fn revoke_tokens(id: Thing, now: DateTime<Utc>) -> Option<srql::UpdateStatement> {
+        let mut update = vec![];
+        now.push_field(srql::field("revoked_at"), &mut update);
+        srql::obj_update_query(id, update)
+    }
+
+    /// Builds the update that restricts the account. This also revokes all of
+    /// the account's tokens so that they can't be used once the restriction
+    /// has been lifted.
+    pub fn restrict(
+        id: Thing,
+        state: AccountState,
+        params: RestrictAccount,
+    ) -> Result<Option<srql::UpdateStatement>> {
+        let now = Utc::now();
+        let restriction = srql::object([
+            (
+                "state".to_owned(),
+                srql::to_value(state).map_err(SrlError::from)?,
+            ),
+            (
+                "reason".to_owned(),
+                params.reason.map_or(srql::Value::None, Into::into),
+            ),
+            (
+                "expires_at".to_owned(),
+                params.expires_at.map_or(srql::Value::None, |expires_at| {
+                    srql::Value::Datetime(srql::Datetime(expires_at))
+                }),
+            ),
+            (
+                "created_at".to_owned(),
+                srql::Value::Datetime(srql::Datetime(now)),
+            ),
+        ]);
+
+        let mut update = vec![(
+            srql::field("restriction"),
+            srql::Operator::Equal,
+            restriction,
+        )];
+        now.push_field(srql::field("revoked_at"), &mut update);
+        Ok(srql::obj_update_query(id, update))
+    }
+
+    /// The restriction on the account, if it hasn't expired yet.
+    pub fn active_restriction(&self) -> Option<&AccountRestriction> {
+        self.restriction
+            .as_ref()
+            .filter(|restriction| restriction.is_active())
+    }
+}
+
+/// The ways in which an administrator can restrict an account.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountState {
+    /// The account has been temporarily suspended, usually as a punishment.
+    Suspended,
+    /// The account has been disabled.
+    Disabled,
+}
+
+/// A restriction placed on an account by an administrator.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AccountRestriction {
+    /// How the account is restricted.
+    pub state: AccountState,
+    /// Why the account was restricted.
+    pub reason: Option<String>,
+    /// When the restriction is lifted. If not present, the restriction lasts
+    /// until an administrator lifts it.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// A timestamp indicating when the account was restricted.
+    pub created_at: DateTime<Utc>,
+}
+
+impl AccountRestriction {
+    pub fn is_active(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at > Utc::now(),
+            None => true,
+        }
+    }
+
+    /// The error returned when the account tries to authenticate.
+    pub fn error(&self) -> Error {
+        match self.state {
+            AccountState::Suspended => Error::AccountSuspended,
+            AccountState::Disabled => Error::AccountDisabled,
+        }
+    }
+}
+
+/// The information needed to restrict an account.
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct RestrictAccount {
+    /// Why the account is being restricted. This is shown to the account's
+    /// owner.
+    #[graphql(validator(max_length = 4096))]
+    pub reason: Option<String>,
+    /// When the restriction should be lifted. If not given, the restriction
+    /// lasts until an administrator lifts it.
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 /// An account that has been authenticated, along with tokens to access it.
@@ -88,7 +213,7 @@ impl AuthenticatedAccount {
     async fn refresh_token(&self, ctx: &Context<'_>) -> GqlResult<String> {
         create_refresh_token(
             self.account.id.to_gql_id(),
-            ctx.data_unchecked::<EncodingKey>(),
+            &ctx.data_unchecked::<SigningKeys>().encoding(),
         )
         .extend()
     }
@@ -98,7 +223,7 @@ impl AuthenticatedAccount {
     async fn access_token(&self, ctx: &Context<'_>) -> GqlResult<String> {
         create_access_token(
             &PartialAccount::new(self.account.id.to_gql_id(), self.account.user_id.clone()),
-            ctx.data_unchecked::<EncodingKey>(),
+            &ctx.data_unchecked::<SigningKeys>().encoding(),
         )
         .extend()
     }
@@ -115,6 +240,9 @@ impl From<Account> for AuthenticatedAccount {
 pub struct CreateAccount {
     /// The account's unique user ID. This is used to create default names for
     /// resources and for logging in.
+    ///
+    /// It can only contain letters, numbers, `-` and `_`, and must be unique
+    /// ignoring case.
     #[graphql(validator(min_length = 1, max_length = 128))]
     pub user_id: String,
     /// The account's password.
@@ -129,6 +257,7 @@ pub struct CreateAccount {
 
 impl CreateObject for CreateAccount {
     fn append(self, expr: &mut srql::SetExpr) {
+        ident::normalise(&self.user_id).push_field(srql::field("normalised_user_id"), expr);
         self.user_id.push_field(srql::field("user_id"), expr);
         // Other fields are intentionally omitted.
     }
@@ -164,18 +293,30 @@ mod private {
     enum Inner {
         #[default]
         Unauthenticated,
-        Authenticated(PartialAccount, DateTime<Utc>),
+        Authenticated(PartialAccount, DateTime<Utc>, Option<DateTime<Utc>>),
     }
 
     impl CurrentAccount {
         pub fn new(acc: PartialAccount, expiry: DateTime<Utc>) -> Self {
-            Self(Inner::Authenticated(acc, expiry))
+            Self(Inner::Authenticated(acc, expiry, None))
+        }
+
+        pub fn with_issued_at(
+            acc: PartialAccount,
+            expiry: DateTime<Utc>,
+            issued_at: DateTime<Utc>,
+        ) -> Self {
+            let mut current = Self::new(acc, expiry);
+            if let Inner::Authenticated(_, _, iat) = &mut current.0 {
+                *iat = Some(issued_at);
+            }
+            current
         }
 
         pub fn account(&self) -> Result<&PartialAccount> {
             match &self.0 {
                 Inner::Unauthenticated => Err(Error::Unauthenticated),
-                Inner::Authenticated(acc, expiry) => {
+                Inner::Authenticated(acc, expiry, _) => {
                     if Utc::now() >= *expiry {
                         Err(Error::Unauthenticated)
                     } else {
@@ -192,6 +333,14 @@ mod private {
         pub fn user_id(&self) -> Result<&str> {
             self.account().map(|acc| acc.uid.as_str())
         }
+
+        /// When the token that authenticated the account was issued, if known.
+        pub fn issued_at(&self) -> Option<DateTime<Utc>> {
+            match &self.0 {
+                Inner::Unauthenticated => None,
+                Inner::Authenticated(_, _, issued_at) => *issued_at,
+            }
+        }
     }
 
     /// Account information stored in the JWT.
@@ -2,7 +2,7 @@
 mod tests;
 
 #[cfg(test)]
-use base64::{prelude::BASE64_STANDARD_NO_PAD, Engine as _};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine as _};
 use chrono::{DateTime, Utc};
 #[cfg(test)]
 use ring::rand::SecureRandom as _;
@@ -12,15 +12,15 @@ use tracing::instrument;
 
 use super::{
     create_creds, verify_creds, verify_refresh_token, Account, AuthCreds, AuthenticatedAccount,
-    CreateAccount, CurrentAccount, ACC_TABLE_NAME,
+    CreateAccount, CurrentAccount, DecodingKeys, ACC_TABLE_NAME,
 };
-use crate::{persist::Persist, prelude::*};
+use crate::{ident, persist::Persist, prelude::*};
 
 pub struct AccountPersist<'a> {
     persist: &'a Persist,
     current: &'a CurrentAccount,
     csrng: &'a SystemRandom,
-    jwt_dec_key: &'a jsonwebtoken::DecodingKey,
+    jwt_dec_keys: DecodingKeys,
 }
 
 impl<'a> AccountPersist<'a> {
@@ -28,13 +28,13 @@ impl<'a> AccountPersist<'a> {
         persist: &'a Persist,
         current: &'a CurrentAccount,
         csrng: &'a SystemRandom,
-        jwt_dec_key: &'a jsonwebtoken::DecodingKey,
+        jwt_dec_keys: DecodingKeys,
     ) -> Self {
         Self {
             persist,
             current,
             csrng,
-            jwt_dec_key,
+            jwt_dec_keys,
         }
     }
 
@@ -52,12 +52,16 @@ impl<'a> AccountPersist<'a> {
 
         verify_creds(&creds.pword, &acc.pword_salt, &acc.pword_hash)?;
 
+        if let Some(restriction) = acc.active_restriction() {
+            return Err(restriction.error());
+        }
+
         Ok(acc.into())
     }
 
     #[instrument(skip_all)]
     pub async fn refresh(&self, refresh_token: String) -> Result<AuthenticatedAccount> {
-        let Ok(claims) = verify_refresh_token(&refresh_token, self.jwt_dec_key) else {
+        let Ok(claims) = verify_refresh_token(&refresh_token, &self.jwt_dec_keys) else {
             return Err(Error::CredentialsInvalid);
         };
 
@@ -65,6 +69,10 @@ impl<'a> AccountPersist<'a> {
             return Err(Error::CredentialsInvalid);
         };
 
+        if let Some(restriction) = acc.active_restriction() {
+            return Err(restriction.error());
+        }
+
         if let Some(revoked_at) = acc.revoked_at {
             if revoked_at >= claims.issued_at()? {
                 return Err(Error::CredentialsInvalid);
@@ -89,9 +97,9 @@ impl<'a> AccountPersist<'a> {
                 what: srql::table(ACC_TABLE_NAME),
                 cond: srql::Cond(
                     srql::Expression::Binary {
-                        l: srql::field("user_id").into(),
+                        l: srql::field("normalised_user_id").into(),
                         o: srql::Operator::Equal,
-                        r: srql::string(user_id).into(),
+                        r: srql::string(ident::normalise(user_id)).into(),
                     }
                     .into(),
                 )
@@ -105,6 +113,7 @@ impl<'a> AccountPersist<'a> {
 
     #[instrument(skip_all)]
     pub async fn create(&self, acc: CreateAccount) -> Result<AuthenticatedAccount> {
+        ident::validate(&acc.user_id, &self.persist.settings().reserved_idents)?;
         let creds = create_creds(self.csrng, acc.pword.expose_secret())?;
 
         // TODO: support invites and reject if required/invalid
@@ -126,9 +135,7 @@ impl<'a> AccountPersist<'a> {
         let acc = self.current.id()?;
         let now = Utc::now();
 
-        let mut updates = vec![];
-        now.push_field(srql::field("revoked_at"), &mut updates);
-        let Some(update) = srql::obj_update_query((ACC_TABLE_NAME, &***acc).into(), updates) else {
+        let Some(update) = Account::revoke_tokens((ACC_TABLE_NAME, &***acc).into(), now) else {
             return Err("".into());
         };
 
@@ -143,10 +150,10 @@ impl AccountPersist<'_> {
     pub async fn create_test_user(&self) -> super::testing::AccData {
         let mut user_id = [0u8; 16];
         self.csrng.fill(&mut user_id).unwrap();
-        let user_id = BASE64_STANDARD_NO_PAD.encode(user_id);
+        let user_id = BASE64_URL_SAFE_NO_PAD.encode(user_id);
         let mut pword = [0u8; 16];
         self.csrng.fill(&mut pword).unwrap();
-        let pword = BASE64_STANDARD_NO_PAD.encode(pword);
+        let pword = BASE64_URL_SAFE_NO_PAD.encode(pword);
 
         let acc = CreateAccount {
             user_id: user_id.clone(),
@@ -74,6 +74,60 @@ async fn test_duplicate_user_id() {
     assert_eq!(res, Error::UnavailableIdent);
 }
 
+#[tokio::test]
+async fn test_duplicate_user_id_case() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+    let AccData { user_id, .. } = acc_persist.create_test_user().await;
+
+    let acc = CreateAccount {
+        user_id: user_id.to_uppercase(),
+        pword: "test2".to_owned().into(),
+        invite: None,
+    };
+
+    let res = acc_persist.create(acc).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+}
+
+#[tokio::test]
+async fn test_invalid_user_id() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+
+    for (user_id, err) in [
+        ("has space", Error::InvalidIdent),
+        ("Admin", Error::ReservedIdent),
+    ] {
+        let acc = CreateAccount {
+            user_id: user_id.into(),
+            pword: "password".to_owned().into(),
+            invite: None,
+        };
+
+        let res = acc_persist.create(acc).await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), err);
+    }
+}
+
+#[tokio::test]
+async fn test_login_case_insensitive() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+    let AccData { user_id, pword, .. } = acc_persist.create_test_user().await;
+
+    let res = acc_persist
+        .login(AuthCreds {
+            user_id: user_id.to_uppercase(),
+            pword,
+        })
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().account.user_id, user_id);
+}
+
 #[tokio::test]
 async fn test_login() {
     let data = TestData::new().await;
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use async_graphql::ID;
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+
+use super::{Account, AccountRestriction, CurrentAccount, ACC_TABLE_NAME};
+use crate::{persist::Persist, prelude::*};
+
+/// How long an account's status is cached for before it is read again. This
+/// is the longest it takes for a restriction or token revocation to stop
+/// access tokens that have already been issued.
+static STATUS_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+struct Status {
+    fetched_at: Instant,
+    /// Whether the account still exists. Tokens for deleted accounts are
+    /// treated as revoked.
+    exists: bool,
+    revoked_at: Option<DateTime<Utc>>,
+    restriction: Option<AccountRestriction>,
+}
+
+/// Checks authenticated requests against the status of their account, so that
+/// access tokens stop working soon after the account is restricted or its
+/// tokens are revoked.
+#[derive(Clone)]
+pub struct AccountStatusCache {
+    persist: Persist,
+    statuses: Arc<Mutex<HashMap<ID, Status>>>,
+}
+
+impl AccountStatusCache {
+    pub fn new(persist: Persist) -> Self {
+        Self {
+            persist,
+            statuses: Arc::default(),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn check(&self, current: &CurrentAccount) -> Result<()> {
+        let Ok(id) = current.id() else {
+            return Ok(());
+        };
+
+        let status = match self.get(id) {
+            Some(status) => status,
+            None => self.fetch(id).await?,
+        };
+
+        if !status.exists {
+            return Err(Error::JwtRevoked);
+        }
+
+        if let Some(restriction) = status.restriction.filter(AccountRestriction::is_active) {
+            return Err(restriction.error());
+        }
+
+        if let (Some(revoked_at), Some(issued_at)) = (status.revoked_at, current.issued_at()) {
+            if revoked_at >= issued_at {
+                return Err(Error::JwtRevoked);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, id: &ID) -> Option<Status> {
+        let statuses = self.statuses.lock().unwrap_or_else(PoisonError::into_inner);
+        statuses
+            .get(id)
+            .filter(|status| status.fetched_at.elapsed() < STATUS_TTL)
+            .cloned()
+    }
+
+    async fn fetch(&self, id: &ID) -> Result<Status> {
+        let acc: Option<Account> = self.persist.db().select((ACC_TABLE_NAME, &**id)).await?;
+        let status = Status {
+            fetched_at: Instant::now(),
+            exists: acc.is_some(),
+            revoked_at: acc.as_ref().and_then(|acc| acc.revoked_at),
+            restriction: acc.and_then(|acc| acc.restriction),
+        };
+
+        let mut statuses = self.statuses.lock().unwrap_or_else(PoisonError::into_inner);
+        // Drop stale entries so that the cache only holds recently active
+        // accounts.
+        statuses.retain(|_, status| status.fetched_at.elapsed() < STATUS_TTL);
+        statuses.insert(id.clone(), status.clone());
+
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::account::{testing::*, AccountState, PartialAccount, RestrictAccount};
+
+    fn current_issued_at(acc: &AccData, issued_at: DateTime<Utc>) -> CurrentAccount {
+        CurrentAccount::with_issued_at(
+            PartialAccount::new(acc.id.to_gql_id(), acc.user_id.clone()),
+            Utc::now() + Duration::minutes(15),
+            issued_at,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_check_unauthenticated() {
+        let data = TestData::new().await;
+        let cache = AccountStatusCache::new(data.persist.clone());
+
+        let res = cache.check(&CurrentAccount::default()).await;
+        println!("{res:?}");
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_active() {
+        let (data, acc) = TestData::with_user().await;
+        let cache = AccountStatusCache::new(data.persist.clone());
+
+        let res = cache.check(&current_issued_at(&acc, Utc::now())).await;
+        println!("{res:?}");
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_revoked() {
+        let (data, acc) = TestData::with_user().await;
+        let cache = AccountStatusCache::new(data.persist.clone());
+        let current = current_issued_at(&acc, Utc::now() - Duration::minutes(1));
+
+        data.account().revoke_tokens().await.unwrap();
+
+        let res = cache.check(&current).await;
+        println!("{res:?}");
+        assert_eq!(res, Err(Error::JwtRevoked));
+    }
+
+    #[tokio::test]
+    async fn test_check_restricted() {
+        let data = TestData::new().await;
+        let cache = AccountStatusCache::new(data.persist.clone());
+
+        let acc = data.account().create_test_user().await;
+        let current = current_issued_at(&acc, Utc::now() + Duration::minutes(1));
+
+        data.persist
+            .db()
+            .query(
+                Account::restrict(
+                    acc.id.clone(),
+                    AccountState::Suspended,
+                    RestrictAccount::default(),
+                )
+                .unwrap()
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let res = cache.check(&current).await;
+        println!("{res:?}");
+        assert_eq!(res, Err(Error::AccountSuspended));
+    }
+
+    #[tokio::test]
+    async fn test_check_deleted() {
+        let (data, acc) = TestData::with_user().await;
+        let cache = AccountStatusCache::new(data.persist.clone());
+        let current = current_issued_at(&acc, Utc::now());
+
+        let _: Option<Account> = data
+            .persist
+            .db()
+            .delete((ACC_TABLE_NAME, acc.id.id.to_raw()))
+            .await
+            .unwrap();
+
+        let res = cache.check(&current).await;
+        println!("{res:?}");
+        assert_eq!(res, Err(Error::JwtRevoked));
+        let res = cache.check(&current).await;
+        assert_eq!(res, Err(Error::JwtRevoked));
+    }
+
+    #[tokio::test]
+    async fn test_check_cached() {
+        let (data, acc) = TestData::with_user().await;
+        let cache = AccountStatusCache::new(data.persist.clone());
+        let current = current_issued_at(&acc, Utc::now() - Duration::minutes(1));
+
+        assert!(cache.check(&current).await.is_ok());
+        data.account().revoke_tokens().await.unwrap();
+
+        // The status is only read again once the cached one is stale.
+        let res = cache.check(&current).await;
+        println!("{res:?}");
+        assert!(res.is_ok());
+    }
+}
@@ -0,0 +1,7 @@
+mod models;
+mod persist;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
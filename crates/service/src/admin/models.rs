@@ -0,0 +1,19 @@
+use async_graphql::SimpleObject;
+use serde::Deserialize;
+
+/// Counts of the records stored on the server.
+#[derive(SimpleObject, Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct ServerStats {
+    /// The number of registered accounts.
+    pub accounts: i64,
+    /// The number of accounts that are currently suspended.
+    pub suspended_accounts: i64,
+    /// The number of accounts that are currently disabled.
+    pub disabled_accounts: i64,
+    /// The number of boards.
+    pub boards: i64,
+    /// The number of posts.
+    pub posts: i64,
+    /// The number of reports waiting for a moderator.
+    pub open_reports: i64,
+}
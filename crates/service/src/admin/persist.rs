@@ -0,0 +1,336 @@
+#[cfg(test)]
+mod tests;
+
+use async_graphql::{
+    connection::{Connection, Edge},
+    ID,
+};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+
+use super::ServerStats;
+use crate::{
+    account::{
+        Account, AccountCursor, AccountState, CurrentAccount, RestrictAccount, ACC_TABLE_NAME,
+    },
+    board::{Board, BOARD_TABLE_NAME},
+    ident,
+    moderation::{ReportStatus, REPORT_TABLE_NAME},
+    persist::Persist,
+    post::POST_TABLE_NAME,
+    prelude::*,
+    query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+};
+
+pub struct AdminPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> AdminPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Checks that the current account is a site administrator.
+    ///
+    /// The flag is read from the database rather than the token, so that
+    /// removing it takes effect immediately.
+    #[instrument(skip_all)]
+    pub async fn check(&self) -> Result<()> {
+        let id = self.current.id()?;
+        let acc: Option<Account> = self.persist.db().select((ACC_TABLE_NAME, &**id)).await?;
+        match acc {
+            Some(acc) if acc.admin && acc.active_restriction().is_none() => Ok(()),
+            _ => Err(Error::Unauthorized),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn accounts(&self) -> Result<AccountListRequest<'_>> {
+        self.check().await?;
+        Ok(AccountListRequest::new(self.persist))
+    }
+
+    /// Suspends or disables an account, which also revokes all of its tokens.
+    #[instrument(skip_all)]
+    pub async fn restrict_account(
+        &self,
+        id: &str,
+        state: AccountState,
+        params: RestrictAccount,
+    ) -> Result<Option<Account>> {
+        self.check().await?;
+
+        let Some(update) = Account::restrict((ACC_TABLE_NAME, id).into(), state, params)? else {
+            return Err("".into());
+        };
+
+        Ok(self.persist.db().query(update).await?.take(0)?)
+    }
+
+    /// Lifts any restriction on an account.
+    #[instrument(skip_all)]
+    pub async fn enable_account(&self, id: &str) -> Result<Option<Account>> {
+        self.check().await?;
+
+        let update = vec![(
+            srql::field("restriction"),
+            srql::Operator::Equal,
+            srql::Value::None,
+        )];
+        self.update_account(id, update).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn set_admin(&self, id: &str, admin: bool) -> Result<Option<Account>> {
+        self.check().await?;
+
+        let mut update = vec![];
+        admin.push_field(srql::field("admin"), &mut update);
+        self.update_account(id, update).await
+    }
+
+    /// Revokes all tokens issued for any account.
+    #[instrument(skip_all)]
+    pub async fn revoke_tokens(&self, id: &str) -> Result<Option<DateTime<Utc>>> {
+        self.check().await?;
+
+        let now = Utc::now();
+        let Some(update) = Account::revoke_tokens((ACC_TABLE_NAME, id).into(), now) else {
+            return Err("".into());
+        };
+
+        let acc: Option<Account> = self.persist.db().query(update).await?.take(0)?;
+        Ok(acc.map(|_| now))
+    }
+
+    /// Makes another account the owner of a board. The previous owner loses
+    /// all control over the board.
+    #[instrument(skip_all)]
+    pub async fn transfer_board(&self, id: &str, owner_id: &ID) -> Result<Option<Board>> {
+        self.check().await?;
+
+        let owner_id = owner_id.to_account_thing();
+        let owner: Option<Account> = self.persist.db().select(owner_id.clone()).await?;
+        if owner.is_none() {
+            return Err(Error::MissingIdent);
+        }
+
+        let mut update = vec![];
+        owner_id
+            .clone()
+            .push_field(srql::field("owner_id"), &mut update);
+        // The owner is always a moderator, so doesn't need to be listed.
+        update.push((
+            srql::field("moderator_ids"),
+            srql::Operator::Dec,
+            owner_id.into(),
+        ));
+
+        let Some(update) = srql::obj_update_query((BOARD_TABLE_NAME, id).into(), update) else {
+            return Err("".into());
+        };
+
+        Ok(self.persist.db().query(update).await?.take(0)?)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn stats(&self) -> Result<ServerStats> {
+        self.check().await?;
+
+        let suspended = active_restriction(AccountState::Suspended)?;
+        let disabled = active_restriction(AccountState::Disabled)?;
+        let open = srql::Cond(srql::expr(
+            srql::field("status"),
+            srql::Operator::Equal,
+            srql::to_value(ReportStatus::Open).map_err(SrlError::from)?,
+        ));
+
+        let mut res = self
+            .persist
+            .db()
+            .query(srql::count(ACC_TABLE_NAME, None))
+            .query(srql::count(ACC_TABLE_NAME, suspended.into()))
+            .query(srql::count(ACC_TABLE_NAME, disabled.into()))
+            .query(srql::count(BOARD_TABLE_NAME, None))
+            .query(srql::count(POST_TABLE_NAME, None))
+            .query(srql::count(REPORT_TABLE_NAME, open.into()))
+            .await?;
+
+        let mut count = |index| -> Result<i64> {
+            let count: Option<i64> = res.take((index, "count"))?;
+            Ok(count.unwrap_or_default())
+        };
+
+        Ok(ServerStats {
+            accounts: count(0)?,
+            suspended_accounts: count(1)?,
+            disabled_accounts: count(2)?,
+            boards: count(3)?,
+            posts: count(4)?,
+            open_reports: count(5)?,
+        })
+    }
+
+    async fn update_account(&self, id: &str, update: srql::SetExpr) -> Result<Option<Account>> {
+        let Some(update) = srql::obj_update_query((ACC_TABLE_NAME, id).into(), update) else {
+            return Err("".into());
+        };
+
+        Ok(self.persist.db().query(update).await?.take(0)?)
+    }
+}
+
+/// Matches accounts with an unexpired restriction in the given state.
+fn active_restriction(state: AccountState) -> Result<srql::Cond> {
+    let field = |name: &str| srql::nested_field(["restriction", name]);
+    Ok(srql::Cond(srql::expr(
+        srql::expr(
+            field("state"),
+            srql::Operator::Equal,
+            srql::to_value(state).map_err(SrlError::from)?,
+        ),
+        srql::Operator::And,
+        srql::expr(
+            srql::expr(
+                field("expires_at"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ),
+            srql::Operator::Or,
+            srql::expr(
+                field("expires_at"),
+                srql::Operator::MoreThan,
+                srql::time_now(),
+            ),
+        ),
+    )))
+}
+
+/// Grants (or removes) the site administrator flag on the accounts with the
+/// given user IDs, returning the accounts that were updated.
+///
+/// This does not require an administrator, and so must only be used by
+/// trusted callers, e.g. when bootstrapping from the server's config.
+#[instrument(skip(persist))]
+pub async fn grant_admins(
+    persist: &Persist,
+    user_ids: &[String],
+    admin: bool,
+) -> surrealdb::Result<Vec<Account>> {
+    if user_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let user_ids = user_ids
+        .iter()
+        .map(|user_id| srql::string(ident::normalise(user_id)).into())
+        .collect::<Vec<_>>();
+
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::table(ACC_TABLE_NAME),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("admin"),
+                srql::Operator::Equal,
+                srql::Value::Bool(admin),
+            )])
+            .into(),
+            cond: srql::Cond(srql::expr(
+                srql::field("normalised_user_id"),
+                srql::Operator::Inside,
+                srql::array(user_ids),
+            ))
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)
+}
+
+pub struct AccountListRequest<'a> {
+    persist: &'a Persist,
+    pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+}
+
+impl<'a> AccountListRequest<'a> {
+    fn new(persist: &'a Persist) -> Self {
+        Self {
+            persist,
+            pagination: None,
+        }
+    }
+
+    pub fn with_pagination(
+        mut self,
+        args: impl Into<PaginationInput<OpaqueCursor<String>>>,
+    ) -> Self {
+        self.pagination = Some(args.into());
+        self
+    }
+
+    #[instrument(skip_all)]
+    pub async fn execute(self) -> Result<Connection<AccountCursor, Account>> {
+        let PaginationOptions {
+            cond,
+            order,
+            limit,
+            result_slice_opts,
+        } = (self.pagination, ACC_TABLE_NAME).into();
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(ACC_TABLE_NAME),
+            order: srql::Orders(order.into_iter().collect()).into(),
+            cond,
+            limit,
+            ..Default::default()
+        };
+
+        let accounts: Vec<Account> = self.persist.db().query(query).await?.take(0)?;
+        let ResultSlice {
+            results: accounts,
+            has_previous_page,
+            has_next_page,
+        } = ResultSlice::new(accounts, result_slice_opts);
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        connection.edges = accounts
+            .into_iter()
+            .map(|account| Edge::new(OpaqueCursor(account.id.to_gql_id().0), account))
+            .collect();
+
+        Ok(connection)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use async_trait::async_trait;
+
+    use crate::account::testing::{AccData, TestData};
+
+    use super::{grant_admins, AdminPersist};
+
+    #[async_trait]
+    pub trait AdminTestData {
+        fn admin(&self) -> AdminPersist<'_>;
+
+        async fn make_admin(&self, acc: &AccData);
+    }
+
+    #[async_trait]
+    impl AdminTestData for TestData {
+        fn admin(&self) -> AdminPersist<'_> {
+            AdminPersist::new(&self.persist, &self.current)
+        }
+
+        async fn make_admin(&self, acc: &AccData) {
+            grant_admins(&self.persist, std::slice::from_ref(&acc.user_id), true)
+                .await
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::AdminTestData as _, *};
+use crate::{
+    account::{testing::*, AuthCreds, AuthenticatedAccount},
+    board::testing::BoardTestData as _,
+    post::testing::PostTestData as _,
+};
+
+async fn setup() -> (TestData, AccData) {
+    let (data, acc) = TestData::with_user().await;
+    data.make_admin(&acc).await;
+    (data, acc)
+}
+
+#[tokio::test]
+async fn test_check() {
+    let (data, _) = setup().await;
+
+    let res = data.admin().check().await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_check_not_admin() {
+    let (data, _) = TestData::with_user().await;
+
+    let res = data.admin().check().await;
+    println!("{res:?}");
+    assert_eq!(res, Err(Error::Unauthorized));
+}
+
+#[tokio::test]
+async fn test_check_unauthenticated() {
+    let data = TestData::new().await;
+
+    let res = data.admin().check().await;
+    println!("{res:?}");
+    assert_eq!(res, Err(Error::Unauthenticated));
+}
+
+#[tokio::test]
+async fn test_grant_missing() {
+    let data = TestData::new().await;
+
+    let res = grant_admins(&data.persist, &["missing".into()], true).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_accounts() {
+    let (data, _) = setup().await;
+    data.account().create_test_user().await;
+
+    let res = data
+        .admin()
+        .accounts()
+        .await
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await;
+    assert!(res.is_ok());
+    assert_eq!(res.unwrap().edges.len(), 2);
+}
+
+async fn login(data: &TestData, acc: &AccData) -> Result<AuthenticatedAccount> {
+    data.account()
+        .login(AuthCreds {
+            user_id: acc.user_id.clone(),
+            pword: acc.pword.clone(),
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_disable_account() {
+    let (data, _) = setup().await;
+    let acc = data.account().create_test_user().await;
+
+    let res = data
+        .admin()
+        .restrict_account(
+            &acc.id.to_gql_id(),
+            AccountState::Disabled,
+            RestrictAccount {
+                reason: Some("Spam".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    println!("{res:?}");
+    let res = res.unwrap().unwrap();
+    let restriction = res.restriction.unwrap();
+    assert_eq!(restriction.state, AccountState::Disabled);
+    assert_eq!(restriction.reason, Some("Spam".into()));
+    assert!(res.revoked_at.is_some());
+
+    let res = login(&data, &acc).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::AccountDisabled);
+
+    let res = data.admin().enable_account(&acc.id.to_gql_id()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().unwrap().restriction.is_none());
+
+    let res = login(&data, &acc).await;
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_suspend_account() {
+    let (data, _) = setup().await;
+    let acc = data.account().create_test_user().await;
+
+    let res = data
+        .admin()
+        .restrict_account(
+            &acc.id.to_gql_id(),
+            AccountState::Suspended,
+            RestrictAccount {
+                expires_at: Some(Utc::now() + chrono::Duration::days(1)),
+                ..Default::default()
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert!(res.unwrap().unwrap().restriction.is_some());
+
+    let res = login(&data, &acc).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::AccountSuspended);
+}
+
+#[tokio::test]
+async fn test_suspend_account_expired() {
+    let (data, _) = setup().await;
+    let acc = data.account().create_test_user().await;
+
+    data.admin()
+        .restrict_account(
+            &acc.id.to_gql_id(),
+            AccountState::Suspended,
+            RestrictAccount {
+                expires_at: Some(Utc::now() - chrono::Duration::minutes(1)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    let res = login(&data, &acc).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_set_admin() {
+    let (data, _) = setup().await;
+    let acc = data.account().create_test_user().await;
+
+    let res = data.admin().set_admin(&acc.id.to_gql_id(), true).await;
+    println!("{res:?}");
+    assert!(res.unwrap().unwrap().admin);
+
+    let current = acc.current();
+    let res = AdminPersist::new(&data.persist, &current).check().await;
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_revoke_tokens() {
+    let (data, _) = setup().await;
+    let acc = data.account().create_test_user().await;
+
+    let res = data.admin().revoke_tokens(&acc.id.to_gql_id()).await;
+    println!("{res:?}");
+    let revoked_at = res.unwrap().unwrap();
+
+    let res = data.account().get(&acc.id.to_gql_id()).await;
+    assert_eq!(res.unwrap().unwrap().revoked_at, Some(revoked_at));
+
+    let res = data.admin().revoke_tokens("missing").await;
+    assert_eq!(res, Ok(None));
+}
+
+#[tokio::test]
+async fn test_transfer_board() {
+    let (data, _) = setup().await;
+    let board = data.generate_board().await;
+    let acc = data.account().create_test_user().await;
+
+    let res = data
+        .admin()
+        .transfer_board(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await;
+    println!("{res:?}");
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.owner_id, Some(acc.id.clone()));
+
+    // The previous owner can no longer manage the board.
+    let res = data
+        .board()
+        .add_moderator(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    let res = data
+        .admin()
+        .transfer_board(&board.id.to_gql_id(), &"missing".into())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::MissingIdent);
+}
+
+#[tokio::test]
+async fn test_stats() {
+    let (data, _) = setup().await;
+    data.generate_board().await;
+    data.generate_posts(3).await;
+    let acc = data.account().create_test_user().await;
+    data.admin()
+        .restrict_account(
+            &acc.id.to_gql_id(),
+            AccountState::Disabled,
+            RestrictAccount::default(),
+        )
+        .await
+        .unwrap();
+
+    let res = data.admin().stats().await;
+    println!("{res:?}");
+    assert_eq!(
+        res.unwrap(),
+        ServerStats {
+            accounts: 2,
+            suspended_accounts: 0,
+            disabled_accounts: 1,
+            boards: 1,
+            posts: 3,
+            open_reports: 0,
+        }
+    );
+}
@@ -0,0 +1,148 @@
+use async_graphql::{connection::Connection, Context, Object, ID};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+
+use super::ServerStats;
+use crate::{
+    account::{Account, AccountCursor, AccountState, RestrictAccount},
+    board::Board,
+    prelude::*,
+    query::{connection_complexity, PaginationArgs},
+};
+
+#[derive(Default)]
+pub struct AdminQuery;
+
+#[Object]
+impl AdminQuery {
+    /// Queries that are only available to site administrators.
+    #[instrument(skip_all)]
+    async fn admin(&self, ctx: &Context<'_>) -> GqlResult<AdminQueries> {
+        ctx.admin_persist().check().await.extend()?;
+        Ok(AdminQueries)
+    }
+}
+
+pub struct AdminQueries;
+
+#[Object]
+impl AdminQueries {
+    /// Lists all accounts.
+    #[graphql(complexity = "connection_complexity(child_complexity, first, last)")]
+    #[instrument(skip_all)]
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> GqlResult<Connection<AccountCursor, Account>> {
+        let pagination = PaginationArgs {
+            after,
+            before,
+            first,
+            last,
+        }
+        .validate()
+        .extend()?;
+
+        ctx.admin_persist()
+            .accounts()
+            .await
+            .extend()?
+            .with_pagination(pagination)
+            .execute()
+            .await
+            .extend()
+    }
+
+    /// Gets counts of the records stored on the server.
+    #[instrument(skip_all)]
+    async fn stats(&self, ctx: &Context<'_>) -> GqlResult<ServerStats> {
+        ctx.admin_persist().stats().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct AdminMutation;
+
+#[Object]
+impl AdminMutation {
+    /// Mutations that are only available to site administrators.
+    #[instrument(skip_all)]
+    async fn admin(&self, ctx: &Context<'_>) -> GqlResult<AdminMutations> {
+        ctx.admin_persist().check().await.extend()?;
+        Ok(AdminMutations)
+    }
+}
+
+pub struct AdminMutations;
+
+#[Object]
+impl AdminMutations {
+    /// Temporarily suspends an account and revokes all of its tokens.
+    #[instrument(skip_all)]
+    async fn suspend_account(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        #[graphql(default)] restrict: RestrictAccount,
+    ) -> GqlResult<Option<Account>> {
+        ctx.admin_persist()
+            .restrict_account(&id, AccountState::Suspended, restrict)
+            .await
+            .extend()
+    }
+
+    /// Disables an account and revokes all of its tokens.
+    #[instrument(skip_all)]
+    async fn disable_account(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        #[graphql(default)] restrict: RestrictAccount,
+    ) -> GqlResult<Option<Account>> {
+        ctx.admin_persist()
+            .restrict_account(&id, AccountState::Disabled, restrict)
+            .await
+            .extend()
+    }
+
+    /// Lifts a suspension or re-enables a disabled account.
+    #[instrument(skip_all)]
+    async fn enable_account(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Account>> {
+        ctx.admin_persist().enable_account(&id).await.extend()
+    }
+
+    /// Grants or removes the site administrator flag on an account.
+    #[instrument(skip_all)]
+    async fn set_admin(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        admin: bool,
+    ) -> GqlResult<Option<Account>> {
+        ctx.admin_persist().set_admin(&id, admin).await.extend()
+    }
+
+    /// Revokes all tokens issued for an account.
+    #[instrument(skip_all)]
+    async fn revoke_tokens(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<DateTime<Utc>>> {
+        ctx.admin_persist().revoke_tokens(&id).await.extend()
+    }
+
+    /// Transfers the ownership of a board to another account.
+    #[instrument(skip_all)]
+    async fn transfer_board(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        owner_id: ID,
+    ) -> GqlResult<Option<Board>> {
+        ctx.admin_persist()
+            .transfer_board(&id, &owner_id)
+            .await
+            .extend()
+    }
+}
@@ -0,0 +1,590 @@
+use std::io::{BufRead, Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+use thiserror::Error;
+use tracing::{debug, info, instrument};
+
+use crate::{
+    account::ACC_TABLE_NAME,
+    board::{BOARD_TABLE_NAME, HANDLE_TABLE_NAME},
+    job::JOB_TABLE_NAME,
+    migration::{MigrationError, Migrations, SUBSYSTEMS, UPDATE_TABLE},
+    moderation::{BAN_TABLE_NAME, LOG_TABLE_NAME, REPORT_TABLE_NAME},
+    persist::Persist,
+    post::{CONTAINS_TABLE_NAME, POST_TABLE_NAME},
+    prelude::*,
+    query::SRQL_ORDER_ASC,
+};
+
+/// Identifies a file as a Plazer archive.
+pub static ARCHIVE_FORMAT: &str = "plazer-archive";
+/// The version of the archive format. This changes whenever archives written
+/// by one version can't be read by another.
+pub const ARCHIVE_VERSION: u32 = 1;
+/// How many records are read from the database at a time while exporting.
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+/// The tables that are archived, in the order that they are written and
+/// imported. The migration state comes first so that the database can be
+/// brought up to the same step before anything else is imported, and
+/// relations come after the records that they link.
+static TABLES: &[(&str, TableKind)] = &[
+    (UPDATE_TABLE, TableKind::Record),
+    (ACC_TABLE_NAME, TableKind::Record),
+    (BOARD_TABLE_NAME, TableKind::Record),
+    (HANDLE_TABLE_NAME, TableKind::Record),
+    (POST_TABLE_NAME, TableKind::Record),
+    (CONTAINS_TABLE_NAME, TableKind::Relation),
+    (REPORT_TABLE_NAME, TableKind::Record),
+    (LOG_TABLE_NAME, TableKind::Record),
+    (BAN_TABLE_NAME, TableKind::Record),
+    (JOB_TABLE_NAME, TableKind::Record),
+];
+
+/// The fields on accounts that hold their password hash, which are left out
+/// of archives unless asked for, and always left out of personal data exports.
+pub(crate) static PWORD_FIELDS: &[&str] = &["pword_salt", "pword_hash"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Record,
+    /// A graph edge, which has to be created with `RELATE` so that the records
+    /// it links know about it.
+    Relation,
+}
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("Failed to read or write archive: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse archive: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Db(#[from] surrealdb::Error),
+    #[error(transparent)]
+    Migration(#[from] MigrationError),
+    #[error("Invalid archive: {0}")]
+    Invalid(String),
+    #[error("The database already has data in it, archives can only be imported into a fresh one")]
+    NotEmpty,
+}
+
+/// The first line of an archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveHeader {
+    format: String,
+    version: u32,
+    exported_at: DateTime<Utc>,
+    /// Whether accounts include their password hashes. If they don't, they
+    /// can't be logged into until they are given a new password.
+    password_hashes: bool,
+}
+
+/// Every line after the header, each holding a single record.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveRecord {
+    table: String,
+    record: Json,
+}
+
+/// Writes everything in the database to `out` as a JSON Lines archive,
+/// returning how many records were written.
+///
+/// Password hashes are only written if `password_hashes` is set.
+#[instrument(skip(persist, out))]
+pub async fn export(
+    persist: &Persist,
+    mut out: impl Write,
+    password_hashes: bool,
+) -> std::result::Result<usize, ArchiveError> {
+    let header = ArchiveHeader {
+        format: ARCHIVE_FORMAT.to_owned(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        password_hashes,
+    };
+    serde_json::to_writer(&mut out, &header)?;
+    out.write_all(b"\n")?;
+
+    let mut count = 0;
+    for &(table, _) in TABLES {
+        let mut last = None;
+        loop {
+            let batch = export_batch(persist, table, last.take()).await?;
+            let Some(srql::Value::Object(record)) = batch.last() else {
+                break;
+            };
+            if let Some(srql::Value::Thing(id)) = record.get("id") {
+                last = Some(id.clone());
+            }
+
+            for record in batch {
+                let srql::Value::Object(mut record) = record else {
+                    continue;
+                };
+                if table == ACC_TABLE_NAME && !password_hashes {
+                    for field in PWORD_FIELDS {
+                        record.remove(*field);
+                    }
+                }
+
+                let record = ArchiveRecord {
+                    table: table.to_owned(),
+                    record: to_json(srql::Value::Object(record))?,
+                };
+                serde_json::to_writer(&mut out, &record)?;
+                out.write_all(b"\n")?;
+                count += 1;
+            }
+
+            if last.is_none() {
+                break;
+            }
+        }
+        debug!(table, "Exported table");
+    }
+    out.flush()?;
+
+    info!(count, "Export complete");
+    Ok(count)
+}
+
+/// Reads the records in `table` that come after `after`, in ID order.
+async fn export_batch(
+    persist: &Persist,
+    table: &str,
+    after: Option<srql::Thing>,
+) -> std::result::Result<Vec<srql::Value>, ArchiveError> {
+    let query = srql::SelectStatement {
+        expr: srql::Fields::all(),
+        what: srql::table(table),
+        cond: after.map(|after| {
+            srql::Cond(srql::expr(
+                srql::field("id"),
+                srql::Operator::MoreThan,
+                srql::Value::Thing(after),
+            ))
+        }),
+        order: srql::Orders(vec![srql::Order {
+            order: srql::field("id"),
+            direction: SRQL_ORDER_ASC,
+            ..Default::default()
+        }])
+        .into(),
+        limit: Some(srql::Limit(srql::Number::Int(EXPORT_BATCH_SIZE).into())),
+        ..Default::default()
+    };
+
+    match persist.db().query(query).await?.take(0)? {
+        srql::Value::Array(records) => Ok(records.0),
+        _ => Ok(vec![]),
+    }
+}
+
+/// Reads a JSON Lines archive from `input` into the database, returning how
+/// many records were imported. Records keep the IDs they had when they were
+/// exported.
+///
+/// The database must be fresh. Before anything else is imported, each
+/// subsystem is migrated to the step it was at when the archive was written,
+/// and once everything has been imported the remaining steps are applied.
+#[instrument(skip_all)]
+pub async fn import(
+    persist: &Persist,
+    input: impl BufRead,
+) -> std::result::Result<usize, ArchiveError> {
+    let updates: srql::Value = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(UPDATE_TABLE),
+            limit: Some(srql::Limit(srql::Number::Int(1).into())),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    if matches!(updates, srql::Value::Array(updates) if !updates.is_empty()) {
+        return Err(ArchiveError::NotEmpty);
+    }
+
+    let mut lines = input.lines();
+    let Some(header) = lines.next() else {
+        return Err(ArchiveError::Invalid("archive is empty".into()));
+    };
+    let header: ArchiveHeader = serde_json::from_str(&header?)?;
+    if header.format != ARCHIVE_FORMAT {
+        return Err(ArchiveError::Invalid(format!(
+            "unknown format {:?}",
+            header.format
+        )));
+    }
+    if header.version != ARCHIVE_VERSION {
+        return Err(ArchiveError::Invalid(format!(
+            "version {} is not supported, expected version {ARCHIVE_VERSION}",
+            header.version
+        )));
+    }
+    debug!(exported_at = %header.exported_at, "Importing archive");
+
+    let mut count = 0;
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let ArchiveRecord { table, record } = serde_json::from_str(&line)?;
+        let Some(&(table, kind)) = TABLES.iter().find(|(t, _)| *t == table) else {
+            return Err(ArchiveError::Invalid(format!("unknown table {table:?}")));
+        };
+        let srql::Value::Object(mut record) = from_json(record)? else {
+            return Err(ArchiveError::Invalid(format!(
+                "record in {table} is not an object"
+            )));
+        };
+        let Some(srql::Value::Thing(id)) = record.remove("id") else {
+            return Err(ArchiveError::Invalid(format!(
+                "record in {table} has no ID"
+            )));
+        };
+        if id.tb != table {
+            return Err(ArchiveError::Invalid(format!(
+                "record {id} is in the wrong table"
+            )));
+        }
+
+        if table == UPDATE_TABLE {
+            prepare_subsystem(persist, &id, &record).await?;
+        }
+
+        let statement = match kind {
+            TableKind::Record => srql::Statement::Update(srql::UpdateStatement {
+                what: srql::thing(id),
+                data: srql::Data::ContentExpression(srql::Value::Object(record)).into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            }),
+            TableKind::Relation => {
+                let (Some(from), Some(with)) = (record.remove("in"), record.remove("out")) else {
+                    return Err(ArchiveError::Invalid(format!(
+                        "relation {id} is missing its ends"
+                    )));
+                };
+                srql::Statement::Relate(srql::RelateStatement {
+                    kind: srql::Value::Thing(id),
+                    from,
+                    with,
+                    data: srql::Data::ContentExpression(srql::Value::Object(record)).into(),
+                    output: srql::Output::None.into(),
+                    ..Default::default()
+                })
+            }
+        };
+        persist.db().query(statement).await?.check()?;
+        count += 1;
+    }
+
+    // Anything written since the archive's migration state was needs to be
+    // migrated, along with any subsystems the archive didn't know about.
+    Migrations::run(persist).await?;
+
+    info!(count, "Import complete");
+    Ok(count)
+}
+
+/// Migrates a subsystem up to the step recorded in the archive, so that the
+/// records being imported fit the schema that they were exported from.
+async fn prepare_subsystem(
+    persist: &Persist,
+    id: &srql::Thing,
+    record: &srql::Object,
+) -> std::result::Result<(), ArchiveError> {
+    let subsystem = id.id.to_raw();
+    let Some(&subsystem) = SUBSYSTEMS.iter().find(|s| **s == subsystem) else {
+        return Err(ArchiveError::Invalid(format!(
+            "unknown subsystem {subsystem:?}"
+        )));
+    };
+
+    if let Some(srql::Value::Strand(step)) = record.get("current") {
+        Migrations::new(persist, Some(subsystem))
+            .apply_to(Some(step.as_str()))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Converts a value to JSON. Values that JSON can't tell apart from strings
+/// are wrapped in an object with a single key naming their type, such as
+/// `{"$thing": "account:abc"}`.
+fn to_json(value: srql::Value) -> std::result::Result<Json, ArchiveError> {
+    let json = match value {
+        srql::Value::None | srql::Value::Null => Json::Null,
+        srql::Value::Bool(value) => Json::Bool(value),
+        srql::Value::Number(srql::Number::Int(value)) => value.into(),
+        srql::Value::Number(srql::Number::Float(value)) => value.into(),
+        srql::Value::Number(srql::Number::Decimal(value)) => tagged("$decimal", value.to_string()),
+        srql::Value::Strand(value) => Json::String(value.0),
+        srql::Value::Datetime(value) => tagged(
+            "$datetime",
+            value.0.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        ),
+        srql::Value::Duration(value) => tagged("$duration", value.to_raw()),
+        srql::Value::Thing(value) => tagged("$thing", value.to_string()),
+        srql::Value::Array(values) => Json::Array(
+            values
+                .0
+                .into_iter()
+                .map(to_json)
+                .collect::<std::result::Result<_, _>>()?,
+        ),
+        srql::Value::Object(values) => Json::Object(
+            values
+                .0
+                .into_iter()
+                .filter(|(_, value)| !value.is_none())
+                .map(|(key, value)| Ok((key, to_json(value)?)))
+                .collect::<std::result::Result<_, ArchiveError>>()?,
+        ),
+        value => {
+            return Err(ArchiveError::Invalid(format!(
+                "value {value} can't be archived"
+            )))
+        }
+    };
+
+    Ok(json)
+}
+
+fn tagged(tag: &str, value: String) -> Json {
+    Json::Object(Map::from_iter([(tag.to_owned(), Json::String(value))]))
+}
+
+/// Converts JSON written by [`to_json`] back to a value.
+fn from_json(json: Json) -> std::result::Result<srql::Value, ArchiveError> {
+    let invalid =
+        |kind: &str, value: &str| ArchiveError::Invalid(format!("{value:?} is not a valid {kind}"));
+
+    let value = match json {
+        Json::Null => srql::Value::Null,
+        Json::Bool(value) => srql::Value::Bool(value),
+        Json::Number(value) => match value.as_i64() {
+            Some(value) => srql::Value::Number(value.into()),
+            None => srql::Value::Number(value.as_f64().unwrap_or_default().into()),
+        },
+        Json::String(value) => srql::Value::Strand(value.into()),
+        Json::Array(values) => srql::array(
+            values
+                .into_iter()
+                .map(from_json)
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+        ),
+        Json::Object(values) => {
+            if let Some((tag, value)) = type_tag(&values) {
+                return match tag {
+                    "$datetime" => DateTime::parse_from_rfc3339(value)
+                        .map(|value| srql::Value::Datetime(srql::Datetime(value.into())))
+                        .map_err(|_| invalid("datetime", value)),
+                    "$duration" => srql::Duration::try_from(value)
+                        .map(srql::Value::Duration)
+                        .map_err(|()| invalid("duration", value)),
+                    "$thing" => surrealdb::sql::thing(value)
+                        .map(srql::Value::Thing)
+                        .map_err(|_| invalid("record ID", value)),
+                    "$decimal" => value
+                        .parse()
+                        .map(|value| srql::Value::Number(srql::Number::Decimal(value)))
+                        .map_err(|_| invalid("decimal", value)),
+                    _ => Err(ArchiveError::Invalid(format!("unknown type {tag:?}"))),
+                };
+            }
+
+            srql::object(
+                values
+                    .into_iter()
+                    .map(|(key, value)| Ok((key, from_json(value)?)))
+                    .collect::<std::result::Result<std::collections::BTreeMap<_, _>, ArchiveError>>(
+                    )?,
+            )
+        }
+    };
+
+    Ok(value)
+}
+
+/// Gets the type tag and the value it wraps, if the object is one written by
+/// [`to_json`].
+fn type_tag(values: &Map<String, Json>) -> Option<(&str, &str)> {
+    let mut entries = values.iter();
+    match (entries.next(), entries.next()) {
+        (Some((tag, Json::String(value))), None) if tag.starts_with('$') => {
+            Some((tag.as_str(), value.as_str()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::{testing::*, AuthCreds},
+        board::testing::BoardTestData as _,
+        moderation::testing::ModerationTestData as _,
+        persist,
+        post::testing::PostTestData as _,
+    };
+
+    async fn export_data(data: &TestData, password_hashes: bool) -> Vec<u8> {
+        let mut out = vec![];
+        export(&data.persist, &mut out, password_hashes)
+            .await
+            .unwrap();
+        out
+    }
+
+    async fn fresh() -> TestData {
+        let mut data = TestData::new().await;
+        data.persist = persist::testing::unmigrated().await;
+        data
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let value = srql::object([
+            (
+                "thing".to_owned(),
+                srql::Thing::from(("account", "abc")).into(),
+            ),
+            (
+                "datetime".to_owned(),
+                srql::Value::Datetime(srql::Datetime(Utc::now())),
+            ),
+            (
+                "duration".to_owned(),
+                srql::Value::Duration(std::time::Duration::from_secs(90).into()),
+            ),
+            ("string".to_owned(), "2023-10-01T12:00:00Z".into()),
+            ("number".to_owned(), 12.into()),
+            ("array".to_owned(), srql::array([true.into(), 1.5.into()])),
+        ]);
+
+        let json = to_json(value.clone()).unwrap();
+        assert_eq!(json["thing"]["$thing"], "account:abc");
+        assert_eq!(from_json(json).unwrap(), value);
+    }
+
+    #[tokio::test]
+    async fn test_export_import() {
+        let (data, acc) = TestData::with_user().await;
+        let board = data.generate_board().await;
+        let post = data.generate_post_in(&board.id).await;
+        // This also writes to the moderation log, which can't be changed.
+        let post = data
+            .moderation()
+            .pin_post(&post.id.to_gql_id(), true)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let archive = export_data(&data, true).await;
+        let mut lines = archive.split(|b| *b == b'\n');
+        let header: ArchiveHeader = serde_json::from_slice(lines.next().unwrap()).unwrap();
+        assert_eq!(header.version, ARCHIVE_VERSION);
+        assert!(header.password_hashes);
+
+        let target = fresh().await;
+        let count = import(&target.persist, archive.as_slice()).await.unwrap();
+        assert_eq!(count, archive.as_slice().lines().count() - 1);
+
+        // Everything keeps its ID, and relations still link their records.
+        let res = target
+            .account()
+            .login(AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: acc.pword.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.account.id, acc.id);
+        let res = target.board().get(&board.id.to_gql_id()).await.unwrap();
+        assert_eq!(res.unwrap().handle, board.handle);
+        let res = target.post().get(&post.id.to_gql_id()).await.unwrap();
+        assert_eq!(res.unwrap().pinned_at, post.pinned_at);
+        let res: srql::Value = target
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(CONTAINS_TABLE_NAME),
+                cond: srql::Cond(srql::expr(
+                    srql::field("in"),
+                    srql::Operator::Equal,
+                    srql::Value::Thing(board.id.clone()),
+                ))
+                .into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert!(matches!(res, srql::Value::Array(res) if res.len() == 1));
+
+        // Exporting the import gives back the same records.
+        let res = export_data(&target, true).await;
+        assert_eq!(
+            res.split(|b| *b == b'\n').skip(1).collect::<Vec<_>>(),
+            archive.split(|b| *b == b'\n').skip(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_without_passwords() {
+        let (data, acc) = TestData::with_user().await;
+
+        let archive = export_data(&data, false).await;
+        let text = String::from_utf8(archive.clone()).unwrap();
+        assert!(PWORD_FIELDS.iter().all(|field| !text.contains(field)));
+
+        let target = fresh().await;
+        import(&target.persist, archive.as_slice()).await.unwrap();
+
+        // The account is still there, but can't be logged into.
+        let res = target.account().get(&acc.id.to_gql_id()).await.unwrap();
+        assert_eq!(res.unwrap().user_id, acc.user_id);
+        let res = target
+            .account()
+            .login(AuthCreds {
+                user_id: acc.user_id,
+                pword: acc.pword,
+            })
+            .await;
+        assert!(matches!(res, Err(Error::CredentialsInvalid)));
+    }
+
+    #[tokio::test]
+    async fn test_import_not_empty() {
+        let data = TestData::new().await;
+        let archive = export_data(&data, false).await;
+
+        let res = import(&data.persist, archive.as_slice()).await;
+        assert!(matches!(res, Err(ArchiveError::NotEmpty)));
+    }
+
+    #[tokio::test]
+    async fn test_import_invalid() {
+        let target = fresh().await;
+        for archive in [
+            "",
+            "{}",
+            r#"{"format":"other","version":1,"exported_at":"2023-10-01T12:00:00Z","password_hashes":false}"#,
+            r#"{"format":"plazer-archive","version":99,"exported_at":"2023-10-01T12:00:00Z","password_hashes":false}"#,
+        ] {
+            assert!(import(&target.persist, archive.as_bytes()).await.is_err());
+        }
+    }
+}
@@ -7,6 +7,8 @@ use crate::{migration::Migration, prelude::*};
 pub enum BoardMigration {
     #[default]
     Init,
+    Moderation,
+    NormalisedHandle,
 }
 
 impl Migration for BoardMigration {
@@ -14,7 +16,9 @@ impl Migration for BoardMigration {
 
     fn next(self) -> Option<Self> {
         match self {
-            Self::Init => None,
+            Self::Init => Some(Self::Moderation),
+            Self::Moderation => Some(Self::NormalisedHandle),
+            Self::NormalisedHandle => None,
         }
     }
 
@@ -22,8 +26,20 @@ impl Migration for BoardMigration {
         use BoardMigration as S;
         match self {
             S::Init => Self::build_init(statements),
+            S::Moderation => Self::build_moderation(statements),
+            S::NormalisedHandle => Self::build_normalised_handle(statements),
         }
     }
+
+    fn build_down(&self, statements: &mut Vec<srql::Statement>) -> bool {
+        use BoardMigration as S;
+        match self {
+            S::Init => Self::build_init_down(statements),
+            S::Moderation => Self::build_moderation_down(statements),
+            S::NormalisedHandle => Self::build_normalised_handle_down(statements),
+        }
+        true
+    }
 }
 
 impl BoardMigration {
@@ -34,4 +50,80 @@ impl BoardMigration {
             [srql::field("handle")],
         ));
     }
+
+    fn build_init_down(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::remove_index("board_handle_index", BOARD_TABLE_NAME));
+    }
+
+    fn build_moderation(statements: &mut Vec<srql::Statement>) {
+        // Boards created before ownership existed are owned by their creator.
+        statements.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::table(BOARD_TABLE_NAME),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("owner_id"),
+                srql::Operator::Equal,
+                srql::field("creator_id").into(),
+            )])
+            .into(),
+            cond: srql::Cond(srql::expr(
+                srql::field("owner_id"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ))
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        statements.push(srql::define_index(
+            "board_owner_id_index",
+            BOARD_TABLE_NAME,
+            [srql::field("owner_id")],
+        ));
+    }
+
+    fn build_moderation_down(statements: &mut Vec<srql::Statement>) {
+        // Owners are kept, as boards created since can't be told apart from
+        // the ones that were given their creator as owner.
+        statements.push(srql::remove_index("board_owner_id_index", BOARD_TABLE_NAME));
+    }
+
+    fn build_normalised_handle(statements: &mut Vec<srql::Statement>) {
+        // Handles are unique regardless of case. If existing boards only differ
+        // by case, this will fail and they must be renamed manually.
+        statements.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::table(BOARD_TABLE_NAME),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("normalised_handle"),
+                srql::Operator::Equal,
+                srql::lowercase(srql::field("handle")),
+            )])
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        statements.push(srql::remove_index("board_handle_index", BOARD_TABLE_NAME));
+        statements.push(srql::define_uniq_index(
+            "board_normalised_handle_index",
+            BOARD_TABLE_NAME,
+            [srql::field("normalised_handle")],
+        ));
+    }
+
+    fn build_normalised_handle_down(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::remove_index(
+            "board_normalised_handle_index",
+            BOARD_TABLE_NAME,
+        ));
+        statements.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::table(BOARD_TABLE_NAME),
+            data: srql::Data::UnsetExpression(vec![srql::field("normalised_handle")]).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        statements.push(srql::define_uniq_index(
+            "board_handle_index",
+            BOARD_TABLE_NAME,
+            [srql::field("handle")],
+        ));
+    }
 }
@@ -9,3 +9,4 @@ pub use persist::*;
 pub use schema::*;
 
 pub static BOARD_TABLE_NAME: &str = "board";
+pub static HANDLE_TABLE_NAME: &str = "board_handle";
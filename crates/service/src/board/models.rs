@@ -1,10 +1,10 @@
-use async_graphql::{ComplexObject, InputObject, MaybeUndefined, SimpleObject, ID};
-use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use async_graphql::{ComplexObject, Enum, InputObject, MaybeUndefined, SimpleObject, ID};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 
-use super::BOARD_TABLE_NAME;
-use crate::{id_obj_impls, prelude::*, query::OpaqueCursor};
+use super::{BOARD_TABLE_NAME, HANDLE_TABLE_NAME};
+use crate::{id_obj_impls, ident, prelude::*, query::OpaqueCursor};
 
 pub type BoardCursor = OpaqueCursor<String>;
 
@@ -16,15 +16,33 @@ pub struct Board {
     pub id: Thing,
     #[graphql(skip)]
     pub creator_id: Option<Thing>,
+    #[graphql(skip)]
+    pub owner_id: Option<Thing>,
+    #[graphql(skip)]
+    #[serde(default)]
+    pub moderator_ids: Vec<Thing>,
+    #[graphql(skip)]
+    #[serde(default)]
+    pub member_ids: Vec<Thing>,
 
     /// The board's unique handle. This is used to refer to the board in URLs
     /// and by users. It must be unique, but can be changed (if the server allows it).
+    ///
+    /// Previous handles still lead to the board, so if this differs from the
+    /// handle that was used to get the board, links should be updated to use it.
     pub handle: String,
     /// The board's display name. If not present, the handle is (usually) used instead.
     pub name: Option<String>,
     /// The board's description.
     pub description: Option<String>,
+    /// Who can find and read the board.
+    #[serde(default)]
+    pub visibility: BoardVisibility,
 
+    /// A timestamp indicating when the board was hidden by a moderator.
+    ///
+    /// Hidden boards are only visible to their moderators.
+    pub hidden_at: Option<DateTime<Utc>>,
     /// A timestamp indicating the last time the board was updated.
     pub updated_at: DateTime<Utc>,
 }
@@ -44,6 +62,24 @@ impl Board {
     async fn creator_id(&self) -> Option<ID> {
         self.creator_id.as_ref().map(ToGqlId::to_gql_id)
     }
+
+    /// The ID of the account that owns this board. The owner can manage the
+    /// board's moderators, and is a moderator themselves.
+    async fn owner_id(&self) -> Option<ID> {
+        self.owner_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The IDs of the accounts that can moderate this board, not including
+    /// the owner.
+    async fn moderator_ids(&self) -> Vec<ID> {
+        self.moderator_ids.iter().map(ToGqlId::to_gql_id).collect()
+    }
+
+    /// The IDs of the accounts that are members of this board, not including
+    /// the moderators. Only members can read private boards.
+    async fn member_ids(&self) -> Vec<ID> {
+        self.member_ids.iter().map(ToGqlId::to_gql_id).collect()
+    }
 }
 
 id_obj_impls!(Board);
@@ -51,10 +87,101 @@ id_obj_impls!(Board);
 impl Board {
     pub fn create(creator_id: Option<Thing>, params: CreateBoard) -> srql::CreateStatement {
         let mut create = vec![];
-        creator_id.push_field(srql::field("creator_id"), &mut create);
+        creator_id
+            .clone()
+            .push_field(srql::field("creator_id"), &mut create);
+        creator_id.push_field(srql::field("owner_id"), &mut create);
         params.append(&mut create);
         srql::obj_create_query(BOARD_TABLE_NAME, create)
     }
+
+    /// Whether the given account owns this board.
+    pub fn is_owner(&self, account: &Thing) -> bool {
+        self.owner_id.as_ref() == Some(account)
+    }
+
+    /// Whether the given account is allowed to moderate this board.
+    pub fn is_moderator(&self, account: &Thing) -> bool {
+        self.is_owner(account) || self.moderator_ids.contains(account)
+    }
+
+    /// Whether the given account is a member of this board. Moderators are
+    /// always members.
+    pub fn is_member(&self, account: &Thing) -> bool {
+        self.is_moderator(account) || self.member_ids.contains(account)
+    }
+}
+
+/// Who can find and read a board.
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoardVisibility {
+    /// Anyone can find and read the board.
+    #[default]
+    Public,
+    /// Anyone with a link can read the board, but it isn't listed.
+    Unlisted,
+    /// Only the board's members can find and read the board.
+    Private,
+}
+
+impl QueryValue for BoardVisibility {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|v| (field, srql::Operator::Equal, v))
+    }
+}
+
+/// A handle that a board has stopped using, either because it was renamed or
+/// deleted. Released handles still lead to the board that used them, and are
+/// reserved for it for a while so that nobody else can take over its links.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReleasedHandle {
+    pub board_id: Thing,
+    pub released_at: DateTime<Utc>,
+}
+
+impl ReleasedHandle {
+    /// Released handles are keyed on the normalised handle, so each only ever
+    /// leads to the last board that used it.
+    pub fn thing(handle: &str) -> Thing {
+        Thing {
+            tb: HANDLE_TABLE_NAME.into(),
+            id: ident::normalise(handle).into(),
+        }
+    }
+
+    pub fn release(handle: &str, board_id: Thing) -> srql::UpdateStatement {
+        let mut data = vec![];
+        board_id.push_field(srql::field("board_id"), &mut data);
+        data.push((
+            srql::field("released_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ));
+
+        srql::UpdateStatement {
+            what: srql::thing(Self::thing(handle)),
+            data: srql::Data::SetExpression(data).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Removes the record of a released handle once a board has taken it.
+    pub fn claim(handle: &str) -> srql::DeleteStatement {
+        srql::DeleteStatement {
+            what: srql::thing(Self::thing(handle)),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the handle can only be taken by the board that released it.
+    pub fn is_reserved(&self, cooldown_days: u32) -> bool {
+        self.released_at + Duration::days(cooldown_days.into()) > Utc::now()
+    }
 }
 
 #[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
@@ -62,6 +189,9 @@ pub struct CreateBoard {
     /// The board's unique handle. This is used to refer to the board in URLs
     /// and by users. It must be unique, but can be changed (if the server allows it).
     ///
+    /// It can only contain letters, numbers, `-` and `_`, and must be unique
+    /// ignoring case.
+    ///
     /// This will default to the user's ID if not present.
     #[graphql(validator(max_length = 128))]
     pub handle: Option<String>,
@@ -71,20 +201,30 @@ pub struct CreateBoard {
     /// The board's description.
     #[graphql(validator(max_length = 32_768))]
     pub description: Option<String>,
+    /// Who can find and read the board. Defaults to public.
+    pub visibility: Option<BoardVisibility>,
 }
 
 impl CreateObject for CreateBoard {
     fn append(self, expr: &mut srql::SetExpr) {
+        self.handle
+            .as_deref()
+            .map(ident::normalise)
+            .push_field(srql::field("normalised_handle"), expr);
         self.handle.push_field(srql::field("handle"), expr);
         self.name.push_field(srql::field("name"), expr);
         self.description
             .push_field(srql::field("description"), expr);
+        self.visibility.push_field(srql::field("visibility"), expr);
     }
 }
 
 #[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
 pub struct UpdateBoard {
     /// The new handle. If not given, the handle is not changed.
+    ///
+    /// The old handle is reserved for the board for a while after it is
+    /// changed, and will keep leading to the board until another takes it.
     #[graphql(validator(max_length = 128))]
     pub handle: Option<String>,
     /// The new name. If not given, the name is not changed. If null is given,
@@ -95,15 +235,23 @@ pub struct UpdateBoard {
     /// null is given, the description is cleared.
     #[graphql(validator(max_length = 32_768))]
     pub description: MaybeUndefined<String>,
+    /// The new visibility. If not given, the visibility is not changed.
+    pub visibility: Option<BoardVisibility>,
 }
 
 impl IntoUpdateQuery for UpdateBoard {
     fn into_update(self, thing: srql::Thing) -> Option<srql::UpdateStatement> {
         let mut update = vec![];
+        self.handle
+            .as_deref()
+            .map(ident::normalise)
+            .push_field(srql::field("normalised_handle"), &mut update);
         self.handle.push_field(srql::field("handle"), &mut update);
         self.name.push_field(srql::field("name"), &mut update);
         self.description
             .push_field(srql::field("description"), &mut update);
+        self.visibility
+            .push_field(srql::field("visibility"), &mut update);
         srql::obj_update_query(thing, update)
     }
 }
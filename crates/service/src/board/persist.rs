@@ -1,12 +1,18 @@
 #[cfg(test)]
 mod tests;
 
-use async_graphql::connection::{Connection, Edge};
+use async_graphql::{
+    connection::{Connection, Edge},
+    ID,
+};
 use tracing::instrument;
 
-use super::{Board, BoardCursor, CreateBoard, UpdateBoard, BOARD_TABLE_NAME};
+use super::{
+    Board, BoardCursor, BoardVisibility, CreateBoard, ReleasedHandle, UpdateBoard, BOARD_TABLE_NAME,
+};
 use crate::{
     account::CurrentAccount,
+    ident,
     persist::Persist,
     prelude::*,
     query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
@@ -27,9 +33,21 @@ impl<'a> BoardPersist<'a> {
         Ok(self.persist.db().select((BOARD_TABLE_NAME, id)).await?)
     }
 
+    /// Gets a board by its handle, or by a handle it used previously.
     #[instrument(skip_all)]
     pub async fn get_by_handle(&self, handle: &str) -> Result<Option<Board>> {
-        let board = self
+        let board = match self.get_by_current_handle(handle).await? {
+            Some(board) => Some(board),
+            None => match self.get_released_handle(handle).await? {
+                Some(released) => self.persist.db().select(released.board_id).await?,
+                None => None,
+            },
+        };
+        Ok(board.filter(|board| self.can_view(board)))
+    }
+
+    async fn get_by_current_handle(&self, handle: &str) -> Result<Option<Board>> {
+        Ok(self
             .persist
             .db()
             .query(srql::SelectStatement {
@@ -37,9 +55,9 @@ impl<'a> BoardPersist<'a> {
                 what: srql::table(BOARD_TABLE_NAME),
                 cond: srql::Cond(
                     srql::Expression::Binary {
-                        l: srql::field("handle").into(),
+                        l: srql::field("normalised_handle").into(),
                         o: srql::Operator::Equal,
-                        r: srql::string(handle).into(),
+                        r: srql::string(ident::normalise(handle)).into(),
                     }
                     .into(),
                 )
@@ -47,13 +65,34 @@ impl<'a> BoardPersist<'a> {
                 ..Default::default()
             })
             .await?
-            .take(0)?;
-        Ok(board)
+            .take(0)?)
+    }
+
+    async fn get_released_handle(&self, handle: &str) -> Result<Option<ReleasedHandle>> {
+        Ok(self
+            .persist
+            .db()
+            .select(ReleasedHandle::thing(handle))
+            .await?)
+    }
+
+    /// Checks that a handle is not reserved for a board other than the given
+    /// one.
+    async fn check_handle(&self, handle: &str, board_id: Option<&srql::Thing>) -> Result<()> {
+        let cooldown_days = self.persist.settings().board_handle_cooldown_days;
+        match self.get_released_handle(handle).await? {
+            Some(released)
+                if Some(&released.board_id) != board_id && released.is_reserved(cooldown_days) =>
+            {
+                Err(Error::UnavailableIdent)
+            }
+            _ => Ok(()),
+        }
     }
 
     #[instrument(skip_all)]
     pub fn list(&self) -> BoardListRequest<'_> {
-        BoardListRequest::new(self.persist)
+        BoardListRequest::new(self.persist, self.current)
     }
 
     #[instrument(skip_all)]
@@ -70,15 +109,21 @@ impl<'a> BoardPersist<'a> {
                 .into();
         }
 
-        let board = self
-            .persist
-            .db()
-            .query(Board::create(
-                self.current.id().map(ToAccountThing::to_account_thing).ok(),
-                board,
-            ))
-            .await?
-            .take(0)?;
+        let handle = board.handle.clone().unwrap_or_default();
+        ident::validate(&handle, &self.persist.settings().reserved_idents)?;
+        self.check_handle(&handle, None).await?;
+
+        let create = Board::create(
+            self.current.id().map(ToAccountThing::to_account_thing).ok(),
+            board,
+        );
+        let statements = vec![
+            srql::trans_begin(),
+            srql::Statement::Create(create),
+            srql::Statement::Delete(ReleasedHandle::claim(&handle)),
+            srql::trans_end(),
+        ];
+        let board = self.persist.db().query(statements).await?.take(0)?;
 
         match board {
             Some(board) => Ok(board),
@@ -88,37 +133,268 @@ impl<'a> BoardPersist<'a> {
 
     #[instrument(skip_all)]
     pub async fn update(&self, id: &str, update: UpdateBoard) -> Result<Option<Board>> {
-        // TODO: check config to see if anon users can update boards
-        // TODO: check perms to see if authd user can update boards
+        let Some(board) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        // Moderators can change how the board is presented, but only its
+        // owner can change who can read it.
+        let changes_visibility = update
+            .visibility
+            .is_some_and(|visibility| visibility != board.visibility);
+        if !self.can_moderate(&board) || (changes_visibility && !self.can_own(&board)) {
+            return Err(Error::Unauthorized);
+        }
+
+        if let Some(handle) = &update.handle {
+            if ident::normalise(handle) != ident::normalise(&board.handle) {
+                return self.rename(board, handle.clone(), update).await;
+            }
+        }
 
-        let board = if let Some(update) = update.into_update((BOARD_TABLE_NAME, id).into()) {
+        let board = if let Some(update) = update.into_update(board.id.clone()) {
             self.persist.db().query(update).await?.take(0)?
         } else {
-            self.get(id).await?
+            Some(board)
         };
 
         Ok(board)
     }
 
+    /// Updates a board while changing its handle. The old handle is released,
+    /// so it keeps leading to the board and is reserved for it.
+    async fn rename(
+        &self,
+        board: Board,
+        handle: String,
+        update: UpdateBoard,
+    ) -> Result<Option<Board>> {
+        if !self.persist.settings().board_renames {
+            return Err(Error::BoardRenameDisabled);
+        }
+
+        ident::validate(&handle, &self.persist.settings().reserved_idents)?;
+        self.check_handle(&handle, Some(&board.id)).await?;
+
+        let Some(update) = update.into_update(board.id.clone()) else {
+            return Err("".into());
+        };
+
+        let statements = vec![
+            srql::trans_begin(),
+            srql::Statement::Update(update),
+            srql::Statement::Update(ReleasedHandle::release(&board.handle, board.id.clone())),
+            srql::Statement::Delete(ReleasedHandle::claim(&handle)),
+            srql::trans_end(),
+        ];
+        let board = self.persist.db().query(statements).await?.take(0)?;
+
+        Ok(board)
+    }
+
     #[instrument(skip_all)]
     pub async fn delete(&self, id: &str) -> Result<Option<Board>> {
-        // TODO: check config to see if anon users can delete boards
-        // TODO: check perms to see if authd user can delete boards
+        let Some(board) = self.get(id).await? else {
+            return Ok(None);
+        };
+        if !self.can_own(&board) {
+            return Err(Error::Unauthorized);
+        }
+
+        let statements = vec![
+            srql::trans_begin(),
+            srql::Statement::Delete(srql::DeleteStatement {
+                what: srql::thing(board.id.clone()),
+                output: srql::Output::Before.into(),
+                ..Default::default()
+            }),
+            srql::Statement::Update(ReleasedHandle::release(&board.handle, board.id.clone())),
+            srql::trans_end(),
+        ];
+        let board = self.persist.db().query(statements).await?.take(0)?;
 
-        let board = self.persist.db().delete((BOARD_TABLE_NAME, id)).await?;
         Ok(board)
     }
+
+    #[instrument(skip_all)]
+    pub async fn add_moderator(&self, id: &str, account_id: &ID) -> Result<Option<Board>> {
+        self.update_moderators(id, account_id, srql::Operator::Inc)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn remove_moderator(&self, id: &str, account_id: &ID) -> Result<Option<Board>> {
+        self.update_moderators(id, account_id, srql::Operator::Dec)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn add_member(&self, id: &str, account_id: &ID) -> Result<Option<Board>> {
+        self.update_members(id, account_id, srql::Operator::Inc)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn remove_member(&self, id: &str, account_id: &ID) -> Result<Option<Board>> {
+        self.update_members(id, account_id, srql::Operator::Dec)
+            .await
+    }
+
+    async fn update_moderators(
+        &self,
+        id: &str,
+        account_id: &ID,
+        op: srql::Operator,
+    ) -> Result<Option<Board>> {
+        let Some(board) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        let current = self.current.id()?.to_account_thing();
+        if board.owner_id.as_ref() != Some(&current) {
+            return Err(Error::Unauthorized);
+        }
+
+        let account = account_id.to_account_thing();
+        let listed = board.moderator_ids.contains(&account);
+        self.update_account_list(board, "moderator_ids", account, listed, op)
+            .await
+    }
+
+    async fn update_members(
+        &self,
+        id: &str,
+        account_id: &ID,
+        op: srql::Operator,
+    ) -> Result<Option<Board>> {
+        let Some(board) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        if !self.can_moderate(&board) {
+            return Err(Error::Unauthorized);
+        }
+
+        let account = account_id.to_account_thing();
+        let listed = board.member_ids.contains(&account);
+        self.update_account_list(board, "member_ids", account, listed, op)
+            .await
+    }
+
+    /// Adds an account to (or removes one from) one of the board's lists of
+    /// accounts, if it isn't already.
+    async fn update_account_list(
+        &self,
+        board: Board,
+        field: &str,
+        account: srql::Thing,
+        listed: bool,
+        op: srql::Operator,
+    ) -> Result<Option<Board>> {
+        let unchanged = match op {
+            srql::Operator::Inc => listed,
+            _ => !listed,
+        };
+        if unchanged {
+            return Ok(Some(board));
+        }
+
+        let board = if let Some(update) = srql::obj_update_query(
+            board.id.clone(),
+            vec![(srql::field(field), op, account.into())],
+        ) {
+            self.persist.db().query(update).await?.take(0)?
+        } else {
+            Some(board)
+        };
+
+        Ok(board)
+    }
+
+    /// Whether the current account owns the given board.
+    pub fn can_own(&self, board: &Board) -> bool {
+        self.current
+            .id()
+            .is_ok_and(|id| board.is_owner(&id.to_account_thing()))
+    }
+
+    /// Whether the current account is allowed to moderate the given board.
+    pub fn can_moderate(&self, board: &Board) -> bool {
+        self.current
+            .id()
+            .is_ok_and(|id| board.is_moderator(&id.to_account_thing()))
+    }
+
+    /// Whether the current account is allowed to read the given board and
+    /// its posts.
+    pub fn can_read(&self, board: &Board) -> bool {
+        board.visibility != BoardVisibility::Private
+            || self
+                .current
+                .id()
+                .is_ok_and(|id| board.is_member(&id.to_account_thing()))
+    }
+
+    fn can_view(&self, board: &Board) -> bool {
+        (board.hidden_at.is_none() || self.can_moderate(board)) && self.can_read(board)
+    }
+}
+
+/// Matches the boards that the current account can read, where `path` leads
+/// from the record being matched to the board. If `listed` is set, unlisted
+/// boards are only matched for their members.
+pub fn readable_cond(path: &[&str], current: &CurrentAccount, listed: bool) -> Result<srql::Value> {
+    let field = |name: &str| srql::nested_field(path.iter().copied().chain([name]));
+
+    let mut visibilities = vec![srql::to_value(BoardVisibility::Public).map_err(SrlError::from)?];
+    if !listed {
+        visibilities.push(srql::to_value(BoardVisibility::Unlisted).map_err(SrlError::from)?);
+    }
+
+    // Boards created before visibility existed are public.
+    let mut cond = srql::expr(
+        srql::expr(
+            field("visibility"),
+            srql::Operator::Equal,
+            srql::Value::None,
+        ),
+        srql::Operator::Or,
+        srql::expr(
+            field("visibility"),
+            srql::Operator::Inside,
+            srql::array(visibilities),
+        ),
+    );
+
+    if let Ok(id) = current.id() {
+        let account = srql::Value::Thing(id.to_account_thing());
+        for (list, op) in [
+            ("owner_id", srql::Operator::Equal),
+            ("moderator_ids", srql::Operator::Contain),
+            ("member_ids", srql::Operator::Contain),
+        ] {
+            cond = srql::expr(
+                cond,
+                srql::Operator::Or,
+                srql::expr(field(list), op, account.clone()),
+            );
+        }
+    }
+
+    Ok(cond)
 }
 
 pub struct BoardListRequest<'a> {
     persist: &'a Persist,
+    current: &'a CurrentAccount,
     pagination: Option<PaginationInput<OpaqueCursor<String>>>,
 }
 
 impl<'a> BoardListRequest<'a> {
-    fn new(persist: &'a Persist) -> Self {
+    fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
         Self {
             persist,
+            current,
             pagination: None,
         }
     }
@@ -140,11 +416,21 @@ impl<'a> BoardListRequest<'a> {
             result_slice_opts,
         } = (self.pagination, BOARD_TABLE_NAME).into();
 
+        let cond = srql::cond_and(
+            cond,
+            srql::expr(
+                srql::field("hidden_at"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ),
+        );
+        let cond = srql::cond_and(cond.into(), readable_cond(&[], self.current, true)?);
+
         let query = srql::SelectStatement {
             expr: srql::Fields::all(),
             what: srql::table(BOARD_TABLE_NAME),
             order: srql::Orders(order.into_iter().collect()).into(),
-            cond,
+            cond: cond.into(),
             limit,
             ..Default::default()
         };
@@ -188,6 +474,7 @@ pub mod testing {
                     handle: Some("test".into()),
                     name: Some("Test".into()),
                     description: Some("Test".into()),
+                    visibility: None,
                 })
                 .await
                 .unwrap()
@@ -201,6 +488,7 @@ pub mod testing {
                     handle: Some(format!("test-{i}")),
                     name: Some(format!("Test {i}")),
                     description: Some(format!("Test {i}")),
+                    visibility: None,
                 };
 
                 let res = board_persist.create(board).await;
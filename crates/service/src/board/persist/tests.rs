@@ -3,7 +3,11 @@ use pretty_assertions::assert_eq;
 use std::collections::VecDeque;
 
 use super::{testing::BoardTestData as _, *};
-use crate::{account::testing::*, query::testing::Paginator};
+use crate::{
+    account::{testing::*, CurrentAccount},
+    config::ServiceSettings,
+    query::{testing::Paginator, PaginationInput},
+};
 
 #[tokio::test]
 async fn test_create() {
@@ -14,6 +18,7 @@ async fn test_create() {
         handle: Some("test".into()),
         name: Some("Test".into()),
         description: Some("Test".into()),
+        visibility: None,
     };
 
     let res = board_persist.create(board).await;
@@ -81,6 +86,7 @@ async fn test_duplicate_handle() {
         handle: Some(board.handle),
         name: Some("Test".into()),
         description: Some("Test".into()),
+        visibility: None,
     };
 
     let res = board_persist.create(create).await;
@@ -92,6 +98,70 @@ async fn test_duplicate_handle() {
     assert_eq!(res, Error::UnavailableIdent);
 }
 
+#[tokio::test]
+async fn test_duplicate_handle_case() {
+    let data = TestData::new().await;
+    let board = data.generate_board().await;
+
+    let res = data
+        .board()
+        .create(create_with_handle(&board.handle.to_uppercase()))
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+}
+
+#[tokio::test]
+async fn test_get_handle_case_insensitive() {
+    let data = TestData::new().await;
+    let board = data.generate_board().await;
+
+    let res = data
+        .board()
+        .get_by_handle(&board.handle.to_uppercase())
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().id, board.id);
+}
+
+#[tokio::test]
+async fn test_invalid_handle() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+
+    for (handle, err) in [
+        ("has space", Error::InvalidIdent),
+        ("slash/", Error::InvalidIdent),
+        ("API", Error::ReservedIdent),
+    ] {
+        let res = data.board().create(create_with_handle(handle)).await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), err);
+
+        let res = rename(&data, &board, handle).await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), err);
+    }
+}
+
+#[tokio::test]
+async fn test_reserved_handle_configured() {
+    let mut data = TestData::new().await;
+    data.persist = data.persist.with_settings(ServiceSettings {
+        reserved_idents: vec!["Taken".into()],
+        ..Default::default()
+    });
+
+    let res = data.board().create(create_with_handle("taken")).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::ReservedIdent);
+
+    // Only the configured words are reserved.
+    let res = data.board().create(create_with_handle("admin")).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+}
+
 #[tokio::test]
 async fn test_anon_create_default_fail() {
     let data = TestData::new().await;
@@ -101,6 +171,7 @@ async fn test_anon_create_default_fail() {
         handle: None,
         name: Some("Test".into()),
         description: Some("Test".into()),
+        visibility: None,
     };
 
     let res = board_persist.create(board).await;
@@ -121,6 +192,7 @@ async fn test_authed_create_default() {
         handle: None,
         name: Some("Test".into()),
         description: Some("Test".into()),
+        visibility: None,
     };
 
     let res = board_persist.create(board).await;
@@ -197,7 +269,7 @@ async fn test_backward_pagination() {
 
 #[tokio::test]
 async fn test_empty_update() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -223,7 +295,7 @@ async fn test_empty_update() {
 
 #[tokio::test]
 async fn test_update_handle() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -252,7 +324,7 @@ async fn test_update_handle() {
 
 #[tokio::test]
 async fn test_update_name() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -281,7 +353,7 @@ async fn test_update_name() {
 
 #[tokio::test]
 async fn test_update_name_null() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -310,7 +382,7 @@ async fn test_update_name_null() {
 
 #[tokio::test]
 async fn test_update_description() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -339,7 +411,7 @@ async fn test_update_description() {
 
 #[tokio::test]
 async fn test_update_description_null() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -368,7 +440,7 @@ async fn test_update_description_null() {
 
 #[tokio::test]
 async fn test_update_all() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -376,6 +448,7 @@ async fn test_update_all() {
         handle: Some("test".into()),
         name: MaybeUndefined::Value("Test".into()),
         description: MaybeUndefined::Value("Test".into()),
+        visibility: None,
     };
 
     let res = board_persist.update(&board.id.id.to_raw(), update).await;
@@ -405,6 +478,7 @@ async fn test_update_nonexistent() {
         handle: Some("test".into()),
         name: MaybeUndefined::Value("Test".into()),
         description: MaybeUndefined::Value("Test".into()),
+        visibility: None,
     };
 
     let res = board_persist.update("test", update).await;
@@ -425,7 +499,7 @@ async fn test_update_nonexistent() {
 
 #[tokio::test]
 async fn test_delete() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let board_persist = data.board();
     let board = data.generate_board().await;
 
@@ -472,3 +546,342 @@ async fn test_delete_nonexistent() {
     let res = res.unwrap();
     assert!(res.is_none());
 }
+
+async fn rename(data: &TestData, board: &Board, handle: &str) -> Result<Option<Board>> {
+    data.board()
+        .update(
+            &board.id.id.to_raw(),
+            UpdateBoard {
+                handle: Some(handle.into()),
+                ..Default::default()
+            },
+        )
+        .await
+}
+
+fn create_with_handle(handle: &str) -> CreateBoard {
+    CreateBoard {
+        handle: Some(handle.into()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_get_previous_handle() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+
+    let res = rename(&data, &board, "renamed").await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().handle, "renamed");
+
+    let res = data.board().get_by_handle(&board.handle).await;
+    println!("{res:?}");
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.id, board.id);
+    assert_eq!(res.handle, "renamed");
+
+    let res = rename(&data, &board, "again").await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = data.board().get_by_handle(&board.handle).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().handle, "again");
+}
+
+#[tokio::test]
+async fn test_released_handle_reserved() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let other = data
+        .board()
+        .create(create_with_handle("other"))
+        .await
+        .unwrap();
+
+    rename(&data, &board, "renamed").await.unwrap();
+
+    let res = data.board().create(create_with_handle(&board.handle)).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+
+    let res = rename(&data, &other, &board.handle).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+
+    // The board that released the handle can take it back.
+    let res = rename(&data, &board, &board.handle).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().handle, board.handle);
+}
+
+#[tokio::test]
+async fn test_released_handle_cooldown_over() {
+    let (mut data, _) = TestData::with_user().await;
+    data.persist = data.persist.with_settings(ServiceSettings {
+        board_handle_cooldown_days: 0,
+        ..Default::default()
+    });
+    let board = data.generate_board().await;
+
+    rename(&data, &board, "renamed").await.unwrap();
+
+    let res = data.board().create(create_with_handle(&board.handle)).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    // The handle now belongs to the new board.
+    let res = data.board().get_by_handle(&board.handle).await;
+    println!("{res:?}");
+    assert_ne!(res.unwrap().unwrap().id, board.id);
+}
+
+#[tokio::test]
+async fn test_deleted_handle_reserved() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+
+    data.board().delete(&board.id.id.to_raw()).await.unwrap();
+
+    let res = data.board().get_by_handle(&board.handle).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+
+    let res = data.board().create(create_with_handle(&board.handle)).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+}
+
+#[tokio::test]
+async fn test_rename_disabled() {
+    let (mut data, _) = TestData::with_user().await;
+    data.persist = data.persist.with_settings(ServiceSettings {
+        board_renames: false,
+        ..Default::default()
+    });
+    let board = data.generate_board().await;
+
+    let res = rename(&data, &board, "renamed").await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::BoardRenameDisabled);
+
+    // Other fields can still be changed, even if the handle is given.
+    let res = data
+        .board()
+        .update(
+            &board.id.id.to_raw(),
+            UpdateBoard {
+                handle: Some(board.handle.clone()),
+                name: MaybeUndefined::Value("Renamed".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().name, Some("Renamed".into()));
+}
+
+async fn create_with_visibility(data: &TestData, visibility: BoardVisibility) -> Board {
+    data.board()
+        .create(CreateBoard {
+            handle: Some("test".into()),
+            visibility: Some(visibility),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+}
+
+async fn listed(persist: &BoardPersist<'_>) -> Vec<Board> {
+    persist
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| edge.node)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_visibility_public() {
+    let (data, _) = TestData::with_user().await;
+    let board = create_with_visibility(&data, BoardVisibility::Public).await;
+    assert_eq!(board.visibility, BoardVisibility::Public);
+
+    let anon = CurrentAccount::default();
+    let anon = BoardPersist::new(&data.persist, &anon);
+
+    let res = anon.get_by_handle(&board.handle).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+
+    let res = listed(&anon).await;
+    assert_eq!(res.len(), 1);
+}
+
+#[tokio::test]
+async fn test_visibility_unlisted() {
+    let (data, _) = TestData::with_user().await;
+    let board = create_with_visibility(&data, BoardVisibility::Unlisted).await;
+
+    let anon = CurrentAccount::default();
+    let anon = BoardPersist::new(&data.persist, &anon);
+
+    let res = anon.get_by_handle(&board.handle).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+
+    let res = listed(&anon).await;
+    assert!(res.is_empty());
+
+    // Members still see the board in lists.
+    let res = listed(&data.board()).await;
+    assert_eq!(res.len(), 1);
+}
+
+#[tokio::test]
+async fn test_visibility_private() {
+    let (data, _) = TestData::with_user().await;
+    let board = create_with_visibility(&data, BoardVisibility::Private).await;
+    let acc = data.account().create_test_user().await;
+    let current = acc.current();
+    let other = BoardPersist::new(&data.persist, &current);
+
+    let res = other.get_by_handle(&board.handle).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+    assert!(listed(&other).await.is_empty());
+
+    let res = data.board().get_by_handle(&board.handle).await;
+    assert!(res.unwrap().is_some());
+    assert_eq!(listed(&data.board()).await.len(), 1);
+
+    let res = data
+        .board()
+        .add_member(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().member_ids, vec![acc.id.clone()]);
+
+    let res = other.get_by_handle(&board.handle).await;
+    assert!(res.unwrap().is_some());
+    assert_eq!(listed(&other).await.len(), 1);
+
+    // Members can't manage other members.
+    let res = other
+        .remove_member(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    let res = data
+        .board()
+        .remove_member(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await;
+    assert!(res.unwrap().unwrap().member_ids.is_empty());
+
+    let res = other.get_by_handle(&board.handle).await;
+    assert!(res.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_update_visibility() {
+    let (data, _) = TestData::with_user().await;
+    let board = create_with_visibility(&data, BoardVisibility::Public).await;
+
+    let res = data
+        .board()
+        .update(
+            &board.id.id.to_raw(),
+            UpdateBoard {
+                visibility: Some(BoardVisibility::Private),
+                ..Default::default()
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().visibility, BoardVisibility::Private);
+
+    let anon = CurrentAccount::default();
+    let anon = BoardPersist::new(&data.persist, &anon);
+    let res = anon.get_by_handle(&board.handle).await;
+    assert!(res.unwrap().is_none());
+}
+
+fn update_name() -> UpdateBoard {
+    UpdateBoard {
+        name: MaybeUndefined::Value("Renamed".into()),
+        ..Default::default()
+    }
+}
+
+fn update_visibility() -> UpdateBoard {
+    UpdateBoard {
+        visibility: Some(BoardVisibility::Private),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_update_delete_unauthorized() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let id = board.id.id.to_raw();
+
+    let acc = data.account().create_test_user().await;
+    let other = acc.current();
+    let anon = CurrentAccount::default();
+
+    for current in [&other, &anon] {
+        let persist = BoardPersist::new(&data.persist, current);
+
+        let res = persist.update(&id, update_name()).await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+        let res = persist.update(&id, update_visibility()).await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+        let res = persist.delete(&id).await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    }
+
+    let res = data.board().get(&id).await;
+    assert_eq!(res.unwrap().unwrap().name, board.name);
+}
+
+#[tokio::test]
+async fn test_moderator_update() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let id = board.id.id.to_raw();
+
+    let acc = data.account().create_test_user().await;
+    data.board()
+        .add_moderator(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await
+        .unwrap();
+    let current = acc.current();
+    let moderator = BoardPersist::new(&data.persist, &current);
+
+    let res = moderator.update(&id, update_name()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().name, Some("Renamed".into()));
+
+    // Only the owner can change visibility or delete the board.
+    let res = moderator.update(&id, update_visibility()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    let res = moderator.delete(&id).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    let res = data.board().delete(&id).await;
+    assert!(res.unwrap().is_some());
+}
@@ -2,20 +2,26 @@ use async_graphql::{connection::Connection, Context, Object, ID};
 use tracing::instrument;
 
 use super::{Board, BoardCursor, CreateBoard, UpdateBoard};
-use crate::{prelude::*, query::PaginationArgs};
+use crate::{
+    prelude::*,
+    query::{connection_complexity, PaginationArgs},
+};
 
 #[derive(Default)]
 pub struct BoardQuery;
 
 #[Object]
 impl BoardQuery {
-    /// Gets a board by its handle.
+    /// Gets a board by its handle. Handles that the board used previously also
+    /// find it, so check the returned handle to see if it has been renamed.
     #[instrument(skip_all)]
     async fn board(&self, ctx: &Context<'_>, handle: String) -> GqlResult<Option<Board>> {
         ctx.board_persist().get_by_handle(&handle).await.extend()
     }
 
-    /// Lists boards.
+    /// Lists public boards, and the boards that the current account is a
+    /// member of.
+    #[graphql(complexity = "connection_complexity(child_complexity, first, last)")]
     #[instrument(skip_all)]
     async fn boards(
         &self,
@@ -70,4 +76,61 @@ impl BoardMutation {
     async fn delete_board(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Board>> {
         ctx.board_persist().delete(&id).await.extend()
     }
+
+    /// Adds a moderator to a board. Only the board's owner can do this.
+    #[instrument(skip_all)]
+    async fn add_board_moderator(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<Board>> {
+        ctx.board_persist()
+            .add_moderator(&id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Adds a member to a board, allowing them to read it if it's private.
+    /// Only the board's moderators can do this.
+    #[instrument(skip_all)]
+    async fn add_board_member(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<Board>> {
+        ctx.board_persist()
+            .add_member(&id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Removes a member from a board. Only the board's moderators can do this.
+    #[instrument(skip_all)]
+    async fn remove_board_member(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<Board>> {
+        ctx.board_persist()
+            .remove_member(&id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Removes a moderator from a board. Only the board's owner can do this.
+    #[instrument(skip_all)]
+    async fn remove_board_moderator(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<Board>> {
+        ctx.board_persist()
+            .remove_moderator(&id, &account_id)
+            .await
+            .extend()
+    }
 }
@@ -1,19 +1,40 @@
-use std::{env, fmt, fs, net::IpAddr, path::Path};
+use std::{
+    env, fmt, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::Context as _;
 use cfg_if::cfg_if;
 use name_variant::NamedVariant;
 use ring::signature::{self, KeyPair as _};
+use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
+use surrealdb::{
+    dbs::capabilities::Target,
+    opt::capabilities::{Capabilities, Targets},
+};
+use thiserror::Error;
 use tracing::Level;
 
+use crate::persisted_query::Allowlist;
+
 // Defaults
 
 pub static DEFAULT_ADDRESS: &str = "file:./data/db";
 pub static DEFAULT_NAMESPACE: &str = "plazer";
 pub static DEFAULT_DATABASE: &str = "plazer";
+pub static DEFAULT_DB_AUTH_LEVEL: DbAuthLevel = DbAuthLevel::Root;
+pub const DEFAULT_DB_SCRIPTING: bool = true;
+pub const DEFAULT_DB_GUEST_ACCESS: bool = true;
+/// Capability targets that mean everything is allowed or denied.
+pub static DB_CAPABILITY_ALL: &str = "*";
 pub static DEFAULT_PRIVATE_KEY_PATH: &str = "./data/private_key.pem";
 pub static DEFAULT_LOG_DIR: &str = "./data/logs";
+pub static DEFAULT_LOG_ROTATION: LogRotation = LogRotation::Hourly;
+pub const DEFAULT_LOG_MAX_SIZE_MB: u64 = 100;
+pub static DEFAULT_OTLP_SERVICE_NAME: &str = "plazer";
 
 cfg_if! {
 
@@ -30,20 +51,94 @@ if #[cfg(debug_assertions)] {
 pub static DEFAULT_HOST: &str = "0.0.0.0";
 pub const DEFAULT_PORT: u16 = 8080;
 
+pub const DEFAULT_METRICS: bool = false;
+pub const DEFAULT_BOARD_RENAMES: bool = true;
+pub const DEFAULT_BOARD_HANDLE_COOLDOWN_DAYS: u32 = 30;
+pub const DEFAULT_JOB_WORKERS: usize = 2;
+pub const DEFAULT_GRAPHQL_MAX_DEPTH: usize = 16;
+/// Most fields cost one, and connections cost the fields of one of their items
+/// times the size of the page.
+pub const DEFAULT_GRAPHQL_MAX_COMPLEXITY: usize = 10_000;
+/// Matches the largest batches that the UI sends.
+pub const DEFAULT_GRAPHQL_MAX_BATCH_SIZE: usize = 20;
+pub const DEFAULT_GRAPHQL_MAX_BODY_KB: usize = 1024;
+pub const DEFAULT_PERSISTED_QUERY_CACHE_SIZE: usize = 1000;
+/// Identifiers that can't be used as user IDs or board handles, as they could
+/// be mistaken for the server's own pages or staff.
+pub static DEFAULT_RESERVED_IDENTS: &[&str] = &[
+    "about",
+    "account",
+    "accounts",
+    "admin",
+    "administrator",
+    "api",
+    "board",
+    "boards",
+    "graphql",
+    "help",
+    "login",
+    "logout",
+    "mod",
+    "moderator",
+    "plazer",
+    "post",
+    "posts",
+    "register",
+    "root",
+    "settings",
+    "static",
+    "support",
+    "system",
+];
+
 pub static DEFAULT_CONFIG_PATH: &str = "./config.toml";
 
 // Env vars
 
+pub static ENV_VAR_CONFIG: &str = "PLAZER_CONFIG";
 pub static ENV_VAR_ADDRESS: &str = "PLAZER_DB_ADDRESS";
 pub static ENV_VAR_NAMESPACE: &str = "PLAZER_DB_NAMESPACE";
 pub static ENV_VAR_DATABASE: &str = "PLAZER_DB_DATABASE";
+pub static ENV_VAR_DB_USERNAME: &str = "PLAZER_DB_USERNAME";
+pub static ENV_VAR_DB_PASSWORD: &str = "PLAZER_DB_PASSWORD";
+pub static ENV_VAR_DB_AUTH_LEVEL: &str = "PLAZER_DB_AUTH_LEVEL";
+pub static ENV_VAR_DB_TLS_CA: &str = "PLAZER_DB_TLS_CA";
+pub static ENV_VAR_DB_TLS_CERT: &str = "PLAZER_DB_TLS_CERT";
+pub static ENV_VAR_DB_TLS_KEY: &str = "PLAZER_DB_TLS_KEY";
+pub static ENV_VAR_DB_SCRIPTING: &str = "PLAZER_DB_SCRIPTING";
+pub static ENV_VAR_DB_GUEST_ACCESS: &str = "PLAZER_DB_GUEST_ACCESS";
+pub static ENV_VAR_DB_ALLOW_FUNCS: &str = "PLAZER_DB_ALLOW_FUNCS";
+pub static ENV_VAR_DB_DENY_FUNCS: &str = "PLAZER_DB_DENY_FUNCS";
+pub static ENV_VAR_DB_ALLOW_NET: &str = "PLAZER_DB_ALLOW_NET";
+pub static ENV_VAR_DB_DENY_NET: &str = "PLAZER_DB_DENY_NET";
 pub static ENV_VAR_PRIVATE_KEY: &str = "PLAZER_PRIVATE_KEY";
 pub static ENV_VAR_PRIVATE_KEY_PATH: &str = "PLAZER_PRIVATE_KEY_PATH";
 pub static ENV_VAR_LOG_DIR: &str = "PLAZER_LOG_DIR";
 pub static ENV_VAR_LOG_LEVEL_STDOUT: &str = "PLAZER_LOG_LEVEL_STDOUT";
 pub static ENV_VAR_LOG_LEVEL_FILE: &str = "PLAZER_LOG_LEVEL_FILE";
+pub static ENV_VAR_LOG_ROTATION: &str = "PLAZER_LOG_ROTATION";
+pub static ENV_VAR_LOG_MAX_SIZE_MB: &str = "PLAZER_LOG_MAX_SIZE_MB";
+pub static ENV_VAR_LOG_MAX_FILES: &str = "PLAZER_LOG_MAX_FILES";
+pub static ENV_VAR_OTLP_ENDPOINT: &str = "PLAZER_OTLP_ENDPOINT";
+pub static ENV_VAR_OTLP_SERVICE_NAME: &str = "PLAZER_OTLP_SERVICE_NAME";
 pub static ENV_VAR_HOST: &str = "PLAZER_HOST";
 pub static ENV_VAR_PORT: &str = "PLAZER_PORT";
+pub static ENV_VAR_TLS_CERT: &str = "PLAZER_TLS_CERT";
+pub static ENV_VAR_TLS_KEY: &str = "PLAZER_TLS_KEY";
+pub static ENV_VAR_CORS_ORIGINS: &str = "PLAZER_CORS_ORIGINS";
+pub static ENV_VAR_PUBLIC_URL: &str = "PLAZER_PUBLIC_URL";
+pub static ENV_VAR_METRICS: &str = "PLAZER_METRICS";
+pub static ENV_VAR_ADMINS: &str = "PLAZER_ADMINS";
+pub static ENV_VAR_BOARD_RENAMES: &str = "PLAZER_BOARD_RENAMES";
+pub static ENV_VAR_BOARD_HANDLE_COOLDOWN_DAYS: &str = "PLAZER_BOARD_HANDLE_COOLDOWN_DAYS";
+pub static ENV_VAR_RESERVED_IDENTS: &str = "PLAZER_RESERVED_IDENTS";
+pub static ENV_VAR_JOB_WORKERS: &str = "PLAZER_JOB_WORKERS";
+pub static ENV_VAR_GRAPHQL_MAX_DEPTH: &str = "PLAZER_GRAPHQL_MAX_DEPTH";
+pub static ENV_VAR_GRAPHQL_MAX_COMPLEXITY: &str = "PLAZER_GRAPHQL_MAX_COMPLEXITY";
+pub static ENV_VAR_GRAPHQL_MAX_BATCH_SIZE: &str = "PLAZER_GRAPHQL_MAX_BATCH_SIZE";
+pub static ENV_VAR_GRAPHQL_MAX_BODY_KB: &str = "PLAZER_GRAPHQL_MAX_BODY_KB";
+pub static ENV_VAR_PERSISTED_QUERY_CACHE_SIZE: &str = "PLAZER_PERSISTED_QUERY_CACHE_SIZE";
+pub static ENV_VAR_PERSISTED_QUERY_ALLOWLIST: &str = "PLAZER_PERSISTED_QUERY_ALLOWLIST";
 
 // Config
 
@@ -83,13 +178,107 @@ impl From<LogLevel> for tracing::Level {
     }
 }
 
+/// When the log file is swapped for a new one.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, NamedVariant,
+)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// Start a new file every hour
+    Hourly,
+    /// Start a new file every day
+    Daily,
+    /// Start a new file once the current one reaches the maximum size
+    Size,
+    /// Keep writing to the same file
+    Never,
+}
+
+impl fmt::Display for LogRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.variant_name().to_ascii_lowercase())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("expected one of hourly, daily, size or never")]
+pub struct InvalidLogRotation;
+
+impl FromStr for LogRotation {
+    type Err = InvalidLogRotation;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_ascii_lowercase() {
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            "size" => Ok(Self::Size),
+            "never" => Ok(Self::Never),
+            _ => Err(InvalidLogRotation),
+        }
+    }
+}
+
+/// The level that the database user signs in at.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, NamedVariant,
+)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum DbAuthLevel {
+    /// A root user, which can access every namespace
+    Root,
+    /// A user of the configured namespace
+    Namespace,
+    /// A user of the configured database
+    Database,
+}
+
+impl fmt::Display for DbAuthLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.variant_name().to_ascii_lowercase())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("expected one of root, namespace or database")]
+pub struct InvalidDbAuthLevel;
+
+impl FromStr for DbAuthLevel {
+    type Err = InvalidDbAuthLevel;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_ascii_lowercase() {
+            "root" => Ok(Self::Root),
+            "namespace" => Ok(Self::Namespace),
+            "database" => Ok(Self::Database),
+            _ => Err(InvalidDbAuthLevel),
+        }
+    }
+}
+
 pub type PrivateKeyCreate = fn(&Path) -> anyhow::Result<String>;
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServiceConfigBuilder {
+    #[serde(skip)]
+    config_paths: Option<Vec<String>>,
     address: Option<String>,
     namespace: Option<String>,
     database: Option<String>,
+    db_username: Option<String>,
+    db_password: Option<String>,
+    db_auth_level: Option<DbAuthLevel>,
+    db_tls_ca: Option<String>,
+    db_tls_cert: Option<String>,
+    db_tls_key: Option<String>,
+    db_scripting: Option<bool>,
+    db_guest_access: Option<bool>,
+    db_allow_funcs: Option<Vec<String>>,
+    db_deny_funcs: Option<Vec<String>>,
+    db_allow_net: Option<Vec<String>>,
+    db_deny_net: Option<Vec<String>>,
     private_key: Option<String>,
     private_key_path: Option<String>,
     #[serde(skip)]
@@ -97,8 +286,29 @@ pub struct ServiceConfigBuilder {
     log_dir: Option<String>,
     log_level_stdout: Option<LogLevel>,
     log_level_file: Option<LogLevel>,
+    log_rotation: Option<LogRotation>,
+    log_max_size_mb: Option<u64>,
+    log_max_files: Option<usize>,
+    otlp_endpoint: Option<String>,
+    otlp_service_name: Option<String>,
     host: Option<String>,
     port: Option<u16>,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    cors_origins: Option<Vec<String>>,
+    public_url: Option<String>,
+    metrics: Option<bool>,
+    admins: Option<Vec<String>>,
+    board_renames: Option<bool>,
+    board_handle_cooldown_days: Option<u32>,
+    reserved_idents: Option<Vec<String>>,
+    job_workers: Option<usize>,
+    graphql_max_depth: Option<usize>,
+    graphql_max_complexity: Option<usize>,
+    graphql_max_batch_size: Option<usize>,
+    graphql_max_body_kb: Option<usize>,
+    persisted_query_cache_size: Option<usize>,
+    persisted_query_allowlist: Option<String>,
 }
 
 impl ServiceConfigBuilder {
@@ -143,6 +353,159 @@ impl ServiceConfigBuilder {
         self
     }
 
+    #[must_use]
+    pub fn db_username(mut self, db_username: impl Into<String>) -> Self {
+        self.db_username = Some(db_username.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_username(mut self, db_username: Option<String>) -> Self {
+        self.db_username = db_username;
+        self
+    }
+
+    #[must_use]
+    pub fn db_password(mut self, db_password: impl Into<String>) -> Self {
+        self.db_password = Some(db_password.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_password(mut self, db_password: Option<String>) -> Self {
+        self.db_password = db_password;
+        self
+    }
+
+    #[must_use]
+    pub fn db_auth_level(mut self, db_auth_level: DbAuthLevel) -> Self {
+        self.db_auth_level = Some(db_auth_level);
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_auth_level(mut self, db_auth_level: Option<DbAuthLevel>) -> Self {
+        self.db_auth_level = db_auth_level;
+        self
+    }
+
+    #[must_use]
+    pub fn db_tls_ca(mut self, db_tls_ca: impl Into<String>) -> Self {
+        self.db_tls_ca = Some(db_tls_ca.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_tls_ca(mut self, db_tls_ca: Option<String>) -> Self {
+        self.db_tls_ca = db_tls_ca;
+        self
+    }
+
+    #[must_use]
+    pub fn db_tls_cert(mut self, db_tls_cert: impl Into<String>) -> Self {
+        self.db_tls_cert = Some(db_tls_cert.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_tls_cert(mut self, db_tls_cert: Option<String>) -> Self {
+        self.db_tls_cert = db_tls_cert;
+        self
+    }
+
+    #[must_use]
+    pub fn db_tls_key(mut self, db_tls_key: impl Into<String>) -> Self {
+        self.db_tls_key = Some(db_tls_key.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_tls_key(mut self, db_tls_key: Option<String>) -> Self {
+        self.db_tls_key = db_tls_key;
+        self
+    }
+
+    #[must_use]
+    pub fn db_scripting(mut self, db_scripting: bool) -> Self {
+        self.db_scripting = Some(db_scripting);
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_scripting(mut self, db_scripting: Option<bool>) -> Self {
+        self.db_scripting = db_scripting;
+        self
+    }
+
+    #[must_use]
+    pub fn db_guest_access(mut self, db_guest_access: bool) -> Self {
+        self.db_guest_access = Some(db_guest_access);
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_guest_access(mut self, db_guest_access: Option<bool>) -> Self {
+        self.db_guest_access = db_guest_access;
+        self
+    }
+
+    #[must_use]
+    pub fn db_allow_funcs(
+        mut self,
+        db_allow_funcs: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.db_allow_funcs = Some(db_allow_funcs.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_allow_funcs(mut self, db_allow_funcs: Option<Vec<String>>) -> Self {
+        self.db_allow_funcs = db_allow_funcs;
+        self
+    }
+
+    #[must_use]
+    pub fn db_deny_funcs(
+        mut self,
+        db_deny_funcs: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.db_deny_funcs = Some(db_deny_funcs.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_deny_funcs(mut self, db_deny_funcs: Option<Vec<String>>) -> Self {
+        self.db_deny_funcs = db_deny_funcs;
+        self
+    }
+
+    #[must_use]
+    pub fn db_allow_net(
+        mut self,
+        db_allow_net: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.db_allow_net = Some(db_allow_net.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_allow_net(mut self, db_allow_net: Option<Vec<String>>) -> Self {
+        self.db_allow_net = db_allow_net;
+        self
+    }
+
+    #[must_use]
+    pub fn db_deny_net(mut self, db_deny_net: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.db_deny_net = Some(db_deny_net.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_deny_net(mut self, db_deny_net: Option<Vec<String>>) -> Self {
+        self.db_deny_net = db_deny_net;
+        self
+    }
+
     #[must_use]
     pub fn private_key(mut self, private_key: impl Into<String>) -> Self {
         self.private_key = Some(private_key.into());
@@ -215,6 +578,66 @@ impl ServiceConfigBuilder {
         self
     }
 
+    #[must_use]
+    pub fn log_rotation(mut self, log_rotation: LogRotation) -> Self {
+        self.log_rotation = Some(log_rotation);
+        self
+    }
+
+    #[must_use]
+    pub fn set_log_rotation(mut self, log_rotation: Option<LogRotation>) -> Self {
+        self.log_rotation = log_rotation;
+        self
+    }
+
+    #[must_use]
+    pub fn log_max_size_mb(mut self, log_max_size_mb: u64) -> Self {
+        self.log_max_size_mb = Some(log_max_size_mb);
+        self
+    }
+
+    #[must_use]
+    pub fn set_log_max_size_mb(mut self, log_max_size_mb: Option<u64>) -> Self {
+        self.log_max_size_mb = log_max_size_mb;
+        self
+    }
+
+    #[must_use]
+    pub fn log_max_files(mut self, log_max_files: usize) -> Self {
+        self.log_max_files = Some(log_max_files);
+        self
+    }
+
+    #[must_use]
+    pub fn set_log_max_files(mut self, log_max_files: Option<usize>) -> Self {
+        self.log_max_files = log_max_files;
+        self
+    }
+
+    #[must_use]
+    pub fn otlp_endpoint(mut self, otlp_endpoint: impl Into<String>) -> Self {
+        self.otlp_endpoint = Some(otlp_endpoint.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_otlp_endpoint(mut self, otlp_endpoint: Option<String>) -> Self {
+        self.otlp_endpoint = otlp_endpoint;
+        self
+    }
+
+    #[must_use]
+    pub fn otlp_service_name(mut self, otlp_service_name: impl Into<String>) -> Self {
+        self.otlp_service_name = Some(otlp_service_name.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_otlp_service_name(mut self, otlp_service_name: Option<String>) -> Self {
+        self.otlp_service_name = otlp_service_name;
+        self
+    }
+
     #[must_use]
     pub fn host(mut self, host: impl Into<String>) -> Self {
         self.host = Some(host.into());
@@ -239,223 +662,1373 @@ impl ServiceConfigBuilder {
         self
     }
 
-    pub fn build(self) -> anyhow::Result<ServiceConfig> {
-        let file_config = match fs::read_to_string(DEFAULT_CONFIG_PATH) {
-            Ok(file_config) => toml::from_str(&file_config).context("Config invalid")?,
-            Err(err) => {
-                if let std::io::ErrorKind::NotFound = err.kind() {
-                    ServiceConfigBuilder::default()
-                } else {
-                    return Err(err).context("Unable to read config file");
-                }
-            }
-        };
+    #[must_use]
+    pub fn tls_cert(mut self, tls_cert: impl Into<String>) -> Self {
+        self.tls_cert = Some(tls_cert.into());
+        self
+    }
 
-        Ok(ServiceConfig {
-            address: config_str_value(
-                self.address,
-                ENV_VAR_ADDRESS,
-                file_config.address,
-                DEFAULT_ADDRESS,
-            )?,
-            namespace: config_str_value(
-                self.namespace,
-                ENV_VAR_NAMESPACE,
-                file_config.namespace,
-                DEFAULT_NAMESPACE,
-            )?,
-            database: config_str_value(
-                self.database,
-                ENV_VAR_DATABASE,
-                file_config.database,
-                DEFAULT_DATABASE,
-            )?,
-            private_key: match self.private_key {
-                Some(private_key) => Some(private_key),
-                None => env_value(ENV_VAR_PRIVATE_KEY)?.or(file_config.private_key),
-            },
-            private_key_path: config_str_value(
-                self.private_key_path,
-                ENV_VAR_PRIVATE_KEY_PATH,
-                file_config.private_key_path,
-                DEFAULT_PRIVATE_KEY_PATH,
-            )?,
-            private_key_create: self.private_key_create,
-            log_dir: config_str_value(
-                self.log_dir,
-                ENV_VAR_LOG_DIR,
-                file_config.log_dir,
-                DEFAULT_LOG_DIR,
-            )?,
-            log_level_stdout: config_level_value(
-                self.log_level_stdout,
-                ENV_VAR_LOG_LEVEL_STDOUT,
-                file_config.log_level_stdout,
-                DEFAULT_LOG_LEVEL_STDOUT,
-            )?,
-            log_level_file: config_level_value(
-                self.log_level_file,
-                ENV_VAR_LOG_LEVEL_FILE,
-                file_config.log_level_file,
-                DEFAULT_LOG_LEVEL_FILE,
-            )?,
-            host: config_str_value(self.host, ENV_VAR_HOST, file_config.host, DEFAULT_HOST)?,
-            port: config_parsed_value(self.port, ENV_VAR_PORT, file_config.port, DEFAULT_PORT)?,
-        })
+    #[must_use]
+    pub fn set_tls_cert(mut self, tls_cert: Option<String>) -> Self {
+        self.tls_cert = tls_cert;
+        self
     }
-}
 
-fn config_str_value(
-    arg: Option<String>,
-    env_var: &str,
-    file: Option<String>,
-    default: &str,
-) -> anyhow::Result<String> {
-    let value = match arg {
-        Some(arg) => arg,
-        None => env_value(env_var)?
-            .or(file)
-            .unwrap_or_else(|| default.to_owned()),
-    };
+    #[must_use]
+    pub fn tls_key(mut self, tls_key: impl Into<String>) -> Self {
+        self.tls_key = Some(tls_key.into());
+        self
+    }
 
-    Ok(value)
-}
+    #[must_use]
+    pub fn set_tls_key(mut self, tls_key: Option<String>) -> Self {
+        self.tls_key = tls_key;
+        self
+    }
 
-fn config_parsed_value<T>(
-    arg: Option<T>,
-    env_var: &str,
-    file: Option<T>,
-    default: T,
-) -> anyhow::Result<T>
-where
-    T: std::str::FromStr,
-    T::Err: std::error::Error + Send + Sync + 'static,
-{
-    let value = match arg {
-        Some(arg) => arg,
-        None => match env_value(env_var)? {
-            Some(value) => value.parse().with_context(|| {
-                format!("Invalid value `{value}` in environment variable {env_var}")
-            })?,
-            None => file.unwrap_or(default),
-        },
-    };
-
-    Ok(value)
-}
-
-fn config_level_value(
-    arg: Option<LogLevel>,
-    env_var: &str,
-    file: Option<LogLevel>,
-    default: LogLevel,
-) -> anyhow::Result<LogLevel> {
-    let value = match arg {
-        Some(arg) => arg,
-        None => match env_value(env_var)? {
-            Some(value) => match &*value.to_ascii_lowercase() {
-                "error" => LogLevel::Error,
-                "warn" => LogLevel::Warn,
-                "info" => LogLevel::Info,
-                "debug" => LogLevel::Debug,
-                "trace" => LogLevel::Trace,
-                value => {
-                    return Err(anyhow::anyhow!(
-                        "Invalid log level {value:?} in environment variable {env_var}"
-                    ))
-                }
-            },
-            None => file.unwrap_or(default),
-        },
-    };
+    #[must_use]
+    pub fn cors_origins(
+        mut self,
+        cors_origins: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.cors_origins = Some(cors_origins.into_iter().map(Into::into).collect());
+        self
+    }
 
-    Ok(value)
-}
+    #[must_use]
+    pub fn set_cors_origins(mut self, cors_origins: Option<Vec<String>>) -> Self {
+        self.cors_origins = cors_origins;
+        self
+    }
 
-fn env_value(env_var: &str) -> anyhow::Result<Option<String>> {
-    match env::var(env_var) {
-        Ok(value) => Ok(Some(value)),
-        Err(env::VarError::NotPresent) => Ok(None),
-        Err(env::VarError::NotUnicode(_)) => Err(anyhow::anyhow!(
-            "Environment variable {env_var} is not valid unicode",
-        )),
+    #[must_use]
+    pub fn public_url(mut self, public_url: impl Into<String>) -> Self {
+        self.public_url = Some(public_url.into());
+        self
     }
-}
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct ServiceConfig {
-    address: String,
-    namespace: String,
-    database: String,
-    private_key: Option<String>,
-    private_key_path: String,
-    #[serde(skip)]
-    private_key_create: Option<PrivateKeyCreate>,
+    #[must_use]
+    pub fn set_public_url(mut self, public_url: Option<String>) -> Self {
+        self.public_url = public_url;
+        self
+    }
+
+    #[must_use]
+    pub fn metrics(mut self, metrics: bool) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    #[must_use]
+    pub fn set_metrics(mut self, metrics: Option<bool>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    #[must_use]
+    pub fn admins(mut self, admins: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.admins = Some(admins.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub fn set_admins(mut self, admins: Option<Vec<String>>) -> Self {
+        self.admins = admins;
+        self
+    }
+
+    #[must_use]
+    pub fn board_renames(mut self, board_renames: bool) -> Self {
+        self.board_renames = Some(board_renames);
+        self
+    }
+
+    #[must_use]
+    pub fn set_board_renames(mut self, board_renames: Option<bool>) -> Self {
+        self.board_renames = board_renames;
+        self
+    }
+
+    #[must_use]
+    pub fn board_handle_cooldown_days(mut self, board_handle_cooldown_days: u32) -> Self {
+        self.board_handle_cooldown_days = Some(board_handle_cooldown_days);
+        self
+    }
+
+    #[must_use]
+    pub fn set_board_handle_cooldown_days(
+        mut self,
+        board_handle_cooldown_days: Option<u32>,
+    ) -> Self {
+        self.board_handle_cooldown_days = board_handle_cooldown_days;
+        self
+    }
+
+    #[must_use]
+    pub fn reserved_idents(
+        mut self,
+        reserved_idents: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.reserved_idents = Some(reserved_idents.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub fn set_reserved_idents(mut self, reserved_idents: Option<Vec<String>>) -> Self {
+        self.reserved_idents = reserved_idents;
+        self
+    }
+
+    #[must_use]
+    pub fn job_workers(mut self, job_workers: usize) -> Self {
+        self.job_workers = Some(job_workers);
+        self
+    }
+
+    #[must_use]
+    pub fn set_job_workers(mut self, job_workers: Option<usize>) -> Self {
+        self.job_workers = job_workers;
+        self
+    }
+
+    #[must_use]
+    pub fn graphql_max_depth(mut self, graphql_max_depth: usize) -> Self {
+        self.graphql_max_depth = Some(graphql_max_depth);
+        self
+    }
+
+    #[must_use]
+    pub fn set_graphql_max_depth(mut self, graphql_max_depth: Option<usize>) -> Self {
+        self.graphql_max_depth = graphql_max_depth;
+        self
+    }
+
+    #[must_use]
+    pub fn graphql_max_complexity(mut self, graphql_max_complexity: usize) -> Self {
+        self.graphql_max_complexity = Some(graphql_max_complexity);
+        self
+    }
+
+    #[must_use]
+    pub fn set_graphql_max_complexity(mut self, graphql_max_complexity: Option<usize>) -> Self {
+        self.graphql_max_complexity = graphql_max_complexity;
+        self
+    }
+
+    #[must_use]
+    pub fn graphql_max_batch_size(mut self, graphql_max_batch_size: usize) -> Self {
+        self.graphql_max_batch_size = Some(graphql_max_batch_size);
+        self
+    }
+
+    #[must_use]
+    pub fn set_graphql_max_batch_size(mut self, graphql_max_batch_size: Option<usize>) -> Self {
+        self.graphql_max_batch_size = graphql_max_batch_size;
+        self
+    }
+
+    #[must_use]
+    pub fn graphql_max_body_kb(mut self, graphql_max_body_kb: usize) -> Self {
+        self.graphql_max_body_kb = Some(graphql_max_body_kb);
+        self
+    }
+
+    #[must_use]
+    pub fn set_graphql_max_body_kb(mut self, graphql_max_body_kb: Option<usize>) -> Self {
+        self.graphql_max_body_kb = graphql_max_body_kb;
+        self
+    }
+
+    #[must_use]
+    pub fn persisted_query_cache_size(mut self, persisted_query_cache_size: usize) -> Self {
+        self.persisted_query_cache_size = Some(persisted_query_cache_size);
+        self
+    }
+
+    #[must_use]
+    pub fn set_persisted_query_cache_size(
+        mut self,
+        persisted_query_cache_size: Option<usize>,
+    ) -> Self {
+        self.persisted_query_cache_size = persisted_query_cache_size;
+        self
+    }
+
+    #[must_use]
+    pub fn persisted_query_allowlist(
+        mut self,
+        persisted_query_allowlist: impl Into<String>,
+    ) -> Self {
+        self.persisted_query_allowlist = Some(persisted_query_allowlist.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_persisted_query_allowlist(
+        mut self,
+        persisted_query_allowlist: Option<String>,
+    ) -> Self {
+        self.persisted_query_allowlist = persisted_query_allowlist;
+        self
+    }
+
+    #[must_use]
+    pub fn config_paths(
+        mut self,
+        config_paths: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config_paths = Some(config_paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub fn set_config_paths(mut self, config_paths: Option<Vec<String>>) -> Self {
+        self.config_paths = config_paths;
+        self
+    }
+
+    /// Resolves the config. Each value is taken from the first of these that
+    /// sets it: the builder, its environment variable, the config files (later
+    /// files first), then its default.
+    ///
+    /// The config files are the paths given to the builder, or in
+    /// `PLAZER_CONFIG`, or `./config.toml` if it exists. Directories stand
+    /// for the `.toml` files in them, in name order.
+    #[allow(clippy::too_many_lines)]
+    pub fn build(self) -> anyhow::Result<ServiceConfig> {
+        let builder = self.clone();
+        let config_paths: Vec<PathBuf> = match self.config_paths {
+            Some(paths) => paths.into_iter().map(Into::into).collect(),
+            None => match env_value(ENV_VAR_CONFIG)? {
+                Some(paths) => env::split_paths(&paths).collect(),
+                None => vec![],
+            },
+        };
+        let files = if config_paths.is_empty() {
+            read_config_files(&[DEFAULT_CONFIG_PATH.into()], false)?
+        } else {
+            read_config_files(&config_paths, true)?
+        };
+        let mut layers = ConfigLayers {
+            files,
+            sources: vec![],
+        };
+
+        Ok(ServiceConfig {
+            address: layers.str_value(
+                "address",
+                self.address,
+                ENV_VAR_ADDRESS,
+                |file| file.address.clone(),
+                DEFAULT_ADDRESS,
+            )?,
+            namespace: layers.str_value(
+                "namespace",
+                self.namespace,
+                ENV_VAR_NAMESPACE,
+                |file| file.namespace.clone(),
+                DEFAULT_NAMESPACE,
+            )?,
+            database: layers.str_value(
+                "database",
+                self.database,
+                ENV_VAR_DATABASE,
+                |file| file.database.clone(),
+                DEFAULT_DATABASE,
+            )?,
+            db_username: layers.opt_str_value(
+                "db_username",
+                self.db_username,
+                ENV_VAR_DB_USERNAME,
+                |file| file.db_username.clone(),
+            )?,
+            db_password: layers.opt_str_value(
+                "db_password",
+                self.db_password,
+                ENV_VAR_DB_PASSWORD,
+                |file| file.db_password.clone(),
+            )?,
+            db_auth_level: layers.parsed_value(
+                "db_auth_level",
+                self.db_auth_level,
+                ENV_VAR_DB_AUTH_LEVEL,
+                |file| file.db_auth_level,
+                DEFAULT_DB_AUTH_LEVEL,
+            )?,
+            db_tls_ca: layers.opt_str_value(
+                "db_tls_ca",
+                self.db_tls_ca,
+                ENV_VAR_DB_TLS_CA,
+                |file| file.db_tls_ca.clone(),
+            )?,
+            db_tls_cert: layers.opt_str_value(
+                "db_tls_cert",
+                self.db_tls_cert,
+                ENV_VAR_DB_TLS_CERT,
+                |file| file.db_tls_cert.clone(),
+            )?,
+            db_tls_key: layers.opt_str_value(
+                "db_tls_key",
+                self.db_tls_key,
+                ENV_VAR_DB_TLS_KEY,
+                |file| file.db_tls_key.clone(),
+            )?,
+            db_scripting: layers.parsed_value(
+                "db_scripting",
+                self.db_scripting,
+                ENV_VAR_DB_SCRIPTING,
+                |file| file.db_scripting,
+                DEFAULT_DB_SCRIPTING,
+            )?,
+            db_guest_access: layers.parsed_value(
+                "db_guest_access",
+                self.db_guest_access,
+                ENV_VAR_DB_GUEST_ACCESS,
+                |file| file.db_guest_access,
+                DEFAULT_DB_GUEST_ACCESS,
+            )?,
+            db_allow_funcs: layers.list_value(
+                "db_allow_funcs",
+                self.db_allow_funcs,
+                ENV_VAR_DB_ALLOW_FUNCS,
+                |file| file.db_allow_funcs.clone(),
+                default_db_allow,
+            )?,
+            db_deny_funcs: layers.list_value(
+                "db_deny_funcs",
+                self.db_deny_funcs,
+                ENV_VAR_DB_DENY_FUNCS,
+                |file| file.db_deny_funcs.clone(),
+                Vec::new,
+            )?,
+            db_allow_net: layers.list_value(
+                "db_allow_net",
+                self.db_allow_net,
+                ENV_VAR_DB_ALLOW_NET,
+                |file| file.db_allow_net.clone(),
+                default_db_allow,
+            )?,
+            db_deny_net: layers.list_value(
+                "db_deny_net",
+                self.db_deny_net,
+                ENV_VAR_DB_DENY_NET,
+                |file| file.db_deny_net.clone(),
+                Vec::new,
+            )?,
+            private_key: layers.opt_str_value(
+                "private_key",
+                self.private_key,
+                ENV_VAR_PRIVATE_KEY,
+                |file| file.private_key.clone(),
+            )?,
+            private_key_path: layers.str_value(
+                "private_key_path",
+                self.private_key_path,
+                ENV_VAR_PRIVATE_KEY_PATH,
+                |file| file.private_key_path.clone(),
+                DEFAULT_PRIVATE_KEY_PATH,
+            )?,
+            private_key_create: self.private_key_create,
+            log_dir: layers.str_value(
+                "log_dir",
+                self.log_dir,
+                ENV_VAR_LOG_DIR,
+                |file| file.log_dir.clone(),
+                DEFAULT_LOG_DIR,
+            )?,
+            log_level_stdout: layers.level_value(
+                "log_level_stdout",
+                self.log_level_stdout,
+                ENV_VAR_LOG_LEVEL_STDOUT,
+                |file| file.log_level_stdout,
+                DEFAULT_LOG_LEVEL_STDOUT,
+            )?,
+            log_level_file: layers.level_value(
+                "log_level_file",
+                self.log_level_file,
+                ENV_VAR_LOG_LEVEL_FILE,
+                |file| file.log_level_file,
+                DEFAULT_LOG_LEVEL_FILE,
+            )?,
+            log_rotation: layers.parsed_value(
+                "log_rotation",
+                self.log_rotation,
+                ENV_VAR_LOG_ROTATION,
+                |file| file.log_rotation,
+                DEFAULT_LOG_ROTATION,
+            )?,
+            log_max_size_mb: layers.parsed_value(
+                "log_max_size_mb",
+                self.log_max_size_mb,
+                ENV_VAR_LOG_MAX_SIZE_MB,
+                |file| file.log_max_size_mb,
+                DEFAULT_LOG_MAX_SIZE_MB,
+            )?,
+            log_max_files: layers.opt_parsed_value(
+                "log_max_files",
+                self.log_max_files,
+                ENV_VAR_LOG_MAX_FILES,
+                |file| file.log_max_files,
+            )?,
+            otlp_endpoint: layers.opt_str_value(
+                "otlp_endpoint",
+                self.otlp_endpoint,
+                ENV_VAR_OTLP_ENDPOINT,
+                |file| file.otlp_endpoint.clone(),
+            )?,
+            otlp_service_name: layers.str_value(
+                "otlp_service_name",
+                self.otlp_service_name,
+                ENV_VAR_OTLP_SERVICE_NAME,
+                |file| file.otlp_service_name.clone(),
+                DEFAULT_OTLP_SERVICE_NAME,
+            )?,
+            host: layers.str_value(
+                "host",
+                self.host,
+                ENV_VAR_HOST,
+                |file| file.host.clone(),
+                DEFAULT_HOST,
+            )?,
+            port: layers.parsed_value(
+                "port",
+                self.port,
+                ENV_VAR_PORT,
+                |file| file.port,
+                DEFAULT_PORT,
+            )?,
+            tls_cert: layers.opt_str_value(
+                "tls_cert",
+                self.tls_cert,
+                ENV_VAR_TLS_CERT,
+                |file| file.tls_cert.clone(),
+            )?,
+            tls_key: layers.opt_str_value("tls_key", self.tls_key, ENV_VAR_TLS_KEY, |file| {
+                file.tls_key.clone()
+            })?,
+            cors_origins: layers.list_value(
+                "cors_origins",
+                self.cors_origins,
+                ENV_VAR_CORS_ORIGINS,
+                |file| file.cors_origins.clone(),
+                Vec::new,
+            )?,
+            public_url: layers.opt_str_value(
+                "public_url",
+                self.public_url,
+                ENV_VAR_PUBLIC_URL,
+                |file| file.public_url.clone(),
+            )?,
+            metrics: layers.parsed_value(
+                "metrics",
+                self.metrics,
+                ENV_VAR_METRICS,
+                |file| file.metrics,
+                DEFAULT_METRICS,
+            )?,
+            admins: layers.list_value(
+                "admins",
+                self.admins,
+                ENV_VAR_ADMINS,
+                |file| file.admins.clone(),
+                Vec::new,
+            )?,
+            board_renames: layers.parsed_value(
+                "board_renames",
+                self.board_renames,
+                ENV_VAR_BOARD_RENAMES,
+                |file| file.board_renames,
+                DEFAULT_BOARD_RENAMES,
+            )?,
+            board_handle_cooldown_days: layers.parsed_value(
+                "board_handle_cooldown_days",
+                self.board_handle_cooldown_days,
+                ENV_VAR_BOARD_HANDLE_COOLDOWN_DAYS,
+                |file| file.board_handle_cooldown_days,
+                DEFAULT_BOARD_HANDLE_COOLDOWN_DAYS,
+            )?,
+            reserved_idents: layers.list_value(
+                "reserved_idents",
+                self.reserved_idents,
+                ENV_VAR_RESERVED_IDENTS,
+                |file| file.reserved_idents.clone(),
+                default_reserved_idents,
+            )?,
+            job_workers: layers.parsed_value(
+                "job_workers",
+                self.job_workers,
+                ENV_VAR_JOB_WORKERS,
+                |file| file.job_workers,
+                DEFAULT_JOB_WORKERS,
+            )?,
+            graphql_max_depth: layers.parsed_value(
+                "graphql_max_depth",
+                self.graphql_max_depth,
+                ENV_VAR_GRAPHQL_MAX_DEPTH,
+                |file| file.graphql_max_depth,
+                DEFAULT_GRAPHQL_MAX_DEPTH,
+            )?,
+            graphql_max_complexity: layers.parsed_value(
+                "graphql_max_complexity",
+                self.graphql_max_complexity,
+                ENV_VAR_GRAPHQL_MAX_COMPLEXITY,
+                |file| file.graphql_max_complexity,
+                DEFAULT_GRAPHQL_MAX_COMPLEXITY,
+            )?,
+            graphql_max_batch_size: layers.parsed_value(
+                "graphql_max_batch_size",
+                self.graphql_max_batch_size,
+                ENV_VAR_GRAPHQL_MAX_BATCH_SIZE,
+                |file| file.graphql_max_batch_size,
+                DEFAULT_GRAPHQL_MAX_BATCH_SIZE,
+            )?,
+            graphql_max_body_kb: layers.parsed_value(
+                "graphql_max_body_kb",
+                self.graphql_max_body_kb,
+                ENV_VAR_GRAPHQL_MAX_BODY_KB,
+                |file| file.graphql_max_body_kb,
+                DEFAULT_GRAPHQL_MAX_BODY_KB,
+            )?,
+            persisted_query_cache_size: layers.parsed_value(
+                "persisted_query_cache_size",
+                self.persisted_query_cache_size,
+                ENV_VAR_PERSISTED_QUERY_CACHE_SIZE,
+                |file| file.persisted_query_cache_size,
+                DEFAULT_PERSISTED_QUERY_CACHE_SIZE,
+            )?,
+            persisted_query_allowlist: layers.opt_str_value(
+                "persisted_query_allowlist",
+                self.persisted_query_allowlist,
+                ENV_VAR_PERSISTED_QUERY_ALLOWLIST,
+                |file| file.persisted_query_allowlist.clone(),
+            )?,
+            config_paths,
+            sources: layers.sources,
+            builder,
+        })
+    }
+}
+
+/// Reads the config files at `paths`, in order. Directories are read as the
+/// `.toml` files directly inside them, in name order. Missing files are an
+/// error if `required` is set, and are skipped otherwise.
+fn read_config_files(
+    paths: &[PathBuf],
+    required: bool,
+) -> anyhow::Result<Vec<(PathBuf, ServiceConfigBuilder)>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut dir_files = fs::read_dir(path)
+                .with_context(|| format!("Unable to read config directory {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Unable to read config directory {}", path.display()))?;
+            dir_files.retain(|file| file.is_file() && file.extension() == Some("toml".as_ref()));
+            dir_files.sort();
+            for file in dir_files {
+                files.push(read_config_file(file)?);
+            }
+            continue;
+        }
+
+        match read_config_file(path.clone()) {
+            Ok(file) => files.push(file),
+            Err(err) if !required && !path.exists() => {
+                tracing::trace!(error = ?err, "No config file");
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(files)
+}
+
+fn read_config_file(path: PathBuf) -> anyhow::Result<(PathBuf, ServiceConfigBuilder)> {
+    let file = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read config file {}", path.display()))?;
+    let config = toml::from_str(&file)
+        .with_context(|| format!("Config file {} is invalid", path.display()))?;
+    Ok((path, config))
+}
+
+/// Where a config value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// The value was given to the builder, e.g. as a command line argument.
+    Argument,
+    /// The value was read from the environment variable.
+    Env(&'static str),
+    /// The value was read from the config file.
+    File(PathBuf),
+    /// The value wasn't set anywhere.
+    Default,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Argument => write!(f, "argument"),
+            Self::Env(env_var) => write!(f, "env {env_var}"),
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// The config files that values can be read from, and where each value that
+/// has been resolved so far came from.
+struct ConfigLayers {
+    files: Vec<(PathBuf, ServiceConfigBuilder)>,
+    sources: Vec<(&'static str, ConfigSource)>,
+}
+
+impl ConfigLayers {
+    fn resolve<T>(
+        &mut self,
+        key: &'static str,
+        arg: Option<T>,
+        env_var: &'static str,
+        parse_env: impl FnOnce(String) -> anyhow::Result<T>,
+        file: impl Fn(&ServiceConfigBuilder) -> Option<T>,
+    ) -> anyhow::Result<Option<T>> {
+        let (value, source) = if let Some(arg) = arg {
+            (Some(arg), ConfigSource::Argument)
+        } else if let Some(value) = env_value(env_var)? {
+            (Some(parse_env(value)?), ConfigSource::Env(env_var))
+        } else if let Some((value, path)) = self
+            .files
+            .iter()
+            .rev()
+            .find_map(|(path, config)| file(config).map(|value| (value, path)))
+        {
+            (Some(value), ConfigSource::File(path.clone()))
+        } else {
+            (None, ConfigSource::Default)
+        };
+
+        self.sources.push((key, source));
+        Ok(value)
+    }
+
+    fn str_value(
+        &mut self,
+        key: &'static str,
+        arg: Option<String>,
+        env_var: &'static str,
+        file: impl Fn(&ServiceConfigBuilder) -> Option<String>,
+        default: &str,
+    ) -> anyhow::Result<String> {
+        let value = self.resolve(key, arg, env_var, Ok, file)?;
+        Ok(value.unwrap_or_else(|| default.to_owned()))
+    }
+
+    fn opt_str_value(
+        &mut self,
+        key: &'static str,
+        arg: Option<String>,
+        env_var: &'static str,
+        file: impl Fn(&ServiceConfigBuilder) -> Option<String>,
+    ) -> anyhow::Result<Option<String>> {
+        self.resolve(key, arg, env_var, Ok, file)
+    }
+
+    /// Reads a list, which is comma-separated in environment variables.
+    fn list_value(
+        &mut self,
+        key: &'static str,
+        arg: Option<Vec<String>>,
+        env_var: &'static str,
+        file: impl Fn(&ServiceConfigBuilder) -> Option<Vec<String>>,
+        default: fn() -> Vec<String>,
+    ) -> anyhow::Result<Vec<String>> {
+        let parse_env = |value: String| {
+            Ok(value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(ToOwned::to_owned)
+                .collect())
+        };
+        let value = self.resolve(key, arg, env_var, parse_env, file)?;
+        Ok(value.unwrap_or_else(default))
+    }
+
+    fn parsed_value<T>(
+        &mut self,
+        key: &'static str,
+        arg: Option<T>,
+        env_var: &'static str,
+        file: impl Fn(&ServiceConfigBuilder) -> Option<T>,
+        default: T,
+    ) -> anyhow::Result<T>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let parse_env = |value: String| {
+            value.parse().with_context(|| {
+                format!("Invalid value `{value}` in environment variable {env_var}")
+            })
+        };
+        let value = self.resolve(key, arg, env_var, parse_env, file)?;
+        Ok(value.unwrap_or(default))
+    }
+
+    fn opt_parsed_value<T>(
+        &mut self,
+        key: &'static str,
+        arg: Option<T>,
+        env_var: &'static str,
+        file: impl Fn(&ServiceConfigBuilder) -> Option<T>,
+    ) -> anyhow::Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let parse_env = |value: String| {
+            value.parse().with_context(|| {
+                format!("Invalid value `{value}` in environment variable {env_var}")
+            })
+        };
+        self.resolve(key, arg, env_var, parse_env, file)
+    }
+
+    fn level_value(
+        &mut self,
+        key: &'static str,
+        arg: Option<LogLevel>,
+        env_var: &'static str,
+        file: impl Fn(&ServiceConfigBuilder) -> Option<LogLevel>,
+        default: LogLevel,
+    ) -> anyhow::Result<LogLevel> {
+        let parse_env = |value: String| match &*value.to_ascii_lowercase() {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            value => Err(anyhow::anyhow!(
+                "Invalid log level {value:?} in environment variable {env_var}"
+            )),
+        };
+        let value = self.resolve(key, arg, env_var, parse_env, file)?;
+        Ok(value.unwrap_or(default))
+    }
+}
+
+fn env_value(env_var: &str) -> anyhow::Result<Option<String>> {
+    match env::var(env_var) {
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) => Err(anyhow::anyhow!(
+            "Environment variable {env_var} is not valid unicode",
+        )),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ServiceConfig {
+    address: String,
+    namespace: String,
+    database: String,
+    /// The user to sign into the database as. Databases that don't need
+    /// signing into, such as local ones, are used without a user.
+    db_username: Option<String>,
+    db_password: Option<String>,
+    #[serde(default = "default_db_auth_level")]
+    db_auth_level: DbAuthLevel,
+    /// A PEM file with extra certificate authorities to trust when connecting
+    /// to a remote database over TLS.
+    db_tls_ca: Option<String>,
+    /// A PEM file with a client certificate to present to a remote database.
+    db_tls_cert: Option<String>,
+    /// A PEM file with the private key for `db_tls_cert`.
+    db_tls_key: Option<String>,
+    /// Whether embedded scripting functions can be run. Capabilities only
+    /// apply to local databases, remote ones are configured on the server.
+    #[serde(default = "default_db_scripting")]
+    db_scripting: bool,
+    /// Whether queries can be run without signing in.
+    #[serde(default = "default_db_guest_access")]
+    db_guest_access: bool,
+    /// The database functions that can be run, such as `time::now` or
+    /// `http`, or `*` for all of them.
+    #[serde(default = "default_db_allow")]
+    db_allow_funcs: Vec<String>,
+    /// Database functions that can't be run, even if they are allowed.
+    #[serde(default)]
+    db_deny_funcs: Vec<String>,
+    /// The hosts and networks that the database can connect to, or `*` for
+    /// any of them.
+    #[serde(default = "default_db_allow")]
+    db_allow_net: Vec<String>,
+    /// Hosts and networks that the database can't connect to, even if they
+    /// are allowed.
+    #[serde(default)]
+    db_deny_net: Vec<String>,
+    private_key: Option<String>,
+    private_key_path: String,
+    #[serde(skip)]
+    private_key_create: Option<PrivateKeyCreate>,
     log_dir: String,
     log_level_stdout: LogLevel,
     log_level_file: LogLevel,
+    /// When the log file is swapped for a new one.
+    #[serde(default = "default_log_rotation")]
+    log_rotation: LogRotation,
+    /// How large a log file can get, in megabytes, when rotating by size.
+    #[serde(default = "default_log_max_size_mb")]
+    log_max_size_mb: u64,
+    /// How many log files are kept, including the one being written to. All
+    /// of them are kept if unset.
+    log_max_files: Option<usize>,
+    /// The address of an OpenTelemetry collector to send traces to over
+    /// OTLP, such as `http://localhost:4317`. Only used by builds with the
+    /// `otlp` feature.
+    otlp_endpoint: Option<String>,
+    /// The name that traces are reported under.
+    #[serde(default = "default_otlp_service_name")]
+    otlp_service_name: String,
     host: String,
     port: u16,
+    /// A PEM file with the certificate chain to serve HTTPS with. HTTP is
+    /// served if unset.
+    tls_cert: Option<String>,
+    /// A PEM file with the private key for `tls_cert`.
+    tls_key: Option<String>,
+    /// The origins that browsers can make requests from, other than the
+    /// server's own, or `*` for any of them.
+    #[serde(default)]
+    cors_origins: Vec<String>,
+    /// The URL that the site is served at, such as `https://example.com`, for
+    /// links in feeds. If unset, links use the host that the feed was
+    /// requested from.
+    public_url: Option<String>,
+    /// Whether Prometheus metrics are served at `/metrics`. They say a lot
+    /// about how the site is used, so only turn them on if that path can't be
+    /// reached from outside.
+    #[serde(default = "default_metrics")]
+    metrics: bool,
+    /// The user IDs of accounts that are made site administrators on startup.
+    #[serde(default)]
+    admins: Vec<String>,
+    /// Whether boards can change their handle.
+    #[serde(default = "default_board_renames")]
+    board_renames: bool,
+    /// How many days a handle released by a board is reserved for, during which
+    /// only that board can take it back.
+    #[serde(default = "default_board_handle_cooldown_days")]
+    board_handle_cooldown_days: u32,
+    /// Identifiers that can't be used as user IDs or board handles.
+    #[serde(default = "default_reserved_idents")]
+    reserved_idents: Vec<String>,
+    /// How many background jobs can run at once.
+    #[serde(default = "default_job_workers")]
+    job_workers: usize,
+    /// How deeply fields can be nested in a GraphQL query.
+    #[serde(default = "default_graphql_max_depth")]
+    graphql_max_depth: usize,
+    /// How costly a GraphQL query can be. Most fields cost one, and
+    /// connections cost the fields of one of their items times the size of
+    /// the page, as given by `first` or `last`.
+    #[serde(default = "default_graphql_max_complexity")]
+    graphql_max_complexity: usize,
+    /// How many GraphQL operations can be sent in one batch.
+    #[serde(default = "default_graphql_max_batch_size")]
+    graphql_max_batch_size: usize,
+    /// How large a GraphQL request body can be, in kilobytes.
+    #[serde(default = "default_graphql_max_body_kb")]
+    graphql_max_body_kb: usize,
+    /// How many automatic persisted queries are remembered, or zero to not
+    /// support them.
+    #[serde(default = "default_persisted_query_cache_size")]
+    persisted_query_cache_size: usize,
+    /// A persisted query manifest, as written by `plazer allowlist`. When set,
+    /// only the operations in it can be run.
+    persisted_query_allowlist: Option<String>,
+    /// The config files that were given, whether or not they exist.
+    #[serde(skip)]
+    config_paths: Vec<PathBuf>,
+    /// Where each value came from, in the order that they were resolved.
+    #[serde(skip)]
+    sources: Vec<(&'static str, ConfigSource)>,
+    /// The builder that this was built from, for resolving it again.
+    #[serde(skip)]
+    builder: ServiceConfigBuilder,
 }
 
-impl TryFrom<ServiceConfig> for (ServeConfig, LogConfig) {
-    type Error = anyhow::Error;
+/// Config values that are hidden when the config is shown.
+static SECRET_CONFIG_KEYS: &[&str] = &["db_password", "private_key"];
 
-    fn try_from(value: ServiceConfig) -> Result<Self, Self::Error> {
-        let private_key = if let Some(private_key) = value.private_key {
-            private_key
-        } else {
-            match fs::read_to_string(&value.private_key_path) {
-                Ok(private_key) => private_key,
-                Err(err) => {
-                    if let std::io::ErrorKind::NotFound = err.kind() {
-                        match value.private_key_create {
-                            Some(create) => create(value.private_key_path.as_ref())?,
-                            None => {
-                                return Err(anyhow::anyhow!(
-                                    "Private key not found at {}",
-                                    value.private_key_path
-                                ))
-                            }
-                        }
+/// Config values that take effect when the config is reloaded while the
+/// server is running. Others need a restart.
+pub static RELOADABLE_CONFIG_KEYS: &[&str] = &[
+    "private_key",
+    "private_key_path",
+    "log_level_stdout",
+    "log_level_file",
+    "board_renames",
+    "board_handle_cooldown_days",
+    "reserved_idents",
+    "tls_cert",
+    "tls_key",
+];
+
+/// A resolved config value, for showing to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigValue {
+    pub key: &'static str,
+    /// The value as it would be written in a config file, or `None` if it
+    /// isn't set. Secrets are hidden.
+    pub value: Option<String>,
+    pub source: ConfigSource,
+}
+
+fn default_db_auth_level() -> DbAuthLevel {
+    DEFAULT_DB_AUTH_LEVEL
+}
+
+fn default_db_scripting() -> bool {
+    DEFAULT_DB_SCRIPTING
+}
+
+fn default_db_guest_access() -> bool {
+    DEFAULT_DB_GUEST_ACCESS
+}
+
+fn default_db_allow() -> Vec<String> {
+    vec![DB_CAPABILITY_ALL.to_owned()]
+}
+
+fn default_log_rotation() -> LogRotation {
+    DEFAULT_LOG_ROTATION
+}
+
+fn default_log_max_size_mb() -> u64 {
+    DEFAULT_LOG_MAX_SIZE_MB
+}
+
+fn default_otlp_service_name() -> String {
+    DEFAULT_OTLP_SERVICE_NAME.to_owned()
+}
+
+fn default_metrics() -> bool {
+    DEFAULT_METRICS
+}
+
+fn default_board_renames() -> bool {
+    DEFAULT_BOARD_RENAMES
+}
+
+fn default_board_handle_cooldown_days() -> u32 {
+    DEFAULT_BOARD_HANDLE_COOLDOWN_DAYS
+}
+
+fn default_job_workers() -> usize {
+    DEFAULT_JOB_WORKERS
+}
+
+fn default_graphql_max_depth() -> usize {
+    DEFAULT_GRAPHQL_MAX_DEPTH
+}
+
+fn default_graphql_max_complexity() -> usize {
+    DEFAULT_GRAPHQL_MAX_COMPLEXITY
+}
+
+fn default_graphql_max_batch_size() -> usize {
+    DEFAULT_GRAPHQL_MAX_BATCH_SIZE
+}
+
+fn default_graphql_max_body_kb() -> usize {
+    DEFAULT_GRAPHQL_MAX_BODY_KB
+}
+
+fn default_persisted_query_cache_size() -> usize {
+    DEFAULT_PERSISTED_QUERY_CACHE_SIZE
+}
+
+fn default_reserved_idents() -> Vec<String> {
+    DEFAULT_RESERVED_IDENTS
+        .iter()
+        .map(|&ident| ident.to_owned())
+        .collect()
+}
+
+impl ServiceConfig {
+    /// The config files that were given, or none if the default was used.
+    #[must_use]
+    pub fn config_paths(&self) -> &[PathBuf] {
+        &self.config_paths
+    }
+
+    /// Lists the resolved values along with where they came from.
+    pub fn values(&self) -> anyhow::Result<Vec<ConfigValue>> {
+        let values = toml::Table::try_from(self)?;
+
+        let values = self
+            .sources
+            .iter()
+            .map(|(key, source)| ConfigValue {
+                key,
+                value: values.get(*key).map(|value| {
+                    if SECRET_CONFIG_KEYS.contains(key) {
+                        "<hidden>".to_owned()
                     } else {
-                        return Err(err).context("Unable to read private key");
+                        value.to_string()
                     }
+                }),
+                source: source.clone(),
+            })
+            .collect();
+        Ok(values)
+    }
+
+    /// Resolves the config again from the same builder, picking up changes to
+    /// the environment and config files.
+    pub fn reload(&self) -> anyhow::Result<ServiceConfig> {
+        self.builder.clone().build()
+    }
+
+    /// Lists the keys of the values that differ from `other`.
+    pub fn changed_keys(&self, other: &ServiceConfig) -> anyhow::Result<Vec<String>> {
+        let values = toml::Table::try_from(self)?;
+        let other_values = toml::Table::try_from(other)?;
+
+        let mut keys: Vec<_> = values
+            .keys()
+            .chain(other_values.keys())
+            .filter(|key| values.get(*key) != other_values.get(*key))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// Gets the private key that tokens are signed with, creating it if it is
+    /// configured to be created.
+    pub fn private_key_pem(&self) -> anyhow::Result<String> {
+        if let Some(private_key) = &self.private_key {
+            return Ok(private_key.clone());
+        }
+
+        match fs::read_to_string(&self.private_key_path) {
+            Ok(private_key) => Ok(private_key),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                match self.private_key_create {
+                    Some(create) => create(self.private_key_path.as_ref()),
+                    None => Err(anyhow::anyhow!(
+                        "Private key not found at {}",
+                        self.private_key_path
+                    )),
                 }
             }
+            Err(err) => Err(err).context("Unable to read private key"),
+        }
+    }
+
+    #[must_use]
+    pub fn settings(&self) -> ServiceSettings {
+        ServiceSettings {
+            board_renames: self.board_renames,
+            board_handle_cooldown_days: self.board_handle_cooldown_days,
+            reserved_idents: self.reserved_idents.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn query_limits(&self) -> QueryLimits {
+        QueryLimits {
+            max_depth: self.graphql_max_depth,
+            max_complexity: self.graphql_max_complexity,
+            max_batch_size: self.graphql_max_batch_size,
+            max_body_size: self.graphql_max_body_kb.saturating_mul(1024),
+        }
+    }
+
+    /// Gets the paths of the certificate and key to serve HTTPS with, if it
+    /// should be.
+    pub fn tls_config(&self) -> anyhow::Result<Option<TlsConfig>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some(TlsConfig {
+                cert: cert.clone(),
+                key: key.clone(),
+            })),
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("A TLS certificate and key must be given together"),
+        }
+    }
+
+    #[must_use]
+    pub fn log_config(&self) -> LogConfig {
+        LogConfig {
+            dir: self.log_dir.clone(),
+            level_stdout: self.log_level_stdout.into(),
+            level_file: self.log_level_file.into(),
+            rotation: self.log_rotation,
+            max_size: self.log_max_size_mb.saturating_mul(1024 * 1024),
+            max_files: self.log_max_files,
+            otlp: self.otlp_endpoint.as_ref().map(|endpoint| OtlpConfig {
+                endpoint: endpoint.clone(),
+                service_name: self.otlp_service_name.clone(),
+            }),
+        }
+    }
+
+    pub fn db_config(&self) -> anyhow::Result<DbConfig> {
+        let credentials = match (&self.db_username, &self.db_password) {
+            (Some(username), Some(password)) => Some(DbCredentials {
+                level: self.db_auth_level,
+                username: username.clone(),
+                password: password.clone().into(),
+            }),
+            (None, None) => None,
+            (Some(_), None) => anyhow::bail!("A database password is needed with the username"),
+            (None, Some(_)) => anyhow::bail!("A database username is needed with the password"),
         };
 
-        let (enc_key, dec_key) = create_key_pair(&private_key)?;
+        let tls = DbTlsConfig {
+            ca: self.db_tls_ca.clone(),
+            client_auth: match (&self.db_tls_cert, &self.db_tls_key) {
+                (Some(cert), Some(key)) => Some((cert.clone(), key.clone())),
+                (None, None) => None,
+                _ => anyhow::bail!("A TLS client certificate and key must be given together"),
+            },
+        };
+
+        let capabilities = Capabilities::default()
+            .with_scripting(self.db_scripting)
+            .with_guest_access(self.db_guest_access)
+            .with_functions(capability_targets(&self.db_allow_funcs)?)
+            .without_functions(capability_targets(&self.db_deny_funcs)?)
+            .with_network_targets(capability_targets(&self.db_allow_net)?)
+            .without_network_targets(capability_targets(&self.db_deny_net)?);
+
+        Ok(DbConfig {
+            address: self.address.clone(),
+            namespace: self.namespace.clone(),
+            database: self.database.clone(),
+            credentials,
+            tls,
+            capabilities,
+        })
+    }
+}
+
+/// Parses a list of capability targets, where `*` means all targets and an
+/// empty list means none.
+fn capability_targets<T>(targets: &[String]) -> anyhow::Result<Targets<T>>
+where
+    T: FromStr<Err = String> + std::hash::Hash + Eq + Target,
+{
+    if targets.iter().any(|target| target == DB_CAPABILITY_ALL) {
+        return Ok(Targets::All);
+    }
+    if targets.is_empty() {
+        return Ok(Targets::None);
+    }
+
+    let targets = targets
+        .iter()
+        .map(|target| {
+            target
+                .parse()
+                .map_err(|err| anyhow::anyhow!("Invalid database capability `{target}`: {err}"))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Targets::Some(targets))
+}
+
+impl TryFrom<ServiceConfig> for (ServeConfig, LogConfig) {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ServiceConfig) -> Result<Self, Self::Error> {
+        let db = value.db_config()?;
+        let (enc_key, dec_key) = create_key_pair(&value.private_key_pem()?)?;
+        let allowlist = value
+            .persisted_query_allowlist
+            .as_ref()
+            .map(|path| {
+                Allowlist::load(path.as_ref())
+                    .with_context(|| format!("Unable to load the allowlist at {path}"))
+            })
+            .transpose()?;
 
         let serve_config = ServeConfig {
-            address: value.address,
-            namespace: value.namespace,
-            database: value.database,
+            db,
             jwt_enc_key: enc_key,
             jwt_dec_key: dec_key,
             host: value.host.parse()?,
             port: value.port,
+            tls: value.tls_config()?,
+            cors_origins: value.cors_origins.clone(),
+            public_url: value.public_url.clone(),
+            metrics: value.metrics,
+            admins: value.admins.clone(),
+            job_workers: value.job_workers,
+            limits: value.query_limits(),
+            persisted_queries: PersistedQueryConfig {
+                cache_size: value.persisted_query_cache_size,
+                allowlist,
+            },
+            settings: value.settings(),
+            config: Some(value.clone()),
         };
 
-        let log_config = LogConfig {
-            dir: value.log_dir,
-            level_stdout: value.log_level_stdout.into(),
-            level_file: value.log_level_file.into(),
-        };
-
-        Ok((serve_config, log_config))
+        Ok((serve_config, value.log_config()))
     }
 }
 
 #[derive(Clone)]
 pub struct ServeConfig {
-    pub address: String,
-    pub namespace: String,
-    pub database: String,
+    pub db: DbConfig,
     pub jwt_enc_key: jsonwebtoken::EncodingKey,
     pub jwt_dec_key: jsonwebtoken::DecodingKey,
     pub host: IpAddr,
     pub port: u16,
+    /// The certificate and key to serve HTTPS with, if any.
+    pub tls: Option<TlsConfig>,
+    pub cors_origins: Vec<String>,
+    /// The URL that the site is served at, if it is known.
+    pub public_url: Option<String>,
+    /// Whether Prometheus metrics are served.
+    pub metrics: bool,
+    pub admins: Vec<String>,
+    pub job_workers: usize,
+    pub limits: QueryLimits,
+    pub persisted_queries: PersistedQueryConfig,
+    pub settings: ServiceSettings,
+    /// The config that this was made from, which is reloaded on `SIGHUP`.
+    pub config: Option<ServiceConfig>,
+}
+
+/// Settings that change how the service behaves while it is running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSettings {
+    /// Whether boards can change their handle.
+    pub board_renames: bool,
+    /// How many days a handle released by a board is reserved for.
+    pub board_handle_cooldown_days: u32,
+    /// Identifiers that can't be used as user IDs or board handles.
+    pub reserved_idents: Vec<String>,
+}
+
+impl Default for ServiceSettings {
+    fn default() -> Self {
+        Self {
+            board_renames: DEFAULT_BOARD_RENAMES,
+            board_handle_cooldown_days: DEFAULT_BOARD_HANDLE_COOLDOWN_DAYS,
+            reserved_idents: default_reserved_idents(),
+        }
+    }
+}
+
+/// Limits on the GraphQL requests that are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    pub max_depth: usize,
+    pub max_complexity: usize,
+    pub max_batch_size: usize,
+    /// The size in bytes that request bodies can be.
+    pub max_body_size: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_GRAPHQL_MAX_DEPTH,
+            max_complexity: DEFAULT_GRAPHQL_MAX_COMPLEXITY,
+            max_batch_size: DEFAULT_GRAPHQL_MAX_BATCH_SIZE,
+            max_body_size: DEFAULT_GRAPHQL_MAX_BODY_KB * 1024,
+        }
+    }
+}
+
+/// The PEM files that HTTPS is served with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// The certificate chain, starting with the server's own certificate.
+    pub cert: String,
+    pub key: String,
+}
+
+/// How persisted queries are handled.
+#[derive(Debug, Clone)]
+pub struct PersistedQueryConfig {
+    /// How many automatic persisted queries are remembered, or zero to not
+    /// support them.
+    pub cache_size: usize,
+    /// The only operations that can be run, if they are limited.
+    pub allowlist: Option<Allowlist>,
+}
+
+impl Default for PersistedQueryConfig {
+    fn default() -> Self {
+        Self {
+            cache_size: DEFAULT_PERSISTED_QUERY_CACHE_SIZE,
+            allowlist: None,
+        }
+    }
+}
+
+/// The information needed to connect to the database.
+#[derive(Debug, Clone)]
+pub struct DbConfig {
+    pub address: String,
+    pub namespace: String,
+    pub database: String,
+    /// The user to sign in as once connected, if any.
+    pub credentials: Option<DbCredentials>,
+    pub tls: DbTlsConfig,
+    /// What queries are allowed to do. Only used by local databases.
+    pub capabilities: Capabilities,
+}
+
+impl DbConfig {
+    /// A config for a database that doesn't need signing into, with all
+    /// capabilities allowed.
+    pub fn new(
+        address: impl Into<String>,
+        namespace: impl Into<String>,
+        database: impl Into<String>,
+    ) -> Self {
+        Self {
+            address: address.into(),
+            namespace: namespace.into(),
+            database: database.into(),
+            credentials: None,
+            tls: DbTlsConfig::default(),
+            capabilities: Capabilities::all(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DbCredentials {
+    pub level: DbAuthLevel,
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// Options for connecting to a remote database over TLS. When none are set,
+/// the usual web certificate authorities are trusted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DbTlsConfig {
+    /// The path to a PEM file with extra certificate authorities to trust.
+    pub ca: Option<String>,
+    /// The paths to the PEM files with the client certificate and its private
+    /// key, if the database asks for one.
+    pub client_auth: Option<(String, String)>,
+}
+
+impl DbTlsConfig {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ca.is_none() && self.client_auth.is_none()
+    }
 }
 
 #[derive(Clone)]
@@ -463,6 +2036,19 @@ pub struct LogConfig {
     pub dir: String,
     pub level_stdout: Level,
     pub level_file: Level,
+    pub rotation: LogRotation,
+    /// The size in bytes that log files are rotated at, when rotating by size.
+    pub max_size: u64,
+    /// How many log files are kept, if not all of them.
+    pub max_files: Option<usize>,
+    /// Where to export traces to, if anywhere.
+    pub otlp: Option<OtlpConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub service_name: String,
 }
 
 pub fn create_key_pair(
@@ -477,3 +2063,159 @@ pub fn create_key_pair(
 
     Ok((enc_key, dec_key))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("plazer-config-{name}-{}", ulid::Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn build(paths: &[PathBuf]) -> anyhow::Result<ServiceConfig> {
+        ServiceConfigBuilder::new()
+            .config_paths(paths.iter().map(|path| path.display().to_string()))
+            .build()
+    }
+
+    fn source<'a>(config: &'a ServiceConfig, key: &str) -> &'a ConfigSource {
+        &config.sources.iter().find(|(k, _)| *k == key).unwrap().1
+    }
+
+    #[test]
+    fn test_layers() {
+        let dir = config_dir(
+            "layers",
+            &[
+                ("base.toml", "port = 1000\nhost = \"127.0.0.1\"\n"),
+                ("conf.d/20-b.toml", "port = 3000\n"),
+                ("conf.d/10-a.toml", "port = 2000\njob_workers = 4\n"),
+                ("conf.d/ignored.txt", "port = 4000\n"),
+            ],
+        );
+
+        let config = build(&[dir.join("base.toml"), dir.join("conf.d")]).unwrap();
+        assert_eq!(config.port, 3000);
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.job_workers, 4);
+        assert_eq!(config.namespace, DEFAULT_NAMESPACE);
+
+        assert_eq!(
+            source(&config, "port"),
+            &ConfigSource::File(dir.join("conf.d/20-b.toml"))
+        );
+        assert_eq!(
+            source(&config, "host"),
+            &ConfigSource::File(dir.join("base.toml"))
+        );
+        assert_eq!(source(&config, "namespace"), &ConfigSource::Default);
+    }
+
+    #[test]
+    fn test_argument_overrides_files() {
+        let dir = config_dir("argument", &[("base.toml", "port = 1000\n")]);
+
+        let config = ServiceConfigBuilder::new()
+            .config_paths([dir.join("base.toml").display().to_string()])
+            .port(2000u16)
+            .build()
+            .unwrap();
+        assert_eq!(config.port, 2000);
+        assert_eq!(source(&config, "port"), &ConfigSource::Argument);
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let dir = config_dir("unknown", &[("base.toml", "prot = 1000\n")]);
+
+        let res = build(&[dir.join("base.toml")]);
+        let err = format!("{:#}", res.unwrap_err());
+        println!("{err}");
+        assert!(err.contains("unknown field `prot`"));
+    }
+
+    #[test]
+    fn test_log_rotation() {
+        let dir = config_dir(
+            "log-rotation",
+            &[(
+                "base.toml",
+                "log_rotation = \"size\"\nlog_max_size_mb = 5\nlog_max_files = 3\n",
+            )],
+        );
+
+        let log_config = build(&[dir.join("base.toml")]).unwrap().log_config();
+        assert_eq!(log_config.rotation, LogRotation::Size);
+        assert_eq!(log_config.max_size, 5 * 1024 * 1024);
+        assert_eq!(log_config.max_files, Some(3));
+
+        let log_config = build(&[]).unwrap().log_config();
+        assert_eq!(log_config.rotation, DEFAULT_LOG_ROTATION);
+        assert_eq!(log_config.max_files, None);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let dir = config_dir("missing", &[]);
+
+        assert!(build(&[dir.join("missing.toml")]).is_err());
+    }
+
+    #[test]
+    fn test_values_hide_secrets() {
+        let dir = config_dir(
+            "secrets",
+            &[(
+                "base.toml",
+                "db_username = \"plazer\"\ndb_password = \"secret\"\n",
+            )],
+        );
+
+        let values = build(&[dir.join("base.toml")]).unwrap().values().unwrap();
+        let value = |key| {
+            values
+                .iter()
+                .find(|value| value.key == key)
+                .unwrap()
+                .value
+                .clone()
+        };
+        assert_eq!(value("db_username"), Some("\"plazer\"".into()));
+        assert_eq!(value("db_password"), Some("<hidden>".into()));
+        assert_eq!(value("private_key"), None);
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = config_dir(
+            "reload",
+            &[("base.toml", "port = 1000\nboard_renames = true\n")],
+        );
+        let config = ServiceConfigBuilder::new()
+            .config_paths([dir.join("base.toml").display().to_string()])
+            .host("127.0.0.1")
+            .build()
+            .unwrap();
+
+        fs::write(
+            dir.join("base.toml"),
+            "port = 2000\nboard_renames = false\n",
+        )
+        .unwrap();
+        let reloaded = config.reload().unwrap();
+        assert_eq!(reloaded.port, 2000);
+        assert!(!reloaded.board_renames);
+        assert_eq!(reloaded.host, "127.0.0.1");
+        assert_eq!(
+            config.changed_keys(&reloaded).unwrap(),
+            vec!["board_renames".to_owned(), "port".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,9 @@
+mod models;
+mod persist;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static DATA_EXPORT_TABLE_NAME: &str = "data_export";
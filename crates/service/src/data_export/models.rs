@@ -0,0 +1,97 @@
+use async_graphql::{ComplexObject, SimpleObject, ID};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use surrealdb::sql::Thing;
+
+use super::DATA_EXPORT_TABLE_NAME;
+use crate::prelude::*;
+
+/// Identifies a file as a personal data export.
+pub static DATA_EXPORT_FORMAT: &str = "plazer-personal-data";
+/// The version of the personal data export format.
+pub const DATA_EXPORT_VERSION: u32 = 1;
+/// How long a data export can be downloaded for after it is created.
+pub const DATA_EXPORT_EXPIRY_HOURS: i64 = 24;
+
+/// A copy of the data tied to an account, which can be downloaded by the
+/// account until it expires.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct DataExport {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    /// When the export was created.
+    pub created_at: DateTime<Utc>,
+    /// When the export stops being available to download.
+    pub expires_at: DateTime<Utc>,
+    #[graphql(skip)]
+    pub content: String,
+}
+
+#[ComplexObject]
+impl DataExport {
+    /// The ID of the export.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The path to download the export from. Requests must be authenticated
+    /// as the account that the export is for.
+    async fn download_path(&self) -> String {
+        format!("/api/exports/{}", self.id.id.to_raw())
+    }
+}
+
+impl DataExport {
+    pub fn create(account_id: Thing, content: String, now: DateTime<Utc>) -> srql::CreateStatement {
+        let mut create = vec![];
+        account_id.push_field(srql::field("account_id"), &mut create);
+        now.push_field(srql::field("created_at"), &mut create);
+        (now + Duration::hours(DATA_EXPORT_EXPIRY_HOURS))
+            .push_field(srql::field("expires_at"), &mut create);
+        content.push_field(srql::field("content"), &mut create);
+        srql::obj_create_query(DATA_EXPORT_TABLE_NAME, create)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    /// The name that the export is downloaded as.
+    pub fn file_name(&self) -> String {
+        format!("plazer-data-{}.json", self.id.id.to_raw())
+    }
+}
+
+/// Everything tied to an account, as it is given to the account when it asks
+/// for its data.
+///
+/// Records are given as they are stored, with record IDs written as
+/// `table:id` strings, except that secrets such as password hashes are left
+/// out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonalData {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    /// The account itself. Tokens aren't stored, so the only record of the
+    /// account's sessions is when they were last revoked, which is kept here.
+    pub account: Json,
+    /// The boards that the account created.
+    pub boards: Vec<Json>,
+    /// The posts that the account created.
+    pub posts: Vec<Json>,
+    /// The boards that the account is a member of, by ID.
+    pub member_of: Vec<Json>,
+    /// The boards that the account moderates, by ID.
+    pub moderator_of: Vec<Json>,
+    /// The reports that the account filed.
+    pub reports: Vec<Json>,
+    /// The bans from boards that the account has been given.
+    pub board_bans: Vec<Json>,
+    /// The moderation log entries about the account, such as its bans.
+    pub moderation_log: Vec<Json>,
+}
@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests;
+
+use chrono::Utc;
+use serde_json::Value as Json;
+use tracing::instrument;
+
+use super::{
+    DataExport, PersonalData, DATA_EXPORT_FORMAT, DATA_EXPORT_TABLE_NAME, DATA_EXPORT_VERSION,
+};
+use crate::{
+    account::{CurrentAccount, ToAccountThing as _},
+    archive::PWORD_FIELDS,
+    board::BOARD_TABLE_NAME,
+    moderation::{BAN_TABLE_NAME, LOG_TABLE_NAME, REPORT_TABLE_NAME},
+    persist::Persist,
+    post::POST_TABLE_NAME,
+    prelude::*,
+    query::SRQL_ORDER_ASC,
+};
+
+pub struct DataExportPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> DataExportPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gathers everything tied to the current account into an export that it
+    /// can download.
+    #[instrument(skip_all)]
+    pub async fn create(&self) -> Result<DataExport> {
+        let account_id = self.current.id()?.to_account_thing();
+
+        let mut account = match self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::thing(account_id.clone()),
+                ..Default::default()
+            })
+            .await?
+            .take::<srql::Value>(0)?
+        {
+            srql::Value::Array(records) => records.0.into_iter().next(),
+            _ => None,
+        }
+        .ok_or(Error::Unauthenticated)?
+        .into_json();
+        if let Json::Object(fields) = &mut account {
+            for field in PWORD_FIELDS {
+                fields.remove(*field);
+            }
+        }
+
+        let now = Utc::now();
+        let data = PersonalData {
+            format: DATA_EXPORT_FORMAT.into(),
+            version: DATA_EXPORT_VERSION,
+            exported_at: now,
+            account,
+            boards: self.created_by(BOARD_TABLE_NAME, &account_id).await?,
+            posts: self.created_by(POST_TABLE_NAME, &account_id).await?,
+            member_of: self.board_ids(&account_id, "member_ids").await?,
+            moderator_of: self.board_ids(&account_id, "moderator_ids").await?,
+            reports: self
+                .records_where(REPORT_TABLE_NAME, "reporter_id", &account_id)
+                .await?,
+            board_bans: self
+                .records_where(BAN_TABLE_NAME, "account_id", &account_id)
+                .await?,
+            moderation_log: self
+                .records_where(LOG_TABLE_NAME, "account_id", &account_id)
+                .await?,
+        };
+        let content = serde_json::to_string_pretty(&data)
+            .map_err(|err| Error::InternalServerError(err.to_string()))?;
+
+        let export: Option<DataExport> = self
+            .persist
+            .db()
+            .query(DataExport::create(account_id, content, now))
+            .await?
+            .take(0)?;
+        export.ok_or_else(|| "Failed to create data export".into())
+    }
+
+    /// Gets an export of the current account's data, if it hasn't expired.
+    /// Exports of other accounts' data are never returned.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<DataExport>> {
+        let account_id = self.current.id()?.to_account_thing();
+
+        let export: Option<DataExport> = self
+            .persist
+            .db()
+            .select((DATA_EXPORT_TABLE_NAME, id))
+            .await?;
+        Ok(export
+            .filter(|export| export.account_id == account_id && !export.is_expired(Utc::now())))
+    }
+
+    /// Reads the records in `table` that the current account created, in ID
+    /// order.
+    async fn created_by(&self, table: &str, account_id: &srql::Thing) -> Result<Vec<Json>> {
+        self.records_where(table, "creator_id", account_id).await
+    }
+
+    /// Reads the IDs of the boards that list the current account in `list`.
+    async fn board_ids(&self, account_id: &srql::Thing, list: &str) -> Result<Vec<Json>> {
+        Ok(self
+            .select(
+                BOARD_TABLE_NAME,
+                srql::expr(
+                    srql::field(list),
+                    srql::Operator::Contain,
+                    srql::Value::Thing(account_id.clone()),
+                ),
+            )
+            .await?
+            .into_iter()
+            .filter_map(|mut board| board.get_mut("id").map(Json::take))
+            .collect())
+    }
+
+    /// Reads the records in `table` whose `field` is the current account, in
+    /// ID order.
+    async fn records_where(
+        &self,
+        table: &str,
+        field: &str,
+        account_id: &srql::Thing,
+    ) -> Result<Vec<Json>> {
+        self.select(
+            table,
+            srql::expr(
+                srql::field(field),
+                srql::Operator::Equal,
+                srql::Value::Thing(account_id.clone()),
+            ),
+        )
+        .await
+    }
+
+    async fn select(&self, table: &str, cond: srql::Value) -> Result<Vec<Json>> {
+        let records = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(table),
+                cond: srql::Cond(cond).into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_ASC,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take::<srql::Value>(0)?;
+
+        match records {
+            srql::Value::Array(records) => {
+                Ok(records.0.into_iter().map(srql::Value::into_json).collect())
+            }
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+/// Removes data exports that have expired, returning how many were removed.
+#[instrument(skip_all)]
+pub async fn purge_expired_data_exports(persist: &Persist) -> Result<usize> {
+    let purged: Vec<DataExport> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(DATA_EXPORT_TABLE_NAME),
+            cond: srql::Cond(srql::expr(
+                srql::field("expires_at"),
+                srql::Operator::LessThanOrEqual,
+                srql::time_now(),
+            ))
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    Ok(purged.len())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::DataExportPersist;
+
+    pub trait DataExportTestData {
+        fn data_export(&self) -> DataExportPersist<'_>;
+    }
+
+    impl DataExportTestData for TestData {
+        fn data_export(&self) -> DataExportPersist<'_> {
+            DataExportPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::DataExportTestData as _, *};
+use crate::{
+    account::testing::*,
+    board::testing::BoardTestData as _,
+    moderation::{testing::ModerationTestData as _, CreateReport, ModerationPersist, ReportTarget},
+    post::testing::PostTestData as _,
+};
+
+fn personal_data(export: &DataExport) -> PersonalData {
+    serde_json::from_str(&export.content).unwrap()
+}
+
+#[tokio::test]
+async fn test_create() {
+    let (data, acc) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let post = data.generate_post_in(&board.id).await;
+
+    let res = data.data_export().create().await;
+    println!("{res:?}");
+    let export = res.unwrap();
+    assert_eq!(export.account_id, acc.id);
+    assert!(!export.is_expired(Utc::now()));
+
+    let res = personal_data(&export);
+    assert_eq!(res.format, DATA_EXPORT_FORMAT);
+    assert_eq!(res.account["id"], acc.id.to_string());
+    assert_eq!(res.account["user_id"], acc.user_id);
+    assert_eq!(res.boards.len(), 1);
+    assert_eq!(res.boards[0]["id"], board.id.to_string());
+    assert_eq!(res.posts.len(), 1);
+    assert_eq!(res.posts[0]["id"], post.id.to_string());
+}
+
+#[tokio::test]
+async fn test_create_no_secrets() {
+    let (data, _) = TestData::with_user().await;
+
+    let export = data.data_export().create().await.unwrap();
+    for field in PWORD_FIELDS {
+        assert!(!export.content.contains(field), "{field}");
+    }
+}
+
+#[tokio::test]
+async fn test_create_only_own() {
+    let (data, _) = TestData::with_user().await;
+    data.generate_board().await;
+    data.generate_post().await;
+
+    let other = data.account().create_test_user().await;
+    let current = other.current();
+    let export = DataExportPersist::new(&data.persist, &current)
+        .create()
+        .await
+        .unwrap();
+
+    let res = personal_data(&export);
+    assert_eq!(res.account["id"], other.id.to_string());
+    assert!(res.boards.is_empty());
+    assert!(res.posts.is_empty());
+    assert!(res.member_of.is_empty());
+    assert!(res.moderator_of.is_empty());
+}
+
+#[tokio::test]
+async fn test_create_moderation() {
+    let (data, _) = TestData::with_user().await;
+    let boards = data.generate_boards(2).await;
+    let post = data.generate_post_in(&boards[0].id).await;
+
+    let other = data.account().create_test_user().await;
+    data.board()
+        .add_member(&boards[0].id.to_gql_id(), &other.id.to_gql_id())
+        .await
+        .unwrap();
+    data.board()
+        .add_moderator(&boards[1].id.to_gql_id(), &other.id.to_gql_id())
+        .await
+        .unwrap();
+
+    let current = other.current();
+    let report = ModerationPersist::new(&data.persist, &current)
+        .report(CreateReport {
+            target: ReportTarget::Post(post.id.to_gql_id()),
+            reason: "Test".into(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    data.moderation()
+        .ban(
+            &boards[0].id.to_gql_id(),
+            &other.id.to_gql_id(),
+            Some("Spam".into()),
+            None,
+        )
+        .await
+        .unwrap();
+
+    let export = DataExportPersist::new(&data.persist, &current)
+        .create()
+        .await
+        .unwrap();
+    let res = personal_data(&export);
+    assert_eq!(res.member_of, vec![boards[0].id.to_string()]);
+    assert_eq!(res.moderator_of, vec![boards[1].id.to_string()]);
+    assert_eq!(res.reports.len(), 1);
+    assert_eq!(res.reports[0]["id"], report.id.to_string());
+    assert_eq!(res.board_bans.len(), 1);
+    assert_eq!(res.board_bans[0]["reason"], "Spam");
+    assert_eq!(res.moderation_log.len(), 1);
+    assert_eq!(res.moderation_log[0]["account_id"], other.id.to_string());
+
+    // None of it is in the exports of other accounts.
+    let res = personal_data(&data.data_export().create().await.unwrap());
+    assert!(res.member_of.is_empty());
+    assert!(res.reports.is_empty());
+    assert!(res.board_bans.is_empty());
+    assert!(res.moderation_log.is_empty());
+}
+
+#[tokio::test]
+async fn test_create_unauthenticated() {
+    let data = TestData::new().await;
+
+    let res = data.data_export().create().await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_get() {
+    let (data, _) = TestData::with_user().await;
+    let export = data.data_export().create().await.unwrap();
+
+    let res = data.data_export().get(&export.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().map(|res| res.id), Some(export.id));
+}
+
+#[tokio::test]
+async fn test_get_other_account() {
+    let (data, _) = TestData::with_user().await;
+    let export = data.data_export().create().await.unwrap();
+
+    let other = data.account().create_test_user().await;
+    let current = other.current();
+    let res = DataExportPersist::new(&data.persist, &current)
+        .get(&export.id.id.to_raw())
+        .await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_expired() {
+    let (data, _) = TestData::with_user().await;
+    let expired = data.data_export().create().await.unwrap();
+    let export = data.data_export().create().await.unwrap();
+
+    data.persist
+        .db()
+        .query(format!(
+            "UPDATE {} SET expires_at = time::now() - 1m",
+            expired.id
+        ))
+        .await
+        .unwrap();
+
+    let res = data.data_export().get(&expired.id.id.to_raw()).await;
+    assert!(res.unwrap().is_none());
+
+    let res = purge_expired_data_exports(&data.persist).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap(), 1);
+
+    let res = data.data_export().get(&export.id.id.to_raw()).await;
+    assert!(res.unwrap().is_some());
+}
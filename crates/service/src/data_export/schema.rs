@@ -0,0 +1,21 @@
+use async_graphql::{Context, Object};
+use tracing::instrument;
+
+use super::DataExport;
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct DataExportMutation;
+
+#[Object]
+impl DataExportMutation {
+    /// Gather everything tied to the current account into a JSON file, which
+    /// can be downloaded from the export's `downloadPath` while authenticated
+    /// as the current account until it expires.
+    ///
+    /// Passwords are never included.
+    #[instrument(skip_all)]
+    async fn export_my_data(&self, ctx: &Context<'_>) -> GqlResult<DataExport> {
+        ctx.data_export_persist().create().await.extend()
+    }
+}
@@ -1,5 +1,5 @@
-use async_graphql::ErrorExtensions;
 pub use async_graphql::{Error as GqlError, Result as GqlResult};
+use async_graphql::{ErrorExtensions, ServerError};
 use axum::Json;
 use base64::DecodeError as Base64DecodeError;
 use hyper::StatusCode;
@@ -11,6 +11,8 @@ use thiserror::Error;
 use tracing::error;
 use typeshare::typeshare;
 
+use crate::metrics::metrics;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[typeshare]
@@ -23,16 +25,53 @@ pub enum Error {
     Unauthorized,
     #[error("Credentials are invalid")]
     CredentialsInvalid,
+    #[error("Account has been suspended")]
+    AccountSuspended,
+    #[error("Account has been disabled")]
+    AccountDisabled,
+    #[error("Account is banned from this board")]
+    BannedFromBoard,
+    #[error("Report has already been closed")]
+    ReportClosed,
+    #[error("This action can't be taken against the reported content")]
+    InvalidReportAction,
+    #[error("Post has been locked")]
+    PostLocked,
+    #[error("Board handles cannot be changed on this server")]
+    BoardRenameDisabled,
 
     #[error("This identifier is already in use")]
     UnavailableIdent,
+    #[error("This identifier is reserved")]
+    ReservedIdent,
+    #[error("Identifiers can only contain letters, numbers, `-` and `_`")]
+    InvalidIdent,
     #[error("Missing identifier")]
     MissingIdent,
+    #[error("Not found")]
+    NotFound,
     #[error("Pagination arguments are invalid: {0}")]
     PaginationInvalid(String),
 
     #[error("JSON is malformed: {0}")]
     ParseError(String),
+    #[error("Query is nested too deeply")]
+    QueryTooDeep,
+    #[error("Query is too complex")]
+    QueryTooComplex,
+    #[error("Too many operations were sent in one batch")]
+    BatchTooLarge,
+    #[error("Request body is too large")]
+    RequestTooLarge,
+    // Apollo clients look for these exact messages.
+    #[error("PersistedQueryNotFound")]
+    PersistedQueryNotFound,
+    #[error("PersistedQueryNotSupported")]
+    PersistedQueryNotSupported,
+    #[error("Persisted query is invalid: {0}")]
+    PersistedQueryInvalid(String),
+    #[error("Operation is not in the allowlist")]
+    OperationNotAllowed,
 
     #[error("JWT is malformed")]
     JwtMalformed,
@@ -40,6 +79,8 @@ pub enum Error {
     JwtExpired,
     #[error("JWT is invalid")]
     JwtInvalid,
+    #[error("JWT has been revoked")]
+    JwtRevoked,
 
     #[error("GraphQL WebSocket init must be an object, null, or undefined")]
     WsInitNotObject,
@@ -52,6 +93,8 @@ pub enum Error {
     InternalServerError(String),
     #[error("Feature is not implemented yet")]
     NotImplemented,
+    #[error("The server is starting up")]
+    NotReady,
 }
 
 impl Error {
@@ -66,6 +109,14 @@ impl Error {
         self.variant_name().into()
     }
 
+    /// Converts this into an error for a whole request, rather than one field.
+    pub fn to_server_error(&self) -> ServerError {
+        let err = self.extend();
+        let mut server_err = ServerError::new(err.message, None);
+        server_err.extensions = err.extensions;
+        server_err
+    }
+
     fn log(&self) {
         match self {
             Self::ServerMisconfigured(err) => error!("Server misconfigured: {}", err),
@@ -99,6 +150,7 @@ impl ErrorExtensions for Error {
         // Since this is the end for our errors before they are sent to the client,
         // we should log important ones here.
         self.log();
+        metrics().record_error(&self.code());
 
         GqlError::new(self.to_string()).extend_with(|_, e| {
             e.set("code", self.code());
@@ -171,18 +223,38 @@ impl Error {
             Error::Unauthenticated
             | Error::CredentialsInvalid
             | Error::JwtExpired
-            | Error::JwtInvalid => StatusCode::UNAUTHORIZED,
-            Error::Unauthorized => StatusCode::FORBIDDEN,
-            Error::UnavailableIdent => StatusCode::CONFLICT,
+            | Error::JwtInvalid
+            | Error::JwtRevoked => StatusCode::UNAUTHORIZED,
+            Error::Unauthorized
+            | Error::AccountSuspended
+            | Error::AccountDisabled
+            | Error::BannedFromBoard
+            | Error::BoardRenameDisabled
+            | Error::OperationNotAllowed => StatusCode::FORBIDDEN,
+            Error::UnavailableIdent
+            | Error::ReservedIdent
+            | Error::ReportClosed
+            | Error::PostLocked => StatusCode::CONFLICT,
             Error::MissingIdent
+            | Error::InvalidIdent
             | Error::JwtMalformed
             | Error::PaginationInvalid(_)
             | Error::ParseError(_)
+            | Error::QueryTooDeep
+            | Error::QueryTooComplex
+            | Error::BatchTooLarge
+            | Error::PersistedQueryNotFound
+            | Error::PersistedQueryNotSupported
+            | Error::PersistedQueryInvalid(_)
+            | Error::InvalidReportAction
             | Error::WsInitNotObject
             | Error::WsInitTokenNotString => StatusCode::BAD_REQUEST,
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::RequestTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
             Error::ServerMisconfigured(_)
             | Error::InternalServerError(_)
             | Error::NotImplemented => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::NotReady => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 }
@@ -192,6 +264,7 @@ impl From<Error> for ErrorResponse {
         // This is the last point before the error is sent back to the client, so
         // log the important ones here.
         err.log();
+        metrics().record_error(&err.code());
 
         let code = err.as_status_code();
         let data = ErrorData {
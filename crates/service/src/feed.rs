@@ -0,0 +1,648 @@
+use std::{cmp, fmt::Write as _, sync::Arc, time::SystemTime};
+
+use axum::{
+    extract::{Path, State},
+    headers::{ETag, HeaderMapExt as _, IfModifiedSince, IfNoneMatch, LastModified},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse as _, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use tracing::instrument;
+
+use crate::{
+    account::CurrentAccount,
+    board::{Board, BoardPersist},
+    error::ErrorResponse,
+    persist::Persist,
+    persisted_query::sha256_hex,
+    post::{Post, PostPersist},
+    prelude::*,
+    query::PaginationArgs,
+    ServiceState,
+};
+
+/// How many posts or boards are in a feed.
+const FEED_SIZE: i32 = 20;
+/// How long the title of a post without one can be, when it is taken from
+/// the post's content.
+const MAX_TITLE_LENGTH: usize = 80;
+/// The name of the site-wide feed of new boards.
+const BOARDS_FEED: &str = "boards";
+
+/// Atom and RSS feeds, for following boards from feed readers. Feed readers
+/// don't sign in, so feeds only have what anyone can read.
+pub fn routes() -> Router<ServiceState> {
+    Router::new()
+        .route("/feeds/b/:file", get(board_feed_handler))
+        .route("/feeds/:file", get(boards_feed_handler))
+}
+
+/// The URL that links in feeds start with.
+#[derive(Debug, Clone)]
+pub struct SiteUrl {
+    public_url: Option<Arc<str>>,
+    scheme: &'static str,
+}
+
+impl SiteUrl {
+    #[must_use]
+    pub fn new(public_url: Option<&str>, tls: bool) -> Self {
+        Self {
+            public_url: public_url.map(|url| url.trim_end_matches('/').into()),
+            scheme: if tls { "https" } else { "http" },
+        }
+    }
+
+    /// The configured URL, or else the one that the request was made to.
+    fn resolve(&self, headers: &HeaderMap) -> String {
+        if let Some(url) = &self.public_url {
+            return url.to_string();
+        }
+        let host = headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .unwrap_or("localhost");
+        format!("{}://{host}", self.scheme)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+impl FeedFormat {
+    /// Splits a file name such as `name.atom` into its name and format.
+    fn parse(file: &str) -> Option<(&str, Self)> {
+        let (name, extension) = file.rsplit_once('.')?;
+        let format = match extension {
+            "atom" => Self::Atom,
+            "rss" => Self::Rss,
+            _ => return None,
+        };
+        Some((name, format))
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Atom => "application/atom+xml; charset=utf-8",
+            Self::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Feed {
+    /// The page that the feed follows.
+    link: String,
+    /// The feed's own URL, without its extension.
+    self_link: String,
+    title: String,
+    description: Option<String>,
+    /// When the feed or any of its entries last changed.
+    updated: DateTime<Utc>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    /// A URL that identifies the entry permanently.
+    id: String,
+    link: String,
+    title: String,
+    content: Option<String>,
+    published: DateTime<Utc>,
+    updated: DateTime<Utc>,
+}
+
+impl Entry {
+    fn post(post: Post, base: &str, board_link: &str) -> Self {
+        let id = post.id.to_gql_id().0;
+        let published = post
+            .publish_at
+            .or_else(|| srql::ulid_time(&id))
+            .unwrap_or_default();
+        let title = post
+            .title
+            .filter(|title| !title.trim().is_empty())
+            .or_else(|| post.content.as_deref().and_then(title_from_content))
+            .unwrap_or_else(|| "Untitled".to_owned());
+        Self {
+            id: format!("{base}/api/v1/posts/{id}"),
+            link: board_link.to_owned(),
+            title,
+            content: post.content,
+            published,
+            // Posts that were edited before they were published haven't
+            // changed since.
+            updated: post
+                .updated_at
+                .map_or(published, |updated| cmp::max(updated, published)),
+        }
+    }
+
+    fn board(board: Board, base: &str) -> Self {
+        let link = format!("{base}/b/{}", board.handle);
+        Self {
+            id: link.clone(),
+            link,
+            title: board.name.unwrap_or(board.handle),
+            content: board.description,
+            published: srql::ulid_time(&board.id.to_gql_id()).unwrap_or(board.updated_at),
+            updated: board.updated_at,
+        }
+    }
+}
+
+/// The first line of `content`, shortened if it is long.
+fn title_from_content(content: &str) -> Option<String> {
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?;
+    if line.chars().count() <= MAX_TITLE_LENGTH {
+        return Some(line.to_owned());
+    }
+    let mut title = line
+        .chars()
+        .take(MAX_TITLE_LENGTH - 1)
+        .collect::<String>()
+        .trim_end()
+        .to_owned();
+    title.push('…');
+    Some(title)
+}
+
+impl Feed {
+    fn atom(&self) -> String {
+        let mut xml = String::new();
+        let _ = write!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+             \x20 <id>{link}</id>\n\
+             \x20 <title>{title}</title>\n",
+            link = escape(&self.link),
+            title = escape(&self.title),
+        );
+        if let Some(description) = &self.description {
+            let _ = writeln!(xml, "  <subtitle>{}</subtitle>", escape(description));
+        }
+        let _ = write!(
+            xml,
+            "  <updated>{updated}</updated>\n\
+             \x20 <author><name>{title}</name></author>\n\
+             \x20 <link rel=\"self\" type=\"application/atom+xml\" href=\"{self_link}.atom\"/>\n\
+             \x20 <link rel=\"alternate\" type=\"text/html\" href=\"{link}\"/>\n",
+            updated = rfc3339(self.updated),
+            title = escape(&self.title),
+            self_link = escape(&self.self_link),
+            link = escape(&self.link),
+        );
+        for entry in &self.entries {
+            let _ = write!(
+                xml,
+                "  <entry>\n\
+                 \x20   <id>{id}</id>\n\
+                 \x20   <title>{title}</title>\n\
+                 \x20   <published>{published}</published>\n\
+                 \x20   <updated>{updated}</updated>\n\
+                 \x20   <link rel=\"alternate\" type=\"text/html\" href=\"{link}\"/>\n",
+                id = escape(&entry.id),
+                title = escape(&entry.title),
+                published = rfc3339(entry.published),
+                updated = rfc3339(entry.updated),
+                link = escape(&entry.link),
+            );
+            if let Some(content) = &entry.content {
+                let _ = writeln!(
+                    xml,
+                    "    <content type=\"text\">{}</content>",
+                    escape(content)
+                );
+            }
+            xml.push_str("  </entry>\n");
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    fn rss(&self) -> String {
+        let mut xml = String::new();
+        let _ = write!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n\
+             <channel>\n\
+             \x20 <title>{title}</title>\n\
+             \x20 <link>{link}</link>\n\
+             \x20 <description>{description}</description>\n\
+             \x20 <lastBuildDate>{updated}</lastBuildDate>\n\
+             \x20 <atom:link rel=\"self\" type=\"application/rss+xml\" href=\"{self_link}.rss\"/>\n",
+            title = escape(&self.title),
+            link = escape(&self.link),
+            // Channels must have a description, even if it's empty.
+            description = escape(self.description.as_deref().unwrap_or_default()),
+            updated = self.updated.to_rfc2822(),
+            self_link = escape(&self.self_link),
+        );
+        for entry in &self.entries {
+            let _ = write!(
+                xml,
+                "  <item>\n\
+                 \x20   <guid isPermaLink=\"false\">{id}</guid>\n\
+                 \x20   <title>{title}</title>\n\
+                 \x20   <link>{link}</link>\n\
+                 \x20   <pubDate>{published}</pubDate>\n",
+                id = escape(&entry.id),
+                title = escape(&entry.title),
+                link = escape(&entry.link),
+                published = entry.published.to_rfc2822(),
+            );
+            if let Some(content) = &entry.content {
+                let _ = writeln!(xml, "    <description>{}</description>", escape(content));
+            }
+            xml.push_str("  </item>\n");
+        }
+        xml.push_str("</channel>\n</rss>\n");
+        xml
+    }
+
+    /// Responds with the feed, or with 304 Not Modified if the client already
+    /// has this version of it.
+    fn respond(&self, headers: &HeaderMap, format: FeedFormat) -> Response {
+        let body = match format {
+            FeedFormat::Atom => self.atom(),
+            FeedFormat::Rss => self.rss(),
+        };
+        let etag = format!("\"{}\"", sha256_hex(&body))
+            .parse::<ETag>()
+            .expect("hex digests are valid entity tags");
+        let last_modified = SystemTime::from(self.updated);
+
+        // If-None-Match is more precise, so it's used over If-Modified-Since
+        // when both are sent.
+        let modified = match headers.typed_get::<IfNoneMatch>() {
+            Some(if_none_match) => if_none_match.precondition_passes(&etag),
+            None => headers
+                .typed_get::<IfModifiedSince>()
+                .is_none_or(|since| since.is_modified(last_modified)),
+        };
+
+        let mut res = if modified {
+            ([(header::CONTENT_TYPE, format.content_type())], body).into_response()
+        } else {
+            StatusCode::NOT_MODIFIED.into_response()
+        };
+        res.headers_mut().typed_insert(etag);
+        res.headers_mut()
+            .typed_insert(LastModified::from(last_modified));
+        res
+    }
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Escapes text for XML, leaving out the control characters that XML can't
+/// have at all.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The latest posts in a board, at `/feeds/b/:handle.atom` or
+/// `/feeds/b/:handle.rss`.
+#[instrument(skip_all)]
+async fn board_feed_handler(
+    State(persist): State<Persist>,
+    State(site_url): State<SiteUrl>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+) -> std::result::Result<Response, ErrorResponse> {
+    let (handle, format) = FeedFormat::parse(&file).ok_or(Error::NotFound)?;
+    let current = CurrentAccount::default();
+    let board = BoardPersist::new(&persist, &current)
+        .get_by_handle(handle)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let posts = PostPersist::new(&persist, &current)
+        .list()
+        .in_board(&board.id.to_gql_id())
+        .with_pagination(
+            PaginationArgs {
+                after: None,
+                before: None,
+                first: Some(FEED_SIZE),
+                last: None,
+            }
+            .validate()?,
+        )
+        .execute()
+        .await?;
+
+    let base = site_url.resolve(&headers);
+    let link = format!("{base}/b/{}", board.handle);
+    let entries = posts
+        .edges
+        .into_iter()
+        .map(|edge| Entry::post(edge.node, &base, &link))
+        .collect::<Vec<_>>();
+    let feed = Feed {
+        self_link: format!("{base}/feeds/b/{}", board.handle),
+        title: board.name.unwrap_or_else(|| board.handle.clone()),
+        description: board.description,
+        updated: entries
+            .iter()
+            .map(|entry| entry.updated)
+            .fold(board.updated_at, cmp::max),
+        link,
+        entries,
+    };
+    Ok(feed.respond(&headers, format))
+}
+
+/// The newest public boards, at `/feeds/boards.atom` or `/feeds/boards.rss`.
+#[instrument(skip_all)]
+async fn boards_feed_handler(
+    State(persist): State<Persist>,
+    State(site_url): State<SiteUrl>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+) -> std::result::Result<Response, ErrorResponse> {
+    let Some((BOARDS_FEED, format)) = FeedFormat::parse(&file) else {
+        return Err(Error::NotFound.into());
+    };
+    let current = CurrentAccount::default();
+    let boards = BoardPersist::new(&persist, &current)
+        .list()
+        .with_pagination(
+            PaginationArgs {
+                after: None,
+                before: None,
+                first: Some(FEED_SIZE),
+                last: None,
+            }
+            .validate()?,
+        )
+        .execute()
+        .await?;
+
+    let base = site_url.resolve(&headers);
+    let entries = boards
+        .edges
+        .into_iter()
+        .map(|edge| Entry::board(edge.node, &base))
+        .collect::<Vec<_>>();
+    let feed = Feed {
+        link: format!("{base}/b"),
+        self_link: format!("{base}/feeds/{BOARDS_FEED}"),
+        title: "New boards".to_owned(),
+        description: None,
+        updated: entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or_default(),
+        entries,
+    };
+    Ok(feed.respond(&headers, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{HeaderValue, Request},
+    };
+    use pretty_assertions::assert_eq;
+    use tower::ServiceExt as _;
+
+    use super::*;
+    use crate::{
+        account::testing::TestData,
+        board::{testing::BoardTestData as _, BoardVisibility, CreateBoard},
+        post::{testing::PostTestData as _, CreatePost},
+    };
+
+    fn router(data: &TestData, public_url: Option<&str>) -> Router {
+        let mut state = ServiceState::for_tests(data);
+        state.site_url = SiteUrl::new(public_url, false);
+        routes().with_state(state)
+    }
+
+    async fn get(
+        app: &Router,
+        uri: &str,
+        headers: &[(&str, &str)],
+    ) -> (StatusCode, HeaderMap, String) {
+        let mut req = Request::get(uri).header(header::HOST, "plazer.test");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let res = app
+            .clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn header(headers: &HeaderMap, name: header::HeaderName) -> &str {
+        headers.get(name).map(HeaderValue::to_str).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_title_from_content() {
+        assert_eq!(
+            title_from_content("\n  First line  \nSecond line"),
+            Some("First line".to_owned())
+        );
+        assert_eq!(title_from_content(" \n "), None);
+
+        let title = title_from_content(&"a".repeat(100)).unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_LENGTH);
+        assert!(title.ends_with('…'));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<a href=\"x\">Tom & Jerry's</a>\u{0}\n"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_board_feed() {
+        let (data, _) = TestData::with_user().await;
+        let board = data.generate_board().await;
+        data.post()
+            .create(CreatePost {
+                board_id: Some(board.id.to_gql_id()),
+                title: Some("Hello & welcome".into()),
+                content: Some("<b>Hi</b>".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        data.generate_post_in(&board.id).await;
+        let app = router(&data, None);
+
+        let (status, headers, atom) = get(&app, "/feeds/b/test.atom", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            header(&headers, header::CONTENT_TYPE),
+            FeedFormat::Atom.content_type()
+        );
+        assert_eq!(atom.matches("<entry>").count(), 2);
+        assert!(atom.contains("<title>Hello &amp; welcome</title>"));
+        assert!(atom.contains("<content type=\"text\">&lt;b&gt;Hi&lt;/b&gt;</content>"));
+        assert!(atom.contains("href=\"http://plazer.test/feeds/b/test.atom\""));
+        // Posts without a publish time were published when they were made.
+        assert!(!atom.contains("1970-01-01"));
+
+        let (status, _, rss) = get(&app, "/feeds/b/test.rss", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rss.matches("<item>").count(), 2);
+        assert!(rss.contains("<link>http://plazer.test/b/test</link>"));
+
+        // The configured URL is used over the requested one.
+        let (_, _, atom) = get(
+            &router(&data, Some("https://example.com/")),
+            "/feeds/b/test.atom",
+            &[],
+        )
+        .await;
+        assert!(atom.contains("<id>https://example.com/b/test</id>"));
+
+        for uri in [
+            "/feeds/b/test.json",
+            "/feeds/b/test",
+            "/feeds/b/missing.atom",
+        ] {
+            let (status, _, body) = get(&app, uri, &[]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+            assert!(body.contains("\"NotFound\""), "{uri}");
+        }
+
+        // Private boards don't have feeds, as feed readers don't sign in.
+        data.board()
+            .create(CreateBoard {
+                handle: Some("private".into()),
+                visibility: Some(BoardVisibility::Private),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let (status, _, _) = get(&app, "/feeds/b/private.atom", &[]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let (data, _) = TestData::with_user().await;
+        let board = data.generate_board().await;
+        data.generate_post_in(&board.id).await;
+        let app = router(&data, None);
+
+        let (_, headers, _) = get(&app, "/feeds/b/test.atom", &[]).await;
+        let etag = header(&headers, header::ETAG).to_owned();
+        let last_modified = header(&headers, header::LAST_MODIFIED).to_owned();
+
+        let (status, headers, body) =
+            get(&app, "/feeds/b/test.atom", &[("if-none-match", &etag)]).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert_eq!(header(&headers, header::ETAG), etag);
+        assert!(body.is_empty());
+
+        let (status, _, _) = get(
+            &app,
+            "/feeds/b/test.atom",
+            &[("if-modified-since", &last_modified)],
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+        let (status, _, _) = get(
+            &app,
+            "/feeds/b/test.atom",
+            &[("if-modified-since", "Thu, 01 Jan 1970 00:00:00 GMT")],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // The ETag is checked over the date when both are sent.
+        let (status, _, _) = get(
+            &app,
+            "/feeds/b/test.atom",
+            &[
+                ("if-none-match", "\"stale\""),
+                ("if-modified-since", &last_modified),
+            ],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // New posts change the feed.
+        data.generate_post_in(&board.id).await;
+        let (status, headers, _) =
+            get(&app, "/feeds/b/test.atom", &[("if-none-match", &etag)]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(header(&headers, header::ETAG), etag);
+    }
+
+    #[tokio::test]
+    async fn test_boards_feed() {
+        let (data, _) = TestData::with_user().await;
+        data.generate_boards(3).await;
+        for (handle, visibility) in [
+            ("unlisted", BoardVisibility::Unlisted),
+            ("private", BoardVisibility::Private),
+        ] {
+            data.board()
+                .create(CreateBoard {
+                    handle: Some(handle.into()),
+                    visibility: Some(visibility),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let app = router(&data, None);
+
+        let (status, headers, atom) = get(&app, "/feeds/boards.atom", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers.contains_key(header::ETAG));
+        assert_eq!(atom.matches("<entry>").count(), 3);
+        assert!(atom.contains("<id>http://plazer.test/b/test-0</id>"));
+        assert!(!atom.contains("unlisted"));
+        assert!(!atom.contains("private"));
+
+        let (status, _, rss) = get(&app, "/feeds/boards.rss", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rss.matches("<item>").count(), 3);
+
+        let (status, _, _) = get(&app, "/feeds/posts.atom", &[]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
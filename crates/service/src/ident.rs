@@ -0,0 +1,75 @@
+//! Identifiers chosen by users, such as account user IDs and board handles.
+//!
+//! These are used in URLs, so they are limited to a URL-safe set of
+//! characters. They are unique regardless of case, which is enforced by
+//! storing a normalised copy next to the original and indexing that instead.
+
+use crate::prelude::*;
+
+/// Normalises an identifier so that identifiers which only differ by case are
+/// the same.
+///
+/// This matches the database's `string::lowercase`, which is used to normalise
+/// identifiers that were stored before they were validated.
+pub fn normalise(ident: &str) -> String {
+    ident.to_lowercase()
+}
+
+/// Checks that an identifier only uses allowed characters and isn't reserved.
+pub fn validate(ident: &str, reserved: &[String]) -> Result<()> {
+    if ident.is_empty() || !ident.chars().all(is_ident_char) {
+        return Err(Error::InvalidIdent);
+    }
+
+    let ident = normalise(ident);
+    if reserved.iter().any(|reserved| normalise(reserved) == ident) {
+        return Err(Error::ReservedIdent);
+    }
+
+    Ok(())
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reserved() -> Vec<String> {
+        vec!["admin".into(), "API".into()]
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(validate("Some_board-1", &reserved()), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_chars() {
+        for ident in ["", "has space", "slash/", "dot.", "ünïcode", "аdmin"] {
+            assert_eq!(
+                validate(ident, &reserved()),
+                Err(Error::InvalidIdent),
+                "{ident}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_reserved() {
+        for ident in ["admin", "Admin", "api"] {
+            assert_eq!(
+                validate(ident, &reserved()),
+                Err(Error::ReservedIdent),
+                "{ident}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalise() {
+        assert_eq!(normalise("Some_Board"), "some_board");
+    }
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use super::JOB_TABLE_NAME;
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for JobMigration {
+    const SUBSYSTEM: &'static str = "subsys_job";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use JobMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+
+    fn build_down(&self, statements: &mut Vec<srql::Statement>) -> bool {
+        use JobMigration as S;
+        match self {
+            S::Init => Self::build_init_down(statements),
+        }
+        true
+    }
+}
+
+impl JobMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "job_due_index",
+            JOB_TABLE_NAME,
+            [srql::field("status"), srql::field("run_at")],
+        ));
+    }
+
+    fn build_init_down(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::remove_index("job_due_index", JOB_TABLE_NAME));
+    }
+}
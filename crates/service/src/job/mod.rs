@@ -0,0 +1,13 @@
+mod migration;
+mod models;
+mod persist;
+mod runner;
+mod schedule;
+
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use runner::*;
+pub use schedule::*;
+
+pub static JOB_TABLE_NAME: &str = "job";
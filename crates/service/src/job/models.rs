@@ -0,0 +1,319 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::{Schedule, JOB_TABLE_NAME};
+use crate::prelude::*;
+
+/// How many times a job is attempted before it is given up on.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// How long to wait before retrying a job after its first failure. This is
+/// doubled with each failure after that.
+const RETRY_BACKOFF_SECS: i64 = 30;
+/// The longest that a job waits before it is retried.
+const RETRY_BACKOFF_MAX_SECS: i64 = 60 * 60;
+
+/// The work that a job does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Publishes the scheduled posts that are due.
+    PublishScheduledPosts,
+    /// Removes board bans that have expired.
+    PurgeExpiredBans,
+    /// Removes jobs that finished a while ago.
+    PurgeFinishedJobs,
+    /// Removes personal data exports that have expired.
+    PurgeExpiredDataExports,
+}
+
+impl JobKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PublishScheduledPosts => "publish_scheduled_posts",
+            Self::PurgeExpiredBans => "purge_expired_bans",
+            Self::PurgeFinishedJobs => "purge_finished_jobs",
+            Self::PurgeExpiredDataExports => "purge_expired_data_exports",
+        }
+    }
+}
+
+impl QueryValue for JobKind {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|v| (field, srql::Operator::Equal, v))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// The job is waiting to run, either for the first time or to be retried.
+    Pending,
+    /// The job ran successfully.
+    Succeeded,
+    /// The job failed too many times and won't be retried.
+    Failed,
+}
+
+impl QueryValue for JobStatus {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|v| (field, srql::Operator::Equal, v))
+    }
+}
+
+/// A unit of background work.
+///
+/// Jobs either run once, or recur on a schedule. Recurring jobs are keyed on
+/// their kind, so there is only ever one of each, and they go back to pending
+/// once they have finished.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Job {
+    pub id: Thing,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    /// The schedule that the job recurs on, if any.
+    pub schedule: Option<String>,
+    /// When the job should next run.
+    pub run_at: DateTime<Utc>,
+    /// How many times the job has failed since it last succeeded.
+    pub attempts: u32,
+    pub max_attempts: u32,
+    /// The error from the last time the job failed.
+    pub last_error: Option<String>,
+    /// A timestamp indicating the last time the job was updated.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Job {
+    pub fn create(kind: JobKind, run_at: DateTime<Utc>) -> srql::CreateStatement {
+        let mut create = vec![];
+        kind.push_field(srql::field("kind"), &mut create);
+        JobStatus::Pending.push_field(srql::field("status"), &mut create);
+        run_at.push_field(srql::field("run_at"), &mut create);
+        push_count(srql::field("attempts"), 0, &mut create);
+        push_count(
+            srql::field("max_attempts"),
+            DEFAULT_MAX_ATTEMPTS,
+            &mut create,
+        );
+        srql::obj_create_query(JOB_TABLE_NAME, create)
+    }
+
+    /// The ID of the recurring job of the given kind.
+    pub fn recurring_thing(kind: JobKind) -> Thing {
+        Thing::from((JOB_TABLE_NAME, kind.name()))
+    }
+
+    /// Creates or replaces the recurring job of the given kind.
+    pub fn recurring(
+        kind: JobKind,
+        schedule: &str,
+        run_at: DateTime<Utc>,
+    ) -> srql::UpdateStatement {
+        let mut data = vec![];
+        kind.push_field(srql::field("kind"), &mut data);
+        JobStatus::Pending.push_field(srql::field("status"), &mut data);
+        schedule
+            .to_owned()
+            .push_field(srql::field("schedule"), &mut data);
+        run_at.push_field(srql::field("run_at"), &mut data);
+        push_count(srql::field("attempts"), 0, &mut data);
+        push_count(srql::field("max_attempts"), DEFAULT_MAX_ATTEMPTS, &mut data);
+        data.push((
+            srql::field("last_error"),
+            srql::Operator::Equal,
+            srql::Value::None,
+        ));
+        data.push((
+            srql::field("updated_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ));
+
+        srql::UpdateStatement {
+            what: srql::thing(Self::recurring_thing(kind)),
+            data: srql::Data::SetExpression(data).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.status == JobStatus::Pending && self.run_at <= now
+    }
+
+    /// Records the result of running the job.
+    pub fn finish(
+        &self,
+        result: std::result::Result<(), String>,
+        now: DateTime<Utc>,
+    ) -> Result<srql::UpdateStatement> {
+        let JobOutcome {
+            status,
+            run_at,
+            attempts,
+            last_error,
+        } = self.outcome(result, now)?;
+
+        let mut data = vec![];
+        status.push_field(srql::field("status"), &mut data);
+        run_at.push_field(srql::field("run_at"), &mut data);
+        push_count(srql::field("attempts"), attempts, &mut data);
+        data.push((
+            srql::field("last_error"),
+            srql::Operator::Equal,
+            last_error.map_or(srql::Value::None, |err| srql::Value::Strand(err.into())),
+        ));
+
+        srql::obj_update_query(self.id.clone(), data).ok_or_else(|| "Empty job update".into())
+    }
+
+    /// Works out what happens to the job after it has run.
+    ///
+    /// Failed jobs are retried with an exponential backoff until they run out
+    /// of attempts. Recurring jobs then wait for their next run, while other
+    /// jobs are marked as failed.
+    fn outcome(
+        &self,
+        result: std::result::Result<(), String>,
+        now: DateTime<Utc>,
+    ) -> Result<JobOutcome> {
+        let next_run = match &self.schedule {
+            Some(schedule) => schedule.parse::<Schedule>()?.next_after(now),
+            None => None,
+        };
+
+        let outcome = match result {
+            Ok(()) => JobOutcome {
+                status: next_run.map_or(JobStatus::Succeeded, |_| JobStatus::Pending),
+                run_at: next_run.unwrap_or(self.run_at),
+                attempts: 0,
+                last_error: None,
+            },
+            Err(err) => {
+                let attempts = self.attempts + 1;
+                if attempts < self.max_attempts {
+                    JobOutcome {
+                        status: JobStatus::Pending,
+                        run_at: now + backoff(attempts),
+                        attempts,
+                        last_error: Some(err),
+                    }
+                } else if let Some(next_run) = next_run {
+                    JobOutcome {
+                        status: JobStatus::Pending,
+                        run_at: next_run,
+                        attempts: 0,
+                        last_error: Some(err),
+                    }
+                } else {
+                    JobOutcome {
+                        status: JobStatus::Failed,
+                        run_at: self.run_at,
+                        attempts,
+                        last_error: Some(err),
+                    }
+                }
+            }
+        };
+
+        Ok(outcome)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct JobOutcome {
+    status: JobStatus,
+    run_at: DateTime<Utc>,
+    attempts: u32,
+    last_error: Option<String>,
+}
+
+/// How long to wait before retrying a job that has failed `attempts` times.
+pub fn backoff(attempts: u32) -> Duration {
+    let secs = RETRY_BACKOFF_SECS.saturating_mul(1 << attempts.saturating_sub(1).min(16));
+    Duration::seconds(secs.min(RETRY_BACKOFF_MAX_SECS))
+}
+
+fn push_count(field: srql::Idiom, count: u32, expr: &mut srql::SetExpr) {
+    expr.push((
+        field,
+        srql::Operator::Equal,
+        srql::Value::Number(i64::from(count).into()),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(schedule: Option<&str>, attempts: u32) -> Job {
+        Job {
+            id: Thing::from((JOB_TABLE_NAME, "test")),
+            kind: JobKind::PurgeFinishedJobs,
+            status: JobStatus::Pending,
+            schedule: schedule.map(Into::into),
+            run_at: Utc::now(),
+            attempts,
+            max_attempts: 3,
+            last_error: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::seconds(30));
+        assert_eq!(backoff(2), Duration::seconds(60));
+        assert_eq!(backoff(3), Duration::seconds(120));
+        assert_eq!(backoff(100), Duration::seconds(RETRY_BACKOFF_MAX_SECS));
+    }
+
+    #[test]
+    fn test_outcome_once() {
+        let now = Utc::now();
+        let job = job(None, 0);
+
+        let res = job.outcome(Ok(()), now).unwrap();
+        assert_eq!(res.status, JobStatus::Succeeded);
+
+        let res = job.outcome(Err("retry".into()), now).unwrap();
+        assert_eq!(res.status, JobStatus::Pending);
+        assert_eq!(res.run_at, now + backoff(1));
+        assert_eq!(res.attempts, 1);
+        assert_eq!(res.last_error, Some("retry".into()));
+    }
+
+    #[test]
+    fn test_outcome_once_exhausted() {
+        let now = Utc::now();
+        let job = job(None, 2);
+
+        let res = job.outcome(Err("give up".into()), now).unwrap();
+        assert_eq!(res.status, JobStatus::Failed);
+        assert_eq!(res.attempts, 3);
+    }
+
+    #[test]
+    fn test_outcome_recurring() {
+        let now: DateTime<Utc> = "2023-10-01T12:30:00Z".parse().unwrap();
+        let next_run: DateTime<Utc> = "2023-10-01T13:00:00Z".parse().unwrap();
+
+        let res = job(Some("0 * * * *"), 0).outcome(Ok(()), now).unwrap();
+        assert_eq!(res.status, JobStatus::Pending);
+        assert_eq!(res.run_at, next_run);
+
+        // Once a recurring job runs out of attempts, it waits for its next run.
+        let res = job(Some("0 * * * *"), 2)
+            .outcome(Err("wait".into()), now)
+            .unwrap();
+        assert_eq!(res.status, JobStatus::Pending);
+        assert_eq!(res.run_at, next_run);
+        assert_eq!(res.attempts, 0);
+        assert_eq!(res.last_error, Some("wait".into()));
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{Duration, Utc};
+use tracing::instrument;
+
+use super::{Job, JobKind, JobStatus, Schedule, JOB_TABLE_NAME};
+use crate::{persist::Persist, prelude::*, query::SRQL_ORDER_ASC};
+
+/// How long finished jobs are kept for before they are purged.
+const FINISHED_JOB_RETENTION_DAYS: i64 = 7;
+
+pub struct JobPersist<'a> {
+    persist: &'a Persist,
+}
+
+impl<'a> JobPersist<'a> {
+    pub fn new(persist: &'a Persist) -> Self {
+        Self { persist }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &srql::Thing) -> Result<Option<Job>> {
+        Ok(self.persist.db().select(id.clone()).await?)
+    }
+
+    /// Lists the jobs that are due to run, oldest first.
+    #[instrument(skip_all)]
+    pub async fn due(&self, limit: i64) -> Result<Vec<Job>> {
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(JOB_TABLE_NAME),
+            cond: srql::Cond(srql::expr(
+                srql::expr(
+                    srql::field("status"),
+                    srql::Operator::Equal,
+                    srql::to_value(JobStatus::Pending).map_err(SrlError::from)?,
+                ),
+                srql::Operator::And,
+                srql::expr(
+                    srql::field("run_at"),
+                    srql::Operator::LessThanOrEqual,
+                    srql::time_now(),
+                ),
+            ))
+            .into(),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("run_at"),
+                direction: SRQL_ORDER_ASC,
+                ..Default::default()
+            }])
+            .into(),
+            limit: Some(srql::Limit(srql::Number::Int(limit).into())),
+            ..Default::default()
+        };
+
+        Ok(self.persist.db().query(query).await?.take(0)?)
+    }
+
+    /// Makes sure that the recurring job of the given kind exists and runs on
+    /// the given schedule. If the job already exists on the same schedule, it
+    /// is left alone so that restarting doesn't put off its next run.
+    #[instrument(skip(self))]
+    pub async fn register_recurring(&self, kind: JobKind, schedule: &str) -> Result<()> {
+        let job = self.get(&Job::recurring_thing(kind)).await?;
+        if job.is_some_and(|job| job.schedule.as_deref() == Some(schedule)) {
+            return Ok(());
+        }
+
+        let Some(run_at) = schedule.parse::<Schedule>()?.next_after(Utc::now()) else {
+            return Err(Error::ServerMisconfigured(format!(
+                "Job schedule `{schedule}` never runs"
+            )));
+        };
+        self.persist
+            .db()
+            .query(Job::recurring(kind, schedule, run_at))
+            .await?
+            .check()?;
+
+        Ok(())
+    }
+
+    /// Records the result of running a job.
+    #[instrument(skip_all)]
+    pub async fn finish(&self, job: &Job, result: std::result::Result<(), String>) -> Result<()> {
+        self.persist
+            .db()
+            .query(job.finish(result, Utc::now())?)
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Removes jobs that finished more than a week ago, returning how many
+    /// were removed.
+    #[instrument(skip_all)]
+    pub async fn purge_finished(&self) -> Result<usize> {
+        let statuses = [JobStatus::Succeeded, JobStatus::Failed]
+            .into_iter()
+            .map(srql::to_value)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(SrlError::from)?;
+        let cutoff = Utc::now() - Duration::days(FINISHED_JOB_RETENTION_DAYS);
+
+        let purged: Vec<Job> = self
+            .persist
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::table(JOB_TABLE_NAME),
+                cond: srql::Cond(srql::expr(
+                    srql::expr(
+                        srql::field("status"),
+                        srql::Operator::Inside,
+                        srql::array(statuses),
+                    ),
+                    srql::Operator::And,
+                    srql::expr(
+                        srql::field("updated_at"),
+                        srql::Operator::LessThan,
+                        srql::Value::Datetime(srql::Datetime(cutoff)),
+                    ),
+                ))
+                .into(),
+                output: srql::Output::Before.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+
+        Ok(purged.len())
+    }
+}
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::future::join_all;
+use tokio::{sync::watch, time};
+use tracing::{debug, error, info, instrument, warn};
+
+use super::{Job, JobKind, JobPersist};
+use crate::{data_export, moderation, persist::Persist, post, prelude::*};
+
+/// How long workers wait before checking for due jobs again when there was
+/// nothing to run.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How many due jobs a worker looks at each time it checks. Jobs that other
+/// workers are already running are skipped.
+const DUE_BATCH_SIZE: i64 = 10;
+
+/// The jobs that recur, and the schedules that they run on.
+///
+/// Scheduled posts also get a job of their own that runs when they are due,
+/// so this only catches posts that were missed, e.g. while the service was
+/// down.
+static RECURRING_JOBS: &[(JobKind, &str)] = &[
+    (JobKind::PublishScheduledPosts, "*/5 * * * *"),
+    (JobKind::PurgeExpiredBans, "0 * * * *"),
+    (JobKind::PurgeFinishedJobs, "30 3 * * *"),
+    (JobKind::PurgeExpiredDataExports, "15 * * * *"),
+];
+
+/// Runs background jobs as they become due.
+///
+/// Each job is run while holding a lock on it, so when several instances
+/// share a database a job is only run by one of them.
+pub struct JobRunner {
+    persist: Persist,
+    workers: usize,
+}
+
+impl JobRunner {
+    pub fn new(persist: Persist, workers: usize) -> Self {
+        Self { persist, workers }
+    }
+
+    /// Registers the recurring jobs, then runs jobs until `shutdown` changes
+    /// or is dropped. Jobs that are already running are allowed to finish.
+    #[instrument(skip_all)]
+    pub async fn run(self, shutdown: watch::Receiver<bool>) -> Result<()> {
+        self.register().await?;
+
+        info!(workers = self.workers, "Starting job workers");
+        join_all((0..self.workers).map(|_| self.work(shutdown.clone()))).await;
+        info!("Job workers stopped");
+
+        Ok(())
+    }
+
+    async fn register(&self) -> Result<()> {
+        for &(kind, schedule) in RECURRING_JOBS {
+            self.job_persist()
+                .register_recurring(kind, schedule)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn work(&self, mut shutdown: watch::Receiver<bool>) {
+        while !*shutdown.borrow() {
+            let ran = match self.run_next().await {
+                Ok(ran) => ran,
+                Err(err) => {
+                    error!(error = ?err, "Failed to run jobs");
+                    false
+                }
+            };
+
+            if !ran {
+                tokio::select! {
+                    () = time::sleep(POLL_INTERVAL) => {},
+                    res = shutdown.changed() => if res.is_err() {
+                        break;
+                    },
+                }
+            }
+        }
+    }
+
+    /// Runs the next due job that isn't already being run. Returns whether a
+    /// job was run.
+    async fn run_next(&self) -> Result<bool> {
+        for job in self.job_persist().due(DUE_BATCH_SIZE).await? {
+            let lock = format!("job_{}", job.id.id.to_raw());
+            let ran = self
+                .persist
+                .execute_in_lock(&lock, || self.run_job(&job))
+                .await?;
+            // The job is skipped if another worker holds its lock, or ran it
+            // since it was listed.
+            if ran.transpose()? == Some(true) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    #[instrument(skip_all, fields(job = %job.id, kind = job.kind.name()))]
+    async fn run_job(&self, job: &Job) -> Result<bool> {
+        // Another worker might have run the job between it being listed and
+        // the lock being taken.
+        let job = self.job_persist().get(&job.id).await?;
+        let Some(job) = job.filter(|job| job.is_due(Utc::now())) else {
+            return Ok(false);
+        };
+
+        let result = self.execute(job.kind).await.map_err(|err| err.to_string());
+        if let Err(err) = &result {
+            warn!(error = err, attempts = job.attempts + 1, "Job failed");
+        }
+        self.job_persist().finish(&job, result).await?;
+
+        Ok(true)
+    }
+
+    async fn execute(&self, kind: JobKind) -> Result<()> {
+        let count = match kind {
+            JobKind::PublishScheduledPosts => post::publish_scheduled(&self.persist).await?,
+            JobKind::PurgeExpiredBans => moderation::purge_expired_bans(&self.persist).await?,
+            JobKind::PurgeFinishedJobs => self.job_persist().purge_finished().await?,
+            JobKind::PurgeExpiredDataExports => {
+                data_export::purge_expired_data_exports(&self.persist).await?
+            }
+        };
+
+        if count > 0 {
+            info!(count, "Job complete");
+        } else {
+            debug!("Job complete, nothing to do");
+        }
+
+        Ok(())
+    }
+
+    fn job_persist(&self) -> JobPersist<'_> {
+        JobPersist::new(&self.persist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{
+        account::testing::*,
+        job::JobStatus,
+        post::{testing::PostTestData as _, CreatePost, PostState},
+    };
+
+    async fn enqueue(data: &TestData, kind: JobKind) -> Job {
+        let job: Option<Job> = data
+            .persist
+            .db()
+            .query(Job::create(kind, Utc::now() - Duration::seconds(1)))
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        job.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let data = TestData::new().await;
+        let runner = JobRunner::new(data.persist.clone(), 1);
+        let job_persist = JobPersist::new(&data.persist);
+
+        runner.register().await.unwrap();
+        let job = job_persist
+            .get(&Job::recurring_thing(JobKind::PurgeExpiredBans))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Pending);
+        assert!(job.run_at > Utc::now());
+
+        // Registering again doesn't change when the jobs run.
+        runner.register().await.unwrap();
+        let res = job_persist.get(&job.id).await.unwrap().unwrap();
+        assert_eq!(res.run_at, job.run_at);
+
+        assert!(!runner.run_next().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_run_next() {
+        let data = TestData::new().await;
+        let runner = JobRunner::new(data.persist.clone(), 1);
+        let job = enqueue(&data, JobKind::PurgeFinishedJobs).await;
+
+        assert!(runner.run_next().await.unwrap());
+        let res = JobPersist::new(&data.persist).get(&job.id).await.unwrap();
+        assert_eq!(res.unwrap().status, JobStatus::Succeeded);
+
+        assert!(!runner.run_next().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_run_locked() {
+        let data = TestData::new().await;
+        let runner = JobRunner::new(data.persist.clone(), 1);
+        let job = enqueue(&data, JobKind::PurgeFinishedJobs).await;
+
+        let res = data
+            .persist
+            .execute_in_lock(&format!("job_{}", job.id.id.to_raw()), || runner.run_next())
+            .await
+            .unwrap();
+        assert!(!res.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_publish_scheduled_post() {
+        let (data, _) = TestData::with_user().await;
+        let runner = JobRunner::new(data.persist.clone(), 1);
+
+        let post = data
+            .post()
+            .create(CreatePost {
+                content: Some("Test".into()),
+                publish_at: Some(Utc::now() + Duration::seconds(1)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(post.state, PostState::Scheduled);
+        assert!(!runner.run_next().await.unwrap());
+
+        time::sleep(Duration::seconds(1).to_std().unwrap()).await;
+        assert!(runner.run_next().await.unwrap());
+
+        let res = data.post().get(&post.id.to_gql_id()).await.unwrap();
+        assert_eq!(res.unwrap().state, PostState::Published);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let data = TestData::new().await;
+        let runner = JobRunner::new(data.persist.clone(), 2);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let handle = tokio::spawn(runner.run(shutdown_rx));
+        shutdown_tx.send(true).unwrap();
+
+        let res = time::timeout(Duration::seconds(5).to_std().unwrap(), handle).await;
+        assert!(res.unwrap().unwrap().is_ok());
+    }
+}
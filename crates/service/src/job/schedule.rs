@@ -0,0 +1,180 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike as _, Duration, TimeZone as _, Timelike as _, Utc};
+
+use crate::prelude::*;
+
+/// How far ahead to look for the next time a schedule matches. Schedules that
+/// don't match within this time, such as the 31st of February, never run.
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 5;
+
+/// A cron-like schedule for recurring jobs, in the form
+/// `minute hour day-of-month month day-of-week`.
+///
+/// Each field is either `*`, a number, a range such as `1-5`, or a list of
+/// those such as `0,30`, and can be given a step such as `*/15`. Days of the
+/// week go from 0 (Sunday) to 6. Unlike cron, a day has to match both the
+/// day-of-month and day-of-week fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+}
+
+impl Schedule {
+    /// The first time after `after` that matches the schedule, to the minute.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(MAX_LOOKAHEAD_DAYS);
+
+        let mut time = start;
+        while time < limit {
+            time = if !matches(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?
+            } else if !matches(self.days, time.day())
+                || !matches(self.weekdays, time.weekday().num_days_from_sunday())
+            {
+                (time + Duration::days(1)).with_hour(0)?.with_minute(0)?
+            } else if !matches(self.hours, time.hour()) {
+                (time + Duration::hours(1)).with_minute(0)?
+            } else if !matches(self.minutes, time.minute()) {
+                time + Duration::minutes(1)
+            } else {
+                return Some(time);
+            };
+        }
+
+        None
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::ServerMisconfigured(format!("Invalid job schedule `{s}`"));
+
+        let fields: Vec<_> = s.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59).ok_or_else(invalid)?,
+            hours: parse_field(hours, 0, 23).ok_or_else(invalid)?,
+            days: parse_field(days, 1, 31).ok_or_else(invalid)?,
+            months: parse_field(months, 1, 12).ok_or_else(invalid)?,
+            weekdays: parse_field(weekdays, 0, 6).ok_or_else(invalid)?,
+        })
+    }
+}
+
+fn matches(field: u64, value: u32) -> bool {
+    field & (1 << value) != 0
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse().ok()?, end.parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            (value, value)
+        };
+
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step) {
+            set |= 1 << value;
+        }
+    }
+
+    Some(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().into()
+    }
+
+    fn next(schedule: &str, after: &str) -> Option<DateTime<Utc>> {
+        schedule
+            .parse::<Schedule>()
+            .unwrap()
+            .next_after(time(after))
+    }
+
+    #[test]
+    fn test_every_minute() {
+        assert_eq!(
+            next("* * * * *", "2023-10-01T12:30:45Z"),
+            Some(time("2023-10-01T12:31:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_step() {
+        assert_eq!(
+            next("*/15 * * * *", "2023-10-01T12:30:00Z"),
+            Some(time("2023-10-01T12:45:00Z"))
+        );
+        assert_eq!(
+            next("*/15 * * * *", "2023-10-01T23:50:00Z"),
+            Some(time("2023-10-02T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_daily() {
+        assert_eq!(
+            next("0 3 * * *", "2023-12-31T04:00:00Z"),
+            Some(time("2024-01-01T03:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_list_and_range() {
+        // 2023-10-01 is a Sunday, so the next weekday is Monday.
+        assert_eq!(
+            next("0,30 9-17 * * 1-5", "2023-10-01T12:00:00Z"),
+            Some(time("2023-10-02T09:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_never() {
+        assert_eq!(next("0 0 31 2 *", "2023-10-01T12:00:00Z"), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for schedule in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(schedule.parse::<Schedule>().is_err(), "{schedule}");
+        }
+    }
+}
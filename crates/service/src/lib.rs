@@ -7,90 +7,225 @@
 #![forbid(unsafe_code)]
 
 mod account;
+mod admin;
+mod archive;
 mod board;
 pub mod config;
 mod conv;
+mod data_export;
 mod error;
+mod feed;
+mod ident;
+mod job;
+mod limits;
+mod log_file;
 mod macros;
+mod metrics;
 mod migration;
+mod moderation;
 mod persist;
+mod persisted_query;
 mod post;
 mod prelude;
 mod query;
+mod reload;
+mod rest;
 mod schema;
+mod security;
+mod telemetry;
+mod tls;
 
-use std::{io, net::SocketAddr, sync::Arc};
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 #[cfg(feature = "graphiql")]
 use async_graphql::http::GraphiQLSource;
 use async_graphql::{http::ALL_WEBSOCKET_PROTOCOLS, Data, ResultExt as _};
 use async_graphql_axum::{GraphQLBatchRequest, GraphQLProtocol, GraphQLResponse, GraphQLWebSocket};
 use axum::{
-    extract::{FromRef, State, WebSocketUpgrade},
+    extract::{Extension, FromRef, Path, State, WebSocketUpgrade},
     headers::{authorization::Bearer, Authorization},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Router, Server, TypedHeader,
 };
 use config::LogConfig;
 use ring::rand::{SecureRandom as _, SystemRandom};
 use thiserror::Error;
-use tokio::signal;
-use tracing::{debug, error, info, instrument, metadata::LevelFilter, trace};
+use tokio::{net::TcpListener, signal, sync::watch};
+use tracing::{debug, error, info, instrument, metadata::LevelFilter, trace, warn};
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::{fmt, layer::SubscriberExt as _, Layer as _};
+use tracing_subscriber::{
+    filter, fmt, layer::SubscriberExt as _, reload::Layer as ReloadLayer, Layer as _,
+};
 
-pub use crate::schema::schema;
 use crate::{
-    account::authenticate, config::ServeConfig, error::ErrorResponse, migration::Migrations,
+    account::{authenticate, AccountStatusCache, SigningKeys},
+    config::{DbConfig, QueryLimits, ServeConfig},
+    data_export::DataExportPersist,
+    error::{Error as ServiceError, ErrorResponse},
+    feed::SiteUrl,
+    job::JobRunner,
+    limits::{check_batch_size, limit_body, GraphQLLimits},
+    log_file::LogFile,
+    metrics::{is_db_target, metrics_handler, track_requests, DbMetricsLayer, GraphQLMetrics},
+    migration::Migrations,
+    persisted_query::PersistedQueries,
+    reload::Reloader,
     schema::ServiceSchema,
+    security::{response_headers, ResponseHeaders},
+    telemetry::{trace_requests, GraphQLLogging, RequestId},
+    tls::TlsCerts,
 };
+pub use crate::{
+    archive::{ArchiveError, ARCHIVE_VERSION},
+    migration::{MigrationError, MigrationRecord, MigrationStatus, MigrationStep, SUBSYSTEMS},
+    persist::Lock,
+    persisted_query::{Allowlist, AllowlistError, AllowlistOperation},
+    reload::LogLevels,
+    rest::openapi,
+    schema::schema,
+    tls::TlsError,
+};
+
+/// Keeps logs, and traces with the `otlp` feature, flowing until dropped, at
+/// which point the ones that are still queued are written out.
+pub struct LogGuard {
+    _file: WorkerGuard,
+    #[cfg(feature = "otlp")]
+    otlp: bool,
+}
+
+#[cfg(feature = "otlp")]
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if self.otlp {
+            telemetry::shutdown_otlp();
+        }
+    }
+}
 
-/// Initialise logging.
+/// Initialise logging, and trace export if there is an OTLP endpoint. The
+/// returned [`LogLevels`] can change the levels later.
 ///
 /// # Panics
 ///
-/// Panics if the global subscriber cannot be set.
+/// Panics if the log file cannot be opened, or if the global subscriber
+/// cannot be set.
 pub fn init_logging(
     LogConfig {
         dir: path,
         level_stdout,
         level_file,
+        rotation,
+        max_size,
+        max_files,
+        otlp,
     }: LogConfig,
-) -> WorkerGuard {
-    let file_appender = tracing_appender::rolling::hourly(path, "service.log");
+) -> (LogGuard, LogLevels) {
+    let file_appender = LogFile::new(path, "service.log", rotation, max_size, max_files)
+        .expect("Unable to open the log file");
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
 
-    let collector = tracing_subscriber::registry()
-        .with(
-            fmt::Layer::new()
-                .with_writer(io::stdout)
-                .pretty()
-                .with_filter(LevelFilter::from_level(level_stdout)),
-        )
-        .with(
-            fmt::Layer::new()
-                .with_writer(non_blocking)
-                .json()
-                .with_filter(LevelFilter::from_level(level_file)),
-        );
+    let (stdout_filter, stdout_handle) = ReloadLayer::new(LevelFilter::from_level(level_stdout));
+    let (file_filter, file_handle) = ReloadLayer::new(LevelFilter::from_level(level_file));
+    // The layers are boxed so that both filters apply to the registry itself,
+    // which keeps the type of their reload handles simple.
+    let layers = vec![
+        fmt::Layer::new()
+            .with_writer(io::stdout)
+            .pretty()
+            .with_filter(stdout_filter)
+            .boxed(),
+        fmt::Layer::new()
+            .with_writer(non_blocking)
+            .json()
+            .with_filter(file_filter)
+            .boxed(),
+        DbMetricsLayer
+            .with_filter(filter::filter_fn(|meta| {
+                meta.is_span() && is_db_target(meta.target())
+            }))
+            .boxed(),
+    ];
+
+    #[cfg(feature = "otlp")]
+    let mut layers = layers;
+    #[cfg(feature = "otlp")]
+    let otlp = match otlp.as_ref().map(telemetry::otlp_layer) {
+        Some(Ok(layer)) => {
+            layers.push(layer.with_filter(LevelFilter::INFO).boxed());
+            Ok(true)
+        }
+        Some(Err(err)) => Err(err),
+        None => Ok(false),
+    };
+
+    let collector = tracing_subscriber::registry().with(layers);
     tracing::subscriber::set_global_default(collector).expect("Unable to set a global subscriber");
 
-    trace!(?level_stdout, ?level_file, "Logging initialised");
+    trace!(
+        ?level_stdout,
+        ?level_file,
+        %rotation,
+        max_size,
+        ?max_files,
+        "Logging initialised"
+    );
+
+    #[cfg(feature = "otlp")]
+    let otlp = otlp.unwrap_or_else(|err| {
+        error!(error = ?err, "Failed to set up trace export");
+        false
+    });
+    #[cfg(not(feature = "otlp"))]
+    if otlp.is_some() {
+        warn!("An OTLP endpoint is set, but traces can't be exported without the `otlp` feature");
+    }
 
-    guard
+    let guard = LogGuard {
+        _file: guard,
+        #[cfg(feature = "otlp")]
+        otlp,
+    };
+    (guard, LogLevels::new(stdout_handle, file_handle))
 }
 
-#[instrument(skip(jwt_enc_key, jwt_dec_key))]
+/// Runs the server until it is shut down.
+///
+/// On `SIGHUP` the config is reloaded, if there is one. Log levels (through
+/// `log_levels`), service settings and the signing key are changed without
+/// dropping any connections. Other values need a restart.
+#[instrument(skip(jwt_enc_key, jwt_dec_key, persisted_queries, config, log_levels))]
 pub async fn serve(
     ServeConfig {
-        address,
-        namespace,
-        database,
+        db,
         jwt_enc_key,
         jwt_dec_key,
         host,
         port,
+        tls,
+        cors_origins,
+        public_url,
+        metrics,
+        admins,
+        job_workers,
+        limits,
+        persisted_queries,
+        settings,
+        config,
     }: ServeConfig,
+    log_levels: Option<LogLevels>,
 ) -> Result<(), ServeError> {
     debug!("Initialising RNG");
     // Call fill once before starting to initialize the RNG.
@@ -98,47 +233,248 @@ pub async fn serve(
     let mut rng_buf = [0u8; 1];
     csrng.fill(&mut rng_buf)?;
 
-    let jwt_enc_key = Arc::new(jwt_enc_key);
-    let jwt_dec_key = Arc::new(jwt_dec_key);
-    let persist = persist::Persist::new(address, namespace, database).await?;
+    let tls = tls.as_ref().map(TlsCerts::load).transpose()?;
+    let keys = SigningKeys::new(jwt_enc_key, jwt_dec_key);
+    let persist = persist::Persist::new(&db).await?.with_settings(settings);
 
-    info!("Configuring database...");
-    if let Err(err) = Migrations::run(&persist).await {
-        error!(
-            error = ?err,
-            "Failed to complete configuration, database may be corrupt"
-        );
-        return Err(err.into());
+    // Feeds fall back to the `Host` header of each request, which clients
+    // can set to anything, so links in them could point elsewhere.
+    if public_url.is_none() {
+        warn!("No public URL is set, so links in feeds use the host that each request names");
     }
-    info!("Database configuration complete");
 
+    let ready = Ready::default();
+    let status = AccountStatusCache::new(persist.clone());
     let schema = schema(|s| {
-        s.data(persist)
+        s.data(persist.clone())
             .data(csrng)
-            .data(jwt_enc_key.clone())
-            .data(jwt_dec_key.clone())
+            .data(keys.clone())
+            .extension(GraphQLMetrics::new(persisted_queries.allowlist.as_ref()))
+            .extension(GraphQLLogging)
+            .extension(PersistedQueries::new(&persisted_queries))
+            .extension(GraphQLLimits(limits))
     });
 
-    let state = ServiceState::new(schema, jwt_enc_key, jwt_dec_key);
+    let state = ServiceState::new(
+        schema,
+        persist.clone(),
+        keys.clone(),
+        status,
+        ready.clone(),
+        limits,
+        SiteUrl::new(public_url.as_deref(), tls.is_some()),
+    );
 
     let router = Router::new();
     #[cfg(feature = "graphiql")]
     let router = router.route("/", get(graphiql));
     let app = router
-        .route("/api/graphql", post(graphql_handler))
+        .route(
+            "/api/graphql",
+            post(graphql_handler).layer(middleware::from_fn_with_state(limits, limit_body)),
+        )
         .route("/api/graphql/ws", get(graphql_ws_handler))
+        .route("/api/exports/:id", get(data_export_handler))
+        .merge(rest::routes())
+        .merge(feed::routes())
+        // Only the health checks and metrics are served until the database
+        // has been configured.
+        .route_layer(middleware::from_fn_with_state(ready.clone(), require_ready))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz));
+    let app = if metrics {
+        app.route("/metrics", get(metrics_handler))
+    } else {
+        app
+    };
+    let app = app
+        .route_layer(middleware::from_fn(track_requests))
+        .layer(middleware::from_fn_with_state(
+            ResponseHeaders::new(&cors_origins, tls.is_some()),
+            response_headers,
+        ))
+        .layer(middleware::from_fn(trace_requests))
         .with_state(state);
 
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    if let Some(config) = config {
+        let reloader = Reloader::new(config, persist.clone(), keys, tls.clone(), log_levels);
+        tokio::spawn(reload_signal(reloader, shutdown_rx.clone()));
+    }
+
+    // The server is started before the database is configured, so that it
+    // can report that it isn't ready yet.
     let addr = SocketAddr::new(host, port);
-    info!("Listening on {}", addr);
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    info!("Listening on {scheme}://{addr}");
     #[cfg(feature = "graphiql")]
-    info!("GraphQL Playground: http://localhost:{}/", addr.port());
-    Server::try_bind(&addr)?
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    info!("GraphQL Playground: {scheme}://localhost:{}/", addr.port());
+    let shutdown = async move {
+        shutdown_signal().await;
+        shutdown_tx.send_replace(true);
+    };
+    let server: Pin<Box<dyn Future<Output = hyper::Result<()>> + Send>> = match tls {
+        Some(certs) => Box::pin(
+            Server::builder(tls::incoming(TcpListener::bind(addr).await?, certs))
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(shutdown),
+        ),
+        None => Box::pin(
+            Server::try_bind(&addr)?
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(shutdown),
+        ),
+    };
+    tokio::pin!(server);
+
+    let startup = async {
+        info!("Configuring database...");
+        if let Err(err) = Migrations::run(&persist).await {
+            error!(
+                error = ?err,
+                "Failed to complete configuration, database may be corrupt"
+            );
+            return Err(ServeError::from(err));
+        }
+        info!("Database configuration complete");
+
+        let granted = admin::grant_admins(&persist, &admins, true).await?;
+        if granted.len() < admins.len() {
+            warn!(
+                granted = granted.len(),
+                configured = admins.len(),
+                "Some configured admin accounts do not exist"
+            );
+        }
+
+        ready.set();
+
+        // Jobs are stopped along with the server, and are given the chance to
+        // finish what they are doing.
+        Ok(tokio::spawn(
+            JobRunner::new(persist.clone(), job_workers).run(shutdown_rx),
+        ))
+    };
+
+    let jobs = tokio::select! {
+        res = &mut server => return Ok(res?),
+        jobs = startup => jobs?,
+    };
+    let res = server.await;
+
+    match jobs.await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => error!(error = ?err, "Failed to run jobs"),
+        Err(err) => error!(error = ?err, "Job runner panicked"),
+    }
+
+    Ok(res?)
+}
+
+/// Grants (or removes) the site administrator flag on an account, without
+/// starting the server. Returns whether the account was found.
+#[instrument]
+pub async fn set_admin(db: DbConfig, user_id: String, admin: bool) -> Result<bool, ServeError> {
+    let persist = persist::Persist::new(&db).await?;
+    Migrations::run(&persist).await?;
+
+    let updated = admin::grant_admins(&persist, &[user_id], admin).await?;
+    Ok(!updated.is_empty())
+}
+
+/// Gets the state of the migrations for each subsystem, or for just the given
+/// one, without applying anything or starting the server.
+#[instrument]
+pub async fn migration_status(
+    db: DbConfig,
+    subsystem: Option<String>,
+) -> Result<Vec<MigrationStatus>, ServeError> {
+    check_subsystem(subsystem.as_deref())?;
+    let persist = persist::Persist::new(&db).await?;
+    Ok(Migrations::new(&persist, subsystem.as_deref())
+        .status()
+        .await?)
+}
+
+/// Applies the pending migrations for each subsystem, or for just the given
+/// one, without starting the server. Returns the steps that were applied.
+#[instrument]
+pub async fn migrate(
+    db: DbConfig,
+    subsystem: Option<String>,
+) -> Result<Vec<MigrationStep>, ServeError> {
+    check_subsystem(subsystem.as_deref())?;
+    let persist = persist::Persist::new(&db).await?;
+    Ok(Migrations::new(&persist, subsystem.as_deref())
+        .apply()
+        .await?)
+}
+
+/// Rolls a subsystem back to the given step, or undoes every step if there is
+/// no target, without starting the server. Returns the steps that were undone,
+/// or that would be if `dry_run` is set.
+#[instrument]
+pub async fn rollback_migrations(
+    db: DbConfig,
+    subsystem: String,
+    target: Option<String>,
+    dry_run: bool,
+) -> Result<Vec<MigrationStep>, ServeError> {
+    check_subsystem(Some(&subsystem))?;
+    let persist = persist::Persist::new(&db).await?;
+    let migrations = Migrations::new(&persist, Some(&subsystem));
+    if dry_run {
+        Ok(migrations.plan_rollback(target.as_deref()).await?)
+    } else {
+        Ok(migrations.rollback(target.as_deref()).await?)
+    }
+}
+
+fn check_subsystem(subsystem: Option<&str>) -> Result<(), ServeError> {
+    match subsystem {
+        Some(subsystem) if !SUBSYSTEMS.contains(&subsystem) => {
+            Err(ServeError::UnknownSubsystem(subsystem.to_owned()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes everything in the database to `out` as an archive, without starting
+/// the server. Returns how many records were written.
+#[instrument(skip(out))]
+pub async fn export(
+    db: DbConfig,
+    out: impl io::Write,
+    password_hashes: bool,
+) -> Result<usize, ServeError> {
+    let persist = persist::Persist::new(&db).await?;
+    Ok(archive::export(&persist, out, password_hashes).await?)
+}
 
-    Ok(())
+/// Reads an archive into a fresh database, without starting the server.
+/// Returns how many records were imported.
+#[instrument(skip(input))]
+pub async fn import(db: DbConfig, input: impl io::BufRead) -> Result<usize, ServeError> {
+    let persist = persist::Persist::new(&db).await?;
+    Ok(archive::import(&persist, input).await?)
+}
+
+/// Lists the locks that are currently held, without starting the server.
+///
+/// Migrations aren't run first, as they need a lock of their own and so would
+/// wait on any lock that is stuck.
+#[instrument]
+pub async fn list_locks(db: DbConfig) -> Result<Vec<Lock>, ServeError> {
+    let persist = persist::Persist::new(&db).await?;
+    Ok(persist.locks().await?)
+}
+
+/// Releases a lock regardless of which instance holds it, without starting the
+/// server. Returns the lock that was released, if it was held.
+#[instrument]
+pub async fn release_lock(db: DbConfig, id: String) -> Result<Option<Lock>, ServeError> {
+    let persist = persist::Persist::new(&db).await?;
+    Ok(persist.force_release_lock(&id).await?)
 }
 
 async fn shutdown_signal() {
@@ -167,6 +503,31 @@ async fn shutdown_signal() {
     info!("Shutting down");
 }
 
+/// Reloads the config each time the process is sent `SIGHUP`, until shutdown.
+async fn reload_signal(mut reloader: Reloader, mut shutdown_rx: watch::Receiver<bool>) {
+    #[cfg(unix)]
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+        .expect("failed to install signal handler");
+
+    loop {
+        #[cfg(unix)]
+        let hangup = hangup.recv();
+
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = hangup => {
+                info!("Reloading config");
+                if let Err(err) = reloader.reload() {
+                    error!(error = ?err, "Failed to reload config, keeping the current one");
+                }
+            }
+            _ = shutdown_rx.changed() => break,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ServeError {
     #[error("Invalid host: {0}")]
@@ -175,20 +536,38 @@ pub enum ServeError {
     ServeError(#[from] hyper::Error),
     #[error("Failed to initialise database: {0}")]
     PersistError(#[from] surrealdb::Error),
+    #[error("Failed to connect to database: {0}")]
+    ConnectError(#[from] persist::ConnectError),
     #[error("Failed to initialise cryptography")]
     CryptoError(#[from] ring::error::Unspecified),
+    #[error("Unknown subsystem {0:?}, expected one of {SUBSYSTEMS:?}")]
+    UnknownSubsystem(String),
+    #[error("Failed to migrate database: {0}")]
+    MigrationError(#[from] MigrationError),
+    #[error("{0}")]
+    ArchiveError(#[from] ArchiveError),
+    #[error("{0}")]
+    TlsError(#[from] TlsError),
+    #[error("Failed to listen: {0}")]
+    ListenError(#[from] io::Error),
 }
 
 #[instrument(skip_all)]
 async fn graphql_handler(
     State(schema): State<ServiceSchema>,
-    State(dec_key): State<DecodingKey>,
+    State(keys): State<SigningKeys>,
+    State(status): State<AccountStatusCache>,
+    State(limits): State<QueryLimits>,
+    Extension(request_id): Extension<RequestId>,
     auth_header: Option<TypedHeader<Authorization<Bearer>>>,
     req: GraphQLBatchRequest,
 ) -> Result<GraphQLResponse, ErrorResponse> {
-    let current = authenticate(auth_header, &dec_key)?;
+    let req = req.into_inner();
+    check_batch_size(&req, &limits)?;
+    let current = authenticate(auth_header, &keys.decoding())?;
+    status.check(&current).await?;
     Ok(schema
-        .execute_batch(req.into_inner().data(Arc::new(current)))
+        .execute_batch(req.data(Arc::new(current)).data(request_id))
         .await
         .into())
 }
@@ -196,7 +575,9 @@ async fn graphql_handler(
 #[instrument(skip_all)]
 async fn graphql_ws_handler(
     State(schema): State<ServiceSchema>,
-    State(dec_key): State<DecodingKey>,
+    State(keys): State<SigningKeys>,
+    State(status): State<AccountStatusCache>,
+    Extension(request_id): Extension<RequestId>,
     protocol: GraphQLProtocol,
     upgrade: WebSocketUpgrade,
 ) -> axum::response::Response {
@@ -206,14 +587,83 @@ async fn graphql_ws_handler(
             GraphQLWebSocket::new(stream, schema, protocol)
                 .on_connection_init(|init| async move {
                     let mut data = Data::default();
-                    let current = authenticate(init, &dec_key).extend()?;
+                    let current = authenticate(init, &keys.decoding()).extend()?;
+                    status.check(&current).await.extend()?;
                     data.insert(current);
+                    data.insert(request_id);
                     Ok(data)
                 })
                 .serve()
         })
 }
 
+/// Downloads an export of the current account's data, created by the
+/// `exportMyData` mutation.
+#[instrument(skip_all)]
+async fn data_export_handler(
+    State(persist): State<persist::Persist>,
+    State(keys): State<SigningKeys>,
+    State(status): State<AccountStatusCache>,
+    auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let current = authenticate(auth_header, &keys.decoding())?;
+    status.check(&current).await?;
+    let export = DataExportPersist::new(&persist, &current)
+        .get(&id)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/json".to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", export.file_name()),
+        ),
+    ];
+    Ok((headers, export.content))
+}
+
+/// Whether the server is running, for liveness checks.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Whether the server can handle requests, which it can't until the database
+/// has been configured, or while the database isn't answering.
+#[instrument(skip_all)]
+async fn readyz(
+    State(persist): State<persist::Persist>,
+    State(ready): State<Ready>,
+) -> (StatusCode, &'static str) {
+    if !ready.get() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database is being configured",
+        );
+    }
+    match persist.db().health().await {
+        Ok(()) => (StatusCode::OK, "ok"),
+        Err(err) => {
+            warn!(error = ?err, "Database health check failed");
+            (StatusCode::SERVICE_UNAVAILABLE, "Database is unavailable")
+        }
+    }
+}
+
+/// Turns requests away until the server has finished starting up, so that
+/// nothing reaches the database before it has been configured.
+async fn require_ready<B>(
+    State(ready): State<Ready>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ErrorResponse> {
+    if !ready.get() {
+        return Err(ServiceError::NotReady.into());
+    }
+    Ok(next.run(req).await)
+}
+
 #[cfg(feature = "graphiql")]
 #[instrument(skip_all)]
 async fn graphiql() -> axum::response::Html<String> {
@@ -225,28 +675,64 @@ async fn graphiql() -> axum::response::Html<String> {
     )
 }
 
-type EncodingKey = Arc<jsonwebtoken::EncodingKey>;
-type DecodingKey = Arc<jsonwebtoken::DecodingKey>;
-
 #[derive(Clone)]
 struct ServiceState {
     schema: ServiceSchema,
-    jwt_enc_key: EncodingKey,
-    jwt_dec_key: DecodingKey,
+    persist: persist::Persist,
+    keys: SigningKeys,
+    status: AccountStatusCache,
+    ready: Ready,
+    limits: QueryLimits,
+    site_url: SiteUrl,
+}
+
+/// Whether the server has finished starting up, and can handle requests.
+#[derive(Clone, Default)]
+struct Ready(Arc<AtomicBool>);
+
+impl Ready {
+    fn get(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    fn set(&self) {
+        self.0.store(true, Ordering::Release);
+    }
 }
 
 impl ServiceState {
     fn new(
         schema: ServiceSchema,
-        jwt_enc_key: impl Into<EncodingKey>,
-        jwt_dec_key: impl Into<DecodingKey>,
+        persist: persist::Persist,
+        keys: SigningKeys,
+        status: AccountStatusCache,
+        ready: Ready,
+        limits: QueryLimits,
+        site_url: SiteUrl,
     ) -> Self {
         Self {
             schema,
-            jwt_enc_key: jwt_enc_key.into(),
-            jwt_dec_key: jwt_dec_key.into(),
+            persist,
+            keys,
+            status,
+            ready,
+            limits,
+            site_url,
         }
     }
+
+    #[cfg(test)]
+    fn for_tests(data: &account::testing::TestData) -> Self {
+        Self::new(
+            schema(|s| s),
+            data.persist.clone(),
+            SigningKeys::new(data.jwt_enc_key.clone(), data.jwt_dec_key.clone()),
+            AccountStatusCache::new(data.persist.clone()),
+            Ready::default(),
+            QueryLimits::default(),
+            SiteUrl::new(None, false),
+        )
+    }
 }
 
 impl FromRef<ServiceState> for ServiceSchema {
@@ -255,14 +741,38 @@ impl FromRef<ServiceState> for ServiceSchema {
     }
 }
 
-impl FromRef<ServiceState> for EncodingKey {
+impl FromRef<ServiceState> for persist::Persist {
+    fn from_ref(state: &ServiceState) -> Self {
+        state.persist.clone()
+    }
+}
+
+impl FromRef<ServiceState> for SigningKeys {
+    fn from_ref(state: &ServiceState) -> Self {
+        state.keys.clone()
+    }
+}
+
+impl FromRef<ServiceState> for AccountStatusCache {
+    fn from_ref(state: &ServiceState) -> Self {
+        state.status.clone()
+    }
+}
+
+impl FromRef<ServiceState> for Ready {
+    fn from_ref(state: &ServiceState) -> Self {
+        state.ready.clone()
+    }
+}
+
+impl FromRef<ServiceState> for QueryLimits {
     fn from_ref(state: &ServiceState) -> Self {
-        state.jwt_enc_key.clone()
+        state.limits
     }
 }
 
-impl FromRef<ServiceState> for DecodingKey {
+impl FromRef<ServiceState> for SiteUrl {
     fn from_ref(state: &ServiceState) -> Self {
-        state.jwt_dec_key.clone()
+        state.site_url.clone()
     }
 }
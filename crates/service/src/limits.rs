@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    async_trait,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextValidation},
+    BatchRequest, ServerError, ValidationResult,
+};
+use axum::{
+    body::{Body, HttpBody as _},
+    extract::State,
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    config::QueryLimits,
+    error::{Error, ErrorResponse, Result},
+};
+
+/// Rejects GraphQL queries that are nested too deeply or are too complex,
+/// over HTTP and WebSocket alike.
+pub struct GraphQLLimits(pub QueryLimits);
+
+impl ExtensionFactory for GraphQLLimits {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQLLimitsExtension(self.0))
+    }
+}
+
+struct GraphQLLimitsExtension(QueryLimits);
+
+#[async_trait::async_trait]
+impl Extension for GraphQLLimitsExtension {
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> std::result::Result<ValidationResult, Vec<ServerError>> {
+        let res = next.run(ctx).await?;
+        if res.depth > self.0.max_depth {
+            return Err(vec![Error::QueryTooDeep.to_server_error()]);
+        }
+        if res.complexity > self.0.max_complexity {
+            return Err(vec![Error::QueryTooComplex.to_server_error()]);
+        }
+        Ok(res)
+    }
+}
+
+/// Checks that a batch doesn't have more operations than are allowed.
+pub fn check_batch_size(req: &BatchRequest, limits: &QueryLimits) -> Result<()> {
+    match req {
+        BatchRequest::Batch(requests) if requests.len() > limits.max_batch_size => {
+            Err(Error::BatchTooLarge)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Rejects request bodies that are larger than allowed. The body is read here
+/// so that bodies without a `Content-Length` are limited too, which is fine
+/// for GraphQL requests as they are read whole before being parsed anyway.
+pub async fn limit_body(
+    State(limits): State<QueryLimits>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> std::result::Result<Response, ErrorResponse> {
+    let (parts, mut body) = req.into_parts();
+    let content_length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+    if content_length.is_some_and(|len| len > limits.max_body_size) {
+        return Err(Error::RequestTooLarge.into());
+    }
+
+    let mut buf = Vec::with_capacity(content_length.unwrap_or_default());
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|err| Error::ParseError(err.to_string()))?;
+        if buf.len() + chunk.len() > limits.max_body_size {
+            return Err(Error::RequestTooLarge.into());
+        }
+        buf.extend_from_slice(&chunk);
+    }
+
+    Ok(next.run(Request::from_parts(parts, Body::from(buf))).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{
+        extensions::NextExecute, Request as GqlRequest, Response as GqlResponse, Value,
+    };
+    use axum::{http::StatusCode, middleware, routing::post, Router};
+    use tower::ServiceExt as _;
+
+    use super::*;
+
+    fn limits() -> QueryLimits {
+        QueryLimits {
+            max_depth: 4,
+            max_complexity: 50,
+            max_batch_size: 2,
+            max_body_size: 64,
+        }
+    }
+
+    /// Stops queries once they are validated, as there is nothing for them
+    /// to run against.
+    struct SkipExecution;
+
+    impl ExtensionFactory for SkipExecution {
+        fn create(&self) -> Arc<dyn Extension> {
+            Arc::new(SkipExecution)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Extension for SkipExecution {
+        async fn execute(
+            &self,
+            _ctx: &ExtensionContext<'_>,
+            _operation_name: Option<&str>,
+            _next: NextExecute<'_>,
+        ) -> GqlResponse {
+            GqlResponse::new(Value::Null)
+        }
+    }
+
+    async fn error_code(limits: QueryLimits, query: &str) -> Option<Value> {
+        let schema = crate::schema(|s| s.extension(GraphQLLimits(limits)).extension(SkipExecution));
+        let res = schema.execute(GqlRequest::new(query)).await;
+        let extensions = res.errors.first()?.extensions.as_ref()?;
+        extensions.get("code").cloned()
+    }
+
+    #[tokio::test]
+    async fn test_depth() {
+        let limits = QueryLimits {
+            max_depth: 3,
+            ..limits()
+        };
+        assert_eq!(
+            error_code(limits, "{ posts(first: 1) { pageInfo { hasNextPage } } }").await,
+            None
+        );
+        assert_eq!(
+            error_code(limits, "{ posts(first: 1) { edges { node { id } } } }").await,
+            Some(Value::String("QueryTooDeep".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complexity() {
+        // Each post costs three, so a page of ten fits and a page of twenty
+        // doesn't.
+        assert_eq!(
+            error_code(limits(), "{ posts(first: 10) { edges { node { id } } } }").await,
+            None
+        );
+        assert_eq!(
+            error_code(limits(), "{ posts(first: 20) { edges { node { id } } } }").await,
+            Some(Value::String("QueryTooComplex".into()))
+        );
+        assert_eq!(
+            error_code(limits(), "{ posts { edges { node { id } } } }").await,
+            Some(Value::String("QueryTooComplex".into()))
+        );
+    }
+
+    #[test]
+    fn test_batch_size() {
+        let batch = |size| {
+            BatchRequest::Batch(
+                (0..size)
+                    .map(|_| GqlRequest::new("{ __typename }"))
+                    .collect(),
+            )
+        };
+        assert_eq!(check_batch_size(&batch(2), &limits()), Ok(()));
+        assert_eq!(
+            check_batch_size(&batch(3), &limits()),
+            Err(Error::BatchTooLarge)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_body_size() {
+        let app = Router::new()
+            .route("/", post(|body: String| async move { body }))
+            .route_layer(middleware::from_fn_with_state(limits(), limit_body));
+        let send = |body: &str, content_length: bool| {
+            let mut req = Request::post("/");
+            if content_length {
+                req = req.header(header::CONTENT_LENGTH, body.len());
+            }
+            app.clone()
+                .oneshot(req.body(Body::from(body.to_owned())).unwrap())
+        };
+
+        let res = send(&"a".repeat(64), true).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = send(&"a".repeat(65), true).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Without a length up front, the body is only found to be too large
+        // while it is read.
+        let res = send(&"a".repeat(65), false).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, DurationRound as _, Utc};
+
+use crate::config::LogRotation;
+
+/// A log file that is swapped for a new one every hour or day, or once it gets
+/// too large, and that can be limited to a number of files.
+///
+/// Files rotated by time are named after the period they start at, such as
+/// `service.log.2023-10-01-13`. Files rotated by size are numbered, with
+/// `service.log.1` being the most recent one before `service.log`.
+pub struct LogFile {
+    dir: PathBuf,
+    name: String,
+    rotation: LogRotation,
+    max_size: u64,
+    max_files: Option<usize>,
+    file: File,
+    /// How much has been written to the current file.
+    size: u64,
+    /// When the current file should be swapped, when rotating by time.
+    next_rotation: Option<DateTime<Utc>>,
+}
+
+impl LogFile {
+    /// Opens the log file in `dir`, creating the directory if needed.
+    /// `max_files` counts the file being written to, so is at least one.
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        name: impl Into<String>,
+        rotation: LogRotation,
+        max_size: u64,
+        max_files: Option<usize>,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let name = name.into();
+        let (file, size, next_rotation) = open(&dir, &name, rotation, Utc::now())?;
+        let log_file = Self {
+            dir,
+            name,
+            rotation,
+            max_size,
+            max_files: max_files.map(|max| max.max(1)),
+            file,
+            size,
+            next_rotation,
+        };
+        log_file.remove_dated()?;
+        Ok(log_file)
+    }
+
+    fn rotate_time(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        self.file.flush()?;
+        (self.file, self.size, self.next_rotation) =
+            open(&self.dir, &self.name, self.rotation, now)?;
+        self.remove_dated()
+    }
+
+    fn rotate_size(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let numbered = |i: usize| self.dir.join(format!("{}.{i}", self.name));
+
+        let mut last = 0;
+        while numbered(last + 1).exists() {
+            last += 1;
+        }
+        if let Some(max) = self.max_files {
+            while last > 0 && last + 1 >= max {
+                fs::remove_file(numbered(last))?;
+                last -= 1;
+            }
+        }
+        for i in (1..=last).rev() {
+            fs::rename(numbered(i), numbered(i + 1))?;
+        }
+
+        let current = self.dir.join(&self.name);
+        if self.max_files == Some(1) {
+            fs::remove_file(current)?;
+        } else {
+            fs::rename(current, numbered(1))?;
+        }
+        (self.file, self.size, self.next_rotation) =
+            open(&self.dir, &self.name, self.rotation, Utc::now())?;
+        Ok(())
+    }
+
+    /// Removes the oldest files rotated by time, so that there are no more
+    /// than the maximum.
+    fn remove_dated(&self) -> io::Result<()> {
+        let Some(max) = self.max_files else {
+            return Ok(());
+        };
+        if !matches!(self.rotation, LogRotation::Hourly | LogRotation::Daily) {
+            return Ok(());
+        }
+
+        let prefix = format!("{}.", self.name);
+        let mut dated = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|file_name| {
+                file_name.strip_prefix(&prefix).is_some_and(|suffix| {
+                    suffix.contains('-') && suffix.chars().all(|c| c.is_ascii_digit() || c == '-')
+                })
+            })
+            .collect::<Vec<_>>();
+        // The dates sort in the same order as their names.
+        dated.sort_unstable();
+
+        let excess = dated.len().saturating_sub(max);
+        for file_name in &dated[..excess] {
+            fs::remove_file(self.dir.join(file_name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the file for the period that `now` is in, returning its size and
+/// when the next period starts.
+fn open(
+    dir: &Path,
+    name: &str,
+    rotation: LogRotation,
+    now: DateTime<Utc>,
+) -> io::Result<(File, u64, Option<DateTime<Utc>>)> {
+    let period = match rotation {
+        LogRotation::Hourly => Some((Duration::hours(1), "%Y-%m-%d-%H")),
+        LogRotation::Daily => Some((Duration::days(1), "%Y-%m-%d")),
+        LogRotation::Size | LogRotation::Never => None,
+    };
+
+    let (path, next_rotation) = match period {
+        Some((length, format)) => {
+            let start = now
+                .duration_trunc(length)
+                .expect("log rotation periods are valid");
+            (
+                dir.join(format!("{name}.{}", start.format(format))),
+                Some(start + length),
+            )
+        }
+        None => (dir.join(name), None),
+    };
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size, next_rotation))
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(next_rotation) = self.next_rotation {
+            let now = Utc::now();
+            if now >= next_rotation {
+                self.rotate_time(now)?;
+            }
+        }
+        if self.rotation == LogRotation::Size
+            && self.size > 0
+            && self.size + buf.len() as u64 > self.max_size
+        {
+            self.rotate_size()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("plazer-log-{name}-{}", ulid::Ulid::new()))
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn test_rotate_size() {
+        let dir = temp_dir("size");
+        let mut log_file =
+            LogFile::new(&dir, "service.log", LogRotation::Size, 10, Some(3)).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log_file.write_all(line.as_bytes()).unwrap();
+        }
+        log_file.flush().unwrap();
+
+        assert_eq!(
+            file_names(&dir),
+            ["service.log", "service.log.1", "service.log.2"]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("service.log")).unwrap(),
+            "fourth\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("service.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("service.log.2")).unwrap(),
+            "second\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_dated() {
+        let dir = temp_dir("dated");
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "service.log.2023-01-01-00",
+            "service.log.2023-01-01-01",
+            "service.log.2023-01-01-02",
+            "service.log.1",
+            "other.log",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let _log_file = LogFile::new(&dir, "service.log", LogRotation::Hourly, 0, Some(2)).unwrap();
+
+        let current = format!("service.log.{}", Utc::now().format("%Y-%m-%d-%H"));
+        assert_eq!(
+            file_names(&dir),
+            [
+                "other.log",
+                "service.log.1",
+                "service.log.2023-01-01-02",
+                &current
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,8 +4,8 @@ use plazer_service::{config::ServiceConfigBuilder, init_logging, serve};
 async fn main() -> anyhow::Result<()> {
     let (serve_config, log_config) = ServiceConfigBuilder::default().build()?.try_into()?;
 
-    let _guard = init_logging(log_config);
-    serve(serve_config).await?;
+    let (_guard, log_levels) = init_logging(log_config);
+    serve(serve_config, Some(log_levels)).await?;
 
     Ok(())
 }
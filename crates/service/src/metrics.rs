@@ -0,0 +1,358 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+use async_graphql::{
+    async_trait,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextExecute},
+    Response,
+};
+use axum::{
+    extract::MatchedPath,
+    http::{header, Request},
+    middleware::Next,
+    response::IntoResponse,
+};
+use prometheus::{
+    Encoder as _, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use tracing::{span, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::persisted_query::Allowlist;
+
+/// The label that operations which aren't in the allowlist are recorded
+/// under, so that clients can't make up as many labels as they like.
+const OTHER_OPERATION: &str = "other";
+/// How many operation names are recorded when there is no allowlist. Any more
+/// are recorded as [`OTHER_OPERATION`].
+const MAX_OPERATION_LABELS: usize = 100;
+
+/// The metrics of the service, which are served in the Prometheus text format.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    graphql_operation_duration: HistogramVec,
+    db_query_duration: HistogramVec,
+    errors: IntCounterVec,
+}
+
+/// Gets the metrics of the service. There is a single set of metrics for the
+/// process, in the same way that there is a single place that logs go to.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("plazer".into()), None).expect("metrics registry is valid");
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests that were handled."),
+            &["method", "route", "status"],
+        )
+        .expect("metric is valid");
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "How long HTTP requests took to handle.",
+            ),
+            &["method", "route"],
+        )
+        .expect("metric is valid");
+        let graphql_operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "graphql_operation_duration_seconds",
+                "How long GraphQL operations took to execute, by operation name.",
+            ),
+            &["operation"],
+        )
+        .expect("metric is valid");
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "db_query_duration_seconds",
+                "How long database operations took, by the persist function that ran them.",
+            ),
+            &["query"],
+        )
+        .expect("metric is valid");
+        let errors = IntCounterVec::new(
+            Opts::new("errors_total", "Errors returned to clients, by error code."),
+            &["code"],
+        )
+        .expect("metric is valid");
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(graphql_operation_duration.clone()),
+            Box::new(db_query_duration.clone()),
+            Box::new(errors.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metrics are only registered once");
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            graphql_operation_duration,
+            db_query_duration,
+            errors,
+        }
+    }
+
+    pub fn record_error(&self, code: &str) {
+        self.errors.with_label_values(&[code]).inc();
+    }
+
+    fn record_http_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn record_graphql_operation(&self, operation: &str, duration: Duration) {
+        self.graphql_operation_duration
+            .with_label_values(&[operation])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn record_db_query(&self, query: &str, duration: Duration) {
+        self.db_query_duration
+            .with_label_values(&[query])
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Renders the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buf = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("metrics can be encoded");
+        String::from_utf8(buf).expect("metrics are valid UTF-8")
+    }
+}
+
+/// Serves the metrics to Prometheus.
+pub async fn metrics_handler() -> impl IntoResponse {
+    let content_type = TextEncoder::new().format_type().to_owned();
+    ([(header::CONTENT_TYPE, content_type)], metrics().render())
+}
+
+/// Counts and times HTTP requests by the route that they matched.
+pub async fn track_requests<B>(
+    matched_path: Option<MatchedPath>,
+    req: Request<B>,
+    next: Next<B>,
+) -> axum::response::Response {
+    let start = Instant::now();
+    let method = req.method().clone();
+    let route = matched_path.map_or_else(|| "unknown".to_owned(), |path| path.as_str().to_owned());
+
+    let res = next.run(req).await;
+    metrics().record_http_request(
+        method.as_str(),
+        &route,
+        res.status().as_u16(),
+        start.elapsed(),
+    );
+    res
+}
+
+/// Times GraphQL operations, over HTTP and WebSocket alike. Operations are
+/// labelled by name if they are in the allowlist, or if there is no allowlist,
+/// for the first names that are seen.
+pub struct GraphQLMetrics(Arc<OperationLabels>);
+
+enum OperationLabels {
+    Allowlist(HashSet<String>),
+    Seen {
+        names: Mutex<HashSet<String>>,
+        max: usize,
+    },
+}
+
+impl GraphQLMetrics {
+    #[must_use]
+    pub fn new(allowlist: Option<&Allowlist>) -> Self {
+        let labels = match allowlist {
+            Some(allowlist) => OperationLabels::Allowlist(
+                allowlist
+                    .operations()
+                    .iter()
+                    .map(|op| op.name.clone())
+                    .collect(),
+            ),
+            None => OperationLabels::seen(MAX_OPERATION_LABELS),
+        };
+        Self(Arc::new(labels))
+    }
+}
+
+impl OperationLabels {
+    fn seen(max: usize) -> Self {
+        Self::Seen {
+            names: Mutex::default(),
+            max,
+        }
+    }
+
+    fn label<'a>(&self, operation_name: Option<&'a str>) -> &'a str {
+        let Some(name) = operation_name else {
+            return "anonymous";
+        };
+        let is_labelled = match self {
+            Self::Allowlist(names) => names.contains(name),
+            Self::Seen { names, max } => {
+                let mut names = names.lock().unwrap_or_else(PoisonError::into_inner);
+                names.contains(name) || (names.len() < *max && names.insert(name.to_owned()))
+            }
+        };
+        if is_labelled {
+            name
+        } else {
+            OTHER_OPERATION
+        }
+    }
+}
+
+impl ExtensionFactory for GraphQLMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQLMetricsExtension(self.0.clone()))
+    }
+}
+
+struct GraphQLMetricsExtension(Arc<OperationLabels>);
+
+#[async_trait::async_trait]
+impl Extension for GraphQLMetricsExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let start = Instant::now();
+        let res = next.run(ctx, operation_name).await;
+        metrics().record_graphql_operation(self.0.label(operation_name), start.elapsed());
+        res
+    }
+}
+
+/// Times the spans of the persist functions, which are where the database is
+/// queried from.
+pub struct DbMetricsLayer;
+
+/// Whether spans with the given target are from the persist modules, which
+/// are where the database is queried from.
+pub fn is_db_target(target: &str) -> bool {
+    target.starts_with("plazer_service::") && target.ends_with("persist")
+}
+
+struct SpanStart(Instant);
+
+impl<S> Layer<S> for DbMetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(start) = span.extensions().get::<SpanStart>().map(|start| start.0) else {
+            return;
+        };
+
+        let module = span
+            .metadata()
+            .target()
+            .trim_start_matches("plazer_service::")
+            .trim_end_matches("::persist");
+        let query = format!("{module}::{}", span.name());
+        metrics().record_db_query(&query, start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        metrics().record_error("NotFound");
+        metrics().record_db_query("board::get", Duration::from_millis(3));
+
+        let rendered = metrics().render();
+        assert!(rendered.contains("# TYPE plazer_errors_total counter"));
+        assert!(rendered.contains("plazer_errors_total{code=\"NotFound\"}"));
+        assert!(rendered.contains("plazer_db_query_duration_seconds_count{query=\"board::get\"}"));
+    }
+
+    #[tokio::test]
+    async fn test_graphql_operation() {
+        let allowlist = Allowlist::from_sources([(
+            "probe.tsx",
+            "const PROBE = gql`query MetricsProbe { __typename }`;",
+        )])
+        .unwrap();
+        let schema = crate::schema(|s| s.extension(GraphQLMetrics::new(Some(&allowlist))));
+        let res = schema.execute("query MetricsProbe { __typename }").await;
+        assert!(res.is_ok());
+        let res = schema.execute("query MetricsUnlisted { __typename }").await;
+        assert!(res.is_ok());
+
+        let rendered = metrics().render();
+        assert!(rendered.contains(
+            "plazer_graphql_operation_duration_seconds_count{operation=\"MetricsProbe\"} 1"
+        ));
+        assert!(!rendered.contains("MetricsUnlisted"));
+        assert!(rendered
+            .contains("plazer_graphql_operation_duration_seconds_count{operation=\"other\"}"));
+    }
+
+    #[tokio::test]
+    async fn test_graphql_operation_no_allowlist() {
+        let labels = GraphQLMetrics(Arc::new(OperationLabels::seen(1)));
+        let schema = crate::schema(|s| s.extension(labels));
+        for query in [
+            "query MetricsSeen { __typename }",
+            "query MetricsOverflow { __typename }",
+            "query MetricsSeen { __typename }",
+        ] {
+            assert!(schema.execute(query).await.is_ok());
+        }
+
+        let rendered = metrics().render();
+        assert!(rendered.contains(
+            "plazer_graphql_operation_duration_seconds_count{operation=\"MetricsSeen\"} 2"
+        ));
+        assert!(!rendered.contains("MetricsOverflow"));
+        assert!(rendered
+            .contains("plazer_graphql_operation_duration_seconds_count{operation=\"other\"}"));
+    }
+
+    #[test]
+    fn test_db_targets() {
+        assert!(is_db_target("plazer_service::board::persist"));
+        assert!(is_db_target("plazer_service::persist"));
+        assert!(!is_db_target("plazer_service::board::schema"));
+        assert!(!is_db_target("surrealdb::dbs::executor"));
+    }
+}
@@ -1,82 +1,366 @@
 use std::{fmt::Debug, time::Duration};
 
+use chrono::{DateTime, Utc};
 use nanorand::{Rng as _, WyRand};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
 use tokio::time::sleep;
 use tracing::{debug, instrument, trace};
 
-use crate::{account::AccountMigration, board::BoardMigration, persist::Persist, prelude::*};
+use crate::{
+    account::AccountMigration, board::BoardMigration, job::JobMigration,
+    moderation::ModerationMigration, persist::Persist, prelude::*,
+};
 
-pub trait Migration: Sized + Default + Serialize + DeserializeOwned + Debug + Send + Sync {
+pub trait Migration:
+    Sized + Clone + Default + Serialize + DeserializeOwned + Debug + Send + Sync
+{
     const SUBSYSTEM: &'static str;
 
     fn next(self) -> Option<Self>;
     fn build(&self, statements: &mut Vec<srql::Statement>);
+
+    /// Builds the statements that undo this step, returning whether it can be
+    /// undone. Steps can't be undone unless this is implemented.
+    fn build_down(&self, _statements: &mut Vec<srql::Statement>) -> bool {
+        false
+    }
 }
 
 pub struct Migrations<'a> {
     persist: &'a Persist,
+    subsystem: Option<&'a str>,
 }
 
-static UPDATE_TABLE: &str = "updates";
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error(transparent)]
+    Db(#[from] surrealdb::Error),
+    #[error("Subsystem {subsystem} has no step {step:?}")]
+    UnknownStep {
+        subsystem: &'static str,
+        step: String,
+    },
+    #[error("Step {step} of subsystem {subsystem} cannot be rolled back")]
+    Irreversible {
+        subsystem: &'static str,
+        step: String,
+    },
+}
+
+pub static UPDATE_TABLE: &str = "updates";
+
+/// The subsystems that have migrations, in the order that they are run.
+pub static SUBSYSTEMS: &[&str] = &[
+    AccountMigration::SUBSYSTEM,
+    BoardMigration::SUBSYSTEM,
+    ModerationMigration::SUBSYSTEM,
+    JobMigration::SUBSYSTEM,
+];
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
 struct Update<M> {
-    current: M,
+    /// The last step that was applied. This is unset once every step has been
+    /// rolled back.
+    current: Option<M>,
+    #[serde(default = "Vec::new")]
+    history: Vec<UpdateHistory<M>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateHistory<M> {
+    timestamp: Option<DateTime<Utc>>,
+    update: M,
+    #[serde(default)]
+    rollback: bool,
+}
+
+/// The state of the migrations for a subsystem.
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub subsystem: &'static str,
+    /// The last step that was applied, if any have been.
+    pub current: Option<String>,
+    /// The steps that have been applied or rolled back, oldest first.
+    pub history: Vec<MigrationRecord>,
+    /// The steps that have yet to be applied, in the order they will be.
+    pub pending: Vec<MigrationStep>,
 }
 
-impl Migrations<'_> {
+#[derive(Debug, Clone)]
+pub struct MigrationRecord {
+    pub step: String,
+    /// Whether the step was rolled back, rather than applied.
+    pub rollback: bool,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// A single migration step, along with the query that applies or undoes it.
+#[derive(Debug, Clone)]
+pub struct MigrationStep {
+    pub subsystem: &'static str,
+    pub step: String,
+    pub query: srql::Query,
+    /// The step that has to be the subsystem's current step for this one to
+    /// run, if any.
+    requires: Option<String>,
+}
+
+impl<'a> Migrations<'a> {
+    /// Creates migrations for a single subsystem, or for all of them if
+    /// `subsystem` is `None`.
+    pub fn new(persist: &'a Persist, subsystem: Option<&'a str>) -> Self {
+        Self { persist, subsystem }
+    }
+
     #[instrument(skip_all)]
-    pub async fn run(persist: &Persist) -> surrealdb::Result<()> {
-        let migrations = Migrations { persist };
+    pub async fn run(persist: &Persist) -> std::result::Result<(), MigrationError> {
+        Migrations::new(persist, None).apply().await?;
+        Ok(())
+    }
 
+    /// Applies all of the pending migration steps, returning the steps that
+    /// were applied.
+    #[instrument(skip_all, fields(subsystem = self.subsystem))]
+    pub async fn apply(&self) -> std::result::Result<Vec<MigrationStep>, MigrationError> {
+        self.apply_to(None).await
+    }
+
+    /// Applies the pending migration steps up to and including `target`, or
+    /// all of them if there is no target, returning the steps that were
+    /// applied.
+    #[instrument(skip_all, fields(subsystem = self.subsystem))]
+    pub async fn apply_to(
+        &self,
+        target: Option<&str>,
+    ) -> std::result::Result<Vec<MigrationStep>, MigrationError> {
         debug!("Running migrations");
-        migrations.iterate::<AccountMigration>().await?;
-        migrations.iterate::<BoardMigration>().await?;
+        let mut applied = vec![];
+        applied.extend(self.iterate::<AccountMigration>(target).await?);
+        applied.extend(self.iterate::<BoardMigration>(target).await?);
+        applied.extend(self.iterate::<ModerationMigration>(target).await?);
+        applied.extend(self.iterate::<JobMigration>(target).await?);
         debug!("Migrations complete");
 
-        Ok(())
+        Ok(applied)
+    }
+
+    /// Undoes the steps that were applied after `target`, or every step if
+    /// there is no target, returning the steps that were undone.
+    ///
+    /// Nothing is undone if any of the steps can't be.
+    #[instrument(skip_all, fields(subsystem = self.subsystem))]
+    pub async fn rollback(
+        &self,
+        target: Option<&str>,
+    ) -> std::result::Result<Vec<MigrationStep>, MigrationError> {
+        let mut undone = vec![];
+        'plan: loop {
+            for step in self.plan_rollback(target).await? {
+                // Another instance got there first, so work out what is left
+                // to undo again.
+                if !self.execute(&step).await? {
+                    continue 'plan;
+                }
+                undone.push(step);
+            }
+
+            return Ok(undone);
+        }
+    }
+
+    /// Works out the steps that rolling back to `target` would undo, in the
+    /// order they would be undone, without undoing anything. Subsystems are
+    /// rolled back in the reverse of the order that they are run in.
+    #[instrument(skip_all, fields(subsystem = self.subsystem))]
+    pub async fn plan_rollback(
+        &self,
+        target: Option<&str>,
+    ) -> std::result::Result<Vec<MigrationStep>, MigrationError> {
+        let mut plan = vec![];
+        plan.extend(self.rollback_plan::<JobMigration>(target).await?);
+        plan.extend(self.rollback_plan::<ModerationMigration>(target).await?);
+        plan.extend(self.rollback_plan::<BoardMigration>(target).await?);
+        plan.extend(self.rollback_plan::<AccountMigration>(target).await?);
+
+        Ok(plan)
+    }
+
+    /// Gets the state of the migrations for each subsystem, without applying
+    /// anything.
+    #[instrument(skip_all, fields(subsystem = self.subsystem))]
+    pub async fn status(&self) -> surrealdb::Result<Vec<MigrationStatus>> {
+        let mut status = vec![];
+        status.extend(self.status_of::<AccountMigration>().await?);
+        status.extend(self.status_of::<BoardMigration>().await?);
+        status.extend(self.status_of::<ModerationMigration>().await?);
+        status.extend(self.status_of::<JobMigration>().await?);
+
+        Ok(status)
+    }
+
+    fn selected<M: Migration>(&self) -> bool {
+        match self.subsystem {
+            Some(subsystem) => subsystem == M::SUBSYSTEM,
+            None => true,
+        }
+    }
+
+    async fn status_of<M: Migration>(&self) -> surrealdb::Result<Option<MigrationStatus>> {
+        if !self.selected::<M>() {
+            return Ok(None);
+        }
+
+        let (current, history) = match self.current::<M>().await? {
+            Some(Update { current, history }) => (current, history),
+            None => (None, vec![]),
+        };
+
+        let mut pending = vec![];
+        let mut next = match &current {
+            Some(current) => current.clone().next(),
+            None => Some(M::default()),
+        };
+        while let Some(step) = next {
+            pending.push(MigrationStep::up(&step)?);
+            next = step.next();
+        }
+
+        Ok(Some(MigrationStatus {
+            subsystem: M::SUBSYSTEM,
+            current: current.map(|current| format!("{current:?}")),
+            history: history
+                .into_iter()
+                .map(
+                    |UpdateHistory {
+                         timestamp,
+                         update,
+                         rollback,
+                     }| MigrationRecord {
+                        step: format!("{update:?}"),
+                        rollback,
+                        timestamp,
+                    },
+                )
+                .collect(),
+            pending,
+        }))
     }
 
     #[instrument(skip_all, fields(subsystem = M::SUBSYSTEM))]
-    async fn iterate<M: Migration>(&self) -> surrealdb::Result<()> {
-        let mut prng = WyRand::new();
-        let mut migrated = false;
+    async fn iterate<M: Migration>(
+        &self,
+        target: Option<&str>,
+    ) -> std::result::Result<Vec<MigrationStep>, MigrationError> {
+        let mut applied = vec![];
+        if !self.selected::<M>() {
+            return Ok(applied);
+        }
+
+        let steps = steps::<M>();
+        let last = match target {
+            Some(target) => step_index(&steps, target)?,
+            None => steps.len(),
+        };
         while let Some(update) = self.next_update::<M>().await? {
+            if step_index(&steps, &format!("{update:?}"))? > last {
+                break;
+            }
+            let step = MigrationStep::up(&update)?;
+            if self.execute(&step).await? {
+                applied.push(step);
+            }
+        }
+
+        if applied.is_empty() {
+            debug!("No migrations to run");
+        } else {
+            debug!("Migration complete");
+        }
+
+        Ok(applied)
+    }
+
+    /// Works out the steps that need to be undone to roll a subsystem back to
+    /// `target`, most recent first.
+    async fn rollback_plan<M: Migration>(
+        &self,
+        target: Option<&str>,
+    ) -> std::result::Result<Vec<MigrationStep>, MigrationError> {
+        if !self.selected::<M>() {
+            return Ok(vec![]);
+        }
+
+        let steps = steps::<M>();
+        let target = match target {
+            Some(target) => Some(step_index(&steps, target)?),
+            None => None,
+        };
+        let current = match self.current::<M>().await? {
+            Some(Update {
+                current: Some(current),
+                ..
+            }) => Some(step_index(&steps, &format!("{current:?}"))?),
+            _ => None,
+        };
+
+        let mut plan = vec![];
+        let Some(current) = current else {
+            return Ok(plan);
+        };
+        let first = match target {
+            Some(target) => target + 1,
+            None => 0,
+        };
+        for index in (first..=current).rev() {
+            let previous = index.checked_sub(1).map(|i| &steps[i]);
+            plan.push(MigrationStep::down(&steps[index], previous)?);
+        }
+
+        Ok(plan)
+    }
+
+    /// Runs a step while holding the subsystem's lock, waiting for the lock if
+    /// another instance holds it.
+    ///
+    /// The step isn't run if another instance changed the subsystem's current
+    /// step while waiting for the lock, in which case `false` is returned and
+    /// the next step has to be worked out again.
+    async fn execute(&self, step: &MigrationStep) -> surrealdb::Result<bool> {
+        let mut prng = WyRand::new();
+        loop {
             if let Some(res) = self
                 .persist
-                .execute_in_lock(M::SUBSYSTEM, || async {
-                    let mut statements = vec![srql::Statement::Begin(srql::BeginStatement)];
-                    update.build(&mut statements);
-                    statements.push(srql::Statement::Update(iterate_complete_update(
-                        M::SUBSYSTEM,
-                        srql::to_value(&update)?,
-                    )));
-                    statements.push(srql::Statement::Commit(srql::CommitStatement));
-
-                    self.persist.db().query(srql::query(statements)).await
+                .execute_in_lock(step.subsystem, || async {
+                    let update: Option<Update<String>> = self
+                        .persist
+                        .db()
+                        .select((UPDATE_TABLE, step.subsystem))
+                        .await?;
+                    if update.and_then(|update| update.current) != step.requires {
+                        debug!(step = step.step, "Migration step already run");
+                        return Ok(false);
+                    }
+
+                    self.persist.db().query(step.query.clone()).await?.check()?;
+                    Ok(true)
                 })
                 .await?
             {
-                res?.check()?;
-                migrated = true;
-            } else {
-                trace!("Migration locked, sleeping");
-                // Introduce a bit of jitter to avoid thundering herd.
-                sleep(Duration::from_millis(
-                    5000 + prng.generate_range(0..=10_000),
-                ))
-                .await;
+                return res;
             }
-        }
 
-        if migrated {
-            debug!("Migration complete");
-        } else {
-            debug!("No migrations to run");
+            trace!("Migration locked, sleeping");
+            // Introduce a bit of jitter to avoid thundering herd.
+            sleep(Duration::from_millis(
+                5000 + prng.generate_range(0..=10_000),
+            ))
+            .await;
         }
+    }
 
-        Ok(())
+    async fn current<M: Migration>(&self) -> surrealdb::Result<Option<Update<M>>> {
+        self.persist.db().select((UPDATE_TABLE, M::SUBSYSTEM)).await
     }
 
     #[instrument(skip_all)]
@@ -84,11 +368,10 @@ impl Migrations<'_> {
     where
         M: Migration,
     {
-        if let Some::<Update<M>>(Update { current }) = self
-            .persist
-            .db()
-            .select((UPDATE_TABLE, M::SUBSYSTEM))
-            .await?
+        if let Some(Update {
+            current: Some(current),
+            ..
+        }) = self.current::<M>().await?
         {
             if let Some(next) = current.next() {
                 debug!(?next, "Next migration step");
@@ -104,6 +387,88 @@ impl Migrations<'_> {
     }
 }
 
+/// Every step of a subsystem's migrations, in order.
+fn steps<M: Migration>() -> Vec<M> {
+    let mut steps = vec![];
+    let mut next = Some(M::default());
+    while let Some(step) = next {
+        steps.push(step.clone());
+        next = step.next();
+    }
+    steps
+}
+
+/// The position of the step with the given name in a subsystem's migrations.
+fn step_index<M: Migration>(steps: &[M], name: &str) -> std::result::Result<usize, MigrationError> {
+    steps
+        .iter()
+        .position(|step| format!("{step:?}") == name)
+        .ok_or_else(|| MigrationError::UnknownStep {
+            subsystem: M::SUBSYSTEM,
+            step: name.to_owned(),
+        })
+}
+
+impl MigrationStep {
+    /// Builds the query for a step, which applies it and records it as the
+    /// subsystem's current step in a single transaction.
+    fn up<M: Migration>(update: &M) -> surrealdb::Result<Self> {
+        let mut statements = vec![srql::Statement::Begin(srql::BeginStatement)];
+        update.build(&mut statements);
+        statements.push(srql::Statement::Update(iterate_complete_update(
+            M::SUBSYSTEM,
+            srql::to_value(update).map_err(SrlError::from)?,
+        )));
+        statements.push(srql::Statement::Commit(srql::CommitStatement));
+
+        let step = format!("{update:?}");
+        let requires = steps::<M>()
+            .into_iter()
+            .map(|step| format!("{step:?}"))
+            .take_while(|previous| *previous != step)
+            .last();
+        Ok(Self {
+            subsystem: M::SUBSYSTEM,
+            step,
+            query: srql::query(statements),
+            requires,
+        })
+    }
+
+    /// Builds the query that undoes a step, and records `previous` as the
+    /// subsystem's current step in a single transaction.
+    fn down<M: Migration>(
+        update: &M,
+        previous: Option<&M>,
+    ) -> std::result::Result<Self, MigrationError> {
+        let mut statements = vec![srql::Statement::Begin(srql::BeginStatement)];
+        if !update.build_down(&mut statements) {
+            return Err(MigrationError::Irreversible {
+                subsystem: M::SUBSYSTEM,
+                step: format!("{update:?}"),
+            });
+        }
+        let previous = match previous {
+            Some(previous) => srql::to_value(previous).map_err(SrlError::from)?,
+            None => srql::Value::None,
+        };
+        statements.push(srql::Statement::Update(rollback_complete_update(
+            M::SUBSYSTEM,
+            previous,
+            srql::to_value(update).map_err(SrlError::from)?,
+        )));
+        statements.push(srql::Statement::Commit(srql::CommitStatement));
+
+        let step = format!("{update:?}");
+        Ok(Self {
+            subsystem: M::SUBSYSTEM,
+            requires: Some(step.clone()),
+            step,
+            query: srql::query(statements),
+        })
+    }
+}
+
 fn iterate_complete_update(subsystem: &str, update: srql::Value) -> srql::UpdateStatement {
     srql::UpdateStatement {
         what: srql::thing((UPDATE_TABLE, subsystem)),
@@ -126,3 +491,209 @@ fn iterate_complete_update(subsystem: &str, update: srql::Value) -> srql::Update
         ..Default::default()
     }
 }
+
+fn rollback_complete_update(
+    subsystem: &str,
+    previous: srql::Value,
+    update: srql::Value,
+) -> srql::UpdateStatement {
+    srql::UpdateStatement {
+        what: srql::thing((UPDATE_TABLE, subsystem)),
+        data: srql::Data::SetExpression(vec![
+            (srql::field("current"), srql::Operator::Equal, previous),
+            (
+                srql::field("history"),
+                srql::Operator::Inc,
+                srql::array([srql::object([
+                    ("timestamp".into(), srql::time_now()),
+                    ("update".into(), update),
+                    ("rollback".into(), srql::Value::Bool(true)),
+                ])]),
+            ),
+        ])
+        .into(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::ACC_TABLE_NAME,
+        board::BOARD_TABLE_NAME,
+        job::JOB_TABLE_NAME,
+        moderation::{LOG_TABLE_NAME, REPORT_TABLE_NAME},
+        persist::testing,
+    };
+
+    #[tokio::test]
+    async fn test_status_and_apply() {
+        let persist = testing::unmigrated().await;
+        let migrations = Migrations::new(&persist, None);
+
+        let status = migrations.status().await.unwrap();
+        assert_eq!(
+            status.iter().map(|s| s.subsystem).collect::<Vec<_>>(),
+            SUBSYSTEMS
+        );
+        assert!(status.iter().all(|s| s.current.is_none()));
+        let pending: Vec<_> = status.into_iter().flat_map(|s| s.pending).collect();
+        assert!(pending[0]
+            .query
+            .to_string()
+            .starts_with("BEGIN TRANSACTION;"));
+
+        // Showing the status doesn't apply anything.
+        let res = migrations.status().await.unwrap();
+        assert!(res.iter().all(|s| s.current.is_none()));
+
+        let applied = migrations.apply().await.unwrap();
+        assert_eq!(
+            applied.iter().map(|s| &s.step).collect::<Vec<_>>(),
+            pending.iter().map(|s| &s.step).collect::<Vec<_>>()
+        );
+
+        for status in migrations.status().await.unwrap() {
+            assert!(status.pending.is_empty());
+            assert_eq!(
+                status.current.as_ref(),
+                status.history.last().map(|h| &h.step)
+            );
+            assert!(status.history.iter().all(|h| h.timestamp.is_some()));
+        }
+        assert!(migrations.apply().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_subsystem() {
+        let persist = testing::unmigrated().await;
+
+        let applied = Migrations::new(&persist, Some(JobMigration::SUBSYSTEM))
+            .apply()
+            .await
+            .unwrap();
+        assert!(!applied.is_empty());
+        assert!(applied
+            .iter()
+            .all(|s| s.subsystem == JobMigration::SUBSYSTEM));
+
+        let status = Migrations::new(&persist, None).status().await.unwrap();
+        for status in status {
+            assert_eq!(
+                status.pending.is_empty(),
+                status.subsystem == JobMigration::SUBSYSTEM
+            );
+        }
+    }
+    /// What migrations define on each table, so that it can be checked that
+    /// rolling back a step leaves things as they were before it. Tables
+    /// themselves are left out, as they are defined implicitly when first
+    /// used and so aren't removed.
+    async fn schema(persist: &Persist) -> Vec<srql::Value> {
+        let mut schema = vec![];
+        for table in [
+            ACC_TABLE_NAME,
+            BOARD_TABLE_NAME,
+            JOB_TABLE_NAME,
+            LOG_TABLE_NAME,
+            REPORT_TABLE_NAME,
+        ] {
+            let mut res = persist
+                .db()
+                .query(srql::Statement::Info(srql::InfoStatement::Tb(table.into())))
+                .await
+                .unwrap();
+            schema.push(res.take::<srql::Value>(0).unwrap());
+        }
+        schema
+    }
+
+    /// Applies each of a subsystem's steps in turn, then rolls each of them
+    /// back, checking that each rollback restores what was there before the
+    /// step was applied.
+    async fn check_up_and_down<M: Migration>() {
+        let persist = testing::unmigrated().await;
+        let migrations = Migrations::new(&persist, Some(M::SUBSYSTEM));
+        let steps = steps::<M>();
+
+        let mut schemas = vec![schema(&persist).await];
+        for step in &steps {
+            assert!(migrations
+                .execute(&MigrationStep::up(step).unwrap())
+                .await
+                .unwrap());
+            schemas.push(schema(&persist).await);
+        }
+        assert!(migrations.status().await.unwrap()[0].pending.is_empty());
+
+        for (index, step) in steps.iter().enumerate().rev() {
+            let target = index.checked_sub(1).map(|i| format!("{:?}", steps[i]));
+            let undone = migrations.rollback(target.as_deref()).await.unwrap();
+            assert_eq!(undone.len(), 1);
+            assert_eq!(undone[0].step, format!("{step:?}"));
+            assert_eq!(schema(&persist).await, schemas[index], "{step:?}");
+
+            let status = migrations.status().await.unwrap().remove(0);
+            assert_eq!(status.current, target);
+            assert!(status.history.last().unwrap().rollback);
+        }
+
+        // Everything can be applied again once it has all been rolled back.
+        assert_eq!(migrations.apply().await.unwrap().len(), steps.len());
+        assert_eq!(schema(&persist).await, schemas[steps.len()]);
+    }
+
+    #[tokio::test]
+    async fn test_up_and_down() {
+        check_up_and_down::<AccountMigration>().await;
+        check_up_and_down::<BoardMigration>().await;
+        check_up_and_down::<ModerationMigration>().await;
+        check_up_and_down::<JobMigration>().await;
+    }
+
+    #[tokio::test]
+    async fn test_step_already_run() {
+        let persist = testing::unmigrated().await;
+        let migrations = Migrations::new(&persist, Some(AccountMigration::SUBSYSTEM));
+        let step = MigrationStep::up(&AccountMigration::default()).unwrap();
+        assert!(migrations.execute(&step).await.unwrap());
+
+        // Another instance that worked out the same step before the first one
+        // ran it doesn't run it again.
+        assert!(!migrations.execute(&step).await.unwrap());
+        let status = migrations.status().await.unwrap().remove(0);
+        assert_eq!(status.history.len(), 1);
+
+        let applied = migrations.apply().await.unwrap();
+        assert_eq!(
+            applied.iter().map(|s| s.step.as_str()).collect::<Vec<_>>(),
+            ["Restriction", "NormalisedUserId"]
+        );
+
+        let step = MigrationStep::down(&AccountMigration::Restriction, None).unwrap();
+        assert!(!migrations.execute(&step).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rollback() {
+        let persist = testing::persist().await;
+        let migrations = Migrations::new(&persist, Some(AccountMigration::SUBSYSTEM));
+
+        let undone = migrations.rollback(Some("Init")).await.unwrap();
+        assert_eq!(
+            undone.iter().map(|s| s.step.as_str()).collect::<Vec<_>>(),
+            ["NormalisedUserId", "Restriction"]
+        );
+        let status = migrations.status().await.unwrap().remove(0);
+        assert_eq!(status.current.as_deref(), Some("Init"));
+        assert_eq!(status.pending.len(), 2);
+
+        // Rolling back to a step that is already current does nothing.
+        assert!(migrations.rollback(Some("Init")).await.unwrap().is_empty());
+        assert!(matches!(
+            migrations.rollback(Some("Missing")).await,
+            Err(MigrationError::UnknownStep { .. })
+        ));
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use super::{LOG_TABLE_NAME, REPORT_TABLE_NAME};
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModerationMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for ModerationMigration {
+    const SUBSYSTEM: &'static str = "subsys_moderation";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use ModerationMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+
+    fn build_down(&self, statements: &mut Vec<srql::Statement>) -> bool {
+        use ModerationMigration as S;
+        match self {
+            S::Init => Self::build_init_down(statements),
+        }
+        true
+    }
+}
+
+impl ModerationMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "report_board_id_index",
+            REPORT_TABLE_NAME,
+            [srql::field("board_id"), srql::field("status")],
+        ));
+        statements.push(srql::define_index(
+            "moderation_log_board_id_index",
+            LOG_TABLE_NAME,
+            [srql::field("board_id")],
+        ));
+
+        // The log is append-only, so any change to an existing entry is
+        // rejected, which also rolls back the transaction it was part of.
+        statements.push(srql::Statement::Define(srql::DefineStatement::Event(
+            srql::DefineEventStatement {
+                name: "moderation_log_immutable".into(),
+                what: LOG_TABLE_NAME.into(),
+                when: srql::expr(
+                    srql::Param::from("event"),
+                    srql::Operator::NotEqual,
+                    srql::string("CREATE"),
+                ),
+                // SurrealDB doesn't expose the AST for blocks, so this constant
+                // one has to be parsed instead.
+                then: srql::Values(vec![srql::value(
+                    r#"{ THROW "The moderation log cannot be changed" }"#,
+                )
+                .expect("block should be valid")]),
+                ..Default::default()
+            },
+        )));
+    }
+
+    fn build_init_down(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::remove_event(
+            "moderation_log_immutable",
+            LOG_TABLE_NAME,
+        ));
+        statements.push(srql::remove_index(
+            "moderation_log_board_id_index",
+            LOG_TABLE_NAME,
+        ));
+        statements.push(srql::remove_index(
+            "report_board_id_index",
+            REPORT_TABLE_NAME,
+        ));
+    }
+}
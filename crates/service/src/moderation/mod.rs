@@ -0,0 +1,13 @@
+mod migration;
+mod models;
+mod persist;
+mod schema;
+
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static REPORT_TABLE_NAME: &str = "report";
+pub static LOG_TABLE_NAME: &str = "moderation_log";
+pub static BAN_TABLE_NAME: &str = "board_ban";
@@ -0,0 +1,392 @@
+use async_graphql::{ComplexObject, Enum, InputObject, OneofObject, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::{BAN_TABLE_NAME, LOG_TABLE_NAME, REPORT_TABLE_NAME};
+use crate::{id_obj_impls, prelude::*, query::OpaqueCursor};
+
+pub type ReportCursor = OpaqueCursor<String>;
+pub type ModerationLogCursor = OpaqueCursor<String>;
+
+/// The state of a report in the moderation queue.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    /// The report is waiting for a moderator.
+    Open,
+    /// A moderator has acted on the report.
+    Resolved,
+    /// A moderator has decided that no action is needed.
+    Dismissed,
+}
+
+impl QueryValue for ReportStatus {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|v| (field, srql::Operator::Equal, v))
+    }
+}
+
+/// A report of abusive content, waiting to be reviewed by the moderators of
+/// the board that the content is in.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Report {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub reporter_id: Option<Thing>,
+    #[graphql(skip)]
+    pub board_id: Option<Thing>,
+    #[graphql(skip)]
+    pub post_id: Option<Thing>,
+    #[graphql(skip)]
+    pub moderator_id: Option<Thing>,
+
+    /// Why the content was reported.
+    pub reason: String,
+    /// The current state of the report.
+    pub status: ReportStatus,
+    /// A note left by the moderator that closed the report.
+    pub note: Option<String>,
+
+    /// A timestamp indicating the last time the report was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Report {
+    /// The report's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the board that the reported content is in. If the reported
+    /// content is a board, then this is the board itself.
+    async fn board_id(&self) -> Option<ID> {
+        self.board_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the reported post, if a post was reported.
+    async fn post_id(&self) -> Option<ID> {
+        self.post_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the moderator that closed the report.
+    async fn moderator_id(&self) -> Option<ID> {
+        self.moderator_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+}
+
+id_obj_impls!(Report);
+
+impl Report {
+    pub fn create(
+        reporter_id: Thing,
+        board_id: Option<Thing>,
+        post_id: Option<Thing>,
+        reason: String,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        reporter_id.push_field(srql::field("reporter_id"), &mut create);
+        board_id.push_field(srql::field("board_id"), &mut create);
+        post_id.push_field(srql::field("post_id"), &mut create);
+        reason.push_field(srql::field("reason"), &mut create);
+        ReportStatus::Open.push_field(srql::field("status"), &mut create);
+        srql::obj_create_query(REPORT_TABLE_NAME, create)
+    }
+
+    pub fn close(
+        &self,
+        status: ReportStatus,
+        moderator_id: Thing,
+        note: Option<String>,
+    ) -> Option<srql::UpdateStatement> {
+        let mut update = vec![];
+        status.push_field(srql::field("status"), &mut update);
+        moderator_id.push_field(srql::field("moderator_id"), &mut update);
+        note.push_field(srql::field("note"), &mut update);
+        srql::obj_update_query(self.id.clone(), update)
+    }
+}
+
+/// The content being reported.
+#[derive(OneofObject, Debug, Clone, PartialEq, Eq)]
+pub enum ReportTarget {
+    /// Report a post by its ID.
+    Post(ID),
+    /// Report a board by its ID.
+    Board(ID),
+}
+
+/// The information needed to report content.
+#[derive(InputObject, Debug, Clone, PartialEq, Eq)]
+pub struct CreateReport {
+    /// The content being reported.
+    pub target: ReportTarget,
+    /// Why the content is being reported.
+    #[graphql(validator(min_length = 1, max_length = 4096))]
+    pub reason: String,
+}
+
+/// An action that a moderator can take when resolving a report.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResolveAction {
+    /// Hide the reported content from everyone except moderators.
+    HideContent,
+    /// Permanently delete the reported post. Boards can't be deleted this way.
+    DeleteContent,
+    /// Ban the author of the reported post from the board. Not allowed for
+    /// reported boards.
+    BanAuthor,
+}
+
+/// The information needed to resolve a report.
+#[derive(InputObject, Debug, Clone, PartialEq, Eq)]
+pub struct ResolveReport {
+    /// The actions to take against the reported content.
+    #[graphql(validator(min_items = 1, max_items = 3))]
+    pub actions: Vec<ResolveAction>,
+    /// A note explaining the decision. This is also used as the ban reason.
+    #[graphql(validator(max_length = 4096))]
+    pub note: Option<String>,
+    /// When a ban from the `BAN_AUTHOR` action should expire. If not given,
+    /// the ban is permanent.
+    pub ban_expires_at: Option<DateTime<Utc>>,
+}
+
+/// An action recorded in the moderation log.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// Content was hidden.
+    HideContent,
+    /// Content was deleted.
+    DeleteContent,
+    /// An account was banned from the board.
+    BanAccount,
+    /// An account's ban from the board was lifted.
+    UnbanAccount,
+    /// A report was dismissed without action.
+    DismissReport,
+    /// A post was pinned to the top of the board.
+    PinPost,
+    /// A post was unpinned.
+    UnpinPost,
+    /// A post was locked.
+    LockPost,
+    /// A post was unlocked.
+    UnlockPost,
+}
+
+impl From<ResolveAction> for ModerationAction {
+    fn from(action: ResolveAction) -> Self {
+        match action {
+            ResolveAction::HideContent => Self::HideContent,
+            ResolveAction::DeleteContent => Self::DeleteContent,
+            ResolveAction::BanAuthor => Self::BanAccount,
+        }
+    }
+}
+
+impl QueryValue for ModerationAction {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|v| (field, srql::Operator::Equal, v))
+    }
+}
+
+/// An entry in a board's moderation log.
+///
+/// Entries cannot be changed or removed once they have been recorded.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct ModerationLogEntry {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub board_id: Option<Thing>,
+    #[graphql(skip)]
+    pub moderator_id: Thing,
+    #[graphql(skip)]
+    pub report_id: Option<Thing>,
+    #[graphql(skip)]
+    pub post_id: Option<Thing>,
+    #[graphql(skip)]
+    pub account_id: Option<Thing>,
+
+    /// The action that was taken.
+    pub action: ModerationAction,
+    /// The note left by the moderator.
+    pub note: Option<String>,
+    /// A timestamp indicating when the action was taken.
+    pub created_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl ModerationLogEntry {
+    /// The entry's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the board the action was taken in.
+    async fn board_id(&self) -> Option<ID> {
+        self.board_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the moderator that took the action.
+    async fn moderator_id(&self) -> ID {
+        self.moderator_id.to_gql_id()
+    }
+
+    /// The ID of the report that led to the action, if any.
+    async fn report_id(&self) -> Option<ID> {
+        self.report_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the post the action was taken against, if any.
+    async fn post_id(&self) -> Option<ID> {
+        self.post_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the account the action was taken against, if any.
+    async fn account_id(&self) -> Option<ID> {
+        self.account_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+}
+
+id_obj_impls!(ModerationLogEntry);
+
+#[derive(Debug, Default)]
+pub struct LogTarget {
+    pub board: Option<Thing>,
+    pub report: Option<Thing>,
+    pub post: Option<Thing>,
+    pub account: Option<Thing>,
+}
+
+impl ModerationLogEntry {
+    pub fn create(
+        moderator_id: Thing,
+        action: ModerationAction,
+        target: LogTarget,
+        note: Option<String>,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        moderator_id.push_field(srql::field("moderator_id"), &mut create);
+        action.push_field(srql::field("action"), &mut create);
+        target
+            .board
+            .push_field(srql::field("board_id"), &mut create);
+        target
+            .report
+            .push_field(srql::field("report_id"), &mut create);
+        target.post.push_field(srql::field("post_id"), &mut create);
+        target
+            .account
+            .push_field(srql::field("account_id"), &mut create);
+        note.push_field(srql::field("note"), &mut create);
+        create.push((
+            srql::field("created_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ));
+        srql::obj_create_query(LOG_TABLE_NAME, create)
+    }
+}
+
+/// A ban preventing an account from posting in a board.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct BoardBan {
+    #[graphql(skip)]
+    pub board_id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+
+    /// Why the account was banned.
+    pub reason: Option<String>,
+    /// When the ban expires. If not present, the ban is permanent.
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// A timestamp indicating the last time the ban was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl BoardBan {
+    /// The ID of the board the account is banned from.
+    async fn board_id(&self) -> ID {
+        self.board_id.to_gql_id()
+    }
+
+    /// The ID of the banned account.
+    async fn account_id(&self) -> ID {
+        self.account_id.to_gql_id()
+    }
+}
+
+impl BoardBan {
+    /// Bans are keyed on the board and account, so there is only ever one ban
+    /// for an account in a board and banning again replaces it.
+    pub fn thing(board_id: &Thing, account_id: &Thing) -> Thing {
+        Thing {
+            tb: BAN_TABLE_NAME.into(),
+            id: srql::Id::Array(
+                vec![
+                    srql::Value::Thing(board_id.clone()),
+                    srql::Value::Thing(account_id.clone()),
+                ]
+                .into(),
+            ),
+        }
+    }
+
+    pub fn upsert(
+        board_id: Thing,
+        account_id: Thing,
+        reason: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> srql::UpdateStatement {
+        let mut data = vec![];
+        let thing = Self::thing(&board_id, &account_id);
+        board_id.push_field(srql::field("board_id"), &mut data);
+        account_id.push_field(srql::field("account_id"), &mut data);
+        // Both of these are always set so that banning again clears them.
+        data.push((
+            srql::field("reason"),
+            srql::Operator::Equal,
+            reason.map_or(srql::Value::None, Into::into),
+        ));
+        data.push((
+            srql::field("expires_at"),
+            srql::Operator::Equal,
+            expires_at.map_or(srql::Value::None, |expires_at| {
+                srql::Value::Datetime(srql::Datetime(expires_at))
+            }),
+        ));
+        data.push((
+            srql::field("updated_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ));
+
+        srql::UpdateStatement {
+            what: srql::thing(thing),
+            data: srql::Data::SetExpression(data).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at > Utc::now(),
+            None => true,
+        }
+    }
+}
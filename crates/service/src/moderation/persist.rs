@@ -0,0 +1,697 @@
+#[cfg(test)]
+mod tests;
+
+use async_graphql::{
+    connection::{Connection, Edge},
+    ID,
+};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+
+use super::{
+    BoardBan, CreateReport, LogTarget, ModerationAction, ModerationLogCursor, ModerationLogEntry,
+    Report, ReportCursor, ReportStatus, ReportTarget, ResolveAction, ResolveReport, BAN_TABLE_NAME,
+    LOG_TABLE_NAME, REPORT_TABLE_NAME,
+};
+use crate::{
+    account::CurrentAccount,
+    board::{Board, BoardPersist, BOARD_TABLE_NAME},
+    persist::Persist,
+    post::{Post, PostPersist, POST_TABLE_NAME},
+    prelude::*,
+    query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+};
+
+pub struct ModerationPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> ModerationPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get_report(&self, id: &str) -> Result<Option<Report>> {
+        Ok(self.persist.db().select((REPORT_TABLE_NAME, id)).await?)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn report(&self, report: CreateReport) -> Result<Option<Report>> {
+        let reporter_id = self.current.id()?.to_account_thing();
+
+        // Only content that the current account can read can be reported.
+        let (board_id, post_id) = match report.target {
+            ReportTarget::Post(id) => {
+                let Some(post) = self.post_persist().get(&id).await? else {
+                    return Ok(None);
+                };
+                (post.board_id, Some(post.id))
+            }
+            ReportTarget::Board(id) => {
+                let board_persist = self.board_persist();
+                let Some(board) = board_persist
+                    .get(&id)
+                    .await?
+                    .filter(|board| board_persist.can_read(board))
+                else {
+                    return Ok(None);
+                };
+                (Some(board.id), None)
+            }
+        };
+
+        let report = self
+            .persist
+            .db()
+            .query(Report::create(
+                reporter_id,
+                board_id,
+                post_id,
+                report.reason,
+            ))
+            .await?
+            .take(0)?;
+
+        match report {
+            Some(report) => Ok(Some(report)),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    /// Lists the open reports in all of the boards that the current account
+    /// moderates.
+    #[instrument(skip_all)]
+    pub fn queue(&self) -> ReportListRequest<'_> {
+        ReportListRequest::new(self.persist, self.current)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn resolve(&self, id: &str, resolve: ResolveReport) -> Result<Option<Report>> {
+        let Some((report, board)) = self.get_open_report(id).await? else {
+            return Ok(None);
+        };
+        let moderator_id = self.current.id()?.to_account_thing();
+
+        // The content might have been removed since it was reported, in which
+        // case the hide and delete actions don't need to do anything.
+        let (content, author_id) = match &report.post_id {
+            Some(post_id) => {
+                let post: Option<Post> = self.persist.db().select(post_id.clone()).await?;
+                let author_id = post.as_ref().and_then(|post| post.creator_id.clone());
+                (post.map(|post| post.id), author_id)
+            }
+            None => (Some(board.id.clone()), board.creator_id.clone()),
+        };
+
+        let mut actions = resolve.actions;
+        actions.sort_unstable();
+        actions.dedup();
+        // Boards can only be hidden. Deleting one is left to its owner, and
+        // its creator can't be banned from it.
+        if report.post_id.is_none()
+            && actions
+                .iter()
+                .any(|action| *action != ResolveAction::HideContent)
+        {
+            return Err(Error::InvalidReportAction);
+        }
+
+        let mut statements = vec![srql::trans_begin()];
+        if let Some(update) = report.close(
+            ReportStatus::Resolved,
+            moderator_id.clone(),
+            resolve.note.clone(),
+        ) {
+            statements.push(srql::Statement::Update(update));
+        }
+
+        for action in actions {
+            let mut target = LogTarget {
+                board: Some(board.id.clone()),
+                report: Some(report.id.clone()),
+                post: report.post_id.clone(),
+                ..Default::default()
+            };
+
+            match (action, &content) {
+                (ResolveAction::HideContent, Some(content)) => {
+                    statements.push(srql::Statement::Update(hide_statement(content.clone())));
+                }
+                (ResolveAction::DeleteContent, Some(content)) => {
+                    statements.push(srql::Statement::Delete(srql::DeleteStatement {
+                        what: srql::thing(content.clone()),
+                        output: srql::Output::None.into(),
+                        ..Default::default()
+                    }));
+                }
+                (ResolveAction::BanAuthor, _) => {
+                    let Some(author_id) = author_id.clone() else {
+                        return Err(Error::MissingIdent);
+                    };
+                    statements.push(srql::Statement::Update(BoardBan::upsert(
+                        board.id.clone(),
+                        author_id.clone(),
+                        resolve.note.clone(),
+                        resolve.ban_expires_at,
+                    )));
+                    target.account = Some(author_id);
+                }
+                (_, None) => {}
+            }
+
+            statements.push(srql::Statement::Create(ModerationLogEntry::create(
+                moderator_id.clone(),
+                action.into(),
+                target,
+                resolve.note.clone(),
+            )));
+        }
+        statements.push(srql::trans_end());
+
+        Ok(self.persist.db().query(statements).await?.take(0)?)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn dismiss(&self, id: &str, note: Option<String>) -> Result<Option<Report>> {
+        let Some((report, board)) = self.get_open_report(id).await? else {
+            return Ok(None);
+        };
+        let moderator_id = self.current.id()?.to_account_thing();
+
+        let mut statements = vec![srql::trans_begin()];
+        if let Some(update) =
+            report.close(ReportStatus::Dismissed, moderator_id.clone(), note.clone())
+        {
+            statements.push(srql::Statement::Update(update));
+        }
+        statements.push(srql::Statement::Create(ModerationLogEntry::create(
+            moderator_id,
+            ModerationAction::DismissReport,
+            LogTarget {
+                board: Some(board.id),
+                report: Some(report.id.clone()),
+                post: report.post_id.clone(),
+                ..Default::default()
+            },
+            note,
+        )));
+        statements.push(srql::trans_end());
+
+        Ok(self.persist.db().query(statements).await?.take(0)?)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn ban(
+        &self,
+        board_id: &str,
+        account_id: &ID,
+        reason: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<BoardBan>> {
+        let Some(board) = self.get_moderated_board(board_id).await? else {
+            return Ok(None);
+        };
+        let moderator_id = self.current.id()?.to_account_thing();
+        let account_id = account_id.to_account_thing();
+
+        let statements = vec![
+            srql::trans_begin(),
+            srql::Statement::Update(BoardBan::upsert(
+                board.id.clone(),
+                account_id.clone(),
+                reason.clone(),
+                expires_at,
+            )),
+            srql::Statement::Create(ModerationLogEntry::create(
+                moderator_id,
+                ModerationAction::BanAccount,
+                LogTarget {
+                    board: Some(board.id.clone()),
+                    account: Some(account_id.clone()),
+                    ..Default::default()
+                },
+                reason,
+            )),
+            srql::trans_end(),
+        ];
+        self.persist.db().query(statements).await?.check()?;
+
+        Ok(self
+            .persist
+            .db()
+            .select(BoardBan::thing(&board.id, &account_id))
+            .await?)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn unban(&self, board_id: &str, account_id: &ID) -> Result<Option<BoardBan>> {
+        let Some(board) = self.get_moderated_board(board_id).await? else {
+            return Ok(None);
+        };
+        let moderator_id = self.current.id()?.to_account_thing();
+        let account_id = account_id.to_account_thing();
+        let ban_id = BoardBan::thing(&board.id, &account_id);
+
+        let ban: Option<BoardBan> = self.persist.db().select(ban_id.clone()).await?;
+        if ban.is_none() {
+            return Ok(None);
+        }
+
+        let statements = vec![
+            srql::trans_begin(),
+            srql::Statement::Delete(srql::DeleteStatement {
+                what: srql::thing(ban_id),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            }),
+            srql::Statement::Create(ModerationLogEntry::create(
+                moderator_id,
+                ModerationAction::UnbanAccount,
+                LogTarget {
+                    board: Some(board.id),
+                    account: Some(account_id),
+                    ..Default::default()
+                },
+                None,
+            )),
+            srql::trans_end(),
+        ];
+        self.persist.db().query(statements).await?.check()?;
+
+        Ok(ban)
+    }
+
+    /// Pins or unpins a post, which lists it before all other posts in its
+    /// board.
+    #[instrument(skip_all)]
+    pub async fn pin_post(&self, post_id: &str, pinned: bool) -> Result<Option<Post>> {
+        let action = if pinned {
+            ModerationAction::PinPost
+        } else {
+            ModerationAction::UnpinPost
+        };
+        self.flag_post(post_id, PostFlag::Pinned, pinned, action)
+            .await
+    }
+
+    /// Locks or unlocks a post, which stops it from being changed.
+    #[instrument(skip_all)]
+    pub async fn lock_post(&self, post_id: &str, locked: bool) -> Result<Option<Post>> {
+        let action = if locked {
+            ModerationAction::LockPost
+        } else {
+            ModerationAction::UnlockPost
+        };
+        self.flag_post(post_id, PostFlag::Locked, locked, action)
+            .await
+    }
+
+    /// Whether the account is currently banned from posting in the board.
+    #[instrument(skip_all)]
+    pub async fn is_banned(
+        &self,
+        board_id: &srql::Thing,
+        account_id: &srql::Thing,
+    ) -> Result<bool> {
+        let ban: Option<BoardBan> = self
+            .persist
+            .db()
+            .select(BoardBan::thing(board_id, account_id))
+            .await?;
+        Ok(ban.is_some_and(|ban| ban.is_active()))
+    }
+
+    /// Lists the moderation log of a board. Only the board's moderators can
+    /// read the log.
+    #[instrument(skip_all)]
+    pub async fn log(&self, board_id: &str) -> Result<Option<ModerationLogListRequest<'_>>> {
+        let board = self.get_moderated_board(board_id).await?;
+        Ok(board.map(|board| ModerationLogListRequest::new(self.persist, board.id)))
+    }
+
+    async fn get_open_report(&self, id: &str) -> Result<Option<(Report, Board)>> {
+        self.current.id()?;
+
+        let Some(report) = self.get_report(id).await? else {
+            return Ok(None);
+        };
+
+        let board = match &report.board_id {
+            Some(board_id) => self.board_persist().get(&board_id.to_gql_id()).await?,
+            None => None,
+        };
+        let Some(board) = board.filter(|board| self.board_persist().can_moderate(board)) else {
+            return Err(Error::Unauthorized);
+        };
+
+        if report.status != ReportStatus::Open {
+            return Err(Error::ReportClosed);
+        }
+
+        Ok(Some((report, board)))
+    }
+
+    async fn flag_post(
+        &self,
+        post_id: &str,
+        flag: PostFlag,
+        set: bool,
+        action: ModerationAction,
+    ) -> Result<Option<Post>> {
+        self.current.id()?;
+
+        let post: Option<Post> = self.persist.db().select((POST_TABLE_NAME, post_id)).await?;
+        let Some(post) = post else {
+            return Ok(None);
+        };
+
+        // Posts outside of a board don't have any moderators.
+        let board = match &post.board_id {
+            Some(board_id) => self.get_moderated_board(&board_id.to_gql_id()).await?,
+            None => return Err(Error::Unauthorized),
+        };
+        let Some(board) = board else {
+            return Ok(None);
+        };
+
+        // Keep the original timestamp if the flag is already in place, as it
+        // decides the order of pinned posts.
+        if flag.is_set(&post) == set {
+            return Ok(Some(post));
+        }
+
+        let moderator_id = self.current.id()?.to_account_thing();
+        let statements = vec![
+            srql::trans_begin(),
+            srql::Statement::Update(srql::UpdateStatement {
+                what: srql::thing(post.id.clone()),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field(flag.field()),
+                    srql::Operator::Equal,
+                    if set {
+                        srql::time_now()
+                    } else {
+                        srql::Value::None
+                    },
+                )])
+                .into(),
+                ..Default::default()
+            }),
+            srql::Statement::Create(ModerationLogEntry::create(
+                moderator_id,
+                action,
+                LogTarget {
+                    board: Some(board.id),
+                    post: Some(post.id),
+                    ..Default::default()
+                },
+                None,
+            )),
+            srql::trans_end(),
+        ];
+
+        Ok(self.persist.db().query(statements).await?.take(0)?)
+    }
+
+    async fn get_moderated_board(&self, id: &str) -> Result<Option<Board>> {
+        self.current.id()?;
+
+        let Some(board) = self.board_persist().get(id).await? else {
+            return Ok(None);
+        };
+
+        if self.board_persist().can_moderate(&board) {
+            Ok(Some(board))
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+
+    fn board_persist(&self) -> BoardPersist<'_> {
+        BoardPersist::new(self.persist, self.current)
+    }
+
+    fn post_persist(&self) -> PostPersist<'_> {
+        PostPersist::new(self.persist, self.current)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PostFlag {
+    Pinned,
+    Locked,
+}
+
+impl PostFlag {
+    fn field(self) -> &'static str {
+        match self {
+            Self::Pinned => "pinned_at",
+            Self::Locked => "locked_at",
+        }
+    }
+
+    fn is_set(self, post: &Post) -> bool {
+        match self {
+            Self::Pinned => post.pinned_at.is_some(),
+            Self::Locked => post.locked_at.is_some(),
+        }
+    }
+}
+
+fn hide_statement(thing: srql::Thing) -> srql::UpdateStatement {
+    srql::UpdateStatement {
+        what: srql::thing(thing),
+        data: srql::Data::SetExpression(vec![(
+            srql::field("hidden_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        )])
+        .into(),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    }
+}
+
+/// Removes board bans that have expired, returning how many were removed.
+#[instrument(skip_all)]
+pub async fn purge_expired_bans(persist: &Persist) -> Result<usize> {
+    let purged: Vec<BoardBan> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(BAN_TABLE_NAME),
+            cond: srql::Cond(srql::expr(
+                srql::expr(
+                    srql::field("expires_at"),
+                    srql::Operator::NotEqual,
+                    srql::Value::None,
+                ),
+                srql::Operator::And,
+                srql::expr(
+                    srql::field("expires_at"),
+                    srql::Operator::LessThanOrEqual,
+                    srql::time_now(),
+                ),
+            ))
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    Ok(purged.len())
+}
+
+pub struct ReportListRequest<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+    pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+}
+
+impl<'a> ReportListRequest<'a> {
+    fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self {
+            persist,
+            current,
+            pagination: None,
+        }
+    }
+
+    pub fn with_pagination(
+        mut self,
+        args: impl Into<PaginationInput<OpaqueCursor<String>>>,
+    ) -> Self {
+        self.pagination = Some(args.into());
+        self
+    }
+
+    #[instrument(skip_all)]
+    pub async fn execute(self) -> Result<Connection<ReportCursor, Report>> {
+        let account_id = self.current.id()?.to_account_thing();
+
+        let PaginationOptions {
+            cond,
+            order,
+            limit,
+            result_slice_opts,
+        } = (self.pagination, REPORT_TABLE_NAME).into();
+
+        // Only the boards that the current account moderates are included.
+        let moderated = srql::SelectStatement {
+            expr: srql::Fields(
+                vec![srql::Field::Single {
+                    expr: srql::field("id").into(),
+                    alias: None,
+                }],
+                true,
+            ),
+            what: srql::table(BOARD_TABLE_NAME),
+            cond: srql::Cond(srql::expr(
+                srql::expr(
+                    srql::field("owner_id"),
+                    srql::Operator::Equal,
+                    account_id.clone(),
+                ),
+                srql::Operator::Or,
+                srql::expr(
+                    srql::field("moderator_ids"),
+                    srql::Operator::Contain,
+                    account_id,
+                ),
+            ))
+            .into(),
+            ..Default::default()
+        };
+
+        let cond = srql::cond_and(
+            cond,
+            srql::expr(
+                srql::expr(
+                    srql::field("status"),
+                    srql::Operator::Equal,
+                    srql::to_value(ReportStatus::Open).map_err(SrlError::from)?,
+                ),
+                srql::Operator::And,
+                srql::expr(
+                    srql::field("board_id"),
+                    srql::Operator::Inside,
+                    srql::Value::Subquery(Box::new(srql::Subquery::Select(moderated))),
+                ),
+            ),
+        );
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(REPORT_TABLE_NAME),
+            order: srql::Orders(order.into_iter().collect()).into(),
+            cond: cond.into(),
+            limit,
+            ..Default::default()
+        };
+
+        let reports: Vec<Report> = self.persist.db().query(query).await?.take(0)?;
+        let ResultSlice {
+            results: reports,
+            has_previous_page,
+            has_next_page,
+        } = ResultSlice::new(reports, result_slice_opts);
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        connection.edges = reports
+            .into_iter()
+            .map(|report| Edge::new(OpaqueCursor(report.id.to_gql_id().0), report))
+            .collect();
+
+        Ok(connection)
+    }
+}
+
+pub struct ModerationLogListRequest<'a> {
+    persist: &'a Persist,
+    board_id: srql::Thing,
+    pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+}
+
+impl<'a> ModerationLogListRequest<'a> {
+    fn new(persist: &'a Persist, board_id: srql::Thing) -> Self {
+        Self {
+            persist,
+            board_id,
+            pagination: None,
+        }
+    }
+
+    pub fn with_pagination(
+        mut self,
+        args: impl Into<PaginationInput<OpaqueCursor<String>>>,
+    ) -> Self {
+        self.pagination = Some(args.into());
+        self
+    }
+
+    #[instrument(skip_all)]
+    pub async fn execute(self) -> Result<Connection<ModerationLogCursor, ModerationLogEntry>> {
+        let PaginationOptions {
+            cond,
+            order,
+            limit,
+            result_slice_opts,
+        } = (self.pagination, LOG_TABLE_NAME).into();
+
+        let cond = srql::cond_and(
+            cond,
+            srql::expr(
+                srql::field("board_id"),
+                srql::Operator::Equal,
+                self.board_id,
+            ),
+        );
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(LOG_TABLE_NAME),
+            order: srql::Orders(order.into_iter().collect()).into(),
+            cond: cond.into(),
+            limit,
+            ..Default::default()
+        };
+
+        let entries: Vec<ModerationLogEntry> = self.persist.db().query(query).await?.take(0)?;
+        let ResultSlice {
+            results: entries,
+            has_previous_page,
+            has_next_page,
+        } = ResultSlice::new(entries, result_slice_opts);
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        connection.edges = entries
+            .into_iter()
+            .map(|entry| Edge::new(OpaqueCursor(entry.id.to_gql_id().0), entry))
+            .collect();
+
+        Ok(connection)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use async_trait::async_trait;
+
+    use crate::account::testing::TestData;
+
+    use super::ModerationPersist;
+
+    #[async_trait]
+    pub trait ModerationTestData {
+        fn moderation(&self) -> ModerationPersist<'_>;
+    }
+
+    #[async_trait]
+    impl ModerationTestData for TestData {
+        fn moderation(&self) -> ModerationPersist<'_> {
+            ModerationPersist::new(&self.persist, &self.current)
+        }
+    }
+}
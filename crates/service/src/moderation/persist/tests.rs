@@ -0,0 +1,481 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::ModerationTestData as _, *};
+use crate::{
+    account::testing::*,
+    board::{testing::BoardTestData as _, Board, BoardVisibility, CreateBoard},
+    post::{testing::PostTestData as _, CreatePost, PostPersist},
+};
+
+fn page() -> PaginationInput<OpaqueCursor<String>> {
+    PaginationInput::new().forward(10)
+}
+
+async fn setup() -> (TestData, AccData, Board, Post, Report) {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+
+    let author = data.account().create_test_user().await;
+    let current = author.current();
+    let post = PostPersist::new(&data.persist, &current)
+        .create(CreatePost {
+            board_id: Some(board.id.to_gql_id()),
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let report = ModerationPersist::new(&data.persist, &current)
+        .report(CreateReport {
+            target: ReportTarget::Post(post.id.to_gql_id()),
+            reason: "Test".into(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    (data, author, board, post, report)
+}
+
+#[tokio::test]
+async fn test_report() {
+    let (_, _, board, post, report) = setup().await;
+
+    assert_eq!(report.status, ReportStatus::Open);
+    assert_eq!(report.board_id, Some(board.id));
+    assert_eq!(report.post_id, Some(post.id));
+    assert_eq!(report.reason, "Test");
+}
+
+#[tokio::test]
+async fn test_report_missing() {
+    let (data, _) = TestData::with_user().await;
+
+    let res = data
+        .moderation()
+        .report(CreateReport {
+            target: ReportTarget::Post("missing".into()),
+            reason: "Test".into(),
+        })
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(res.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_report_unreadable() {
+    let (data, _) = TestData::with_user().await;
+    let board = data
+        .board()
+        .create(CreateBoard {
+            handle: Some("test".into()),
+            visibility: Some(BoardVisibility::Private),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let post = data.generate_post_in(&board.id).await;
+
+    let other = data.account().create_test_user().await;
+    let current = other.current();
+    let moderation = ModerationPersist::new(&data.persist, &current);
+    for target in [
+        ReportTarget::Post(post.id.to_gql_id()),
+        ReportTarget::Board(board.id.to_gql_id()),
+    ] {
+        let res = moderation
+            .report(CreateReport {
+                target,
+                reason: "Test".into(),
+            })
+            .await;
+        println!("{res:?}");
+        assert!(res.unwrap().is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_report_unauthenticated() {
+    let data = TestData::new().await;
+
+    let res = data
+        .moderation()
+        .report(CreateReport {
+            target: ReportTarget::Post("missing".into()),
+            reason: "Test".into(),
+        })
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_queue() {
+    let (data, author, _, _, report) = setup().await;
+
+    let res = data
+        .moderation()
+        .queue()
+        .with_pagination(page())
+        .execute()
+        .await;
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.edges.len(), 1);
+    assert_eq!(res.edges[0].node.id, report.id);
+
+    // The author doesn't moderate the board, so sees nothing.
+    let current = author.current();
+    let res = ModerationPersist::new(&data.persist, &current)
+        .queue()
+        .with_pagination(page())
+        .execute()
+        .await;
+    assert!(res.is_ok());
+    assert!(res.unwrap().edges.is_empty());
+}
+
+#[tokio::test]
+async fn test_queue_added_moderator() {
+    let (data, _, board, _, report) = setup().await;
+
+    let moderator = data.account().create_test_user().await;
+    let res = data
+        .board()
+        .add_moderator(&board.id.to_gql_id(), &moderator.id.to_gql_id())
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let current = moderator.current();
+    let res = ModerationPersist::new(&data.persist, &current)
+        .queue()
+        .with_pagination(page())
+        .execute()
+        .await;
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.edges.len(), 1);
+    assert_eq!(res.edges[0].node.id, report.id);
+}
+
+#[tokio::test]
+async fn test_resolve_hide_and_ban() {
+    let (data, author, board, post, report) = setup().await;
+
+    let res = data
+        .moderation()
+        .resolve(
+            &report.id.to_gql_id(),
+            ResolveReport {
+                // Repeated actions are only taken once.
+                actions: vec![
+                    ResolveAction::HideContent,
+                    ResolveAction::BanAuthor,
+                    ResolveAction::HideContent,
+                ],
+                note: Some("Spam".into()),
+                ban_expires_at: None,
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.status, ReportStatus::Resolved);
+    assert_eq!(res.note, Some("Spam".to_owned()));
+
+    // Only moderators can see the hidden post.
+    let current = author.current();
+    let author_posts = PostPersist::new(&data.persist, &current);
+    let res = author_posts.get(&post.id.to_gql_id()).await;
+    assert!(res.unwrap().is_none());
+    let res = data.post().get(&post.id.to_gql_id()).await;
+    assert!(res.unwrap().unwrap().hidden_at.is_some());
+
+    let res = author_posts
+        .create(CreatePost {
+            board_id: Some(board.id.to_gql_id()),
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::BannedFromBoard);
+
+    let res = data
+        .moderation()
+        .queue()
+        .with_pagination(page())
+        .execute()
+        .await;
+    assert!(res.unwrap().edges.is_empty());
+
+    let res = data
+        .moderation()
+        .log(&board.id.to_gql_id())
+        .await
+        .unwrap()
+        .unwrap()
+        .with_pagination(page())
+        .execute()
+        .await;
+    let res = res.unwrap();
+    assert_eq!(res.edges.len(), 2);
+    assert!(res
+        .edges
+        .iter()
+        .all(|edge| edge.node.report_id == Some(report.id.clone())));
+}
+
+#[tokio::test]
+async fn test_resolve_delete() {
+    let (data, _, _, post, report) = setup().await;
+
+    let res = data
+        .moderation()
+        .resolve(
+            &report.id.to_gql_id(),
+            ResolveReport {
+                actions: vec![ResolveAction::DeleteContent],
+                note: None,
+                ban_expires_at: None,
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = data.post().get(&post.id.to_gql_id()).await;
+    assert!(res.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_resolve_board() {
+    let (data, author, board, _, _) = setup().await;
+
+    let current = author.current();
+    let report = ModerationPersist::new(&data.persist, &current)
+        .report(CreateReport {
+            target: ReportTarget::Board(board.id.to_gql_id()),
+            reason: "Test".into(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    for action in [ResolveAction::DeleteContent, ResolveAction::BanAuthor] {
+        let res = data
+            .moderation()
+            .resolve(
+                &report.id.to_gql_id(),
+                ResolveReport {
+                    actions: vec![ResolveAction::HideContent, action],
+                    note: None,
+                    ban_expires_at: None,
+                },
+            )
+            .await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), Error::InvalidReportAction);
+    }
+
+    let res = data.board().get(&board.id.to_gql_id()).await;
+    let board = res.unwrap().unwrap();
+    assert!(board.hidden_at.is_none());
+
+    let res = data
+        .moderation()
+        .resolve(
+            &report.id.to_gql_id(),
+            ResolveReport {
+                actions: vec![ResolveAction::HideContent],
+                note: None,
+                ban_expires_at: None,
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().status, ReportStatus::Resolved);
+
+    let res = data.board().get(&board.id.to_gql_id()).await;
+    assert!(res.unwrap().unwrap().hidden_at.is_some());
+}
+
+#[tokio::test]
+async fn test_resolve_unauthorized() {
+    let (data, author, _, _, report) = setup().await;
+
+    let current = author.current();
+    let res = ModerationPersist::new(&data.persist, &current)
+        .dismiss(&report.id.to_gql_id(), None)
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_resolve_closed() {
+    let (data, _, _, _, report) = setup().await;
+
+    let res = data
+        .moderation()
+        .dismiss(&report.id.to_gql_id(), Some("Fine".into()))
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().status, ReportStatus::Dismissed);
+
+    let res = data
+        .moderation()
+        .resolve(
+            &report.id.to_gql_id(),
+            ResolveReport {
+                actions: vec![ResolveAction::HideContent],
+                note: None,
+                ban_expires_at: None,
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::ReportClosed);
+}
+
+#[tokio::test]
+async fn test_ban_unban() {
+    let (data, author, board, _, _) = setup().await;
+    let moderation = data.moderation();
+
+    let res = moderation
+        .ban(&board.id.to_gql_id(), &author.id.to_gql_id(), None, None)
+        .await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+    assert!(moderation.is_banned(&board.id, &author.id).await.unwrap());
+
+    let res = moderation
+        .unban(&board.id.to_gql_id(), &author.id.to_gql_id())
+        .await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+    assert!(!moderation.is_banned(&board.id, &author.id).await.unwrap());
+
+    let res = moderation
+        .unban(&board.id.to_gql_id(), &author.id.to_gql_id())
+        .await;
+    assert!(res.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_ban_expired() {
+    let (data, author, board, _, _) = setup().await;
+    let moderation = data.moderation();
+
+    let res = moderation
+        .ban(
+            &board.id.to_gql_id(),
+            &author.id.to_gql_id(),
+            None,
+            Some(Utc::now() - chrono::Duration::minutes(1)),
+        )
+        .await;
+    assert!(res.is_ok());
+    assert!(!moderation.is_banned(&board.id, &author.id).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_log_unauthorized() {
+    let (data, author, board, _, _) = setup().await;
+
+    let current = author.current();
+    let moderation = ModerationPersist::new(&data.persist, &current);
+    let res = moderation.log(&board.id.to_gql_id()).await;
+    assert_eq!(res.err(), Some(Error::Unauthorized));
+}
+
+#[tokio::test]
+async fn test_log_immutable() {
+    let (data, _, _, _, report) = setup().await;
+
+    data.moderation()
+        .dismiss(&report.id.to_gql_id(), None)
+        .await
+        .unwrap();
+
+    let res = data
+        .persist
+        .db()
+        .query(format!("UPDATE {LOG_TABLE_NAME} SET note = 'Changed'"))
+        .await
+        .unwrap()
+        .check();
+    println!("{res:?}");
+    assert!(res.is_err());
+
+    let res = data
+        .persist
+        .db()
+        .query(format!("DELETE {LOG_TABLE_NAME}"))
+        .await
+        .unwrap()
+        .check();
+    println!("{res:?}");
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn test_pin_and_lock() {
+    let (data, _, board, post, _) = setup().await;
+    let moderation = data.moderation();
+
+    let res = moderation.pin_post(&post.id.to_gql_id(), true).await;
+    println!("{res:?}");
+    let pinned_at = res.unwrap().unwrap().pinned_at;
+    assert!(pinned_at.is_some());
+
+    // Pinning again doesn't move the post or log anything.
+    let res = moderation.pin_post(&post.id.to_gql_id(), true).await;
+    assert_eq!(res.unwrap().unwrap().pinned_at, pinned_at);
+
+    let res = moderation.lock_post(&post.id.to_gql_id(), true).await;
+    println!("{res:?}");
+    assert!(res.unwrap().unwrap().locked_at.is_some());
+
+    let res = moderation.pin_post(&post.id.to_gql_id(), false).await;
+    assert!(res.unwrap().unwrap().pinned_at.is_none());
+
+    let log = moderation
+        .log(&board.id.to_gql_id())
+        .await
+        .unwrap()
+        .unwrap()
+        .with_pagination(page())
+        .execute()
+        .await
+        .unwrap();
+    let actions: Vec<_> = log.edges.iter().map(|edge| edge.node.action).collect();
+    assert_eq!(actions.len(), 3);
+    for action in [
+        ModerationAction::PinPost,
+        ModerationAction::LockPost,
+        ModerationAction::UnpinPost,
+    ] {
+        assert!(actions.contains(&action), "{action:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_pin_unauthorized() {
+    let (data, author, _, post, _) = setup().await;
+
+    let current = author.current();
+    let moderation = ModerationPersist::new(&data.persist, &current);
+    let res = moderation.pin_post(&post.id.to_gql_id(), true).await;
+    assert_eq!(res.err(), Some(Error::Unauthorized));
+
+    let res = moderation.lock_post(&post.id.to_gql_id(), true).await;
+    assert_eq!(res.err(), Some(Error::Unauthorized));
+}
@@ -0,0 +1,183 @@
+use async_graphql::{connection::Connection, Context, Object, ID};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+
+use super::{
+    BoardBan, CreateReport, ModerationLogCursor, ModerationLogEntry, Report, ReportCursor,
+    ResolveReport,
+};
+use crate::{
+    post::Post,
+    prelude::*,
+    query::{connection_complexity, PaginationArgs},
+};
+
+#[derive(Default)]
+pub struct ModerationQuery;
+
+#[Object]
+impl ModerationQuery {
+    /// Lists the open reports in the boards that the current account moderates.
+    #[graphql(complexity = "connection_complexity(child_complexity, first, last)")]
+    #[instrument(skip_all)]
+    async fn moderation_queue(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> GqlResult<Connection<ReportCursor, Report>> {
+        ctx.moderation_persist()
+            .queue()
+            .with_pagination(
+                PaginationArgs {
+                    after,
+                    before,
+                    first,
+                    last,
+                }
+                .validate()
+                .extend()?,
+            )
+            .execute()
+            .await
+            .extend()
+    }
+
+    /// Lists the moderation log of a board. Only the board's moderators can
+    /// read the log.
+    #[graphql(complexity = "connection_complexity(child_complexity, first, last)")]
+    #[instrument(skip_all)]
+    async fn moderation_log(
+        &self,
+        ctx: &Context<'_>,
+        board_id: ID,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> GqlResult<Option<Connection<ModerationLogCursor, ModerationLogEntry>>> {
+        let pagination = PaginationArgs {
+            after,
+            before,
+            first,
+            last,
+        }
+        .validate()
+        .extend()?;
+
+        let persist = ctx.moderation_persist();
+        let Some(log) = persist.log(&board_id).await.extend()? else {
+            return Ok(None);
+        };
+        log.with_pagination(pagination)
+            .execute()
+            .await
+            .extend()
+            .map(Some)
+    }
+}
+
+#[derive(Default)]
+pub struct ModerationMutation;
+
+#[Object]
+impl ModerationMutation {
+    /// Reports a post or board to its moderators.
+    #[instrument(skip_all)]
+    async fn report(&self, ctx: &Context<'_>, create: CreateReport) -> GqlResult<Option<Report>> {
+        ctx.moderation_persist().report(create).await.extend()
+    }
+
+    /// Resolves an open report by acting against the reported content.
+    #[instrument(skip_all)]
+    async fn resolve_report(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        resolve: ResolveReport,
+    ) -> GqlResult<Option<Report>> {
+        ctx.moderation_persist()
+            .resolve(&id, resolve)
+            .await
+            .extend()
+    }
+
+    /// Dismisses an open report without taking any action.
+    #[instrument(skip_all)]
+    async fn dismiss_report(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        #[graphql(validator(max_length = 4096))] note: Option<String>,
+    ) -> GqlResult<Option<Report>> {
+        ctx.moderation_persist().dismiss(&id, note).await.extend()
+    }
+
+    /// Bans an account from posting in a board.
+    #[instrument(skip_all)]
+    async fn ban_from_board(
+        &self,
+        ctx: &Context<'_>,
+        board_id: ID,
+        account_id: ID,
+        #[graphql(validator(max_length = 4096))] reason: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> GqlResult<Option<BoardBan>> {
+        ctx.moderation_persist()
+            .ban(&board_id, &account_id, reason, expires_at)
+            .await
+            .extend()
+    }
+
+    /// Lifts an account's ban from a board.
+    #[instrument(skip_all)]
+    async fn unban_from_board(
+        &self,
+        ctx: &Context<'_>,
+        board_id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<BoardBan>> {
+        ctx.moderation_persist()
+            .unban(&board_id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Pins a post, which lists it before all other posts in its board.
+    #[instrument(skip_all)]
+    async fn pin_post(&self, ctx: &Context<'_>, post_id: ID) -> GqlResult<Option<Post>> {
+        ctx.moderation_persist()
+            .pin_post(&post_id, true)
+            .await
+            .extend()
+    }
+
+    /// Unpins a post.
+    #[instrument(skip_all)]
+    async fn unpin_post(&self, ctx: &Context<'_>, post_id: ID) -> GqlResult<Option<Post>> {
+        ctx.moderation_persist()
+            .pin_post(&post_id, false)
+            .await
+            .extend()
+    }
+
+    /// Locks a post, which stops it from being edited until it is unlocked.
+    #[instrument(skip_all)]
+    async fn lock_post(&self, ctx: &Context<'_>, post_id: ID) -> GqlResult<Option<Post>> {
+        ctx.moderation_persist()
+            .lock_post(&post_id, true)
+            .await
+            .extend()
+    }
+
+    /// Unlocks a post.
+    #[instrument(skip_all)]
+    async fn unlock_post(&self, ctx: &Context<'_>, post_id: ID) -> GqlResult<Option<Post>> {
+        ctx.moderation_persist()
+            .lock_post(&post_id, false)
+            .await
+            .extend()
+    }
+}
@@ -1,25 +1,124 @@
-use std::{future::IntoFuture, sync::Arc};
+use std::{
+    future::IntoFuture,
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
 
 use async_graphql::Context;
 use cfg_if::cfg_if;
+use chrono::{DateTime, Utc};
 use ring::rand::SystemRandom;
+use secrecy::ExposeSecret as _;
+use serde::Deserialize;
 use surrealdb::{
     engine,
-    opt::{capabilities::Capabilities, Config as SrlConfig},
+    opt::{
+        auth::{Database, Namespace, Root},
+        Config as SrlConfig,
+    },
     Result as SrlResult, Surreal,
 };
-use tracing::{error, instrument};
+use thiserror::Error;
+use tokio::time;
+use tracing::{error, info, instrument, warn};
 
 use crate::{
-    account::{AccountPersist, CurrentAccount},
+    account::{AccountPersist, CurrentAccount, SigningKeys},
+    admin::AdminPersist,
     board::BoardPersist,
+    config::{DbAuthLevel, DbConfig, DbCredentials, DbTlsConfig, ServiceSettings},
+    data_export::DataExportPersist,
+    moderation::ModerationPersist,
     post::PostPersist,
     prelude::*,
-    DecodingKey,
 };
 
-fn config() -> SrlConfig {
-    SrlConfig::new().capabilities(Capabilities::all())
+fn config(config: &DbConfig) -> std::result::Result<SrlConfig, ConnectError> {
+    let srl_config = SrlConfig::new().capabilities(config.capabilities.clone());
+    if config.tls.is_empty() {
+        Ok(srl_config)
+    } else {
+        with_tls(srl_config, &config.tls)
+    }
+}
+
+#[cfg(feature = "backend-ws")]
+fn with_tls(
+    srl_config: SrlConfig,
+    tls: &DbTlsConfig,
+) -> std::result::Result<SrlConfig, ConnectError> {
+    use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore};
+    use rustls_pemfile::Item;
+
+    let mut roots = RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    if let Some(path) = &tls.ca {
+        for item in read_pem(path)? {
+            if let Item::X509Certificate(cert) = item {
+                roots
+                    .add(&Certificate(cert))
+                    .map_err(|err| ConnectError::Tls(format!("{path}: {err}")))?;
+            }
+        }
+    }
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    let client_config = match &tls.client_auth {
+        Some((cert_path, key_path)) => {
+            let certs = read_pem(cert_path)?
+                .into_iter()
+                .filter_map(|item| match item {
+                    Item::X509Certificate(cert) => Some(Certificate(cert)),
+                    _ => None,
+                })
+                .collect();
+            let key = read_pem(key_path)?
+                .into_iter()
+                .find_map(|item| match item {
+                    Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => {
+                        Some(PrivateKey(key))
+                    }
+                    _ => None,
+                })
+                .ok_or_else(|| ConnectError::Tls(format!("{key_path}: no private key found")))?;
+            builder
+                .with_single_cert(certs, key)
+                .map_err(|err| ConnectError::Tls(err.to_string()))?
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(srl_config.rustls(client_config))
+}
+
+#[cfg(feature = "backend-ws")]
+fn read_pem(path: &str) -> std::result::Result<Vec<rustls_pemfile::Item>, ConnectError> {
+    let tls_err = |err: std::io::Error| ConnectError::Tls(format!("{path}: {err}"));
+    let file = std::fs::File::open(path).map_err(tls_err)?;
+    rustls_pemfile::read_all(&mut std::io::BufReader::new(file)).map_err(tls_err)
+}
+
+#[cfg(not(feature = "backend-ws"))]
+fn with_tls(_: SrlConfig, _: &DbTlsConfig) -> std::result::Result<SrlConfig, ConnectError> {
+    Err(ConnectError::Tls(
+        "remote databases aren't supported by this build".into(),
+    ))
+}
+
+#[derive(Debug, Error)]
+pub enum ConnectError {
+    #[error("{0}")]
+    Db(#[from] surrealdb::Error),
+    #[error("Invalid TLS config: {0}")]
+    Tls(String),
 }
 
 cfg_if! {
@@ -31,8 +130,8 @@ if #[cfg(all(
 ))] {
     pub type DbLayer = Surreal<engine::local::Db>;
 
-    async fn connect(_: String) -> SrlResult<DbLayer> {
-        Surreal::new::<engine::local::Mem>(config()).await
+    async fn connect(_: &str, config: SrlConfig) -> SrlResult<DbLayer> {
+        Surreal::new::<engine::local::Mem>(config).await
     }
 } else if #[cfg(all(
     not(feature = "backend-mem"),
@@ -41,8 +140,8 @@ if #[cfg(all(
 ))] {
     pub type DbLayer = Surreal<engine::local::Db>;
 
-    async fn connect(address: String) -> SrlResult<DbLayer> {
-        Surreal::new::<engine::local::RocksDb>((address, config())).await
+    async fn connect(address: &str, config: SrlConfig) -> SrlResult<DbLayer> {
+        Surreal::new::<engine::local::RocksDb>((address, config)).await
     }
 } else if #[cfg(all(
     not(feature = "backend-mem"),
@@ -51,14 +150,14 @@ if #[cfg(all(
 ))] {
     pub type DbLayer = Surreal<engine::local::Db>;
 
-    async fn connect(address: String) -> SrlResult<DbLayer> {
-        Surreal::new::<engine::local::TiKv>((address, config())).await
+    async fn connect(address: &str, config: SrlConfig) -> SrlResult<DbLayer> {
+        Surreal::new::<engine::local::TiKv>((address, config)).await
     }
 } else {
     pub type DbLayer = Surreal<engine::any::Any>;
 
-    async fn connect(address: String) -> SrlResult<DbLayer> {
-        engine::any::connect((address, config())).await
+    async fn connect(address: &str, config: SrlConfig) -> SrlResult<DbLayer> {
+        engine::any::connect((address, config)).await
     }
 }
 
@@ -69,72 +168,321 @@ pub trait PersistExt {
     fn account_persist(&self) -> AccountPersist;
     fn board_persist(&self) -> BoardPersist;
     fn post_persist(&self) -> PostPersist;
+    fn moderation_persist(&self) -> ModerationPersist;
+    fn admin_persist(&self) -> AdminPersist;
+    fn data_export_persist(&self) -> DataExportPersist;
 }
 
-pub struct Persist(DbLayer);
+#[derive(Clone)]
+pub struct Persist {
+    db: DbLayer,
+    /// Shared between clones, so that reloaded settings are seen everywhere.
+    settings: Arc<RwLock<Arc<ServiceSettings>>>,
+    lock_owner: Arc<str>,
+    lock_lease: Duration,
+}
 
 static LOCK_TABLE: &str = "locks";
+/// How long a lock is held for without being renewed. Locks are renewed while
+/// they are held, so this is how long it takes for another instance to take
+/// over a lock after the instance holding it has stopped without releasing it.
+const LOCK_LEASE: Duration = Duration::from_secs(30);
+
+/// A lock on a subsystem, which stops several instances from doing the same
+/// work at once.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Lock {
+    pub id: srql::Thing,
+    /// The instance holding the lock. Locks that were taken before locks had
+    /// owners don't have one.
+    pub owner: Option<String>,
+    pub acquired_at: Option<DateTime<Utc>>,
+    /// When the lock can be taken over if it isn't renewed.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Lock {
+    #[must_use]
+    pub fn name(&self) -> String {
+        self.id.id.to_raw()
+    }
+
+    /// Whether the lock's lease has run out, so it can be taken over. Locks
+    /// without a lease are given one when an instance first finds them held,
+    /// so they aren't stale until that has run out too.
+    #[must_use]
+    pub fn is_stale(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at < now)
+    }
+}
 
 impl Persist {
-    pub async fn new(
-        address: impl Into<String>,
-        namespace: impl Into<String>,
-        database: impl Into<String>,
-    ) -> SrlResult<Self> {
-        let db = connect(address.into()).await?;
-        db.use_ns(namespace).use_db(database).await?;
-        Ok(Self(db))
+    /// Connects to the database, signing in if the config has credentials.
+    pub async fn new(db_config: &DbConfig) -> std::result::Result<Self, ConnectError> {
+        let db = connect(&db_config.address, config(db_config)?).await?;
+        if let Some(credentials) = &db_config.credentials {
+            signin(&db, db_config, credentials).await?;
+            info!(level = %credentials.level, username = credentials.username, "Signed into database");
+        }
+        db.use_ns(&db_config.namespace)
+            .use_db(&db_config.database)
+            .await?;
+        Ok(Self {
+            db,
+            settings: Arc::default(),
+            lock_owner: format!("{}-{}", std::process::id(), srql::ulid()).into(),
+            lock_lease: LOCK_LEASE,
+        })
+    }
+
+    #[must_use]
+    pub fn with_settings(mut self, settings: ServiceSettings) -> Self {
+        self.settings = Arc::new(RwLock::new(Arc::new(settings)));
+        self
+    }
+
+    /// Replaces the settings for this and every clone of it.
+    pub fn set_settings(&self, settings: ServiceSettings) {
+        *self
+            .settings
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(settings);
+    }
+
+    #[cfg(test)]
+    #[must_use]
+    pub fn with_lock_lease(mut self, lock_lease: Duration) -> Self {
+        self.lock_lease = lock_lease;
+        self
     }
 
     pub fn db(&self) -> &DbLayer {
-        &self.0
+        &self.db
+    }
+
+    pub fn settings(&self) -> Arc<ServiceSettings> {
+        self.settings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
     }
 
+    /// Runs `f` while holding the lock with the given ID, or returns `None`
+    /// without running it if another instance holds the lock.
+    ///
+    /// The lock's lease is renewed while `f` runs. If the instance holding a
+    /// lock stops without releasing it, the lock is taken over once its lease
+    /// runs out.
     #[instrument(skip(self, f))]
     pub async fn execute_in_lock<F, Fut, O>(&self, id: &str, f: F) -> SrlResult<Option<O>>
     where
         F: FnOnce() -> Fut,
         Fut: IntoFuture<Output = O>,
     {
+        if !self.acquire_lock(id).await? {
+            return Ok(None);
+        }
+
+        let fut = f().into_future();
+        tokio::pin!(fut);
+        let mut heartbeat = time::interval(self.lock_lease / 3);
+        // The first tick completes straight away, and the lock was only just
+        // acquired.
+        heartbeat.tick().await;
+        let res = loop {
+            tokio::select! {
+                res = &mut fut => break res,
+                _ = heartbeat.tick() => match self.renew_lock(id).await {
+                    Ok(true) => {}
+                    Ok(false) => error!("Lock was lost while it was held"),
+                    Err(err) => warn!(error = ?err, "Failed to renew lock"),
+                },
+            }
+        };
+
+        match self
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::thing((LOCK_TABLE, id)),
+                cond: self.lock_owner_cond().into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            })
+            .await
+            .and_then(|mut r| r.take(0))
+        {
+            Ok::<Option<()>, _>(_) => Ok(Some(res)),
+            Err::<_, surrealdb::Error>(err) => {
+                error!(
+                    error = ?err,
+                    "Failed to clear lock, database might be corrupt"
+                );
+                Err(err)
+            }
+        }
+    }
+
+    /// Lists all of the locks that are currently held.
+    #[instrument(skip_all)]
+    pub async fn locks(&self) -> SrlResult<Vec<Lock>> {
+        self.db().select(LOCK_TABLE).await
+    }
+
+    /// Releases a lock regardless of which instance holds it. This should only
+    /// be used when the instance holding it is known to have stopped.
+    #[instrument(skip(self))]
+    pub async fn force_release_lock(&self, id: &str) -> SrlResult<Option<Lock>> {
+        self.db().delete((LOCK_TABLE, id)).await
+    }
+
+    async fn acquire_lock(&self, id: &str) -> SrlResult<bool> {
         match self
             .db()
             .query(srql::CreateStatement {
                 what: srql::thing((LOCK_TABLE, id)),
+                data: self.lock_data().into(),
                 output: srql::Output::None.into(),
                 ..Default::default()
             })
             .await
             .and_then(|mut r| r.take(0))
         {
-            Ok::<Option<()>, _>(_) => {
-                let res = f().await;
-
-                match self
-                    .db()
-                    .query(srql::DeleteStatement {
-                        what: srql::thing((LOCK_TABLE, id)),
-                        output: srql::Output::None.into(),
-                        ..Default::default()
-                    })
-                    .await
-                    .and_then(|mut r| r.take(0))
-                {
-                    Ok::<Option<()>, _>(_) => Ok(Some(res)),
-                    Err::<_, surrealdb::Error>(err) => {
-                        error!(
-                            error = ?err,
-                            "Failed to clear lock, database might be corrupt"
-                        );
-                        Err(err)
-                    }
-                }
+            Ok::<Option<()>, _>(_) => return Ok(true),
+            Err(surrealdb::Error::Db(surrealdb::error::Db::RecordExists { .. })) => {}
+            Err(err) => return Err(err),
+        }
+
+        // Locks taken before locks had leases may still be held by an older
+        // instance, so they get a full lease from now before they can be
+        // taken over.
+        self.db()
+            .query(srql::UpdateStatement {
+                what: srql::thing((LOCK_TABLE, id)),
+                data: srql::Data::SetExpression(vec![self.lock_expiry()]).into(),
+                cond: srql::Cond(srql::expr(
+                    srql::field("expires_at"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ))
+                .into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            })
+            .await?
+            .check()?;
+
+        // The lock is held, but if its lease has run out then whoever was
+        // holding it stopped without releasing it.
+        let stale: Vec<Lock> = self
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing((LOCK_TABLE, id)),
+                data: self.lock_data().into(),
+                cond: srql::Cond(srql::expr(
+                    srql::field("expires_at"),
+                    srql::Operator::LessThan,
+                    srql::time_now(),
+                ))
+                .into(),
+                output: srql::Output::Before.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+
+        match stale.first() {
+            Some(lock) => {
+                warn!(previous_owner = ?lock.owner, "Took over stale lock");
+                Ok(true)
             }
-            Err::<_, surrealdb::Error>(err) => match err {
-                surrealdb::Error::Db(surrealdb::error::Db::RecordExists { .. }) => Ok(None),
-                _ => Err(err),
-            },
+            None => Ok(false),
         }
     }
+
+    /// Extends the lease on a lock, returning whether it is still held.
+    async fn renew_lock(&self, id: &str) -> SrlResult<bool> {
+        let renewed: Vec<Lock> = self
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing((LOCK_TABLE, id)),
+                data: srql::Data::SetExpression(vec![self.lock_expiry()]).into(),
+                cond: self.lock_owner_cond().into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(!renewed.is_empty())
+    }
+
+    fn lock_data(&self) -> srql::Data {
+        srql::Data::SetExpression(vec![
+            (
+                srql::field("owner"),
+                srql::Operator::Equal,
+                srql::Value::Strand(self.lock_owner.as_ref().into()),
+            ),
+            (
+                srql::field("acquired_at"),
+                srql::Operator::Equal,
+                srql::time_now(),
+            ),
+            self.lock_expiry(),
+        ])
+    }
+
+    fn lock_expiry(&self) -> srql::SetExprItem {
+        (
+            srql::field("expires_at"),
+            srql::Operator::Equal,
+            srql::expr(
+                srql::time_now(),
+                srql::Operator::Add,
+                srql::Value::Duration(self.lock_lease.into()),
+            ),
+        )
+    }
+
+    fn lock_owner_cond(&self) -> srql::Cond {
+        srql::Cond(srql::expr(
+            srql::field("owner"),
+            srql::Operator::Equal,
+            srql::Value::Strand(self.lock_owner.as_ref().into()),
+        ))
+    }
+}
+
+async fn signin(
+    db: &DbLayer,
+    db_config: &DbConfig,
+    DbCredentials {
+        level,
+        username,
+        password,
+    }: &DbCredentials,
+) -> SrlResult<()> {
+    let password = password.expose_secret();
+    match level {
+        DbAuthLevel::Root => db.signin(Root { username, password }).await?,
+        DbAuthLevel::Namespace => {
+            db.signin(Namespace {
+                namespace: &db_config.namespace,
+                username,
+                password,
+            })
+            .await?
+        }
+        DbAuthLevel::Database => {
+            db.signin(Database {
+                namespace: &db_config.namespace,
+                database: &db_config.database,
+                username,
+                password,
+            })
+            .await?
+        }
+    };
+
+    Ok(())
 }
 
 impl PersistExt for Context<'_> {
@@ -148,7 +496,7 @@ impl PersistExt for Context<'_> {
             self.data_unchecked::<Persist>(),
             self.current_account(),
             self.data_unchecked::<SystemRandom>(),
-            self.data_unchecked::<DecodingKey>(),
+            self.data_unchecked::<SigningKeys>().decoding(),
         )
     }
 
@@ -159,16 +507,27 @@ impl PersistExt for Context<'_> {
     fn post_persist(&self) -> PostPersist {
         PostPersist::new(self.data_unchecked::<Persist>(), self.current_account())
     }
+
+    fn moderation_persist(&self) -> ModerationPersist {
+        ModerationPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn admin_persist(&self) -> AdminPersist {
+        AdminPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn data_export_persist(&self) -> DataExportPersist {
+        DataExportPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use std::time::Duration;
-
     use futures::join;
+    use surrealdb::opt::capabilities::{Capabilities, Targets};
     use tokio::time::sleep;
 
-    use super::testing::*;
+    use super::{testing::*, *};
 
     #[tokio::test]
     async fn test_lock() {
@@ -196,6 +555,124 @@ mod test {
         assert!(a.unwrap().is_some());
         assert!(b.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn test_lock_stale() {
+        let p = persist().await.with_lock_lease(Duration::from_millis(50));
+        let other = p.clone().with_lock_lease(Duration::from_secs(30));
+        let id = "test_lock_stale";
+
+        // A lock left behind by an instance that stopped while holding it.
+        p.acquire_lock(id).await.unwrap();
+        assert!(other
+            .execute_in_lock(id, || async {})
+            .await
+            .unwrap()
+            .is_none());
+
+        sleep(Duration::from_millis(100)).await;
+        let other = Persist {
+            lock_owner: "other".into(),
+            ..other
+        };
+        assert!(other
+            .execute_in_lock(id, || async {})
+            .await
+            .unwrap()
+            .is_some());
+        assert!(p.locks().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lock_legacy() {
+        let p = persist().await.with_lock_lease(Duration::from_millis(50));
+        let id = "test_lock_legacy";
+
+        // Locks taken before locks had leases might still be held, so they
+        // are only taken over a lease after they were first found.
+        let _: Option<Lock> = p.db().create((LOCK_TABLE, id)).await.unwrap();
+        let locks = p.locks().await.unwrap();
+        assert_eq!(locks.len(), 1);
+        assert!(locks[0].owner.is_none());
+        assert!(!locks[0].is_stale(Utc::now()));
+
+        assert!(p.execute_in_lock(id, || async {}).await.unwrap().is_none());
+        let locks = p.locks().await.unwrap();
+        assert!(locks[0].owner.is_none());
+        assert!(locks[0].expires_at.is_some());
+
+        sleep(Duration::from_millis(100)).await;
+        assert!(p.locks().await.unwrap()[0].is_stale(Utc::now()));
+        assert!(p.execute_in_lock(id, || async {}).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_lock_heartbeat() {
+        let p = persist().await.with_lock_lease(Duration::from_millis(60));
+        let other = Persist {
+            lock_owner: "other".into(),
+            ..p.clone()
+        };
+        let id = "test_lock_heartbeat";
+
+        let (a, b) = join!(
+            p.execute_in_lock(id, || sleep(Duration::from_millis(300))),
+            async {
+                // Well past the lease, but the lock has been renewed.
+                sleep(Duration::from_millis(200)).await;
+                other.execute_in_lock(id, || async {}).await
+            },
+        );
+
+        assert!(a.unwrap().is_some());
+        assert!(b.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lock_force_release() {
+        let p = persist().await;
+        let id = "test_lock_force_release";
+
+        p.acquire_lock(id).await.unwrap();
+        let locks = p.locks().await.unwrap();
+        assert_eq!(locks.len(), 1);
+        assert_eq!(locks[0].name(), id);
+        assert_eq!(locks[0].owner.as_deref(), Some(p.lock_owner.as_ref()));
+        assert!(!locks[0].is_stale(Utc::now()));
+
+        let res = p.force_release_lock(id).await.unwrap();
+        assert_eq!(res.unwrap().name(), id);
+        assert!(p.force_release_lock(id).await.unwrap().is_none());
+        assert!(p.execute_in_lock(id, || async {}).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_capabilities() {
+        let query = "RETURN rand::uuid()";
+        let p = unmigrated().await;
+        assert!(p.db().query(query).await.unwrap().check().is_ok());
+
+        let mut config = DbConfig::new("memory", "test", "test");
+        config.capabilities = Capabilities::default()
+            .without_functions(Targets::Some(["rand".parse().unwrap()].into()));
+        let p = Persist::new(&config).await.unwrap();
+        let res = p.db().query(query).await.unwrap().check();
+        println!("{res:?}");
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_signin_invalid() {
+        let mut config = DbConfig::new("memory", "test", "test");
+        config.credentials = Some(DbCredentials {
+            level: DbAuthLevel::Root,
+            username: "root".into(),
+            password: "wrong".to_owned().into(),
+        });
+
+        let res = Persist::new(&config).await;
+        assert!(matches!(res, Err(ConnectError::Db(_))));
+    }
 }
 
 #[cfg(test)]
@@ -205,8 +682,15 @@ pub mod testing {
     use super::*;
 
     pub async fn persist() -> Persist {
-        let persist = Persist::new("memory", "test", "test").await.unwrap();
+        let persist = unmigrated().await;
         Migrations::run(&persist).await.unwrap();
         persist
     }
+
+    /// A fresh in-memory database that hasn't been migrated.
+    pub async fn unmigrated() -> Persist {
+        Persist::new(&DbConfig::new("memory", "test", "test"))
+            .await
+            .unwrap()
+    }
 }
@@ -0,0 +1,941 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufReader},
+    num::NonZeroUsize,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_graphql::{
+    async_trait,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest},
+    from_value,
+    parser::{
+        self,
+        types::{
+            Directive, DocumentOperations, Field, FragmentDefinition, OperationDefinition,
+            OperationType, Selection, SelectionSet, VariableDefinition,
+        },
+        Pos,
+    },
+    Name, Positioned, Request, ServerResult, Value,
+};
+use async_graphql_value::Value as InputValue;
+use lru::LruCache;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{config::PersistedQueryConfig, error::Error};
+
+pub static ALLOWLIST_FORMAT: &str = "apollo-persisted-query-manifest";
+pub const ALLOWLIST_VERSION: u32 = 1;
+
+/// How long a line of arguments can be before they are put on lines of their
+/// own, as GraphQL.js does.
+const MAX_LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Error)]
+pub enum AllowlistError {
+    #[error("Failed to read or write allowlist: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to parse allowlist: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid allowlist: {0}")]
+    Invalid(String),
+    #[error("Invalid GraphQL document in {file}: {message}")]
+    Document { file: String, message: String },
+}
+
+/// The operations that clients are allowed to run, as an Apollo persisted
+/// query manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Allowlist {
+    format: String,
+    version: u32,
+    operations: Vec<AllowlistOperation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowlistOperation {
+    /// The SHA-256 hash of the body, which clients send in its place.
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The whole document, as printed by the client.
+    pub body: String,
+}
+
+impl Allowlist {
+    /// Collects the operations in the `gql` templates of TypeScript sources,
+    /// given as pairs of file names and their contents.
+    ///
+    /// Templates can interpolate the templates of other files by the name of
+    /// the variable they are assigned to. Each operation is printed the way
+    /// the UI's Apollo client sends it, with `__typename` added to selections
+    /// and the interpolated fragments after its own definitions, so that the
+    /// hashes match.
+    pub fn from_sources<'a>(
+        sources: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> std::result::Result<Self, AllowlistError> {
+        let mut templates = vec![];
+        for (file, source) in sources {
+            templates.extend(templates_in(file, source)?);
+        }
+        let documents = Documents::new(&templates)?;
+
+        let mut operations = vec![];
+        for (i, template) in templates.iter().enumerate() {
+            let mut own_operations =
+                documents.definitions[i]
+                    .iter()
+                    .filter_map(|definition| match definition {
+                        Definition::Operation(name, operation) => Some((name, operation)),
+                        Definition::Fragment(..) => None,
+                    });
+            let Some((name, operation)) = own_operations.next() else {
+                continue;
+            };
+            if own_operations.next().is_some() {
+                return Err(template.error("templates can only have one operation"));
+            }
+            let Some(name) = name else {
+                return Err(template.error("operations must be named"));
+            };
+            if operations
+                .iter()
+                .any(|op: &AllowlistOperation| op.name == name.as_str())
+            {
+                return Err(template.error(format!("operation `{name}` is defined more than once")));
+            }
+
+            let body = print_document(&documents.expand(i, &mut vec![])?);
+            operations.push(AllowlistOperation {
+                id: sha256_hex(&body),
+                name: name.to_string(),
+                ty: operation.node.ty.to_string(),
+                body,
+            });
+        }
+        operations.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Self {
+            format: ALLOWLIST_FORMAT.to_owned(),
+            version: ALLOWLIST_VERSION,
+            operations,
+        })
+    }
+
+    /// Reads an allowlist, checking that the operations match their hashes.
+    pub fn load(path: &Path) -> std::result::Result<Self, AllowlistError> {
+        let allowlist: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if allowlist.format != ALLOWLIST_FORMAT {
+            return Err(AllowlistError::Invalid(format!(
+                "unknown format `{}`",
+                allowlist.format
+            )));
+        }
+        if allowlist.version != ALLOWLIST_VERSION {
+            return Err(AllowlistError::Invalid(format!(
+                "unsupported version {}",
+                allowlist.version
+            )));
+        }
+        if let Some(op) = allowlist
+            .operations
+            .iter()
+            .find(|op| sha256_hex(&op.body) != op.id)
+        {
+            return Err(AllowlistError::Invalid(format!(
+                "the hash of `{}` doesn't match its body",
+                op.name
+            )));
+        }
+        Ok(allowlist)
+    }
+
+    pub fn write(&self, out: impl io::Write) -> std::result::Result<(), AllowlistError> {
+        serde_json::to_writer_pretty(out, self)?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn operations(&self) -> &[AllowlistOperation] {
+        &self.operations
+    }
+}
+
+/// A `gql` template literal in a source file.
+struct Template<'a> {
+    file: &'a str,
+    /// The variable that the template is assigned to, if any.
+    binding: Option<&'a str>,
+    /// The template's own text, with interpolations left out.
+    text: String,
+    /// The variables interpolated into the template, in order.
+    interpolations: Vec<&'a str>,
+}
+
+impl Template<'_> {
+    fn error(&self, message: impl Into<String>) -> AllowlistError {
+        AllowlistError::Document {
+            file: self.file.to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Finds the `gql` templates in a source file.
+fn templates_in<'a>(
+    file: &'a str,
+    source: &'a str,
+) -> std::result::Result<Vec<Template<'a>>, AllowlistError> {
+    let unterminated = || AllowlistError::Document {
+        file: file.to_owned(),
+        message: "unterminated `gql` template".to_owned(),
+    };
+
+    let mut templates = vec![];
+    let mut offset = 0;
+    while let Some(found) = source[offset..].find("gql`") {
+        let start = offset + found;
+        let body_start = start + "gql`".len();
+        offset = body_start;
+        // Skip tags that only end in `gql`.
+        if source[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| is_ident(&c.to_string()))
+        {
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut interpolations = vec![];
+        let mut chars = source[body_start..].char_indices();
+        loop {
+            let (i, c) = chars.next().ok_or_else(unterminated)?;
+            match c {
+                '`' => {
+                    offset = body_start + i + 1;
+                    break;
+                }
+                '\\' => text.extend(chars.next().map(|(_, c)| c)),
+                '$' if source[body_start + i + 1..].starts_with('{') => {
+                    chars.next();
+                    let expr_start = body_start + i + 2;
+                    let (j, _) = chars
+                        .by_ref()
+                        .find(|&(_, c)| c == '}')
+                        .ok_or_else(unterminated)?;
+                    let expr_end = body_start + j;
+                    let expr = source[expr_start..expr_end].trim();
+                    if !is_ident(expr) {
+                        return Err(AllowlistError::Document {
+                            file: file.to_owned(),
+                            message: format!(
+                                "only variables can be interpolated into templates, not `{expr}`"
+                            ),
+                        });
+                    }
+                    interpolations.push(expr);
+                    text.push(' ');
+                }
+                c => text.push(c),
+            }
+        }
+
+        templates.push(Template {
+            file,
+            binding: binding(&source[..start]),
+            text,
+            interpolations,
+        });
+    }
+    Ok(templates)
+}
+
+/// Finds the variable that a template is assigned to, from the source before
+/// it, such as `const GQL: TypedDocumentNode<Query, Variables> = `.
+fn binding(before: &str) -> Option<&str> {
+    if !before.trim_end().ends_with('=') {
+        return None;
+    }
+    let decl = before.rfind("const ")? + "const ".len();
+    let name = &before[decl..];
+    let end = name
+        .find(|c: char| !is_ident(&c.to_string()))
+        .unwrap_or(name.len());
+    Some(&name[..end]).filter(|name| is_ident(name))
+}
+
+#[derive(Clone)]
+enum Definition {
+    Operation(Option<Name>, Positioned<OperationDefinition>),
+    Fragment(Name, Positioned<FragmentDefinition>),
+}
+
+impl Definition {
+    fn pos(&self) -> Pos {
+        match self {
+            Definition::Operation(_, operation) => operation.pos,
+            Definition::Fragment(_, fragment) => fragment.pos,
+        }
+    }
+}
+
+/// The definitions of each template, and the templates that can be
+/// interpolated.
+struct Documents<'a> {
+    templates: &'a [Template<'a>],
+    definitions: Vec<Vec<Definition>>,
+    bindings: HashMap<&'a str, usize>,
+}
+
+impl<'a> Documents<'a> {
+    fn new(templates: &'a [Template<'a>]) -> std::result::Result<Self, AllowlistError> {
+        let definitions = templates
+            .iter()
+            .map(definitions)
+            .collect::<std::result::Result<_, _>>()?;
+        let bindings = templates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, template)| Some((template.binding?, i)))
+            .collect();
+        Ok(Self {
+            templates,
+            definitions,
+            bindings,
+        })
+    }
+
+    /// Gets the definitions of a template followed by those of the templates
+    /// it interpolates, without repeating fragments.
+    fn expand(
+        &self,
+        i: usize,
+        stack: &mut Vec<usize>,
+    ) -> std::result::Result<Vec<Definition>, AllowlistError> {
+        let template = &self.templates[i];
+        stack.push(i);
+        let mut definitions = self.definitions[i].clone();
+        for name in &template.interpolations {
+            let &j = self
+                .bindings
+                .get(name)
+                .ok_or_else(|| template.error(format!("no template is assigned to `{name}`")))?;
+            if stack.contains(&j) {
+                return Err(template.error(format!("`{name}` interpolates itself")));
+            }
+            definitions.extend(self.expand(j, stack)?);
+        }
+        stack.pop();
+
+        let mut fragments = HashSet::new();
+        definitions.retain(|definition| match definition {
+            Definition::Operation(..) => true,
+            Definition::Fragment(name, _) => fragments.insert(name.clone()),
+        });
+        Ok(definitions)
+    }
+}
+
+/// Parses the definitions in a template, in the order they are written.
+fn definitions(template: &Template) -> std::result::Result<Vec<Definition>, AllowlistError> {
+    if template.text.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    let parse_error = |err: parser::Error| template.error(err.to_string());
+    // Documents of only fragments are fine here, but not to the parser, so one
+    // is given an operation to be dropped.
+    let (doc, fragments_only) = match parser::parse_query(&template.text) {
+        Ok(doc) => (doc, false),
+        Err(parser::Error::MissingOperation) => (
+            parser::parse_query(format!("{}\n{{ __typename }}", template.text))
+                .map_err(parse_error)?,
+            true,
+        ),
+        Err(err) => return Err(parse_error(err)),
+    };
+
+    let mut definitions = doc
+        .fragments
+        .into_iter()
+        .map(|(name, fragment)| Definition::Fragment(name, fragment))
+        .collect::<Vec<_>>();
+    if !fragments_only {
+        match doc.operations {
+            DocumentOperations::Single(operation) => {
+                definitions.push(Definition::Operation(None, operation));
+            }
+            DocumentOperations::Multiple(operations) => {
+                definitions.extend(
+                    operations
+                        .into_iter()
+                        .map(|(name, operation)| Definition::Operation(Some(name), operation)),
+                );
+            }
+        }
+    }
+    definitions.sort_unstable_by_key(Definition::pos);
+    Ok(definitions)
+}
+
+// Printing follows GraphQL.js, which clients hash the output of.
+
+fn join(parts: impl IntoIterator<Item = String>, separator: &str) -> String {
+    parts
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn wrap(start: &str, middle: &str, end: &str) -> String {
+    if middle.is_empty() {
+        String::new()
+    } else {
+        format!("{start}{middle}{end}")
+    }
+}
+
+fn indent(s: &str) -> String {
+    wrap("  ", &s.replace('\n', "\n  "), "")
+}
+
+fn block(items: Vec<String>) -> String {
+    wrap("{\n", &indent(&join(items, "\n")), "\n}")
+}
+
+fn print_document(definitions: &[Definition]) -> String {
+    join(definitions.iter().map(print_definition), "\n\n")
+}
+
+fn print_definition(definition: &Definition) -> String {
+    match definition {
+        Definition::Operation(name, operation) => {
+            let operation = &operation.node;
+            let variables = print_variables(&operation.variable_definitions);
+            let prefix = join(
+                [
+                    operation.ty.to_string(),
+                    join(
+                        [
+                            name.as_ref().map(ToString::to_string).unwrap_or_default(),
+                            variables,
+                        ],
+                        "",
+                    ),
+                    print_directives(&operation.directives),
+                ],
+                " ",
+            );
+            let selection_set = print_selection_set(&operation.selection_set.node, false);
+            if operation.ty == OperationType::Query && prefix == "query" {
+                selection_set
+            } else {
+                format!("{prefix} {selection_set}")
+            }
+        }
+        Definition::Fragment(name, fragment) => {
+            let fragment = &fragment.node;
+            format!(
+                "fragment {name} on {} {}{}",
+                fragment.type_condition.node.on.node,
+                wrap("", &print_directives(&fragment.directives), " "),
+                print_selection_set(&fragment.selection_set.node, true)
+            )
+        }
+    }
+}
+
+fn print_variables(variables: &[Positioned<VariableDefinition>]) -> String {
+    let variables = variables.iter().map(|variable| {
+        let variable = &variable.node;
+        format!(
+            "${}: {}{}{}",
+            variable.name.node,
+            variable.var_type.node,
+            wrap(
+                " = ",
+                &variable
+                    .default_value
+                    .as_ref()
+                    .map(|value| print_value(&value.node.clone().into_value()))
+                    .unwrap_or_default(),
+                ""
+            ),
+            wrap(" ", &print_directives(&variable.directives), "")
+        )
+    });
+    wrap("(", &join(variables, ", "), ")")
+}
+
+/// Prints a selection set, adding `__typename` to it like Apollo Client does
+/// unless `add_typename` is unset.
+fn print_selection_set(selection_set: &SelectionSet, add_typename: bool) -> String {
+    let mut items = selection_set
+        .items
+        .iter()
+        .map(|selection| match &selection.node {
+            Selection::Field(field) => print_field(&field.node),
+            Selection::FragmentSpread(spread) => format!(
+                "...{}{}",
+                spread.node.fragment_name.node,
+                wrap(" ", &print_directives(&spread.node.directives), "")
+            ),
+            Selection::InlineFragment(fragment) => join(
+                [
+                    "...".to_owned(),
+                    fragment
+                        .node
+                        .type_condition
+                        .as_ref()
+                        .map(|condition| format!("on {}", condition.node.on.node))
+                        .unwrap_or_default(),
+                    print_directives(&fragment.node.directives),
+                    print_selection_set(&fragment.node.selection_set.node, true),
+                ],
+                " ",
+            ),
+        })
+        .collect::<Vec<_>>();
+
+    let has_typename = selection_set.items.iter().any(|selection| {
+        matches!(&selection.node, Selection::Field(field) if field.node.name.node.starts_with("__"))
+    });
+    if add_typename && !items.is_empty() && !has_typename {
+        items.push("__typename".to_owned());
+    }
+    block(items)
+}
+
+fn print_field(field: &Field) -> String {
+    let prefix = format!(
+        "{}{}",
+        wrap(
+            "",
+            &field
+                .alias
+                .as_ref()
+                .map(|alias| alias.node.to_string())
+                .unwrap_or_default(),
+            ": "
+        ),
+        field.name.node
+    );
+    let arguments = field
+        .arguments
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name.node, print_value(&value.node)))
+        .collect::<Vec<_>>();
+    let mut line = format!("{prefix}{}", wrap("(", &arguments.join(", "), ")"));
+    if line.chars().count() > MAX_LINE_LENGTH {
+        line = format!(
+            "{prefix}{}",
+            wrap("(\n", &indent(&arguments.join("\n")), "\n)")
+        );
+    }
+
+    let exported = field
+        .directives
+        .iter()
+        .any(|directive| directive.node.name.node == "export");
+    join(
+        [
+            line,
+            print_directives(&field.directives),
+            print_selection_set(&field.selection_set.node, !exported),
+        ],
+        " ",
+    )
+}
+
+fn print_directives(directives: &[Positioned<Directive>]) -> String {
+    join(
+        directives.iter().map(|directive| {
+            let arguments = directive
+                .node
+                .arguments
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name.node, print_value(&value.node)));
+            format!(
+                "@{}{}",
+                directive.node.name.node,
+                wrap("(", &join(arguments, ", "), ")")
+            )
+        }),
+        " ",
+    )
+}
+
+fn print_value(value: &InputValue) -> String {
+    match value {
+        InputValue::Variable(name) => format!("${name}"),
+        InputValue::String(s) => print_string(s),
+        InputValue::List(items) => format!("[{}]", join(items.iter().map(print_value), ", ")),
+        InputValue::Object(fields) => format!(
+            "{{{}}}",
+            join(
+                fields
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {}", print_value(value))),
+                ", "
+            )
+        ),
+        value => value.to_string(),
+    }
+}
+
+fn print_string(s: &str) -> String {
+    let mut printed = String::with_capacity(s.len() + 2);
+    printed.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => printed.push_str("\\\""),
+            '\\' => printed.push_str("\\\\"),
+            '\u{8}' => printed.push_str("\\b"),
+            '\t' => printed.push_str("\\t"),
+            '\n' => printed.push_str("\\n"),
+            '\u{c}' => printed.push_str("\\f"),
+            '\r' => printed.push_str("\\r"),
+            c if c.is_control() => {
+                let _ = write!(printed, "\\u{:04X}", u32::from(c));
+            }
+            c => printed.push(c),
+        }
+    }
+    printed.push('"');
+    printed
+}
+
+#[must_use]
+pub fn sha256_hex(text: &str) -> String {
+    digest::digest(&digest::SHA256, text.as_bytes())
+        .as_ref()
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// The `persistedQuery` extension of a request.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedQuery {
+    version: u32,
+    sha256_hash: String,
+}
+
+/// Lets clients send the hash of a query in place of the query once it has
+/// been sent in full, as Apollo's Automatic Persisted Queries do. With an
+/// allowlist, only the queries in it can be run, whether sent in full or not.
+pub struct PersistedQueries(Arc<PersistedQueryStore>);
+
+impl PersistedQueries {
+    #[must_use]
+    pub fn new(config: &PersistedQueryConfig) -> Self {
+        Self(Arc::new(PersistedQueryStore {
+            cache: NonZeroUsize::new(config.cache_size).map(|cap| Mutex::new(LruCache::new(cap))),
+            allowlist: config.allowlist.as_ref().map(|allowlist| {
+                allowlist
+                    .operations()
+                    .iter()
+                    .map(|op| (op.id.clone(), op.body.clone()))
+                    .collect()
+            }),
+        }))
+    }
+}
+
+impl ExtensionFactory for PersistedQueries {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(PersistedQueriesExtension(self.0.clone()))
+    }
+}
+
+struct PersistedQueryStore {
+    /// Queries by their hash, if automatic persisted queries are supported.
+    cache: Option<Mutex<LruCache<String, String>>>,
+    /// The only queries that can be run, by their hash.
+    allowlist: Option<HashMap<String, String>>,
+}
+
+impl PersistedQueryStore {
+    /// Finds the query for a request with the `persistedQuery` extension,
+    /// returning `None` if the request's own query should be run.
+    fn query(&self, extension: Value, query: &str) -> std::result::Result<Option<String>, Error> {
+        let persisted: PersistedQuery =
+            from_value(extension).map_err(|err| Error::PersistedQueryInvalid(err.to_string()))?;
+        if persisted.version != 1 {
+            return Err(Error::PersistedQueryInvalid(format!(
+                "version {} isn't supported",
+                persisted.version
+            )));
+        }
+        if !query.is_empty() && sha256_hex(query) != persisted.sha256_hash {
+            return Err(Error::PersistedQueryInvalid(
+                "the hash doesn't match the query".to_owned(),
+            ));
+        }
+
+        if let Some(allowlist) = &self.allowlist {
+            return match allowlist.get(&persisted.sha256_hash) {
+                Some(query) => Ok(Some(query.clone())),
+                None => Err(Error::OperationNotAllowed),
+            };
+        }
+        let Some(cache) = &self.cache else {
+            return Err(Error::PersistedQueryNotSupported);
+        };
+        let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if query.is_empty() {
+            match cache.get(&persisted.sha256_hash) {
+                Some(query) => Ok(Some(query.clone())),
+                None => Err(Error::PersistedQueryNotFound),
+            }
+        } else {
+            cache.put(persisted.sha256_hash, query.to_owned());
+            Ok(None)
+        }
+    }
+}
+
+struct PersistedQueriesExtension(Arc<PersistedQueryStore>);
+
+#[async_trait::async_trait]
+impl Extension for PersistedQueriesExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        if let Some(extension) = request.extensions.remove("persistedQuery") {
+            let query = self
+                .0
+                .query(extension, &request.query)
+                .map_err(|err| err.to_server_error())?;
+            if let Some(query) = query {
+                request.query = query;
+            }
+        } else if let Some(allowlist) = &self.0.allowlist {
+            if !allowlist.contains_key(&sha256_hex(&request.query)) {
+                return Err(Error::OperationNotAllowed.to_server_error());
+            }
+        }
+        next.run(ctx, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::Request as GqlRequest;
+    use serde_json::json;
+
+    use super::*;
+
+    static ACCOUNT: &str = r"
+export const GQL_ACCOUNT: TypedDocumentNode<AccountFieldsFragment, void> = gql`
+  fragment AccountFields on AuthenticatedAccount {
+    account {
+      id
+      userId
+    }
+    accessToken
+  }
+`;
+";
+
+    static LOGIN: &str = r#"
+import { GQL_ACCOUNT } from "~/contexts/account";
+
+const GQL: TypedDocumentNode<LoginMutation, LoginMutationVariables> = gql`
+  ${GQL_ACCOUNT}
+  mutation Login($creds: AuthCreds!) {
+    login(creds: $creds) {
+      ...AccountFields
+    }
+  }
+`;
+
+const other = `not ${a} template`;
+"#;
+
+    static BOARDS: &str = r#"
+const GQL: TypedDocumentNode<ListBoardsQuery, ListBoardsQueryVariables> = gql`
+  query ListBoards($first: Int = 10) {
+    boards(first: $first, filter: { tags: ["a", "b"], name: "say \\"hi\\"" }) {
+      __typename
+      edges {
+        node {
+          id
+        }
+      }
+    }
+  }
+`;
+"#;
+
+    fn allowlist() -> Allowlist {
+        Allowlist::from_sources([
+            ("account.tsx", ACCOUNT),
+            ("login.tsx", LOGIN),
+            ("boards.tsx", BOARDS),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_from_sources() {
+        let allowlist = allowlist();
+        let ops = allowlist.operations();
+        assert_eq!(ops.len(), 2);
+
+        assert_eq!(ops[0].name, "ListBoards");
+        assert_eq!(ops[0].ty, "query");
+        assert_eq!(
+            ops[0].body,
+            "query ListBoards($first: Int = 10) {\n  boards(first: $first, filter: {tags: [\"a\", \"b\"], name: \"say \\\"hi\\\"\"}) {\n    __typename\n    edges {\n      node {\n        id\n        __typename\n      }\n      __typename\n    }\n  }\n}"
+        );
+
+        // Interpolated fragments follow the operation.
+        assert_eq!(ops[1].name, "Login");
+        assert_eq!(ops[1].ty, "mutation");
+        assert_eq!(
+            ops[1].body,
+            "mutation Login($creds: AuthCreds!) {\n  login(creds: $creds) {\n    ...AccountFields\n    __typename\n  }\n}\n\nfragment AccountFields on AuthenticatedAccount {\n  account {\n    id\n    userId\n    __typename\n  }\n  accessToken\n  __typename\n}"
+        );
+        assert_eq!(ops[1].id, sha256_hex(&ops[1].body));
+    }
+
+    #[test]
+    fn test_from_sources_errors() {
+        assert!(matches!(
+            Allowlist::from_sources([("login.tsx", LOGIN)]),
+            Err(AllowlistError::Document { file, .. }) if file == "login.tsx"
+        ));
+        assert!(matches!(
+            Allowlist::from_sources([(
+                "anon.tsx",
+                "const GQL = gql`{ boards { edges { cursor } } }`;"
+            )]),
+            Err(AllowlistError::Document { .. })
+        ));
+        assert!(matches!(
+            Allowlist::from_sources([("open.tsx", "const GQL = gql`query Open { id }")]),
+            Err(AllowlistError::Document { .. })
+        ));
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("plazer-allowlist-{}", ulid::Ulid::new()));
+        let mut allowlist = allowlist();
+        allowlist.write(File::create(&path).unwrap()).unwrap();
+        assert_eq!(Allowlist::load(&path).unwrap(), allowlist);
+
+        allowlist.operations[0].body.push(' ');
+        allowlist.write(File::create(&path).unwrap()).unwrap();
+        assert!(matches!(
+            Allowlist::load(&path),
+            Err(AllowlistError::Invalid(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn persisted(query: &str, hash: &str) -> GqlRequest {
+        let mut request = GqlRequest::new(query);
+        request.extensions.insert(
+            "persistedQuery".to_owned(),
+            Value::from_json(json!({ "version": 1, "sha256Hash": hash })).unwrap(),
+        );
+        request
+    }
+
+    async fn execute(queries: &PersistedQueries, request: GqlRequest) -> Result<Value, String> {
+        let schema = crate::schema(|s| s.extension(PersistedQueries(queries.0.clone())));
+        let res = schema.execute(request).await;
+        match res.errors.first() {
+            Some(err) => Err(err.message.clone()),
+            None => Ok(res.data),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_automatic() {
+        let queries = PersistedQueries::new(&PersistedQueryConfig::default());
+        let query = "{ __typename }";
+        let hash = sha256_hex(query);
+
+        assert_eq!(
+            execute(&queries, persisted("", &hash)).await,
+            Err("PersistedQueryNotFound".to_owned())
+        );
+        assert!(execute(&queries, persisted(query, &hash)).await.is_ok());
+        assert_eq!(
+            execute(&queries, persisted("", &hash)).await,
+            execute(&queries, GqlRequest::new(query)).await
+        );
+
+        assert!(execute(
+            &queries,
+            persisted("{ __schema { queryType { name } } }", &hash)
+        )
+        .await
+        .is_err());
+        assert_eq!(
+            execute(
+                &PersistedQueries::new(&PersistedQueryConfig {
+                    cache_size: 0,
+                    allowlist: None,
+                }),
+                persisted("", &hash)
+            )
+            .await,
+            Err("PersistedQueryNotSupported".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allowlist() {
+        let query = "query Typename {\n  __typename\n}";
+        let allowlist = Allowlist::from_sources([(
+            "typename.tsx",
+            "const GQL = gql`query Typename { __typename }`;",
+        )])
+        .unwrap();
+        assert_eq!(allowlist.operations()[0].body, query);
+        let queries = PersistedQueries::new(&PersistedQueryConfig {
+            allowlist: Some(allowlist),
+            ..PersistedQueryConfig::default()
+        });
+        let hash = sha256_hex(query);
+
+        let typename = Ok(Value::from_json(json!({ "__typename": "Query" })).unwrap());
+        assert_eq!(execute(&queries, persisted("", &hash)).await, typename);
+        assert_eq!(execute(&queries, persisted(query, &hash)).await, typename);
+        assert_eq!(execute(&queries, GqlRequest::new(query)).await, typename);
+
+        let not_allowed = Err("Operation is not in the allowlist".to_owned());
+        let other = "{ __typename }";
+        assert_eq!(execute(&queries, GqlRequest::new(other)).await, not_allowed);
+        assert_eq!(
+            execute(&queries, persisted(other, &sha256_hex(other))).await,
+            not_allowed
+        );
+    }
+}
@@ -1,10 +1,12 @@
-use async_graphql::{ComplexObject, InputObject, MaybeUndefined, SimpleObject, ID};
+use async_graphql::{ComplexObject, Enum, InputObject, MaybeUndefined, SimpleObject, ID};
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 
 use super::POST_TABLE_NAME;
-use crate::{board::BOARD_TABLE_NAME, id_obj_impls, prelude::*, query::OpaqueCursor};
+use crate::{
+    account::CurrentAccount, board::BOARD_TABLE_NAME, id_obj_impls, prelude::*, query::OpaqueCursor,
+};
 
 pub type PostCursor = OpaqueCursor<String>;
 
@@ -22,7 +24,25 @@ pub struct Post {
     pub title: Option<String>,
     /// The post's content.
     pub content: Option<String>,
+    /// Whether the post has been published.
+    #[serde(default)]
+    pub state: PostState,
+    /// When the post is scheduled to be published. Once a draft or scheduled
+    /// post is published, this is when it was published.
+    pub publish_at: Option<DateTime<Utc>>,
 
+    /// A timestamp indicating when the post was hidden by a moderator.
+    ///
+    /// Hidden posts are only visible to the moderators of the post's board.
+    pub hidden_at: Option<DateTime<Utc>>,
+    /// A timestamp indicating when the post was pinned by a moderator.
+    ///
+    /// Pinned posts are listed before all other posts in their board.
+    pub pinned_at: Option<DateTime<Utc>>,
+    /// A timestamp indicating when the post was locked by a moderator.
+    ///
+    /// Locked posts cannot be edited until they are unlocked.
+    pub locked_at: Option<DateTime<Utc>>,
     /// A timestamp indicating the last time the board was updated.
     ///
     /// If not present, the post has never been updated.
@@ -74,6 +94,77 @@ impl Post {
             srql::obj_create_query_id(POST_TABLE_NAME, create, id.into()),
         )
     }
+
+    /// Publishes a draft or scheduled post, or schedules it if `publish_at` is
+    /// in the future.
+    pub fn publish(&self, publish_at: Option<DateTime<Utc>>) -> srql::UpdateStatement {
+        let mut update = vec![];
+        if let Some(publish_at) = publish_at.filter(|publish_at| *publish_at > Utc::now()) {
+            PostState::Scheduled.push_field(srql::field("state"), &mut update);
+            publish_at.push_field(srql::field("publish_at"), &mut update);
+        } else {
+            PostState::Published.push_field(srql::field("state"), &mut update);
+            update.push((
+                srql::field("publish_at"),
+                srql::Operator::Equal,
+                srql::time_now(),
+            ));
+        }
+
+        srql::UpdateStatement {
+            what: srql::thing(self.id.clone()),
+            data: srql::Data::SetExpression(update).into(),
+            ..Default::default()
+        }
+    }
+
+    /// Publishes all scheduled posts that are due.
+    pub fn publish_scheduled() -> Result<srql::UpdateStatement> {
+        let mut update = vec![];
+        PostState::Published.push_field(srql::field("state"), &mut update);
+
+        Ok(srql::UpdateStatement {
+            what: srql::table(POST_TABLE_NAME),
+            data: srql::Data::SetExpression(update).into(),
+            cond: srql::Cond(srql::expr(
+                srql::expr(
+                    srql::field("state"),
+                    srql::Operator::Equal,
+                    srql::to_value(PostState::Scheduled).map_err(SrlError::from)?,
+                ),
+                srql::Operator::And,
+                srql::expr(
+                    srql::field("publish_at"),
+                    srql::Operator::LessThanOrEqual,
+                    srql::time_now(),
+                ),
+            ))
+            .into(),
+            ..Default::default()
+        })
+    }
+
+    /// Whether the account can see the post. Posts that haven't been published
+    /// are only visible to their author.
+    pub fn is_visible_to(&self, current: &CurrentAccount) -> bool {
+        self.state == PostState::Published || self.is_author(current)
+    }
+
+    /// Whether the account created the post.
+    pub fn is_author(&self, current: &CurrentAccount) -> bool {
+        match (&self.creator_id, current.id()) {
+            (Some(creator_id), Ok(id)) => *creator_id == id.to_account_thing(),
+            _ => false,
+        }
+    }
+
+    /// Checks that the post can still be changed.
+    pub fn check_unlocked(&self) -> Result<()> {
+        match self.locked_at {
+            Some(_) => Err(Error::PostLocked),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
@@ -86,18 +177,68 @@ pub struct CreatePost {
     /// The post's content.
     #[graphql(validator(max_length = 32_768))]
     pub content: Option<String>,
+    /// Whether to save the post as a draft, which is only visible to its
+    /// author until it is published.
+    #[graphql(default)]
+    pub draft: bool,
+    /// When to publish the post. If this is in the future, the post is only
+    /// visible to its author until then.
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+impl CreatePost {
+    /// The state that the post is created in.
+    pub fn state(&self) -> PostState {
+        if self.draft {
+            PostState::Draft
+        } else if self
+            .publish_at
+            .is_some_and(|publish_at| publish_at > Utc::now())
+        {
+            PostState::Scheduled
+        } else {
+            PostState::Published
+        }
+    }
 }
 
 impl CreateObject for CreatePost {
     fn append(self, expr: &mut srql::SetExpr) {
+        let state = self.state();
         self.board_id
             .map(|id| (BOARD_TABLE_NAME, id))
             .push_field(srql::field("board_id"), expr);
+        if state == PostState::Scheduled {
+            self.publish_at.push_field(srql::field("publish_at"), expr);
+        }
+        state.push_field(srql::field("state"), expr);
         self.title.push_field(srql::field("title"), expr);
         self.content.push_field(srql::field("content"), expr);
     }
 }
 
+/// Whether a post has been published.
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostState {
+    /// The post is only visible to its author.
+    Draft,
+    /// The post will be published at its `publishAt` time, and is only
+    /// visible to its author until then.
+    Scheduled,
+    /// The post is visible to everyone that can read its board.
+    #[default]
+    Published,
+}
+
+impl QueryValue for PostState {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|v| (field, srql::Operator::Equal, v))
+    }
+}
+
 #[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
 pub struct UpdatePost {
     /// The post's title. If not given, the title is not changed. If null is given,
@@ -1,15 +1,26 @@
 #[cfg(test)]
 mod tests;
 
-use async_graphql::connection::{Connection, Edge};
+use async_graphql::{
+    connection::{Connection, Edge},
+    ID,
+};
+use chrono::{DateTime, Utc};
 use tracing::instrument;
 
-use super::{CreatePost, Post, PostCursor, UpdatePost, CONTAINS_TABLE_NAME, POST_TABLE_NAME};
+use super::{
+    CreatePost, Post, PostCursor, PostState, UpdatePost, CONTAINS_TABLE_NAME, POST_TABLE_NAME,
+};
 use crate::{
     account::CurrentAccount,
+    board::{self, BoardPersist, BOARD_TABLE_NAME},
+    job::{Job, JobKind},
+    moderation::ModerationPersist,
     persist::Persist,
     prelude::*,
-    query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+    query::{
+        OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice, MAX_LIMIT, SRQL_ORDER_DESC,
+    },
 };
 
 pub struct PostPersist<'a> {
@@ -24,39 +35,93 @@ impl<'a> PostPersist<'a> {
 
     #[instrument(skip_all)]
     pub async fn get(&self, id: &str) -> Result<Option<Post>> {
-        Ok(self.persist.db().select((POST_TABLE_NAME, id)).await?)
+        let post: Option<Post> = self.persist.db().select((POST_TABLE_NAME, id)).await?;
+        let Some(post) = post else {
+            return Ok(None);
+        };
+
+        let board = match &post.board_id {
+            Some(board_id) => self.board_persist().get(&board_id.to_gql_id()).await?,
+            None => None,
+        };
+
+        let board_persist = self.board_persist();
+        if !post.is_visible_to(self.current) {
+            return Ok(None);
+        }
+
+        let can_view = match &board {
+            Some(board) => {
+                board_persist.can_read(board)
+                    && (post.hidden_at.is_none() || board_persist.can_moderate(board))
+            }
+            None => post.hidden_at.is_none(),
+        };
+
+        Ok(can_view.then_some(post))
     }
 
     #[instrument(skip_all)]
     pub fn list(&self) -> PostListRequest<'_> {
-        PostListRequest::new(self.persist)
+        PostListRequest::new(self.persist, self.current)
     }
 
     #[instrument(skip_all)]
     pub async fn create(&self, post: CreatePost) -> Result<Post> {
         // TODO: check config to see if anon users can create posts on this board
-        // TODO: check perms to see if authd user can create posts on this board
+
+        // Unpublished posts are only visible to their author, so there has to
+        // be one.
+        if post.state() != PostState::Published {
+            self.current.id()?;
+        }
+        let publish_at = match post.state() {
+            PostState::Scheduled => post.publish_at,
+            _ => None,
+        };
+
+        // Boards the current account can't read are treated as missing, so
+        // private boards aren't revealed to non-members.
+        if let Some(board_id) = &post.board_id {
+            if !self.can_post_in(board_id).await? {
+                return Err(Error::NotFound);
+            }
+        }
+        if let (Some(board_id), Ok(account_id)) = (&post.board_id, self.current.id()) {
+            let is_banned = self
+                .moderation_persist()
+                .is_banned(
+                    &(BOARD_TABLE_NAME, board_id.0.as_str()).into(),
+                    &account_id.to_account_thing(),
+                )
+                .await?;
+            if is_banned {
+                return Err(Error::BannedFromBoard);
+            }
+        }
 
         let (ids, create) = Post::create(
             self.current.id().map(ToAccountThing::to_account_thing).ok(),
             post,
         );
 
-        let query = if let Some((board_id, post_id)) = ids {
-            vec![
-                srql::trans_begin(),
-                srql::Statement::Create(create),
-                srql::Statement::Relate(srql::RelateStatement {
-                    kind: srql::Table(CONTAINS_TABLE_NAME.to_owned()).into(),
-                    from: board_id.into(),
-                    with: srql::Thing::from((POST_TABLE_NAME.to_owned(), post_id)).into(),
-                    ..Default::default()
-                }),
-                srql::trans_end(),
-            ]
-        } else {
-            vec![srql::Statement::Create(create)]
-        };
+        let mut query = vec![srql::trans_begin(), srql::Statement::Create(create)];
+        if let Some((board_id, post_id)) = ids {
+            query.push(srql::Statement::Relate(srql::RelateStatement {
+                kind: srql::Table(CONTAINS_TABLE_NAME.to_owned()).into(),
+                from: board_id.into(),
+                with: srql::Thing::from((POST_TABLE_NAME.to_owned(), post_id)).into(),
+                ..Default::default()
+            }));
+        }
+        // Scheduled posts are published by a job that runs when they are due.
+        if let Some(publish_at) = publish_at {
+            query.push(srql::Statement::Create(Job::create(
+                JobKind::PublishScheduledPosts,
+                publish_at,
+            )));
+        }
+        query.push(srql::trans_end());
 
         let post = self.persist.db().query(query).await?.take(0)?;
 
@@ -68,41 +133,139 @@ impl<'a> PostPersist<'a> {
 
     #[instrument(skip_all)]
     pub async fn update(&self, id: &str, update: UpdatePost) -> Result<Option<Post>> {
-        // TODO: check config to see if anon users can update posts
-        // TODO: check perms to see if authd user can update posts
+        let Some(post) = self.get_writable(id).await? else {
+            return Ok(None);
+        };
+        post.check_unlocked()?;
 
         let post = if let Some(update) = update.into_update((POST_TABLE_NAME, id).into()) {
             self.persist.db().query(update).await?.take(0)?
         } else {
-            self.get(id).await?
+            Some(post)
         };
 
         Ok(post)
     }
 
+    /// Publishes a draft or scheduled post. If `publish_at` is in the future,
+    /// the post is scheduled to be published then instead.
+    #[instrument(skip_all)]
+    pub async fn publish(
+        &self,
+        id: &str,
+        publish_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Post>> {
+        let post: Option<Post> = self.persist.db().select((POST_TABLE_NAME, id)).await?;
+        let Some(post) = post.filter(|post| post.is_visible_to(self.current)) else {
+            return Ok(None);
+        };
+        if !post.is_author(self.current) {
+            return Err(Error::Unauthorized);
+        }
+        if post.state == PostState::Published {
+            return Ok(Some(post));
+        }
+
+        let publish_at = publish_at.filter(|publish_at| *publish_at > Utc::now());
+        let mut query = vec![
+            srql::trans_begin(),
+            srql::Statement::Update(post.publish(publish_at)),
+        ];
+        if let Some(publish_at) = publish_at {
+            query.push(srql::Statement::Create(Job::create(
+                JobKind::PublishScheduledPosts,
+                publish_at,
+            )));
+        }
+        query.push(srql::trans_end());
+
+        Ok(self.persist.db().query(query).await?.take(0)?)
+    }
+
     #[instrument(skip_all)]
     pub async fn delete(&self, id: &str) -> Result<Option<Post>> {
-        // TODO: check config to see if anon users can delete posts
-        // TODO: check perms to see if authd user can delete posts
+        if self.get_writable(id).await?.is_none() {
+            return Ok(None);
+        }
 
         let post = self.persist.db().delete((POST_TABLE_NAME, id)).await?;
         Ok(post)
     }
+
+    /// Gets a post the current account may change, which is one they can see
+    /// and either wrote or moderate the board of. Posts they can't see are
+    /// treated as missing.
+    async fn get_writable(&self, id: &str) -> Result<Option<Post>> {
+        let Some(post) = self.get(id).await? else {
+            return Ok(None);
+        };
+        if post.is_author(self.current) {
+            return Ok(Some(post));
+        }
+
+        let board = match &post.board_id {
+            Some(board_id) => self.board_persist().get(&board_id.to_gql_id()).await?,
+            None => None,
+        };
+        if !board.is_some_and(|board| self.board_persist().can_moderate(&board)) {
+            return Err(Error::Unauthorized);
+        }
+
+        Ok(Some(post))
+    }
+
+    /// Whether the current account can write posts in the given board, which
+    /// requires being able to read it.
+    async fn can_post_in(&self, board_id: &str) -> Result<bool> {
+        let board_persist = self.board_persist();
+        let board = board_persist.get(board_id).await?;
+        Ok(board.is_some_and(|board| board_persist.can_read(&board)))
+    }
+
+    fn board_persist(&self) -> BoardPersist<'_> {
+        BoardPersist::new(self.persist, self.current)
+    }
+
+    fn moderation_persist(&self) -> ModerationPersist<'_> {
+        ModerationPersist::new(self.persist, self.current)
+    }
+}
+
+/// Publishes all scheduled posts that are due, returning the number of posts
+/// that were published.
+#[instrument(skip_all)]
+pub async fn publish_scheduled(persist: &Persist) -> Result<usize> {
+    let published: Vec<Post> = persist
+        .db()
+        .query(Post::publish_scheduled()?)
+        .await?
+        .take(0)?;
+    Ok(published.len())
 }
 
 pub struct PostListRequest<'a> {
     persist: &'a Persist,
+    current: &'a CurrentAccount,
+    board_id: Option<srql::Thing>,
     pagination: Option<PaginationInput<OpaqueCursor<String>>>,
 }
 
 impl<'a> PostListRequest<'a> {
-    fn new(persist: &'a Persist) -> Self {
+    fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
         Self {
             persist,
+            current,
+            board_id: None,
             pagination: None,
         }
     }
 
+    /// Only lists the posts in the given board, with its pinned posts first.
+    pub fn in_board(mut self, board_id: &ID) -> Self {
+        self.board_id = Some((BOARD_TABLE_NAME, board_id.as_str()).into());
+        self
+    }
+
     pub fn with_pagination(
         mut self,
         args: impl Into<PaginationInput<OpaqueCursor<String>>>,
@@ -112,19 +275,45 @@ impl<'a> PostListRequest<'a> {
     }
 
     #[instrument(skip_all)]
-    pub async fn execute(self) -> Result<Connection<PostCursor, Post>> {
+    pub async fn execute(mut self) -> Result<Connection<PostCursor, Post>> {
+        let is_first_page = match &self.pagination {
+            Some(pagination) => pagination.is_first_page(),
+            None => true,
+        };
         let PaginationOptions {
             cond,
             order,
             limit,
             result_slice_opts,
-        } = (self.pagination, POST_TABLE_NAME).into();
+        } = (self.pagination.take(), POST_TABLE_NAME).into();
+
+        let visible_cond = self.visible_cond()?;
+
+        // Pinned posts are left out of the paginated posts so that the cursors
+        // stay the same as posts are pinned and unpinned. Instead, they are all
+        // put in front of the first page.
+        let pinned = match &self.board_id {
+            Some(_) if is_first_page => self.pinned(visible_cond.clone()).await?,
+            _ => vec![],
+        };
+        let cond = srql::cond_and(cond, visible_cond.0);
+        let cond = match &self.board_id {
+            Some(_) => srql::cond_and(
+                cond.into(),
+                srql::expr(
+                    srql::field("pinned_at"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ),
+            ),
+            None => cond,
+        };
 
         let query = srql::SelectStatement {
             expr: srql::Fields::all(),
             what: srql::table(POST_TABLE_NAME),
             order: srql::Orders(order.into_iter().collect()).into(),
-            cond,
+            cond: cond.into(),
             limit,
             ..Default::default()
         };
@@ -137,13 +326,101 @@ impl<'a> PostListRequest<'a> {
         } = ResultSlice::new(posts, result_slice_opts);
 
         let mut connection = Connection::new(has_previous_page, has_next_page);
-        connection.edges = posts
+        connection.edges = pinned
             .into_iter()
+            .chain(posts)
             .map(|post| Edge::new(OpaqueCursor(post.id.to_gql_id().0), post))
             .collect();
 
         Ok(connection)
     }
+
+    fn visible_cond(&self) -> Result<srql::Cond> {
+        let cond = srql::cond_and(
+            None,
+            srql::expr(
+                srql::field("hidden_at"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ),
+        );
+        let cond = srql::cond_and(
+            cond.into(),
+            board::readable_cond(&["board_id"], self.current, false)?,
+        );
+        let cond = srql::cond_and(cond.into(), self.published_cond()?);
+
+        Ok(match &self.board_id {
+            Some(board_id) => srql::cond_and(
+                cond.into(),
+                srql::expr(
+                    srql::field("board_id"),
+                    srql::Operator::Equal,
+                    board_id.clone(),
+                ),
+            ),
+            None => cond,
+        })
+    }
+
+    /// Unpublished posts are only listed for their author.
+    fn published_cond(&self) -> Result<srql::Value> {
+        // Posts created before drafts existed are published.
+        let mut cond = srql::expr(
+            srql::expr(
+                srql::field("state"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ),
+            srql::Operator::Or,
+            srql::expr(
+                srql::field("state"),
+                srql::Operator::Equal,
+                srql::to_value(PostState::Published).map_err(SrlError::from)?,
+            ),
+        );
+
+        if let Ok(id) = self.current.id() {
+            cond = srql::expr(
+                cond,
+                srql::Operator::Or,
+                srql::expr(
+                    srql::field("creator_id"),
+                    srql::Operator::Equal,
+                    srql::Value::Thing(id.to_account_thing()),
+                ),
+            );
+        }
+
+        Ok(cond)
+    }
+
+    async fn pinned(&self, cond: srql::Cond) -> Result<Vec<Post>> {
+        let cond = srql::cond_and(
+            cond.into(),
+            srql::expr(
+                srql::field("pinned_at"),
+                srql::Operator::NotEqual,
+                srql::Value::None,
+            ),
+        );
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(POST_TABLE_NAME),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("pinned_at"),
+                direction: SRQL_ORDER_DESC,
+                ..Default::default()
+            }])
+            .into(),
+            cond: cond.into(),
+            limit: Some(srql::Limit(srql::Number::Int(MAX_LIMIT).into())),
+            ..Default::default()
+        };
+
+        Ok(self.persist.db().query(query).await?.take(0)?)
+    }
 }
 
 #[cfg(test)]
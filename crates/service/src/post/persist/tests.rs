@@ -3,7 +3,14 @@ use std::collections::VecDeque;
 use async_graphql::MaybeUndefined;
 
 use super::{testing::PostTestData as _, *};
-use crate::{account::testing::*, board::testing::BoardTestData as _, query::testing::Paginator};
+use crate::{
+    account::{testing::*, CurrentAccount},
+    board::{testing::BoardTestData as _, BoardVisibility, CreateBoard},
+    moderation::{
+        testing::ModerationTestData as _, CreateReport, ReportTarget, ResolveAction, ResolveReport,
+    },
+    query::{testing::Paginator, PaginationInput},
+};
 
 #[tokio::test]
 async fn test_create_no_board() {
@@ -50,6 +57,7 @@ async fn test_create_with_board() {
         board_id: Some(board.id.to_gql_id()),
         title: Some("Test".into()),
         content: Some("Test".into()),
+        ..Default::default()
     };
 
     let res = post_persist.create(post).await;
@@ -159,7 +167,7 @@ async fn test_backward_pagination() {
 
 #[tokio::test]
 async fn test_empty_update() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let post_persist = data.post();
 
     let post = data.generate_post().await;
@@ -182,7 +190,7 @@ async fn test_empty_update() {
 
 #[tokio::test]
 async fn test_update_title() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let post_persist = data.post();
 
     let post = data.generate_post().await;
@@ -211,7 +219,7 @@ async fn test_update_title() {
 
 #[tokio::test]
 async fn test_update_content() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let post_persist = data.post();
 
     let post = data.generate_post().await;
@@ -240,7 +248,7 @@ async fn test_update_content() {
 
 #[tokio::test]
 async fn test_update_title_null() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let post_persist = data.post();
 
     let post = data.generate_post().await;
@@ -291,7 +299,7 @@ async fn test_update_nonexistent() {
 
 #[tokio::test]
 async fn test_delete() {
-    let data = TestData::new().await;
+    let (data, _) = TestData::with_user().await;
     let post_persist = data.post();
 
     let post = data.generate_post().await;
@@ -313,6 +321,107 @@ async fn test_delete() {
     assert!(res.is_none());
 }
 
+#[tokio::test]
+async fn test_write_not_author() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let post = data.generate_post_in(&board.id).await;
+    let acc = data.account().create_test_user().await;
+    let current = acc.current();
+    let other = PostPersist::new(&data.persist, &current);
+    let update = || UpdatePost {
+        content: MaybeUndefined::Value("Updated".into()),
+        ..Default::default()
+    };
+
+    let res = other.update(&post.id.to_gql_id(), update()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    let res = other.delete(&post.id.to_gql_id()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    let res = data.post().get(&post.id.to_gql_id()).await;
+    assert_eq!(res.unwrap().unwrap().content, post.content);
+
+    data.board()
+        .add_moderator(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await
+        .unwrap();
+    let res = other.update(&post.id.to_gql_id(), update()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().content, Some("Updated".into()));
+    let res = other.delete(&post.id.to_gql_id()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_write_hidden() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let acc = data.account().create_test_user().await;
+    let current = acc.current();
+    let other = PostPersist::new(&data.persist, &current);
+    let post = other
+        .create(CreatePost {
+            board_id: Some(board.id.to_gql_id()),
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let draft = data
+        .post()
+        .create(CreatePost {
+            content: Some("Test".into()),
+            draft: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let update = || UpdatePost {
+        content: MaybeUndefined::Value("Updated".into()),
+        ..Default::default()
+    };
+
+    let res = other.update(&draft.id.to_gql_id(), update()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+    let res = other.delete(&draft.id.to_gql_id()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+    let res = data.post().get(&draft.id.to_gql_id()).await;
+    assert_eq!(res.unwrap().unwrap().content, Some("Test".into()));
+
+    let report = data
+        .moderation()
+        .report(CreateReport {
+            target: ReportTarget::Post(post.id.to_gql_id()),
+            reason: "Test".into(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    data.moderation()
+        .resolve(
+            &report.id.to_gql_id(),
+            ResolveReport {
+                actions: vec![ResolveAction::HideContent],
+                note: None,
+                ban_expires_at: None,
+            },
+        )
+        .await
+        .unwrap();
+    let res = other.update(&post.id.to_gql_id(), update()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+    let res = other.delete(&post.id.to_gql_id()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+}
+
 #[tokio::test]
 async fn test_delete_nonexistent() {
     let data = TestData::new().await;
@@ -326,3 +435,329 @@ async fn test_delete_nonexistent() {
     println!("{res:?}");
     assert!(res.is_none());
 }
+
+#[tokio::test]
+async fn test_private_board() {
+    let (data, _) = TestData::with_user().await;
+    let board = data
+        .board()
+        .create(CreateBoard {
+            handle: Some("test".into()),
+            visibility: Some(BoardVisibility::Private),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let post = data.generate_post_in(&board.id).await;
+    let acc = data.account().create_test_user().await;
+    let current = acc.current();
+    let other = PostPersist::new(&data.persist, &current);
+
+    let res = other.get(&post.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+
+    let res = other
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await;
+    assert!(res.unwrap().edges.is_empty());
+
+    data.board()
+        .add_member(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await
+        .unwrap();
+
+    let res = other.get(&post.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+
+    let res = other
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await;
+    assert_eq!(res.unwrap().edges.len(), 1);
+}
+
+#[tokio::test]
+async fn test_private_board_write() {
+    let (data, _) = TestData::with_user().await;
+    let board = data
+        .board()
+        .create(CreateBoard {
+            handle: Some("test".into()),
+            visibility: Some(BoardVisibility::Private),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let acc = data.account().create_test_user().await;
+    let current = acc.current();
+    let other = PostPersist::new(&data.persist, &current);
+    let anon = CurrentAccount::default();
+    let anon = PostPersist::new(&data.persist, &anon);
+
+    let create = || CreatePost {
+        board_id: Some(board.id.to_gql_id()),
+        content: Some("Test".into()),
+        ..Default::default()
+    };
+
+    for persist in [&other, &anon] {
+        let res = persist.create(create()).await;
+        println!("{res:?}");
+        assert_eq!(res.unwrap_err(), Error::NotFound);
+    }
+
+    data.board()
+        .add_member(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await
+        .unwrap();
+    let post = other.create(create()).await.unwrap();
+
+    data.board()
+        .remove_member(&board.id.to_gql_id(), &acc.id.to_gql_id())
+        .await
+        .unwrap();
+    let update = UpdatePost {
+        content: MaybeUndefined::Value("Updated".into()),
+        ..Default::default()
+    };
+    let res = other.update(&post.id.id.to_raw(), update).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+
+    let res = data.post().get(&post.id.id.to_raw()).await;
+    assert_eq!(res.unwrap().unwrap().content, Some("Test".into()));
+}
+
+#[tokio::test]
+async fn test_pinned_first() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let mut posts = vec![];
+    for _ in 0..5 {
+        posts.push(data.generate_post_in(&board.id).await);
+    }
+    posts.reverse();
+    let moderation = ModerationPersist::new(&data.persist, &data.current);
+    let post_persist = data.post();
+
+    let page = |cursor| async {
+        post_persist
+            .list()
+            .in_board(&board.id.to_gql_id())
+            .with_pagination(PaginationInput::new().forward(2).set_after(cursor))
+            .execute()
+            .await
+            .unwrap()
+    };
+    let ids = |res: &Connection<PostCursor, Post>| {
+        res.edges
+            .iter()
+            .map(|edge| edge.node.id.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let second_page = ids(&page(Some(OpaqueCursor(posts[1].id.to_gql_id().0))).await);
+
+    moderation
+        .pin_post(&posts[3].id.to_gql_id(), true)
+        .await
+        .unwrap();
+    moderation
+        .pin_post(&posts[4].id.to_gql_id(), true)
+        .await
+        .unwrap();
+
+    let res = page(None).await;
+    assert_eq!(
+        ids(&res),
+        vec![
+            posts[4].id.clone(),
+            posts[3].id.clone(),
+            posts[0].id.clone(),
+            posts[1].id.clone(),
+        ]
+    );
+    assert!(res.has_next_page);
+
+    // The pinned posts are not repeated, and the cursors still point at the
+    // same place.
+    let res = page(Some(OpaqueCursor(posts[1].id.to_gql_id().0))).await;
+    assert_eq!(ids(&res), vec![posts[2].id.clone()]);
+    assert_eq!(second_page[0], posts[2].id);
+    assert!(!res.has_next_page);
+
+    moderation
+        .pin_post(&posts[4].id.to_gql_id(), false)
+        .await
+        .unwrap();
+    let res = page(None).await;
+    assert_eq!(
+        ids(&res),
+        vec![
+            posts[3].id.clone(),
+            posts[0].id.clone(),
+            posts[1].id.clone(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_update_locked() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let post = data.generate_post_in(&board.id).await;
+    let moderation = ModerationPersist::new(&data.persist, &data.current);
+    let post_persist = data.post();
+    let update = || UpdatePost {
+        title: MaybeUndefined::Value("Test".into()),
+        ..Default::default()
+    };
+
+    moderation
+        .lock_post(&post.id.to_gql_id(), true)
+        .await
+        .unwrap();
+    let res = post_persist.update(&post.id.to_gql_id(), update()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::PostLocked);
+
+    moderation
+        .lock_post(&post.id.to_gql_id(), false)
+        .await
+        .unwrap();
+    let res = post_persist.update(&post.id.to_gql_id(), update()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().title, Some("Test".into()));
+}
+
+#[tokio::test]
+async fn test_draft() {
+    let (data, _) = TestData::with_user().await;
+    let post_persist = data.post();
+    let acc = data.account().create_test_user().await;
+    let current = acc.current();
+    let other = PostPersist::new(&data.persist, &current);
+
+    let post = post_persist
+        .create(CreatePost {
+            content: Some("Test".into()),
+            draft: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(post.state, PostState::Draft);
+
+    let res = post_persist.get(&post.id.to_gql_id()).await;
+    assert!(res.unwrap().is_some());
+    let res = other.get(&post.id.to_gql_id()).await;
+    assert!(res.unwrap().is_none());
+
+    let res = other
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await;
+    assert!(res.unwrap().edges.is_empty());
+    let res = post_persist
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await;
+    assert_eq!(res.unwrap().edges.len(), 1);
+
+    let res = other.publish(&post.id.to_gql_id(), None).await;
+    assert!(res.unwrap().is_none());
+
+    let res = post_persist.publish(&post.id.to_gql_id(), None).await;
+    println!("{res:?}");
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.state, PostState::Published);
+    assert!(res.publish_at.is_some());
+
+    let res = other.get(&post.id.to_gql_id()).await;
+    assert!(res.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_draft_unauthenticated() {
+    let data = TestData::new().await;
+
+    let res = data
+        .post()
+        .create(CreatePost {
+            content: Some("Test".into()),
+            draft: true,
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_scheduled() {
+    let (data, _) = TestData::with_user().await;
+    let post_persist = data.post();
+    let anon = CurrentAccount::default();
+    let other = PostPersist::new(&data.persist, &anon);
+
+    let post = post_persist
+        .create(CreatePost {
+            content: Some("Test".into()),
+            publish_at: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(post.state, PostState::Scheduled);
+
+    // Posts aren't published before they are due.
+    assert_eq!(publish_scheduled(&data.persist).await, Ok(0));
+    let res = other.get(&post.id.to_gql_id()).await;
+    assert!(res.unwrap().is_none());
+
+    data.persist
+        .db()
+        .query(format!(
+            "UPDATE {} SET publish_at = time::now() - 1m",
+            post.id
+        ))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    assert_eq!(publish_scheduled(&data.persist).await, Ok(1));
+    let res = other.get(&post.id.to_gql_id()).await;
+    assert_eq!(res.unwrap().unwrap().state, PostState::Published);
+}
+
+#[tokio::test]
+async fn test_publish_later() {
+    let (data, _) = TestData::with_user().await;
+    let post_persist = data.post();
+
+    let post = post_persist
+        .create(CreatePost {
+            content: Some("Test".into()),
+            draft: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let publish_at = Utc::now() + chrono::Duration::hours(1);
+    let res = post_persist
+        .publish(&post.id.to_gql_id(), Some(publish_at))
+        .await;
+    println!("{res:?}");
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.state, PostState::Scheduled);
+    assert_eq!(res.publish_at, Some(publish_at));
+}
@@ -1,8 +1,12 @@
 use async_graphql::{connection::Connection, Context, Object, ID};
+use chrono::{DateTime, Utc};
 use tracing::instrument;
 
 use super::{CreatePost, Post, PostCursor, UpdatePost};
-use crate::{prelude::*, query::PaginationArgs};
+use crate::{
+    prelude::*,
+    query::{connection_complexity, PaginationArgs},
+};
 
 #[derive(Default)]
 pub struct PostQuery;
@@ -15,31 +19,41 @@ impl PostQuery {
         ctx.post_persist().get(&id).await.extend()
     }
 
-    /// Lists posts.
+    /// Lists posts. Posts in private boards are only listed for the board's
+    /// members.
+    ///
+    /// If a board is given, only its posts are listed, and its pinned posts
+    /// are put in front of the first page. Pinned posts are never included in
+    /// later pages, so cursors are unaffected by posts being pinned.
+    #[graphql(complexity = "connection_complexity(child_complexity, first, last)")]
     #[instrument(skip_all)]
     async fn posts(
         &self,
         ctx: &Context<'_>,
+        board_id: Option<ID>,
         after: Option<String>,
         before: Option<String>,
         first: Option<i32>,
         last: Option<i32>,
     ) -> GqlResult<Connection<PostCursor, Post>> {
-        ctx.post_persist()
-            .list()
-            .with_pagination(
-                PaginationArgs {
-                    after,
-                    before,
-                    first,
-                    last,
-                }
-                .validate()
-                .extend()?,
-            )
-            .execute()
-            .await
-            .extend()
+        let persist = ctx.post_persist();
+        let mut list = persist.list();
+        if let Some(board_id) = &board_id {
+            list = list.in_board(board_id);
+        }
+        list.with_pagination(
+            PaginationArgs {
+                after,
+                before,
+                first,
+                last,
+            }
+            .validate()
+            .extend()?,
+        )
+        .execute()
+        .await
+        .extend()
     }
 }
 
@@ -65,6 +79,19 @@ impl PostMutation {
         ctx.post_persist().update(&id, update).await.extend()
     }
 
+    /// Publishes a draft or scheduled post. If `publishAt` is in the future,
+    /// the post is scheduled to be published then instead. Only the post's
+    /// author can publish it.
+    #[instrument(skip_all)]
+    async fn publish_post(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        publish_at: Option<DateTime<Utc>>,
+    ) -> GqlResult<Option<Post>> {
+        ctx.post_persist().publish(&id, publish_at).await.extend()
+    }
+
     /// Deletes a post.
     #[instrument(skip_all)]
     async fn delete_post(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Post>> {
@@ -18,6 +18,22 @@ pub const PAGE_EXTRA: i64 = 2;
 pub const SRQL_ORDER_ASC: bool = true;
 pub const SRQL_ORDER_DESC: bool = false;
 
+/// The complexity of a connection field, for limiting how costly queries can
+/// be. It's the complexity of one item times the most items a page can have.
+pub fn connection_complexity(
+    child_complexity: usize,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> usize {
+    let limit = first
+        .or(last)
+        .map_or(MAX_LIMIT, |limit| i64::from(limit).clamp(0, MAX_LIMIT));
+    usize::try_from(limit)
+        .unwrap_or_default()
+        .saturating_mul(child_complexity)
+        .saturating_add(1)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PaginationArgs {
     pub after: Option<String>,
@@ -93,6 +109,19 @@ where
     }
 }
 
+impl<Cursor> PaginationInput<Cursor>
+where
+    Cursor: CursorType + Debug + Default + Clone,
+{
+    /// Whether this requests the very start of a list, i.e. it has no cursors
+    /// and isn't paginating backwards from the end.
+    pub fn is_first_page(&self) -> bool {
+        self.after.is_none()
+            && self.before.is_none()
+            && !matches!(self.direction, Some(PaginationDirection::Last(_)))
+    }
+}
+
 impl<Cursor> TryFrom<PaginationArgs> for PaginationInput<Cursor>
 where
     Cursor: CursorType + Debug + Default + Clone,
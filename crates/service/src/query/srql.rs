@@ -23,6 +23,16 @@ pub fn field(field: impl Into<String>) -> Idiom {
     Idiom(vec![Part::Field(Ident(field.into()))])
 }
 
+#[inline]
+pub fn nested_field<'a>(parts: impl IntoIterator<Item = &'a str>) -> Idiom {
+    Idiom(
+        parts
+            .into_iter()
+            .map(|part| Part::Field(Ident(part.to_owned())))
+            .collect(),
+    )
+}
+
 #[inline]
 pub fn array(array: impl Into<Vec<Value>>) -> Value {
     Value::Array(array.into().into())
@@ -48,6 +58,41 @@ pub fn trans_end() -> Statement {
     Statement::Commit(CommitStatement)
 }
 
+#[inline]
+pub fn expr(l: impl Into<Value>, o: Operator, r: impl Into<Value>) -> Value {
+    Expression::Binary {
+        l: l.into(),
+        o,
+        r: r.into(),
+    }
+    .into()
+}
+
+/// Joins an extra expression onto an (optional) existing condition.
+pub fn cond_and(cond: Option<Cond>, r: impl Into<Value>) -> Cond {
+    match cond {
+        Some(Cond(l)) => Cond(expr(l, Operator::And, r)),
+        None => Cond(r.into()),
+    }
+}
+
+/// Counts the records in a table, returned in the `count` field.
+pub fn count(table: &str, cond: Option<Cond>) -> SelectStatement {
+    SelectStatement {
+        expr: Fields(
+            vec![Field::Single {
+                expr: Function::Normal("count".into(), vec![]).into(),
+                alias: Some(field("count")),
+            }],
+            false,
+        ),
+        what: self::table(table),
+        cond,
+        group: Groups(vec![]).into(),
+        ..Default::default()
+    }
+}
+
 pub type SetExprItem = (Idiom, Operator, Value);
 pub type SetExpr = Vec<SetExprItem>;
 
@@ -55,6 +100,13 @@ pub fn ulid() -> String {
     Ulid::new().to_string().to_ascii_lowercase()
 }
 
+/// When an ID made by [`ulid`] was made.
+pub fn ulid_time(id: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    Ulid::from_string(&id.to_ascii_uppercase())
+        .ok()
+        .map(|ulid| ulid.datetime().into())
+}
+
 pub fn obj_create_query(table: &str, data: SetExpr) -> CreateStatement {
     obj_create_query_id(table, data, ulid().into())
 }
@@ -114,6 +166,42 @@ pub fn define_uniq_index(
     }))
 }
 
+pub fn define_index(
+    index: impl Into<String>,
+    table: &str,
+    fields: impl Into<Vec<Idiom>>,
+) -> Statement {
+    Statement::Define(DefineStatement::Index(DefineIndexStatement {
+        name: index.into().into(),
+        what: table.into(),
+        cols: Idioms(fields.into()),
+        index: Index::Idx,
+        ..Default::default()
+    }))
+}
+
+pub fn remove_index(index: impl Into<String>, table: &str) -> Statement {
+    Statement::Remove(RemoveStatement::Index(RemoveIndexStatement {
+        name: index.into().into(),
+        what: table.into(),
+    }))
+}
+
+pub fn remove_event(event: impl Into<String>, table: &str) -> Statement {
+    Statement::Remove(RemoveStatement::Event(RemoveEventStatement {
+        name: event.into().into(),
+        what: table.into(),
+    }))
+}
+
+#[inline]
+pub fn lowercase(value: impl Into<Value>) -> Value {
+    Value::Function(Box::new(Function::Normal(
+        "string::lowercase".into(),
+        vec![value.into()],
+    )))
+}
+
 #[inline]
 pub fn time_now() -> Value {
     Value::Function(Box::new(Function::Normal("time::now".into(), vec![])))
@@ -27,6 +27,12 @@ impl QueryValue for String {
     }
 }
 
+impl QueryValue for bool {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((field, srql::Operator::Equal, srql::Value::Bool(self)))
+    }
+}
+
 impl QueryValue for (&str, ID) {
     fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
         let (table, id) = self;
@@ -0,0 +1,178 @@
+use tracing::{info, metadata::LevelFilter, warn, Level};
+use tracing_subscriber::{reload, Registry};
+
+use crate::{
+    account::SigningKeys,
+    config::{create_key_pair, ServiceConfig, RELOADABLE_CONFIG_KEYS},
+    persist::Persist,
+    tls::{certified_key, TlsCerts},
+};
+
+/// Changes the log levels of the logging set up by
+/// [`init_logging`](crate::init_logging) while it is running.
+#[derive(Clone)]
+pub struct LogLevels {
+    stdout: reload::Handle<LevelFilter, Registry>,
+    file: reload::Handle<LevelFilter, Registry>,
+}
+
+impl LogLevels {
+    pub(crate) fn new(
+        stdout: reload::Handle<LevelFilter, Registry>,
+        file: reload::Handle<LevelFilter, Registry>,
+    ) -> Self {
+        Self { stdout, file }
+    }
+
+    pub fn set(&self, level_stdout: Level, level_file: Level) -> Result<(), reload::Error> {
+        self.stdout.reload(LevelFilter::from_level(level_stdout))?;
+        self.file.reload(LevelFilter::from_level(level_file))
+    }
+}
+
+/// The parts of a running server that change when its config is reloaded.
+pub(crate) struct Reloader {
+    config: ServiceConfig,
+    private_key: Option<String>,
+    persist: Persist,
+    keys: SigningKeys,
+    tls: Option<TlsCerts>,
+    log_levels: Option<LogLevels>,
+}
+
+impl Reloader {
+    pub fn new(
+        config: ServiceConfig,
+        persist: Persist,
+        keys: SigningKeys,
+        tls: Option<TlsCerts>,
+        log_levels: Option<LogLevels>,
+    ) -> Self {
+        Self {
+            private_key: config.private_key_pem().ok(),
+            config,
+            persist,
+            keys,
+            tls,
+            log_levels,
+        }
+    }
+
+    /// Resolves the config again and applies the values that can be changed
+    /// while running. Nothing is applied if the new config is invalid.
+    ///
+    /// Values that need a restart are left as they are, with a warning. The
+    /// TLS certificate is read again even if its path is the same, so that
+    /// renewed certificates are picked up.
+    pub fn reload(&mut self) -> anyhow::Result<()> {
+        let config = self.config.reload()?;
+        let private_key = config.private_key_pem()?;
+        let key_pair = if self.private_key.as_ref() == Some(&private_key) {
+            None
+        } else {
+            Some(create_key_pair(&private_key)?)
+        };
+        let tls_key = match (&self.tls, config.tls_config()?) {
+            (Some(_), Some(tls)) => Some(certified_key(&tls)?),
+            (Some(_), None) | (None, Some(_)) => {
+                warn!("TLS was turned on or off, which needs a restart to take effect");
+                None
+            }
+            (None, None) => None,
+        };
+
+        for key in self.config.changed_keys(&config)? {
+            if !RELOADABLE_CONFIG_KEYS.contains(&key.as_str()) {
+                warn!(
+                    key,
+                    "Config value changed, but needs a restart to take effect"
+                );
+            }
+        }
+
+        if let Some(log_levels) = &self.log_levels {
+            let log_config = config.log_config();
+            log_levels.set(log_config.level_stdout, log_config.level_file)?;
+        }
+        self.persist.set_settings(config.settings());
+        if let Some((enc_key, dec_key)) = key_pair {
+            self.keys.replace(enc_key, dec_key);
+            info!("Signing key changed, previous tokens are accepted until they expire");
+        }
+
+        if let (Some(tls), Some(tls_key)) = (&self.tls, tls_key) {
+            tls.replace(tls_key);
+        }
+
+        self.private_key = Some(private_key);
+        self.config = config;
+        info!("Config reloaded");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use ring::{rand::SystemRandom, signature::Ed25519KeyPair};
+
+    use super::*;
+    use crate::{
+        account::{create_refresh_token, verify_refresh_token},
+        config::ServiceConfigBuilder,
+        persist::testing::persist,
+    };
+
+    fn write_key(path: &PathBuf) {
+        let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pem = pkcs8::Document::try_from(pkcs8_bytes.as_ref())
+            .unwrap()
+            .to_pem("PRIVATE KEY", pkcs8::LineEnding::LF)
+            .unwrap();
+        fs::write(path, pem).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let dir = env::temp_dir().join(format!("plazer-reload-{}", ulid::Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        let key_path = dir.join("private_key.pem");
+        let config_path = dir.join("config.toml");
+        write_key(&key_path);
+        fs::write(&config_path, "board_renames = true\n").unwrap();
+
+        let config = ServiceConfigBuilder::new()
+            .config_paths([config_path.display().to_string()])
+            .private_key_path(key_path.display().to_string())
+            .build()
+            .unwrap();
+        let prev_key = config.private_key_pem().unwrap();
+        let (enc_key, dec_key) = create_key_pair(&prev_key).unwrap();
+        let keys = SigningKeys::new(enc_key, dec_key);
+        let persist = persist().await.with_settings(config.settings());
+        let mut reloader = Reloader::new(config, persist.clone(), keys.clone(), None, None);
+
+        let token = create_refresh_token("account:test".into(), &keys.encoding()).unwrap();
+        assert!(verify_refresh_token(&token, &keys.decoding()).is_ok());
+
+        fs::write(&config_path, "board_renames = false\nport = 1234\n").unwrap();
+        write_key(&key_path);
+        reloader.reload().unwrap();
+        assert!(!persist.settings().board_renames);
+
+        // Tokens signed with the previous key are still accepted, but new
+        // tokens are signed with the new key.
+        assert!(verify_refresh_token(&token, &keys.decoding()).is_ok());
+        let new_token = create_refresh_token("account:test".into(), &keys.encoding()).unwrap();
+        assert_ne!(new_token, token);
+        assert!(verify_refresh_token(&new_token, &keys.decoding()).is_ok());
+        let (_, prev_dec_key) = create_key_pair(&prev_key).unwrap();
+        assert!(verify_refresh_token(&new_token, &prev_dec_key.into()).is_err());
+
+        // An invalid config is rejected as a whole.
+        fs::write(&config_path, "board_renames = true\nprot = 1234\n").unwrap();
+        assert!(reloader.reload().is_err());
+        assert!(!persist.settings().board_renames);
+    }
+}
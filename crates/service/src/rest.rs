@@ -0,0 +1,494 @@
+use async_graphql::connection::CursorType as _;
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State},
+    headers::{authorization::Bearer, Authorization},
+    routing::get,
+    Json, Router, TypedHeader,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::instrument;
+
+use crate::{
+    account::{authenticate, AccountStatusCache, CurrentAccount, SigningKeys},
+    board::{Board, BoardPersist, BoardVisibility},
+    error::ErrorResponse,
+    persist::Persist,
+    post::{Post, PostPersist, PostState},
+    prelude::*,
+    query::{PaginationArgs, MAX_LIMIT},
+    ServiceState,
+};
+
+/// How many posts are in a page when no limit is given.
+pub const DEFAULT_PAGE_SIZE: i32 = 20;
+
+/// The read-only REST API, for clients that can't use GraphQL. It is described
+/// by [`openapi`], which is served at `/api/v1/openapi.json`.
+pub fn routes() -> Router<ServiceState> {
+    Router::new()
+        .route("/api/v1/boards/:handle", get(board_handler))
+        .route("/api/v1/boards/:handle/posts", get(board_posts_handler))
+        .route("/api/v1/posts/:id", get(post_handler))
+        .route("/api/v1/openapi.json", get(|| async { Json(openapi()) }))
+}
+
+/// A board, as returned by the REST API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardBody {
+    pub id: String,
+    pub handle: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub visibility: BoardVisibility,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Board> for BoardBody {
+    fn from(board: Board) -> Self {
+        Self {
+            id: board.id.to_gql_id().0,
+            handle: board.handle,
+            name: board.name,
+            description: board.description,
+            visibility: board.visibility,
+            updated_at: board.updated_at,
+        }
+    }
+}
+
+/// A post, as returned by the REST API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostBody {
+    pub id: String,
+    pub board_id: Option<String>,
+    pub creator_id: Option<String>,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub state: PostState,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub pinned_at: Option<DateTime<Utc>>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Post> for PostBody {
+    fn from(post: Post) -> Self {
+        Self {
+            id: post.id.to_gql_id().0,
+            board_id: post.board_id.map(|id| id.to_gql_id().0),
+            creator_id: post.creator_id.map(|id| id.to_gql_id().0),
+            title: post.title,
+            content: post.content,
+            state: post.state,
+            publish_at: post.publish_at,
+            pinned_at: post.pinned_at,
+            locked_at: post.locked_at,
+            updated_at: post.updated_at,
+        }
+    }
+}
+
+/// A page of posts. Pass `nextCursor` as the `cursor` of the next request to
+/// get the following page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostPage {
+    pub posts: Vec<PostBody>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PageParams {
+    cursor: Option<String>,
+    limit: Option<i32>,
+}
+
+/// Gets a board by its handle, or by a handle it used previously.
+#[instrument(skip_all)]
+async fn board_handler(
+    State(persist): State<Persist>,
+    State(keys): State<SigningKeys>,
+    State(status): State<AccountStatusCache>,
+    auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+    Path(handle): Path<String>,
+) -> std::result::Result<Json<BoardBody>, ErrorResponse> {
+    let current = current_account(&keys, &status, auth_header).await?;
+    let board = BoardPersist::new(&persist, &current)
+        .get_by_handle(&handle)
+        .await?
+        .ok_or(Error::NotFound)?;
+    Ok(Json(board.into()))
+}
+
+/// Lists a board's posts, newest first, with its pinned posts in front of the
+/// first page.
+#[instrument(skip_all)]
+async fn board_posts_handler(
+    State(persist): State<Persist>,
+    State(keys): State<SigningKeys>,
+    State(status): State<AccountStatusCache>,
+    auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+    Path(handle): Path<String>,
+    params: std::result::Result<Query<PageParams>, QueryRejection>,
+) -> std::result::Result<Json<PostPage>, ErrorResponse> {
+    let Query(PageParams { cursor, limit }) =
+        params.map_err(|err| Error::PaginationInvalid(err.body_text()))?;
+    let current = current_account(&keys, &status, auth_header).await?;
+    let board = BoardPersist::new(&persist, &current)
+        .get_by_handle(&handle)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    let pagination = PaginationArgs {
+        after: cursor,
+        before: None,
+        first: Some(limit.unwrap_or(DEFAULT_PAGE_SIZE)),
+        last: None,
+    }
+    .validate()?;
+    let connection = PostPersist::new(&persist, &current)
+        .list()
+        .in_board(&board.id.to_gql_id())
+        .with_pagination(pagination)
+        .execute()
+        .await?;
+
+    let next_cursor = connection
+        .has_next_page
+        .then(|| connection.edges.last())
+        .flatten()
+        .map(|edge| edge.cursor.encode_cursor());
+    Ok(Json(PostPage {
+        posts: connection
+            .edges
+            .into_iter()
+            .map(|edge| edge.node.into())
+            .collect(),
+        next_cursor,
+    }))
+}
+
+/// Gets a post by its ID.
+#[instrument(skip_all)]
+async fn post_handler(
+    State(persist): State<Persist>,
+    State(keys): State<SigningKeys>,
+    State(status): State<AccountStatusCache>,
+    auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<PostBody>, ErrorResponse> {
+    let current = current_account(&keys, &status, auth_header).await?;
+    let post = PostPersist::new(&persist, &current)
+        .get(&id)
+        .await?
+        .ok_or(Error::NotFound)?;
+    Ok(Json(post.into()))
+}
+
+/// Reads are anonymous unless a token is given, in which case private boards
+/// and unpublished posts can be read as they can with GraphQL.
+async fn current_account(
+    keys: &SigningKeys,
+    status: &AccountStatusCache,
+    auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<CurrentAccount> {
+    let current = authenticate(auth_header, &keys.decoding())?;
+    status.check(&current).await?;
+    Ok(current)
+}
+
+/// The `OpenAPI` document describing the REST API.
+#[must_use]
+#[allow(clippy::too_many_lines)]
+pub fn openapi() -> serde_json::Value {
+    let handle = json!({
+        "name": "handle",
+        "in": "path",
+        "required": true,
+        "description": "The board's handle, or a handle it used previously.",
+        "schema": { "type": "string" },
+    });
+    let errors = json!({
+        "400": { "$ref": "#/components/responses/Error" },
+        "401": { "$ref": "#/components/responses/Error" },
+        "403": { "$ref": "#/components/responses/Error" },
+        "404": { "$ref": "#/components/responses/Error" },
+        "500": { "$ref": "#/components/responses/Error" },
+    });
+    let ok = |description: &str, schema: &str| {
+        let mut responses = errors.clone();
+        responses["200"] = json!({
+            "description": description,
+            "content": {
+                "application/json": {
+                    "schema": { "$ref": format!("#/components/schemas/{schema}") },
+                },
+            },
+        });
+        responses
+    };
+    let id = json!({ "type": "string" });
+    let nullable_string = json!({ "type": "string", "nullable": true });
+    let nullable_time = json!({ "type": "string", "format": "date-time", "nullable": true });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Plazer",
+            "description": "A read-only REST API for clients that can't use GraphQL. \
+                Requests are anonymous unless they have a bearer token.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/api/v1/boards/{handle}": {
+                "get": {
+                    "operationId": "getBoard",
+                    "summary": "Gets a board by its handle, or by a handle it used previously.",
+                    "parameters": [handle],
+                    "responses": ok("The board.", "Board"),
+                },
+            },
+            "/api/v1/boards/{handle}/posts": {
+                "get": {
+                    "operationId": "listBoardPosts",
+                    "summary": "Lists a board's posts, newest first, with its pinned posts \
+                        in front of the first page.",
+                    "parameters": [
+                        handle,
+                        {
+                            "name": "cursor",
+                            "in": "query",
+                            "description": "The `nextCursor` of the previous page.",
+                            "schema": { "type": "string" },
+                        },
+                        {
+                            "name": "limit",
+                            "in": "query",
+                            "description": "How many posts to list, not counting pinned posts.",
+                            "schema": {
+                                "type": "integer",
+                                "minimum": 0,
+                                "maximum": MAX_LIMIT,
+                                "default": DEFAULT_PAGE_SIZE,
+                            },
+                        },
+                    ],
+                    "responses": ok("A page of posts.", "PostPage"),
+                },
+            },
+            "/api/v1/posts/{id}": {
+                "get": {
+                    "operationId": "getPost",
+                    "summary": "Gets a post by its ID.",
+                    "parameters": [{
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "string" },
+                    }],
+                    "responses": ok("The post.", "Post"),
+                },
+            },
+        },
+        "components": {
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" },
+            },
+            "responses": {
+                "Error": {
+                    "description": "The request failed.",
+                    "content": {
+                        "application/json": {
+                            "schema": { "$ref": "#/components/schemas/Error" },
+                        },
+                    },
+                },
+            },
+            "schemas": {
+                "Board": {
+                    "type": "object",
+                    "required": ["id", "handle", "name", "description", "visibility", "updatedAt"],
+                    "properties": {
+                        "id": id,
+                        "handle": { "type": "string" },
+                        "name": nullable_string,
+                        "description": nullable_string,
+                        "visibility": {
+                            "type": "string",
+                            "enum": ["public", "unlisted", "private"],
+                        },
+                        "updatedAt": { "type": "string", "format": "date-time" },
+                    },
+                },
+                "Post": {
+                    "type": "object",
+                    "required": [
+                        "id", "boardId", "creatorId", "title", "content", "state",
+                        "publishAt", "pinnedAt", "lockedAt", "updatedAt",
+                    ],
+                    "properties": {
+                        "id": id,
+                        "boardId": nullable_string,
+                        "creatorId": nullable_string,
+                        "title": nullable_string,
+                        "content": nullable_string,
+                        "state": {
+                            "type": "string",
+                            "enum": ["draft", "scheduled", "published"],
+                        },
+                        "publishAt": nullable_time,
+                        "pinnedAt": nullable_time,
+                        "lockedAt": nullable_time,
+                        "updatedAt": nullable_time,
+                    },
+                },
+                "PostPage": {
+                    "type": "object",
+                    "required": ["posts", "nextCursor"],
+                    "properties": {
+                        "posts": {
+                            "type": "array",
+                            "items": { "$ref": "#/components/schemas/Post" },
+                        },
+                        "nextCursor": nullable_string,
+                    },
+                },
+                "Error": {
+                    "type": "object",
+                    "required": ["code", "message"],
+                    "properties": {
+                        "code": { "type": "string" },
+                        "message": { "type": "string" },
+                    },
+                },
+            },
+        },
+        "security": [{}, { "bearer": [] }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use pretty_assertions::assert_eq;
+    use serde::de::DeserializeOwned;
+    use tower::ServiceExt as _;
+
+    use super::*;
+    use crate::{
+        account::testing::TestData,
+        board::{testing::BoardTestData as _, CreateBoard},
+        post::testing::PostTestData as _,
+    };
+
+    fn app(data: &TestData) -> Router {
+        routes().with_state(ServiceState::for_tests(data))
+    }
+
+    async fn get<T: DeserializeOwned>(app: &Router, uri: &str) -> (StatusCode, T) {
+        let res = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_board() {
+        let (data, _) = TestData::with_user().await;
+        let board = data.generate_board().await;
+        let app = app(&data);
+
+        let (status, res) = get::<BoardBody>(&app, "/api/v1/boards/test").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(res, BoardBody::from(board));
+
+        let (status, res) = get::<serde_json::Value>(&app, "/api/v1/boards/missing").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(res["code"], "NotFound");
+
+        // Private boards are only readable by their members, and requests
+        // without a token are anonymous.
+        data.board()
+            .create(CreateBoard {
+                handle: Some("private".into()),
+                visibility: Some(BoardVisibility::Private),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let (status, _) = get::<serde_json::Value>(&app, "/api/v1/boards/private").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_board_posts() {
+        let (data, _) = TestData::with_user().await;
+        let board = data.generate_board().await;
+        let mut posts = vec![];
+        for _ in 0..5 {
+            posts.push(data.generate_post_in(&board.id).await);
+        }
+        posts.sort_by(|a, b| b.id.cmp(&a.id));
+        let posts = posts.into_iter().map(PostBody::from).collect::<Vec<_>>();
+        let app = app(&data);
+
+        let (status, page) = get::<PostPage>(&app, "/api/v1/boards/test/posts?limit=3").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page.posts, posts[..3]);
+        let cursor = page.next_cursor.unwrap();
+
+        let uri = format!("/api/v1/boards/test/posts?limit=3&cursor={cursor}");
+        let (status, page) = get::<PostPage>(&app, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page.posts, posts[3..]);
+        assert_eq!(page.next_cursor, None);
+
+        let (status, page) = get::<PostPage>(&app, "/api/v1/boards/test/posts").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page.posts, posts);
+
+        for uri in [
+            "/api/v1/boards/test/posts?cursor=nope",
+            "/api/v1/boards/test/posts?limit=-1",
+            "/api/v1/boards/test/posts?limit=many",
+        ] {
+            let (status, res) = get::<serde_json::Value>(&app, uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+            assert_eq!(res["code"], "PaginationInvalid", "{uri}");
+        }
+
+        let uri = format!("/api/v1/posts/{}", posts[0].id);
+        let (status, post) = get::<PostBody>(&app, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(post, posts[0]);
+    }
+
+    #[tokio::test]
+    async fn test_openapi() {
+        let data = TestData::new().await;
+        let (status, doc) = get::<serde_json::Value>(&app(&data), "/api/v1/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(doc, openapi());
+
+        let paths = doc["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/api/v1/boards/{handle}"));
+        assert!(paths.contains_key("/api/v1/boards/{handle}/posts"));
+        // Every reference points at something in the document.
+        let text = doc.to_string();
+        for reference in text.split("\"#/").skip(1) {
+            let pointer = reference.split('"').next().unwrap();
+            assert!(doc.pointer(&format!("/{pointer}")).is_some(), "{pointer}");
+        }
+    }
+}
@@ -2,15 +2,31 @@ use async_graphql::{EmptySubscription, MergedObject, Schema, SchemaBuilder};
 
 use crate::{
     account::{AccountMutation, AccountQuery},
+    admin::{AdminMutation, AdminQuery},
     board::{BoardMutation, BoardQuery},
+    data_export::DataExportMutation,
+    moderation::{ModerationMutation, ModerationQuery},
     post::{PostMutation, PostQuery},
 };
 
 #[derive(MergedObject, Default)]
-pub struct Query(AccountQuery, BoardQuery, PostQuery);
+pub struct Query(
+    AccountQuery,
+    BoardQuery,
+    PostQuery,
+    ModerationQuery,
+    AdminQuery,
+);
 
 #[derive(MergedObject, Default)]
-pub struct Mutation(AccountMutation, BoardMutation, PostMutation);
+pub struct Mutation(
+    AccountMutation,
+    BoardMutation,
+    PostMutation,
+    ModerationMutation,
+    AdminMutation,
+    DataExportMutation,
+);
 
 pub type ServiceSchema = Schema<Query, Mutation, EmptySubscription>;
 
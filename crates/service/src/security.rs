@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse as _, Response},
+};
+
+/// Allows requests from any origin, when given as one of the origins.
+const ANY_ORIGIN: &str = "*";
+const CORS_METHODS: &str = "GET, POST, OPTIONS";
+const CORS_HEADERS: &str = "authorization, content-type, x-request-id";
+/// How long browsers can remember the answer to a preflight request, in
+/// seconds.
+const CORS_MAX_AGE: &str = "86400";
+const HSTS: &str = "max-age=31536000";
+
+static SECURITY_HEADERS: &[(HeaderName, &str)] = &[
+    (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+    (header::X_FRAME_OPTIONS, "DENY"),
+    (header::REFERRER_POLICY, "no-referrer"),
+    (header::CONTENT_SECURITY_POLICY, "frame-ancestors 'none'"),
+];
+
+/// The headers that are added to every response.
+#[derive(Debug, Clone)]
+pub struct ResponseHeaders {
+    /// The origins that can make cross-origin requests, or `None` for any.
+    cors_origins: Option<Arc<[String]>>,
+    /// Whether browsers should only use HTTPS from now on.
+    hsts: bool,
+}
+
+impl ResponseHeaders {
+    #[must_use]
+    pub fn new(cors_origins: &[String], tls: bool) -> Self {
+        let cors_origins = if cors_origins.iter().any(|origin| origin == ANY_ORIGIN) {
+            None
+        } else {
+            Some(
+                cors_origins
+                    .iter()
+                    .map(|origin| origin.trim_end_matches('/').to_owned())
+                    .collect(),
+            )
+        };
+        Self {
+            cors_origins,
+            hsts: tls,
+        }
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        match &self.cors_origins {
+            Some(origins) => origins.iter().any(|allowed| allowed.as_bytes() == origin),
+            None => true,
+        }
+    }
+}
+
+/// Adds CORS and security headers to responses, and answers CORS preflight
+/// requests from the allowed origins.
+pub async fn response_headers(
+    State(config): State<ResponseHeaders>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .filter(|origin| config.allows(origin))
+        .cloned();
+    let preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+
+    let mut res = if preflight && origin.is_some() {
+        let mut res = StatusCode::NO_CONTENT.into_response();
+        let headers = res.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(CORS_METHODS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static(CORS_HEADERS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static(CORS_MAX_AGE),
+        );
+        res
+    } else {
+        next.run(req).await
+    };
+
+    let headers = res.headers_mut();
+    if let Some(origin) = origin {
+        let allowed = match config.cors_origins {
+            Some(_) => origin,
+            None => HeaderValue::from_static(ANY_ORIGIN),
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static("x-request-id"),
+        );
+    }
+    if config
+        .cors_origins
+        .as_ref()
+        .is_some_and(|origins| !origins.is_empty())
+    {
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+    for (name, value) in SECURITY_HEADERS {
+        headers
+            .entry(name)
+            .or_insert_with(|| HeaderValue::from_static(value));
+    }
+    if config.hsts {
+        headers.insert(
+            header::STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_static(HSTS),
+        );
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{middleware, routing::post, Router};
+    use tower::ServiceExt as _;
+
+    use super::*;
+
+    fn app(cors_origins: &[&str], tls: bool) -> Router {
+        let cors_origins = cors_origins
+            .iter()
+            .map(|&origin| origin.to_owned())
+            .collect::<Vec<_>>();
+        Router::new()
+            .route("/", post(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                ResponseHeaders::new(&cors_origins, tls),
+                response_headers,
+            ))
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::options("/")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn post_from(origin: &str) -> Request<Body> {
+        Request::post("/")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let local = app(&["http://localhost:3000/"], false);
+
+        let res = local
+            .clone()
+            .oneshot(preflight("http://localhost:3000"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_METHODS],
+            CORS_METHODS
+        );
+
+        let res = local
+            .clone()
+            .oneshot(post_from("http://localhost:3000"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        assert_eq!(res.headers()[header::VARY], "origin");
+
+        // Other origins are left to the browser to refuse.
+        let res = local
+            .clone()
+            .oneshot(preflight("https://example.com"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let res = local
+            .clone()
+            .oneshot(post_from("https://example.com"))
+            .await
+            .unwrap();
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let res = app(&["*"], false)
+            .oneshot(post_from("https://example.com"))
+            .await
+            .unwrap();
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!res.headers().contains_key(header::VARY));
+    }
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let res = app(&[], false)
+            .oneshot(Request::post("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(res.headers()[header::X_FRAME_OPTIONS], "DENY");
+        assert!(!res.headers().contains_key(header::VARY));
+        assert!(!res
+            .headers()
+            .contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+        let res = app(&[], true)
+            .oneshot(Request::post("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.headers()[header::STRICT_TRANSPORT_SECURITY], HSTS);
+    }
+}
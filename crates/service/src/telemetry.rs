@@ -0,0 +1,458 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+use async_graphql::{
+    async_trait,
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest, NextRequest,
+    },
+    Data, Request as GqlRequest, Response as GqlResponse, ServerResult, Value,
+};
+use axum::{
+    http::{HeaderMap, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info, info_span, Instrument as _};
+use ulid::Ulid;
+
+#[cfg(feature = "otlp")]
+pub use self::otlp::*;
+use crate::account::CurrentAccount;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Identifies a request in logs, and to users who need to report a problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Uses the ID that the caller sent, if it is a reasonable one, such as
+    /// one from a proxy in front of the server. Otherwise a new one is made.
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let id = headers
+            .get(&X_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                (1..=128).contains(&id.len())
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+            });
+        Self(id.map_or_else(|| Ulid::new().to_string(), ToOwned::to_owned))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Runs each request in a span of its own, tagged with its [`RequestId`],
+/// which is also sent back in the `X-Request-Id` header. With the `otlp`
+/// feature, the span continues the caller's trace if they sent a W3C
+/// `traceparent` header.
+pub async fn trace_requests<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let request_id = RequestId::from_headers(req.headers());
+    let span = info_span!(
+        "request",
+        method = %req.method(),
+        path = req.uri().path(),
+        request_id = %request_id,
+    );
+    #[cfg(feature = "otlp")]
+    set_remote_parent(&span, req.headers());
+
+    req.extensions_mut().insert(request_id.clone());
+    let mut res = next.run(req).instrument(span).await;
+    res.headers_mut().insert(
+        X_REQUEST_ID.clone(),
+        HeaderValue::from_str(&request_id.0).expect("request IDs are valid header values"),
+    );
+    res
+}
+
+/// Logs each GraphQL operation with who ran it, how long it took and whether
+/// it succeeded, and adds the request ID to the errors that are returned.
+pub struct GraphQLLogging;
+
+impl ExtensionFactory for GraphQLLogging {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GraphQLLoggingExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct GraphQLLoggingExtension {
+    operation: Mutex<OperationInfo>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct OperationInfo {
+    request_id: Option<String>,
+    account_id: Option<String>,
+    name: Option<String>,
+}
+
+fn request_data<D: 'static>(data: &Data) -> Option<&D> {
+    data.get(&std::any::TypeId::of::<D>())
+        .and_then(|value| value.downcast_ref())
+}
+
+#[async_trait::async_trait]
+impl Extension for GraphQLLoggingExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> GqlResponse {
+        let start = Instant::now();
+        let mut res = next.run(ctx).await;
+        let duration = start.elapsed();
+
+        let operation = self
+            .operation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(request_id) = &operation.request_id {
+            for err in &mut res.errors {
+                err.extensions
+                    .get_or_insert_with(Default::default)
+                    .set("requestId", request_id.clone());
+            }
+        }
+
+        let request_id = operation.request_id.as_deref();
+        let name = operation.name.as_deref().unwrap_or("anonymous");
+        let account_id = operation.account_id.as_deref();
+        let duration_ms = duration.as_secs_f64() * 1000.0;
+        if res.errors.is_empty() {
+            info!(
+                request_id,
+                operation = name,
+                account_id,
+                duration_ms,
+                outcome = "ok",
+                "GraphQL operation"
+            );
+        } else {
+            let codes = res
+                .errors
+                .iter()
+                .filter_map(|err| match err.extensions.as_ref()?.get("code")? {
+                    Value::String(code) => Some(code.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            info!(
+                request_id,
+                operation = name,
+                account_id,
+                duration_ms,
+                outcome = "error",
+                errors = res.errors.len(),
+                codes = %codes.join(","),
+                "GraphQL operation"
+            );
+        }
+        res
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: GqlRequest,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<GqlRequest> {
+        // Data given to a single request isn't in the context until it is
+        // executed, while data given to a WebSocket connection always is.
+        let request_id = request_data::<RequestId>(&request.data)
+            .or_else(|| ctx.data_opt::<RequestId>())
+            .map(ToString::to_string);
+        let account = request_data::<Arc<CurrentAccount>>(&request.data)
+            .map(AsRef::as_ref)
+            .or_else(|| ctx.data_opt::<CurrentAccount>());
+        let account_id = account
+            .and_then(|account| account.id().ok())
+            .map(|id| id.to_string());
+
+        {
+            let mut operation = self
+                .operation
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            operation.request_id = request_id;
+            operation.account_id = account_id;
+            // Replaced by the name of the operation that runs, if the request
+            // gets that far.
+            operation.name.clone_from(&request.operation_name);
+        }
+        next.run(ctx, request).await
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> GqlResponse {
+        self.operation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .name = operation_name.map(ToOwned::to_owned);
+        next.run(ctx, operation_name).await
+    }
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use axum::http::{HeaderMap, HeaderName};
+    use opentelemetry::{
+        global,
+        propagation::Extractor,
+        trace::{SpanKind, TraceError},
+        Key, KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig as _;
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+    use tracing::{span, Subscriber};
+    use tracing_opentelemetry::{OpenTelemetrySpanExt as _, OtelData};
+    use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+    use crate::{config::OtlpConfig, metrics::is_db_target};
+
+    /// Creates a layer that exports spans to an OpenTelemetry collector over
+    /// OTLP, and sets up W3C trace context propagation.
+    pub fn otlp_layer<S>(config: &OtlpConfig) -> Result<impl Layer<S>, TraceError>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&config.endpoint),
+            )
+            .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+                "service.name",
+                config.service_name.clone(),
+            )])))
+            .install_batch(runtime::Tokio)?;
+
+        Ok(tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .and_then(DbSpans))
+    }
+
+    /// Exports the spans that are still queued, and stops exporting.
+    pub fn shutdown_otlp() {
+        global::shutdown_tracer_provider();
+    }
+
+    pub(super) fn set_remote_parent(span: &tracing::Span, headers: &HeaderMap) {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+        span.set_parent(parent);
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(HeaderName::as_str).collect()
+        }
+    }
+
+    /// Marks the spans of the persist functions as database client spans.
+    ///
+    /// The database's own spans are left out, as they run in a task of their
+    /// own and so can't be linked to the request that caused them.
+    struct DbSpans;
+
+    impl<S> Layer<S> for DbSpans
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+            let metadata = attrs.metadata();
+            if !is_db_target(metadata.target()) {
+                return;
+            }
+            let Some(span) = ctx.span(id) else {
+                return;
+            };
+
+            let mut extensions = span.extensions_mut();
+            if let Some(data) = extensions.get_mut::<OtelData>() {
+                data.builder.span_kind = Some(SpanKind::Client);
+                let attributes = data.builder.attributes.get_or_insert_with(Default::default);
+                attributes.insert(Key::new("db.system"), "surrealdb".into());
+                attributes.insert(Key::new("db.operation"), metadata.name().into());
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{
+            net::SocketAddr,
+            sync::{Arc, Mutex},
+        };
+
+        use opentelemetry_proto::tonic::{
+            collector::trace::v1::{
+                trace_service_server::{TraceService, TraceServiceServer},
+                ExportTraceServiceRequest, ExportTraceServiceResponse,
+            },
+            common::v1::any_value,
+            trace::v1::{span::SpanKind as ProtoSpanKind, Span as ProtoSpan},
+        };
+        use tokio::net::TcpListener;
+        use tracing::{info_span, metadata::LevelFilter, Instrument as _};
+        use tracing_subscriber::layer::SubscriberExt as _;
+
+        use super::*;
+        use crate::persist::testing::persist;
+
+        static TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+
+        /// An OpenTelemetry collector that keeps the spans it is sent.
+        #[derive(Clone, Default)]
+        struct Collector {
+            spans: Arc<Mutex<Vec<ProtoSpan>>>,
+        }
+
+        #[tonic::async_trait]
+        impl TraceService for Collector {
+            async fn export(
+                &self,
+                req: tonic::Request<ExportTraceServiceRequest>,
+            ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+                let spans = req
+                    .into_inner()
+                    .resource_spans
+                    .into_iter()
+                    .flat_map(|resource| resource.scope_spans)
+                    .flat_map(|scope| scope.spans);
+                self.spans.lock().unwrap().extend(spans);
+                Ok(tonic::Response::new(ExportTraceServiceResponse {
+                    partial_success: None,
+                }))
+            }
+        }
+
+        async fn start_collector() -> (Collector, SocketAddr) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let collector = Collector::default();
+            let incoming = async_stream::stream! {
+                loop {
+                    yield listener.accept().await.map(|(stream, _)| stream);
+                }
+            };
+            tokio::spawn(
+                tonic::transport::Server::builder()
+                    .add_service(TraceServiceServer::new(collector.clone()))
+                    .serve_with_incoming(incoming),
+            );
+            (collector, addr)
+        }
+
+        fn attribute(span: &ProtoSpan, key: &str) -> Option<String> {
+            span.attributes
+                .iter()
+                .find(|attribute| attribute.key == key)
+                .and_then(|attribute| attribute.value.as_ref()?.value.clone())
+                .and_then(|value| match value {
+                    any_value::Value::StringValue(value) => Some(value),
+                    _ => None,
+                })
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn test_export() {
+            let (collector, addr) = start_collector().await;
+            let layer = otlp_layer(&OtlpConfig {
+                endpoint: format!("http://{addr}"),
+                service_name: "plazer-test".into(),
+            })
+            .unwrap();
+            let subscriber =
+                tracing_subscriber::registry().with(layer.with_filter(LevelFilter::INFO));
+            let guard = tracing::subscriber::set_default(subscriber);
+
+            let persist = persist().await;
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "traceparent",
+                format!("00-{TRACE_ID}-b7ad6b7169203331-01")
+                    .parse()
+                    .unwrap(),
+            );
+            let span = info_span!("request");
+            set_remote_parent(&span, &headers);
+            persist.locks().instrument(span).await.unwrap();
+
+            drop(guard);
+            tokio::task::spawn_blocking(shutdown_otlp).await.unwrap();
+
+            let trace_id = u128::from_str_radix(TRACE_ID, 16).unwrap().to_be_bytes();
+            let spans = collector.spans.lock().unwrap().clone();
+            let request = spans
+                .iter()
+                .find(|span| span.name == "request")
+                .expect("request span is exported");
+            assert_eq!(request.trace_id, trace_id);
+
+            let query = spans
+                .iter()
+                .find(|span| span.name == "locks")
+                .expect("query span is exported");
+            assert_eq!(query.trace_id, trace_id);
+            assert_eq!(query.parent_span_id, request.span_id);
+            assert_eq!(query.kind, ProtoSpanKind::Client as i32);
+            assert_eq!(attribute(query, "db.system").as_deref(), Some("surrealdb"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(&X_REQUEST_ID, HeaderValue::from_static("req-1:a.b_c"));
+        assert_eq!(RequestId::from_headers(&headers).0, "req-1:a.b_c");
+
+        headers.insert(&X_REQUEST_ID, HeaderValue::from_static("not a valid id"));
+        let generated = RequestId::from_headers(&headers).0;
+        assert!(generated.parse::<Ulid>().is_ok());
+
+        assert_ne!(RequestId::from_headers(&HeaderMap::new()).0, generated);
+    }
+
+    #[tokio::test]
+    async fn test_errors_have_request_id() {
+        let schema = crate::schema(|s| s.extension(GraphQLLogging));
+        let req = GqlRequest::new("query Broken { doesNotExist }").data(RequestId("req-1".into()));
+        let res = schema.execute(req).await;
+
+        assert_eq!(res.errors.len(), 1);
+        let extensions = res.errors[0].extensions.as_ref().unwrap();
+        assert_eq!(
+            extensions.get("requestId"),
+            Some(&Value::String("req-1".into()))
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+
+use hyper::server::accept::{self, Accept};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    Certificate, PrivateKey, ServerConfig,
+};
+use rustls_pemfile::Item;
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tracing::{debug, warn};
+
+use crate::config::TlsConfig;
+
+/// How long clients have to finish the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+#[error("Failed to load TLS certificate: {0}")]
+pub struct TlsError(String);
+
+/// The certificate that HTTPS is served with, which can be replaced while the
+/// server is running without dropping any connections.
+#[derive(Clone)]
+pub struct TlsCerts(Arc<RwLock<Arc<CertifiedKey>>>);
+
+impl TlsCerts {
+    pub fn load(config: &TlsConfig) -> Result<Self, TlsError> {
+        Ok(Self(Arc::new(RwLock::new(Arc::new(certified_key(
+            config,
+        )?)))))
+    }
+
+    /// Uses a certificate read by [`certified_key`] for new connections.
+    pub fn replace(&self, key: CertifiedKey) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(key);
+    }
+}
+
+impl ResolvesServerCert for TlsCerts {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.0
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        )
+    }
+}
+
+/// Reads the certificate chain and private key in the files of `config`.
+pub fn certified_key(config: &TlsConfig) -> Result<CertifiedKey, TlsError> {
+    let certs = read_pem(&config.cert)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(cert) => Some(Certificate(cert)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if certs.is_empty() {
+        return Err(TlsError(format!("{}: no certificates found", config.cert)));
+    }
+
+    let key = read_pem(&config.key)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError(format!("{}: no private key found", config.key)))?;
+    let key =
+        sign::any_supported_type(&key).map_err(|err| TlsError(format!("{}: {err}", config.key)))?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+fn read_pem(path: &str) -> Result<Vec<Item>, TlsError> {
+    let tls_err = |err: io::Error| TlsError(format!("{path}: {err}"));
+    let file = File::open(path).map_err(tls_err)?;
+    rustls_pemfile::read_all(&mut BufReader::new(file)).map_err(tls_err)
+}
+
+/// Accepts connections on `listener` over TLS. Handshakes are done apart from
+/// the server, so that slow clients don't hold up others.
+pub fn incoming(
+    listener: TcpListener,
+    certs: TlsCerts,
+) -> impl Accept<Conn = TlsStream<TcpStream>, Error = io::Error> {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(certs));
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                res = listener.accept() => match res {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        // Such as running out of file descriptors, which
+                        // takes a moment to recover from.
+                        warn!(error = ?err, "Failed to accept connection");
+                        time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                },
+                () = tx.closed() => break,
+            };
+
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(stream).await;
+                    }
+                    Ok(Err(err)) => debug!(error = ?err, "TLS handshake failed"),
+                    Err(_) => debug!("TLS handshake timed out"),
+                }
+            });
+        }
+    });
+
+    accept::from_stream(futures::stream::unfold(rx, |mut rx| async move {
+        let stream = rx.recv().await?;
+        Some((Ok(stream), rx))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use hyper::{service::service_fn, Body, Response, Server};
+    use rustls::{ClientConfig, RootCertStore, ServerName};
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+
+    fn config(n: u8) -> TlsConfig {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls");
+        TlsConfig {
+            cert: format!("{dir}/cert{n}.pem"),
+            key: format!("{dir}/key{n}.pem"),
+        }
+    }
+
+    /// Makes a request, returning the server's certificate and the response.
+    async fn get(addr: SocketAddr) -> (Certificate, String) {
+        let mut roots = RootCertStore::empty();
+        let ca = read_pem(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls/ca.pem")).unwrap();
+        for item in ca {
+            if let Item::X509Certificate(cert) = item {
+                roots.add(&Certificate(cert)).unwrap();
+            }
+        }
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).await.unwrap();
+
+        let cert = stream.get_ref().1.peer_certificates().unwrap()[0].clone();
+        (cert, res)
+    }
+
+    #[test]
+    fn test_certified_key() {
+        assert_eq!(certified_key(&config(1)).unwrap().cert.len(), 1);
+
+        let swapped = TlsConfig {
+            cert: config(1).key,
+            key: config(1).cert,
+        };
+        assert!(certified_key(&swapped).is_err());
+        let missing = TlsConfig {
+            cert: "missing.pem".to_owned(),
+            ..config(1)
+        };
+        assert!(certified_key(&missing).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_incoming() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let certs = TlsCerts::load(&config(1)).unwrap();
+        let server = Server::builder(incoming(listener, certs.clone())).serve(
+            hyper::service::make_service_fn(|_| async {
+                Ok::<_, Infallible>(service_fn(|_| async {
+                    Ok::<_, Infallible>(Response::new(Body::from("hello")))
+                }))
+            }),
+        );
+        tokio::spawn(server);
+
+        let (cert, res) = get(addr).await;
+        assert_eq!(cert, certified_key(&config(1)).unwrap().cert[0]);
+        assert!(res.ends_with("hello"));
+
+        // A client that never finishes its handshake doesn't hold up others.
+        let _stalled = TcpStream::connect(addr).await.unwrap();
+
+        certs.replace(certified_key(&config(2)).unwrap());
+        let (cert, _) = get(addr).await;
+        assert_eq!(cert, certified_key(&config(2)).unwrap().cert[0]);
+    }
+}